@@ -0,0 +1,517 @@
+//! Local agent serving secrets to processes of the same user over a Unix socket. Unix
+//! only: the peer-credential check has no named-pipe counterpart here yet.
+//!
+//! Wire format: every message is a 4-byte big-endian length followed by that many
+//! bytes of JSON. Requests carry the protocol `version` and a fresh `nonce`; the
+//! reply echoes the nonce, and a nonce seen twice on one connection is refused.
+//! Values travel as base64, so binary ones arrive intact.
+//! Unknown fields are ignored and unknown ops get an error reply, so newer clients
+//! degrade gracefully against older agents.
+
+use crate::service::SecretService;
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine as _, engine::general_purpose};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Protocol spoken by this build; requests with another version are refused. Version 2
+/// sends values as base64.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Largest frame either side accepts.
+const MAX_FRAME: usize = 1 << 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum AgentOp {
+    Ping,
+    Get {
+        name: String,
+    },
+    List,
+    /// Names starting with `prefix`, for editor completion; served from a short cache.
+    Names {
+        #[serde(default)]
+        prefix: String,
+        #[serde(default)]
+        limit: Option<u32>,
+    },
+    /// Exchange a one-time lease token for the leased secret's value.
+    Redeem {
+        token: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub version: u32,
+    pub nonce: String,
+    #[serde(flatten)]
+    pub op: AgentOp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum Reply {
+    /// Answer to `ping`: the agent's release; the protocol is in the envelope.
+    Pong {
+        agent: String,
+    },
+    /// A secret's value, base64-encoded
+    Value {
+        value_base64: String,
+    },
+    Names {
+        names: Vec<String>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    pub version: u32,
+    pub nonce: String,
+    #[serde(flatten)]
+    pub reply: Reply,
+}
+
+/// `$XDG_RUNTIME_DIR/devinventory/agent.sock`, or next to the vault without one.
+pub fn default_socket_path(db_path: &Path) -> PathBuf {
+    dirs::runtime_dir()
+        .map(|dir| dir.join("devinventory").join("agent.sock"))
+        .unwrap_or_else(|| db_path.with_file_name("agent.sock"))
+}
+
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        bail!("agent frame of {len} bytes exceeds the {MAX_FRAME} byte limit");
+    }
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, body: &[u8]) -> Result<()> {
+    if body.len() > MAX_FRAME {
+        bail!("agent frame of {} bytes exceeds the limit", body.len());
+    }
+    writer.write_all(&(body.len() as u32).to_be_bytes()).await?;
+    writer.write_all(body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Live secret names kept between `names` requests, which editors send on every
+/// keystroke. Changes show up once the list is older than the TTL.
+#[derive(Debug)]
+pub struct NameCache {
+    ttl: Duration,
+    names: Mutex<Option<(Instant, Arc<Vec<String>>)>>,
+}
+
+impl NameCache {
+    /// A zero `ttl` turns caching off.
+    pub fn new(ttl: Duration) -> NameCache {
+        NameCache {
+            ttl,
+            names: Mutex::new(None),
+        }
+    }
+
+    async fn names(
+        &self,
+        service: &SecretService,
+        prefix: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<String>> {
+        if self.ttl.is_zero() {
+            return service.secret_names(prefix, limit).await;
+        }
+        let fresh = self
+            .names
+            .lock()
+            .expect("name cache lock")
+            .as_ref()
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, names)| names.clone());
+        let names = match fresh {
+            Some(names) => names,
+            None => {
+                let names = Arc::new(service.secret_names("", None).await?);
+                *self.names.lock().expect("name cache lock") =
+                    Some((Instant::now(), names.clone()));
+                names
+            }
+        };
+        let start = names.partition_point(|name| name.as_str() < prefix);
+        Ok(names[start..]
+            .iter()
+            .take_while(|name| name.starts_with(prefix))
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Answer requests on one connection until the peer hangs up.
+pub async fn handle_connection<S>(
+    stream: &mut S,
+    service: &SecretService,
+    names: &NameCache,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut seen = HashSet::new();
+    while let Some(frame) = read_frame(stream).await? {
+        let request: Value = serde_json::from_slice(&frame)?;
+        let nonce = request
+            .get("nonce")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let version = request.get("version").and_then(Value::as_u64);
+        let reply = if nonce.is_empty() {
+            error_reply("request has no nonce")
+        } else if !seen.insert(nonce.clone()) {
+            error_reply("nonce already used on this connection")
+        } else if version != Some(PROTOCOL_VERSION as u64) {
+            error_reply(&format!(
+                "unsupported protocol version {}; agent speaks {PROTOCOL_VERSION}",
+                version.map_or("none".to_string(), |v| v.to_string())
+            ))
+        } else {
+            match serde_json::from_value::<AgentOp>(request) {
+                Ok(op) => dispatch(op, service, names)
+                    .await
+                    .unwrap_or_else(|e| error_reply(&e.to_string())),
+                Err(_) => error_reply("unknown request"),
+            }
+        };
+        let response = Response {
+            version: PROTOCOL_VERSION,
+            nonce,
+            reply,
+        };
+        write_frame(stream, &serde_json::to_vec(&response)?).await?;
+    }
+    Ok(())
+}
+
+async fn dispatch(op: AgentOp, service: &SecretService, names: &NameCache) -> Result<Reply> {
+    Ok(match op {
+        AgentOp::Ping => Reply::Pong {
+            agent: env!("CARGO_PKG_VERSION").to_string(),
+        },
+        AgentOp::Get { name } => {
+            let secret = service.get_secret(&name).await?;
+            Reply::value(&secret.plaintext)
+        }
+        AgentOp::Redeem { token } => {
+            let secret = service.redeem_lease(&token).await?;
+            Reply::value(&secret.plaintext)
+        }
+        AgentOp::List => Reply::Names {
+            names: service
+                .list_secrets()
+                .await?
+                .into_iter()
+                .map(|m| m.name)
+                .collect(),
+        },
+        AgentOp::Names { prefix, limit } => Reply::Names {
+            names: names.names(service, &prefix, limit).await?,
+        },
+    })
+}
+
+impl Reply {
+    fn value(plaintext: &[u8]) -> Self {
+        Reply::Value {
+            value_base64: general_purpose::STANDARD.encode(plaintext),
+        }
+    }
+
+    /// The bytes of a `value` reply.
+    pub fn into_value(self) -> Result<Vec<u8>> {
+        match self {
+            Reply::Value { value_base64 } => general_purpose::STANDARD
+                .decode(value_base64)
+                .context("agent sent a value that is not base64"),
+            other => bail!("unexpected agent reply: {other:?}"),
+        }
+    }
+}
+
+fn error_reply(message: &str) -> Reply {
+    Reply::Error {
+        message: message.to_string(),
+    }
+}
+
+/// Send one request and wait for its reply, checking that the nonce comes back.
+pub async fn call<S>(stream: &mut S, op: AgentOp) -> Result<Reply>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = Request {
+        version: PROTOCOL_VERSION,
+        nonce: uuid::Uuid::new_v4().to_string(),
+        op,
+    };
+    write_frame(stream, &serde_json::to_vec(&request)?).await?;
+    let frame = read_frame(stream)
+        .await?
+        .ok_or_else(|| anyhow!("agent closed the connection"))?;
+    let response: Response = serde_json::from_slice(&frame)?;
+    if response.nonce != request.nonce {
+        bail!("agent reply does not match the request nonce");
+    }
+    match response.reply {
+        Reply::Error { message } => bail!("agent: {message}"),
+        reply => Ok(reply),
+    }
+}
+
+/// Pause between records in a background integrity check, keeping it light on the disk.
+const INTEGRITY_PAUSE: Duration = Duration::from_millis(200);
+
+/// Verify `sample` random records every `every` until the task is dropped. Damage is
+/// logged, kept for `doctor` and announced with a desktop notification where one is
+/// available.
+pub async fn watch_integrity(service: Arc<SecretService>, every: Duration, sample: usize) {
+    loop {
+        tokio::time::sleep(every).await;
+        match service.check_integrity(sample, INTEGRITY_PAUSE).await {
+            Ok(report) if report.failures.is_empty() => {
+                debug!("integrity check: {} record(s) fine", report.checked);
+            }
+            Ok(report) => {
+                let message = format!(
+                    "{} of {} checked record(s) failed to decrypt; run `devinventory doctor`",
+                    report.failures.len(),
+                    report.checked
+                );
+                warn!("integrity check: {message}");
+                notify("DevInventory vault damaged", &message);
+            }
+            Err(e) => warn!("integrity check could not run: {e:#}"),
+        }
+    }
+}
+
+/// Best-effort desktop notification.
+fn notify(summary: &str, body: &str) {
+    let command = if cfg!(target_os = "macos") {
+        let script = format!("display notification {body:?} with title {summary:?}");
+        Command::new("osascript").args(["-e", &script]).output()
+    } else {
+        Command::new("notify-send").args([summary, body]).output()
+    };
+    if let Err(e) = command {
+        debug!("no desktop notification: {e}");
+    }
+}
+
+#[cfg(unix)]
+pub use self::unix::{bind, connect, serve};
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use crate::perms;
+    use log::info;
+    use std::os::unix::fs::MetadataExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    /// Create the socket (mode 0600), replacing a stale one left by a dead agent.
+    pub async fn bind(socket: &Path) -> Result<UnixListener> {
+        if let Some(dir) = socket.parent() {
+            perms::create_private_dir_all(dir)?;
+        }
+        if socket.exists() {
+            if UnixStream::connect(socket).await.is_ok() {
+                bail!(
+                    "an agent is already listening on {}",
+                    socket.to_string_lossy()
+                );
+            }
+            std::fs::remove_file(socket)?;
+        }
+        let listener = UnixListener::bind(socket)?;
+        perms::make_owner_only(socket)?;
+        Ok(listener)
+    }
+
+    /// Answer connections on `listener` until Ctrl-C, then remove `socket`.
+    /// Connections from other users are dropped after a peer-credential check.
+    pub async fn serve(
+        service: Arc<SecretService>,
+        names: Arc<NameCache>,
+        listener: UnixListener,
+        socket: &Path,
+    ) -> Result<()> {
+        // We created the socket, so its owner is the user allowed to talk to us.
+        let owner = std::fs::metadata(socket)?.uid();
+        info!("agent listening on {}", socket.to_string_lossy());
+        let result = loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = tokio::signal::ctrl_c() => break Ok(()),
+            };
+            let mut stream = match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => break Err(e.into()),
+            };
+            match stream.peer_cred() {
+                Ok(cred) if cred.uid() == owner => {}
+                Ok(cred) => {
+                    warn!("refused agent connection from uid {}", cred.uid());
+                    continue;
+                }
+                Err(e) => {
+                    warn!("refused agent connection without peer credentials: {e}");
+                    continue;
+                }
+            }
+            let service = service.clone();
+            let names = names.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(&mut stream, &service, &names).await {
+                    debug!("agent connection ended: {e}");
+                }
+            });
+        };
+        std::fs::remove_file(socket).ok();
+        result
+    }
+
+    pub async fn connect(socket: &Path) -> Result<UnixStream> {
+        UnixStream::connect(socket).await.map_err(|e| {
+            anyhow!(
+                "no agent on {} ({e}); start one with `devinventory agent start`",
+                socket.to_string_lossy()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SecretAttributes;
+    use crate::testing::TempVault;
+
+    #[tokio::test]
+    async fn serves_requests_and_refuses_replays_and_other_versions() {
+        let vault = TempVault::builder()
+            .with_secret("api", "token")
+            .with_secret("app/db", "pw")
+            .with_secret("app/key", "k")
+            .build()
+            .await
+            .unwrap();
+        let service = vault.service.clone();
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let cache = NameCache::new(Duration::from_secs(60));
+        let agent = async move { handle_connection(&mut server, &vault.service, &cache).await };
+        let requests = async move {
+            assert_eq!(
+                call(&mut client, AgentOp::Ping).await.unwrap(),
+                Reply::Pong {
+                    agent: env!("CARGO_PKG_VERSION").into()
+                }
+            );
+            assert_eq!(
+                call(&mut client, AgentOp::Get { name: "api".into() })
+                    .await
+                    .unwrap()
+                    .into_value()
+                    .unwrap(),
+                b"token"
+            );
+            // binary values come back byte for byte
+            let binary = [0xff, 0x00, 0xfe, b'\n'];
+            service
+                .add_secret("blob", &binary, SecretAttributes::default())
+                .await
+                .unwrap();
+            assert_eq!(
+                call(
+                    &mut client,
+                    AgentOp::Get {
+                        name: "blob".into()
+                    }
+                )
+                .await
+                .unwrap(),
+                Reply::Value {
+                    value_base64: "/wD+Cg==".into()
+                }
+            );
+            let names = |prefix: &str, limit| AgentOp::Names {
+                prefix: prefix.into(),
+                limit,
+            };
+            assert_eq!(
+                call(&mut client, names("app/", None)).await.unwrap(),
+                Reply::Names {
+                    names: vec!["app/db".into(), "app/key".into()]
+                }
+            );
+            // a cached list does not see the new secret until it expires
+            service
+                .add_secret("app/new", b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+            assert_eq!(
+                call(&mut client, names("app/", Some(1))).await.unwrap(),
+                Reply::Names {
+                    names: vec!["app/db".into()]
+                }
+            );
+            assert_eq!(
+                call(&mut client, names("app/n", None)).await.unwrap(),
+                Reply::Names { names: vec![] }
+            );
+
+            let raw = |version: u32, nonce: &str| {
+                format!(r#"{{"version":{version},"nonce":"{nonce}","op":"ping"}}"#)
+            };
+            let mut replies = Vec::new();
+            for frame in [raw(2, "n1"), raw(2, "n1"), raw(1, "n2")] {
+                write_frame(&mut client, frame.as_bytes()).await.unwrap();
+                let reply = read_frame(&mut client).await.unwrap().unwrap();
+                let response: Response = serde_json::from_slice(&reply).unwrap();
+                replies.push(response.reply);
+            }
+            assert_eq!(
+                replies[0],
+                Reply::Pong {
+                    agent: env!("CARGO_PKG_VERSION").into()
+                }
+            );
+            assert!(matches!(&replies[1], Reply::Error { message } if message.contains("nonce")));
+            assert!(matches!(&replies[2], Reply::Error { message } if message.contains("version")));
+            drop(client);
+        };
+        let (served, ()) = tokio::join!(agent, requests);
+        served.unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+//! Vault integrity snapshots: a signed Merkle root over every record's AEAD tag.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Length of the Poly1305 tag closing every stored ciphertext.
+const TAG_LEN: usize = 16;
+
+/// Signed statement of what the vault held at `created_at`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Snapshot {
+    pub version: u8,
+    pub created_at: DateTime<Utc>,
+    pub secrets: usize,
+    pub devices: usize,
+    /// Master key version the signature was made with.
+    pub key_version: i64,
+    /// Hex Merkle root over the record leaves, in name order.
+    pub root: String,
+    /// Hex HMAC-SHA256 of [`Snapshot::signing_payload`].
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl Snapshot {
+    /// Everything the signature covers, one field per line.
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "devinventory attestation v{}\n{}\n{}\n{}\n{}\n{}\n",
+            self.version,
+            self.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            self.secrets,
+            self.devices,
+            self.key_version,
+            self.root
+        )
+    }
+}
+
+/// Leaf for one record: its name and the tag authenticating its ciphertext.
+pub fn leaf(name: &str, ciphertext: &[u8]) -> [u8; 32] {
+    let tag = &ciphertext[ciphertext.len().saturating_sub(TAG_LEN)..];
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update((name.len() as u64).to_be_bytes());
+    hasher.update(name.as_bytes());
+    hasher.update(tag);
+    hasher.finalize().into()
+}
+
+/// Merkle root of `leaves` (an unpaired node is carried up unchanged).
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return Sha256::digest([]).into();
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut hasher = Sha256::new();
+                    hasher.update([1u8]);
+                    hasher.update(left);
+                    hasher.update(right);
+                    hasher.finalize().into()
+                }
+                [single] => *single,
+                _ => unreachable!("chunks(2) yields one or two nodes"),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_depends_on_every_leaf_and_its_position() {
+        let a = leaf("a", b"nonce-ct-aaaaaaaaaaaaaaaa");
+        let b = leaf("b", b"nonce-ct-bbbbbbbbbbbbbbbb");
+        let c = leaf("c", b"nonce-ct-cccccccccccccccc");
+        let root = merkle_root(&[a, b, c]);
+        assert_eq!(root, merkle_root(&[a, b, c]));
+        assert_ne!(root, merkle_root(&[b, a, c]));
+        assert_ne!(root, merkle_root(&[a, b]));
+        assert_ne!(a, leaf("a", b"nonce-ct-aaaaaaaaaaaaaaab"));
+        assert_eq!(unhex(&hex(&root)).unwrap(), root);
+        assert!(unhex("zz").is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! Audit log export as JSON Lines and shipping to a collector off the machine, so
+//! the access trail outlives the vault file.
+
+use crate::domain::AuditEntry;
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Where shipped entries go, written as `file:PATH`, `syslog` or an `http(s)://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sink {
+    /// Appended to a JSONL file, e.g. on a mounted share
+    File(PathBuf),
+    /// One message per entry to the local syslog daemon (`authpriv.info`)
+    Syslog,
+    /// POSTed as one `application/x-ndjson` body, via curl
+    Http(String),
+}
+
+impl Sink {
+    pub fn parse(target: &str) -> Result<Sink> {
+        if target == "syslog" {
+            Ok(Sink::Syslog)
+        } else if target.starts_with("http://") || target.starts_with("https://") {
+            Ok(Sink::Http(target.to_string()))
+        } else if let Some(path) = target.strip_prefix("file:").filter(|p| !p.is_empty()) {
+            Ok(Sink::File(PathBuf::from(path)))
+        } else {
+            bail!("unknown audit target '{target}' (use file:PATH, syslog or an http(s):// URL)")
+        }
+    }
+
+    /// Key the shipping cursor is kept under, so each target gets every entry once.
+    pub fn cursor_key(&self) -> String {
+        match self {
+            Sink::File(path) => format!("audit_shipped:file:{}", path.to_string_lossy()),
+            Sink::Syslog => "audit_shipped:syslog".to_string(),
+            Sink::Http(url) => format!("audit_shipped:{url}"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    id: i64,
+    at: String,
+    action: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<&'a str>,
+}
+
+/// One JSON object per line, oldest first; values never appear in the audit log.
+pub fn jsonl(entries: &[AuditEntry]) -> Result<String> {
+    let mut out = String::new();
+    for entry in entries {
+        out.push_str(&serde_json::to_string(&Record {
+            id: entry.id,
+            at: entry.at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            action: &entry.action,
+            secret: entry.secret_name.as_deref(),
+            detail: entry.detail.as_deref(),
+            host: entry.host.as_deref(),
+            user: entry.user.as_deref(),
+        })?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Deliver `entries` to `sink`; on error nothing should be considered shipped.
+pub fn ship(sink: &Sink, entries: &[AuditEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let body = jsonl(entries)?;
+    match sink {
+        Sink::File(path) => {
+            let mut options = std::fs::OpenOptions::new();
+            options.create(true).append(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options
+                .open(path)
+                .with_context(|| format!("opening {}", path.to_string_lossy()))?;
+            file.write_all(body.as_bytes())?;
+            file.sync_data()?;
+        }
+        Sink::Syslog => syslog(&body)?,
+        Sink::Http(url) => {
+            let mut child = Command::new("curl")
+                .args(["-fsS", "-X", "POST", "--data-binary", "@-"])
+                .args(["-H", "Content-Type: application/x-ndjson", url])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .spawn()
+                .context("running curl")?;
+            child
+                .stdin
+                .take()
+                .expect("stdin is piped")
+                .write_all(body.as_bytes())?;
+            let status = child.wait()?;
+            if !status.success() {
+                bail!("posting audit entries to {url} failed ({status})");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn syslog(body: &str) -> Result<()> {
+    use std::os::unix::net::UnixDatagram;
+    // LOG_AUTHPRIV (10) * 8 + LOG_INFO (6)
+    const PRIORITY: u8 = 86;
+    let socket = UnixDatagram::unbound()?;
+    ["/dev/log", "/var/run/syslog"]
+        .iter()
+        .find_map(|path| socket.connect(path).ok())
+        .context("no syslog socket at /dev/log or /var/run/syslog")?;
+    for line in body.lines() {
+        socket.send(format!("<{PRIORITY}>devinventory: {line}").as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn syslog(_body: &str) -> Result<()> {
+    bail!("syslog shipping is only available on Unix")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ships_jsonl_to_files() {
+        assert_eq!(Sink::parse("syslog").unwrap(), Sink::Syslog);
+        assert!(Sink::parse("file:").is_err());
+        assert!(Sink::parse("ftp://x").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let sink = Sink::parse(&format!("file:{}", path.to_string_lossy())).unwrap();
+        let entry = |id, secret: Option<&str>| AuditEntry {
+            id,
+            at: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            action: "read".into(),
+            secret_name: secret.map(str::to_string),
+            detail: None,
+            host: Some("build-01".into()),
+            user: None,
+        };
+        ship(&sink, &[entry(1, Some("db/pw"))]).unwrap();
+        ship(&sink, &[entry(2, None)]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"id\":1,\"at\":\"2023-11-14T22:13:20Z\",\"action\":\"read\",\"secret\":\"db/pw\",\"host\":\"build-01\"}\n\
+             {\"id\":2,\"at\":\"2023-11-14T22:13:20Z\",\"action\":\"read\",\"host\":\"build-01\"}\n"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Login-time units for the agent and the daily `check` job (systemd, launchd,
+//! Windows Task Scheduler).
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+
+const AGENT_UNIT: &str = "devinventory-agent";
+const CHECK_UNIT: &str = "devinventory-check";
+const LAUNCHD_PREFIX: &str = "com.devinventory";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    /// systemd user units (Linux)
+    Systemd,
+    /// launchd agents (macOS)
+    Launchd,
+    /// Task Scheduler (Windows)
+    Windows,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Platform::Launchd
+        } else if cfg!(windows) {
+            Platform::Windows
+        } else {
+            Platform::Systemd
+        }
+    }
+
+    /// Where unit files go; Task Scheduler keeps its own store.
+    pub fn unit_dir(self) -> Result<Option<PathBuf>> {
+        Ok(match self {
+            Platform::Systemd => Some(
+                dirs::config_dir()
+                    .ok_or_else(|| anyhow!("cannot resolve config directory"))?
+                    .join("systemd")
+                    .join("user"),
+            ),
+            Platform::Launchd => Some(
+                dirs::home_dir()
+                    .ok_or_else(|| anyhow!("cannot resolve home directory"))?
+                    .join("Library")
+                    .join("LaunchAgents"),
+            ),
+            Platform::Windows => None,
+        })
+    }
+}
+
+/// Files to write and commands to run to register the jobs.
+#[derive(Debug, Default)]
+pub struct ServicePlan {
+    pub files: Vec<(PathBuf, String)>,
+    pub commands: Vec<Vec<String>>,
+}
+
+/// Units starting `exe <global_args> agent start` at login and running
+/// `exe <global_args> check` daily. Windows gets only the check task, as the agent
+/// needs Unix sockets.
+pub fn plan(
+    platform: Platform,
+    exe: &Path,
+    global_args: &[String],
+    unit_dir: Option<&Path>,
+) -> Result<ServicePlan> {
+    let command = |sub: &[&str]| -> Vec<String> {
+        let mut argv = vec![exe.to_string_lossy().into_owned()];
+        argv.extend(global_args.iter().cloned());
+        argv.extend(sub.iter().map(|s| s.to_string()));
+        argv
+    };
+    let agent = command(&["agent", "start"]);
+    let check = command(&["check"]);
+    let dir = || unit_dir.ok_or_else(|| anyhow!("no unit directory for {platform:?}"));
+    let mut plan = ServicePlan::default();
+    match platform {
+        Platform::Systemd => {
+            let dir = dir()?;
+            plan.files.push((
+                dir.join(format!("{AGENT_UNIT}.service")),
+                format!(
+                    "[Unit]\nDescription=DevInventory agent\n\n[Service]\nExecStart={}\nRestart=on-failure\n\n[Install]\nWantedBy=default.target\n",
+                    systemd_command(&agent)
+                ),
+            ));
+            plan.files.push((
+                dir.join(format!("{CHECK_UNIT}.service")),
+                format!(
+                    "[Unit]\nDescription=DevInventory expiry check\n\n[Service]\nType=oneshot\nExecStart={}\n",
+                    systemd_command(&check)
+                ),
+            ));
+            plan.files.push((
+                dir.join(format!("{CHECK_UNIT}.timer")),
+                "[Unit]\nDescription=Daily DevInventory expiry check\n\n[Timer]\nOnCalendar=daily\nPersistent=true\n\n[Install]\nWantedBy=timers.target\n".to_string(),
+            ));
+            plan.commands
+                .push(args(&["systemctl", "--user", "daemon-reload"]));
+            plan.commands.push(args(&[
+                "systemctl",
+                "--user",
+                "enable",
+                "--now",
+                &format!("{AGENT_UNIT}.service"),
+                &format!("{CHECK_UNIT}.timer"),
+            ]));
+        }
+        Platform::Launchd => {
+            let dir = dir()?;
+            for (name, argv, schedule) in [
+                ("agent", &agent, "  <key>KeepAlive</key>\n  <true/>\n"),
+                (
+                    "check",
+                    &check,
+                    "  <key>StartCalendarInterval</key>\n  <dict>\n    <key>Hour</key>\n    <integer>9</integer>\n    <key>Minute</key>\n    <integer>0</integer>\n  </dict>\n",
+                ),
+            ] {
+                let label = format!("{LAUNCHD_PREFIX}.{name}");
+                let path = dir.join(format!("{label}.plist"));
+                plan.files
+                    .push((path.clone(), launchd_plist(&label, argv, schedule)));
+                plan.commands
+                    .push(args(&["launchctl", "load", "-w", &path.to_string_lossy()]));
+            }
+        }
+        Platform::Windows => {
+            plan.commands.push(args(&[
+                "schtasks",
+                "/Create",
+                "/F",
+                "/TN",
+                r"DevInventory\Check",
+                "/SC",
+                "DAILY",
+                "/ST",
+                "09:00",
+                "/TR",
+                &windows_command(&check),
+            ]));
+        }
+    }
+    Ok(plan)
+}
+
+fn args(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+fn systemd_command(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| {
+            let arg = arg.replace('%', "%%");
+            if arg.contains([' ', '"', '\\', '\'']) {
+                format!("\"{}\"", arg.replace('\\', r"\\").replace('"', "\\\""))
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn windows_command(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| {
+            if arg.contains(' ') {
+                format!("\"{arg}\"")
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn launchd_plist(label: &str, argv: &[String], schedule: &str) -> String {
+    let escape = |s: &str| {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let program: String = argv
+        .iter()
+        .map(|arg| format!("    <string>{}</string>\n", escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{label}</string>
+  <key>ProgramArguments</key>
+  <array>
+{program}  </array>
+  <key>RunAtLoad</key>
+  <true/>
+{schedule}</dict>
+</plist>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn systemd_plan_quotes_paths_and_enables_units() {
+        let plan = plan(
+            Platform::Systemd,
+            Path::new("/opt/dev inventory/devinventory"),
+            &["--db-path".into(), "/home/me/vault.db".into()],
+            Some(Path::new("/home/me/.config/systemd/user")),
+        )
+        .unwrap();
+        let (path, agent) = &plan.files[0];
+        assert_eq!(
+            path,
+            Path::new("/home/me/.config/systemd/user/devinventory-agent.service")
+        );
+        assert!(agent.contains(
+            "ExecStart=\"/opt/dev inventory/devinventory\" --db-path /home/me/vault.db agent start\n"
+        ));
+        assert_eq!(plan.files.len(), 3);
+        assert_eq!(
+            plan.commands[1].last().map(String::as_str),
+            Some("devinventory-check.timer")
+        );
+    }
+
+    #[test]
+    fn launchd_and_windows_plans() {
+        let exe = Path::new("/usr/local/bin/devinventory");
+        let launchd = plan(Platform::Launchd, exe, &[], Some(Path::new("/LA"))).unwrap();
+        assert!(launchd.files[0].1.contains("<string>agent</string>"));
+        assert!(launchd.files[1].1.contains("StartCalendarInterval"));
+        let windows = plan(Platform::Windows, exe, &[], None).unwrap();
+        assert!(windows.files.is_empty());
+        assert_eq!(
+            windows.commands[0].last().unwrap(),
+            "/usr/local/bin/devinventory check"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! AWS CLI `credential_process` support.
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+/// Output document of a `credential_process` command (version 1).
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ProcessCredentials {
+    pub version: u8,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration: Option<String>,
+}
+
+/// Read keys from a stored value: either JSON (`AccessKeyId`, `SecretAccessKey`,
+/// `SessionToken`) or `~/.aws/credentials` style `aws_access_key_id = ...` lines.
+pub fn parse_credentials(
+    value: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<ProcessCredentials> {
+    let mut access_key_id = None;
+    let mut secret_access_key = None;
+    let mut session_token = None;
+    let pairs: Vec<(String, String)> = match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(serde_json::Value::Object(map)) => map
+            .into_iter()
+            .filter_map(|(k, v)| Some((k, v.as_str()?.to_string())))
+            .collect(),
+        _ => value
+            .lines()
+            .filter_map(|l| l.split_once('='))
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .collect(),
+    };
+    for (key, val) in pairs {
+        match key.to_ascii_lowercase().replace('_', "").as_str() {
+            "accesskeyid" | "awsaccesskeyid" => access_key_id = Some(val),
+            "secretaccesskey" | "awssecretaccesskey" => secret_access_key = Some(val),
+            "sessiontoken" | "awssessiontoken" => session_token = Some(val),
+            _ => {}
+        }
+    }
+    let (Some(access_key_id), Some(secret_access_key)) = (access_key_id, secret_access_key) else {
+        bail!("value needs an access key id and a secret access key");
+    };
+    Ok(ProcessCredentials {
+        version: 1,
+        access_key_id,
+        secret_access_key,
+        session_token,
+        expiration: expires_at.map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_json_and_credentials_file_values() {
+        let json = r#"{"AccessKeyId":"AKIAEXAMPLE","SecretAccessKey":"s3cr3t"}"#;
+        let ini = "[default]\naws_access_key_id = AKIAEXAMPLE\naws_secret_access_key = s3cr3t\n";
+        assert_eq!(
+            parse_credentials(json, None).unwrap(),
+            parse_credentials(ini, None).unwrap()
+        );
+        let out = serde_json::to_string(&parse_credentials(ini, None).unwrap()).unwrap();
+        assert_eq!(
+            out,
+            r#"{"Version":1,"AccessKeyId":"AKIAEXAMPLE","SecretAccessKey":"s3cr3t"}"#
+        );
+        assert!(parse_credentials("AKIAEXAMPLE", None).is_err());
+    }
+}
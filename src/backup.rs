@@ -0,0 +1,412 @@
+//! Portable vault backups: every live secret in one versioned, checksummed file,
+//! encrypted under the master key or a backup passphrase. A file can additionally be
+//! sealed to a dedicated backup keypair whose public half lives in config, so
+//! scheduled jobs can write backups the master key cannot open.
+//!
+//! Unsealed backups are byte-stable: the same vault contents and key give the same
+//! file, so a backup kept in git only shows up as changed when a secret did.
+
+use crate::attest::hex;
+use crate::crypto::{self, DeviceKeypair, MasterKey, SecretCrypto};
+use crate::domain::{CertificateInfo, Provenance, Secret, SecretAttributes};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+/// Written into every backup; bump when the layout changes.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+const BACKUP_FORMAT: &str = "devinventory-backup";
+const PAYLOAD_AAD: &str = "devinventory backup payload v1";
+/// PBKDF2-HMAC-SHA256 iterations for new passphrase-protected backups.
+const PASSPHRASE_ROUNDS: u32 = 600_000;
+const PASSPHRASE_SALT_INFO: &[u8] = b"devinventory backup salt v1";
+
+/// Which key the payload is encrypted under.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Protection {
+    /// The vault's current master key
+    MasterKey,
+    /// A key derived from a passphrase with PBKDF2-HMAC-SHA256
+    Passphrase { salt: String, rounds: u32 },
+}
+
+impl Protection {
+    /// Passphrase protection with a salt derived from the vault key: unique to the
+    /// vault, yet the same for every backup of it.
+    pub fn passphrase_for(vault_key: &MasterKey) -> Protection {
+        let salt = crypto::keyed_digest(vault_key, PASSPHRASE_SALT_INFO, b"");
+        Protection::Passphrase {
+            salt: STANDARD.encode(&salt[..16]),
+            rounds: PASSPHRASE_ROUNDS,
+        }
+    }
+
+    /// The payload key for passphrase protection; `None` for master-key backups.
+    pub fn passphrase_key(&self, passphrase: &str) -> Result<Option<MasterKey>> {
+        match self {
+            Protection::MasterKey => Ok(None),
+            Protection::Passphrase { salt, rounds } => {
+                let salt = STANDARD.decode(salt).context("backup salt is not base64")?;
+                Ok(Some(crypto::passphrase_key(
+                    passphrase.as_bytes(),
+                    &salt,
+                    *rounds,
+                )))
+            }
+        }
+    }
+}
+
+/// What restoring does with a name the vault already holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RestoreStrategy {
+    /// Keep the vault's secret and leave the backup's out
+    Skip,
+    /// Replace the vault's secret with the backup's
+    Overwrite,
+    /// Restore the backup's under `<name>-restored` next to the vault's
+    Rename,
+}
+
+/// One secret as stored in a backup: its value and everything needed to recreate it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+    #[serde(default)]
+    pub immutable: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_commands: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<CertificateInfo>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Base64 of the value
+    pub value: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl BackupEntry {
+    pub fn value(&self) -> Result<Vec<u8>> {
+        STANDARD
+            .decode(&self.value)
+            .with_context(|| format!("value of '{}' in the backup is not base64", self.name))
+    }
+
+    /// Attributes to store the entry under; tags are kept separately.
+    pub fn attributes(&self) -> SecretAttributes {
+        SecretAttributes {
+            kind: self.kind.clone(),
+            note: self.note.clone(),
+            owner: self.owner.clone(),
+            contact: self.contact.clone(),
+            immutable: self.immutable,
+            bind_host: false,
+            allowed_commands: self.allowed_commands.clone(),
+            expires_at: self.expires_at,
+            certificate: self.certificate.clone(),
+            fields: self.fields.clone(),
+            provenance: self.provenance.clone().unwrap_or_default(),
+        }
+    }
+
+    pub fn new(secret: Secret, tags: Vec<String>) -> BackupEntry {
+        BackupEntry {
+            value: STANDARD.encode(&secret.plaintext),
+            id: secret.id,
+            name: secret.name,
+            kind: secret.kind,
+            note: secret.note,
+            owner: secret.owner,
+            contact: secret.contact,
+            immutable: secret.immutable,
+            allowed_commands: secret.allowed_commands,
+            expires_at: secret.expires_at,
+            certificate: secret.certificate,
+            fields: secret.fields,
+            provenance: secret.provenance,
+            tags,
+            created_at: secret.created_at,
+            updated_at: secret.updated_at,
+        }
+    }
+}
+
+/// Secrets a salvage could not put into its backup, written next to it as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SalvageManifest {
+    pub created_at: DateTime<Utc>,
+    /// Entries in the salvaged backup
+    pub exported: usize,
+    pub unrecoverable: Vec<LostSecret>,
+}
+
+/// What is still known about a secret whose value could not be salvaged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LostSecret {
+    pub id: Uuid,
+    pub name: String,
+    pub key_version: i64,
+    pub updated_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// The backup file: a plain header around the encrypted list of [`BackupEntry`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub format: String,
+    pub format_version: u32,
+    /// When the newest entry last changed, rather than when the file was written
+    pub created_at: DateTime<Utc>,
+    pub protection: Protection,
+    /// Number of entries in the payload
+    pub secrets: usize,
+    /// `sha256:` and the hex digest of the decoded payload
+    pub checksum: String,
+    /// Base64 of nonce || ciphertext of the JSON entry list
+    pub payload: String,
+}
+
+impl BackupFile {
+    /// Encrypt `entries` under `key`, which must match `protection`. Equal entries
+    /// in the same order give an equal file.
+    pub fn create(
+        entries: &[BackupEntry],
+        protection: Protection,
+        key: &MasterKey,
+    ) -> Result<BackupFile> {
+        let mut json = serde_json::to_vec(entries)?;
+        let payload = SecretCrypto::new(key.clone()).encrypt_deterministic(PAYLOAD_AAD, &json);
+        json.zeroize();
+        let payload = payload?;
+        Ok(BackupFile {
+            format: BACKUP_FORMAT.to_string(),
+            format_version: BACKUP_FORMAT_VERSION,
+            created_at: entries
+                .iter()
+                .map(|e| e.updated_at)
+                .max()
+                .unwrap_or(DateTime::UNIX_EPOCH),
+            protection,
+            secrets: entries.len(),
+            checksum: checksum(&payload),
+            payload: STANDARD.encode(payload),
+        })
+    }
+
+    /// Parse a backup and check its format version and checksum; no key is needed.
+    pub fn parse(data: &[u8]) -> Result<BackupFile> {
+        if is_sealed(data) {
+            bail!("backup is sealed to the backup key; open it with `backup-key open` first");
+        }
+        let file: BackupFile = serde_json::from_slice(data).context("not a devinventory backup")?;
+        if file.format != BACKUP_FORMAT {
+            bail!("not a devinventory backup (format '{}')", file.format);
+        }
+        if file.format_version > BACKUP_FORMAT_VERSION {
+            bail!(
+                "backup format version {} is newer than this build understands ({})",
+                file.format_version,
+                BACKUP_FORMAT_VERSION
+            );
+        }
+        file.payload_bytes()?;
+        Ok(file)
+    }
+
+    fn payload_bytes(&self) -> Result<Vec<u8>> {
+        let payload = STANDARD
+            .decode(&self.payload)
+            .context("backup payload is not base64")?;
+        if checksum(&payload) != self.checksum {
+            bail!("backup checksum does not match; the file is damaged or incomplete");
+        }
+        Ok(payload)
+    }
+
+    /// Decrypt the entries with the key matching [`BackupFile::protection`].
+    pub fn entries(&self, key: &MasterKey) -> Result<Vec<BackupEntry>> {
+        let mut json = SecretCrypto::new(key.clone())
+            .decrypt(PAYLOAD_AAD, &self.payload_bytes()?)
+            .map_err(|_| match self.protection {
+                Protection::MasterKey => {
+                    anyhow!("backup cannot be decrypted with this vault's master key")
+                }
+                Protection::Passphrase { .. } => anyhow!("wrong backup passphrase"),
+            })?;
+        let entries = serde_json::from_slice(&json).context("backup payload is malformed");
+        json.zeroize();
+        entries
+    }
+}
+
+fn checksum(payload: &[u8]) -> String {
+    format!("sha256:{}", hex(&Sha256::digest(payload)))
+}
+
+/// Leading bytes of every sealed file, so a wrong input fails before decryption.
+const MAGIC: &[u8] = b"DEVINVENTORY-SEALED-1\n";
+const AAD: &str = "devinventory backup";
+
+/// A fresh X25519 keypair for sealing backups.
+pub fn generate_keypair() -> DeviceKeypair {
+    crypto::generate_device_keypair()
+}
+
+pub fn encode_key(key: &[u8; 32]) -> String {
+    STANDARD.encode(key)
+}
+
+/// Parse a base64 public key as written to `[backup] public_key`.
+pub fn parse_public_key(text: &str) -> Result<[u8; 32]> {
+    let bytes = STANDARD
+        .decode(text.trim())
+        .context("backup public key is not valid base64")?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("backup public key must be 32 bytes"))
+}
+
+/// Private key file contents: the key in base64 after a comment naming its public half.
+pub fn secret_key_file(keypair: &DeviceKeypair) -> String {
+    format!(
+        "# devinventory backup secret key; public key {}\n{}\n",
+        encode_key(&keypair.public),
+        encode_key(&keypair.secret)
+    )
+}
+
+/// Read a private key written by [`secret_key_file`]; `#` lines are skipped.
+pub fn read_secret_key(path: &Path) -> Result<[u8; 32]> {
+    let mut text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading backup key {}", path.to_string_lossy()))?;
+    let decoded = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| STANDARD.decode(line));
+    text.zeroize();
+    let mut bytes = match decoded {
+        Some(Ok(bytes)) => bytes,
+        _ => bail!("{} holds no base64 backup key", path.to_string_lossy()),
+    };
+    let key: Result<[u8; 32]> = bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow!("backup secret key must be 32 bytes"));
+    bytes.zeroize();
+    key
+}
+
+/// Encrypt `plaintext` so only the holder of the backup secret key can read it.
+pub fn seal(public: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut out = MAGIC.to_vec();
+    out.extend(crypto::seal_for_recipient(public, AAD, plaintext)?);
+    Ok(out)
+}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Decrypt a [`seal`]ed file with the backup secret key.
+pub fn open(secret: &[u8; 32], data: &[u8]) -> Result<Vec<u8>> {
+    let Some(blob) = data.strip_prefix(MAGIC) else {
+        bail!("not a sealed devinventory file");
+    };
+    crypto::open_with_secret(secret, AAD, blob)
+        .map_err(|_| anyhow!("sealed file cannot be opened with this backup key"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backups_check_version_checksum_and_key() {
+        let entry = BackupEntry {
+            id: Uuid::new_v4(),
+            name: "db/pass".into(),
+            kind: None,
+            note: None,
+            owner: None,
+            contact: None,
+            immutable: false,
+            allowed_commands: Vec::new(),
+            expires_at: None,
+            certificate: None,
+            fields: BTreeMap::new(),
+            provenance: None,
+            tags: vec!["prod".into()],
+            value: STANDARD.encode(b"pw"),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let protection = Protection::Passphrase {
+            salt: STANDARD.encode([1u8; 16]),
+            rounds: 1000,
+        };
+        let key = protection.passphrase_key("correct horse").unwrap().unwrap();
+        let file =
+            BackupFile::create(std::slice::from_ref(&entry), protection.clone(), &key).unwrap();
+        let data = serde_json::to_vec(&file).unwrap();
+        let again = BackupFile::create(&[entry], protection.clone(), &key).unwrap();
+        assert_eq!(serde_json::to_vec(&again).unwrap(), data);
+
+        let parsed = BackupFile::parse(&data).unwrap();
+        assert_eq!(parsed.secrets, 1);
+        assert_eq!(parsed.entries(&key).unwrap()[0].tags, ["prod"]);
+        let wrong = protection.passphrase_key("wrong").unwrap().unwrap();
+        assert_eq!(
+            parsed.entries(&wrong).unwrap_err().to_string(),
+            "wrong backup passphrase"
+        );
+
+        let mut damaged = file.clone();
+        damaged.payload = STANDARD.encode(b"truncated");
+        assert!(BackupFile::parse(&serde_json::to_vec(&damaged).unwrap()).is_err());
+        let mut newer = file;
+        newer.format_version = BACKUP_FORMAT_VERSION + 1;
+        assert!(BackupFile::parse(&serde_json::to_vec(&newer).unwrap()).is_err());
+    }
+
+    #[test]
+    fn sealed_files_open_only_with_the_backup_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = generate_keypair();
+        let key_path = dir.path().join("backup.key");
+        std::fs::write(&key_path, secret_key_file(&keypair)).unwrap();
+
+        let public = parse_public_key(&encode_key(&keypair.public)).unwrap();
+        let sealed = seal(&public, b"API_KEY='k1'\n").unwrap();
+        assert!(is_sealed(&sealed));
+        let secret = read_secret_key(&key_path).unwrap();
+        assert_eq!(open(&secret, &sealed).unwrap(), b"API_KEY='k1'\n");
+
+        assert!(open(&generate_keypair().secret, &sealed).is_err());
+        assert!(open(&secret, b"API_KEY='k1'\n").is_err());
+        assert!(parse_public_key("c2hvcnQ=").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Vault templates for `init --template`: the secrets a new project needs, each
+//! generated with the password generator. Built-in ones ship with the binary; a
+//! `<name>.toml` in the templates directory adds one or replaces a built-in.
+
+use crate::generator::{self, Charset};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Templates available without any files.
+const BUILTIN: &[(&str, &str)] = &[
+    (
+        "webapp",
+        r#"
+description = "Web application: database, sessions and API access"
+
+[[secret]]
+name = "db/password"
+kind = "password"
+note = "application database user"
+
+[[secret]]
+name = "session/secret"
+kind = "key"
+note = "signs session cookies"
+length = 64
+charset = "hex"
+
+[[secret]]
+name = "api/key"
+kind = "token"
+length = 40
+
+[[secret]]
+name = "admin/password"
+kind = "password"
+words = 6
+"#,
+    ),
+    (
+        "database",
+        r#"
+description = "Database server: superuser, application and replication accounts"
+
+[[secret]]
+name = "db/admin-password"
+kind = "password"
+
+[[secret]]
+name = "db/app-password"
+kind = "password"
+
+[[secret]]
+name = "db/replication-password"
+kind = "password"
+"#,
+    ),
+];
+
+/// A named set of secrets to generate.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Blueprint {
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(rename = "secret", default)]
+    pub secrets: Vec<BlueprintSecret>,
+}
+
+/// One `[[secret]]`: a name, its metadata and how to generate the value. A password
+/// of `length` characters (default 32, alnum) unless `words` asks for a passphrase.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlueprintSecret {
+    pub name: String,
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub length: Option<usize>,
+    #[serde(default)]
+    pub charset: Option<Charset>,
+    #[serde(default)]
+    pub words: Option<usize>,
+    #[serde(default)]
+    pub separator: Option<String>,
+}
+
+impl BlueprintSecret {
+    /// A fresh value and its entropy in bits.
+    pub fn generate(&self) -> Result<(String, f64)> {
+        match self.words {
+            Some(words) => Ok((
+                generator::passphrase(words, self.separator.as_deref().unwrap_or("-"))?,
+                generator::passphrase_bits(words),
+            )),
+            None => {
+                let length = self.length.unwrap_or(32);
+                let charset = self.charset.unwrap_or(Charset::Alnum);
+                Ok((generator::password(length, charset)?, charset.bits(length)))
+            }
+        }
+    }
+}
+
+/// Parse a template file, checking that every secret can be generated.
+pub fn parse(text: &str) -> Result<Blueprint> {
+    let blueprint: Blueprint = toml::from_str(text)?;
+    if blueprint.secrets.is_empty() {
+        bail!("the template has no [[secret]] entries");
+    }
+    for secret in &blueprint.secrets {
+        if secret.words.is_some() && (secret.length.is_some() || secret.charset.is_some()) {
+            bail!(
+                "{}: words cannot be combined with length or charset",
+                secret.name
+            );
+        }
+        if secret.separator.is_some() && secret.words.is_none() {
+            bail!("{}: separator needs words", secret.name);
+        }
+        if secret.length == Some(0) || secret.words == Some(0) {
+            bail!("{}: length and words must be at least 1", secret.name);
+        }
+    }
+    Ok(blueprint)
+}
+
+/// The template called `name`: `<dir>/<name>.toml` when it exists, else a built-in.
+pub fn load(name: &str, dir: &Path) -> Result<Blueprint> {
+    let path = dir.join(format!("{name}.toml"));
+    if path.is_file() {
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.to_string_lossy()))?;
+        return parse(&text).with_context(|| format!("template {}", path.to_string_lossy()));
+    }
+    match BUILTIN.iter().find(|(builtin, _)| *builtin == name) {
+        Some((_, text)) => parse(text),
+        None => bail!(
+            "unknown template '{name}'; available: {}",
+            names(dir).join(", ")
+        ),
+    }
+}
+
+/// Every template name, built-in and from `dir`, sorted.
+pub fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN.iter().map(|(name, _)| name.to_string()).collect();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        names.extend(entries.flatten().filter_map(|entry| {
+            let path = entry.path();
+            (path.extension()? == "toml").then(|| path.file_stem()?.to_str().map(String::from))?
+        }));
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_load_from_files_before_builtins() {
+        for (name, text) in BUILTIN {
+            parse(text).unwrap_or_else(|e| panic!("built-in {name}: {e:#}"));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let webapp = load("webapp", dir.path()).unwrap();
+        let (session, bits) = webapp.secrets[1].generate().unwrap();
+        assert_eq!(session.len(), 64);
+        assert_eq!(bits, 256.0);
+        assert_eq!(
+            webapp.secrets[3].generate().unwrap().0.split('-').count(),
+            6
+        );
+
+        std::fs::write(
+            dir.path().join("webapp.toml"),
+            "[[secret]]\nname = \"token\"\ncharset = \"digits\"\nlength = 6\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("bad.toml"),
+            "[[secret]]\nname = \"x\"\nwords = 3\nlength = 4\n",
+        )
+        .unwrap();
+        let custom = load("webapp", dir.path()).unwrap();
+        assert_eq!(custom.secrets.len(), 1);
+        assert!(
+            custom.secrets[0]
+                .generate()
+                .unwrap()
+                .0
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+        );
+        assert!(load("bad", dir.path()).is_err());
+        let err = load("nope", dir.path()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown template 'nope'; available: bad, database, webapp"
+        );
+    }
+}
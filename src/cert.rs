@@ -0,0 +1,85 @@
+//! Metadata extraction for `kind=certificate` secrets.
+
+use crate::domain::CertificateInfo;
+use anyhow::{Context, Result, anyhow};
+use chrono::DateTime;
+use std::net::IpAddr;
+use x509_parser::extensions::GeneralName;
+use x509_parser::pem::Pem;
+
+/// Kind value that triggers certificate parsing on add.
+pub const CERTIFICATE_KIND: &str = "certificate";
+
+/// Parse the first `CERTIFICATE` block of a PEM value (bundles with keys or chains are fine).
+pub fn parse_certificate(value: &[u8]) -> Result<CertificateInfo> {
+    let pem = Pem::iter_from_buffer(value)
+        .filter_map(|p| p.ok())
+        .find(|p| p.label == "CERTIFICATE")
+        .ok_or_else(|| anyhow!("no PEM CERTIFICATE block found"))?;
+    let cert = pem.parse_x509().context("parsing X.509 certificate")?;
+    let not_after = DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+        .ok_or_else(|| anyhow!("certificate notAfter out of range"))?;
+    let sans = match cert
+        .subject_alternative_name()
+        .context("parsing subjectAltName")?
+    {
+        Some(ext) => ext
+            .value
+            .general_names
+            .iter()
+            .filter_map(general_name)
+            .collect(),
+        None => Vec::new(),
+    };
+    Ok(CertificateInfo {
+        subject: cert.subject().to_string(),
+        sans,
+        not_after,
+    })
+}
+
+fn general_name(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::DNSName(dns) => Some(format!("DNS:{dns}")),
+        GeneralName::RFC822Name(email) => Some(format!("email:{email}")),
+        GeneralName::URI(uri) => Some(format!("URI:{uri}")),
+        GeneralName::IPAddress(bytes) => {
+            let ip = match bytes.len() {
+                4 => IpAddr::from(<[u8; 4]>::try_from(*bytes).ok()?),
+                16 => IpAddr::from(<[u8; 16]>::try_from(*bytes).ok()?),
+                _ => return None,
+            };
+            Some(format!("IP:{ip}"))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBVzCB/qADAgECAgEBMAoGCCqGSM49BAMCMBoxGDAWBgNVBAMMD2FwaS5leGFt
+cGxlLmNvbTAeFw0yNDAxMDEwMDAwMDBaFw0zMDAxMDEwMDAwMDBaMBoxGDAWBgNV
+BAMMD2FwaS5leGFtcGxlLmNvbTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABPph
+5+BlT2oF01cO9wXTH51f1LALCRv4vNiWguf0HIs9/9Uk5lF1FCscffBIjz4yVEu3
+HiqjKvF2Jw6ngO6dHxejNTAzMDEGA1UdEQQqMCiCD2FwaS5leGFtcGxlLmNvbYIP
+d3d3LmV4YW1wbGUuY29thwQKAAABMAoGCCqGSM49BAMCA0gAMEUCIQDG7eCp5pTj
+zwan4W18s0SZ5ajtaqTTx2aD+KtJZoLxfAIgT9vkpD3FYSCEdj4NgfCeZS5fmj8V
+GxaAH5Ssv0GuDm0=
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn parses_subject_sans_and_expiry() {
+        let info = parse_certificate(PEM.as_bytes()).unwrap();
+        assert_eq!(info.subject, "CN=api.example.com");
+        assert_eq!(
+            info.sans,
+            vec!["DNS:api.example.com", "DNS:www.example.com", "IP:10.0.0.1"]
+        );
+        assert_eq!(info.not_after.to_rfc3339(), "2030-01-01T00:00:00+00:00");
+        assert!(parse_certificate(b"not a certificate").is_err());
+    }
+}
@@ -1,9 +1,260 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-use anyhow::{self, Context, Result};
+use anyhow::{self, Context, Result, bail};
+use chrono::{DateTime, Utc};
+use log::LevelFilter;
 use serde::{Deserialize, Serialize};
 
-use crate::keymgr::MasterKeySource;
+use crate::{
+    keymgr::{MasterKeySource, pkcs11},
+    perms,
+    ui::common::TimeStyle,
+    yubikey,
+};
+
+/// Version written into config snapshots; bump when the layout changes.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// Environment variables read by [`Config::build`], between flags and the config file.
+pub const DB_PATH_VAR: &str = "DEVINVENTORY_DB_PATH";
+pub const KEYRING_SERVICE_VAR: &str = "DEVINVENTORY_KEYRING_SERVICE";
+pub const KEYRING_ACCOUNT_VAR: &str = "DEVINVENTORY_KEYRING_ACCOUNT";
+pub const VAULT_VAR: &str = "DEVINVENTORY_VAULT";
+pub const DMK_FILE_VAR: &str = "DEVINVENTORY_DMK_FILE";
+
+/// One config.toml setting, as documented by `explain config`.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigKey {
+    pub section: &'static str,
+    pub key: &'static str,
+    pub ty: &'static str,
+    /// Effective value when the key is absent, as TOML or a description
+    pub default: &'static str,
+    pub doc: &'static str,
+}
+
+/// Every key [`ConfigFile`] accepts; a test keeps this in step with the structs.
+/// `vaults.NAME` stands for any `[vaults.<name>]` profile.
+pub const CONFIG_KEYS: &[ConfigKey] = &[
+    ConfigKey {
+        section: "database",
+        key: "path",
+        ty: "path",
+        default: "<config dir>/devinventory/devinventory.db",
+        doc: "Vault file; --db-path and DEVINVENTORY_DB_PATH win over it",
+    },
+    ConfigKey {
+        section: "keyring",
+        key: "service",
+        ty: "string",
+        default: "\"devinventory\"",
+        doc: "Keyring service the master key is stored under",
+    },
+    ConfigKey {
+        section: "keyring",
+        key: "account",
+        ty: "string",
+        default: "\"dmk\"",
+        doc: "Keyring account the master key is stored under",
+    },
+    ConfigKey {
+        section: "logging",
+        key: "level",
+        ty: "string",
+        default: "\"info\"",
+        doc: "Level: trace, debug, info, warn, error; -v raises it, RUST_LOG overrides it",
+    },
+    ConfigKey {
+        section: "approval",
+        key: "protected_prefixes",
+        ty: "list of strings",
+        default: "[]",
+        doc: "Deletes/exports of secrets under these prefixes need a second device's approval",
+    },
+    ConfigKey {
+        section: "crypto",
+        key: "reencrypt_on_read",
+        ty: "bool",
+        default: "false",
+        doc: "Rewrite records still under a retired key version whenever they are read",
+    },
+    ConfigKey {
+        section: "reveal",
+        key: "max_per_hour",
+        ty: "integer",
+        default: "unlimited",
+        doc: "Plaintext reveals of one secret per hour before re-confirmation is required",
+    },
+    ConfigKey {
+        section: "limits",
+        key: "max_value_kib",
+        ty: "integer",
+        default: "8192",
+        doc: "Values larger than this many KiB are refused when stored (add, edit, generate, import); 0 turns the limit off",
+    },
+    ConfigKey {
+        section: "limits",
+        key: "warn_value_kib",
+        ty: "integer",
+        default: "256",
+        doc: "Values larger than this many KiB are stored with a warning; 0 turns the warning off",
+    },
+    ConfigKey {
+        section: "display",
+        key: "time",
+        ty: "string",
+        default: "\"utc\"",
+        doc: "How list and search tables show times: utc (RFC 3339), local or relative; --time wins over it",
+    },
+    ConfigKey {
+        section: "clipboard",
+        key: "clear_after_secs",
+        ty: "integer",
+        default: "20",
+        doc: "Seconds a copied secret stays on the clipboard",
+    },
+    ConfigKey {
+        section: "audit",
+        key: "ship_to",
+        ty: "string",
+        default: "not shipped",
+        doc: "Collector new audit entries are shipped to after every command: file:PATH, syslog or an http(s):// URL",
+    },
+    ConfigKey {
+        section: "agent",
+        key: "integrity_check_mins",
+        ty: "integer",
+        default: "60",
+        doc: "Minutes between the agent's background integrity checks; 0 turns them off",
+    },
+    ConfigKey {
+        section: "agent",
+        key: "integrity_sample",
+        ty: "integer",
+        default: "16",
+        doc: "Records decrypted and verified per background integrity check",
+    },
+    ConfigKey {
+        section: "agent",
+        key: "names_cache_secs",
+        ty: "integer",
+        default: "2",
+        doc: "Seconds the agent reuses its list of secret names for `names`; 0 reads the vault every time",
+    },
+    ConfigKey {
+        section: "backup",
+        key: "public_key",
+        ty: "string",
+        default: "not sealed",
+        doc: "Backup public key (backup-key generate) exports and backups are sealed to; its private half stays offline",
+    },
+    ConfigKey {
+        section: "security",
+        key: "askpass",
+        ty: "path",
+        default: "none",
+        doc: "Program asked for passphrases and confirmations when there is no terminal (prompt as argument, answer on stdout); DEVINVENTORY_ASKPASS wins over it",
+    },
+    ConfigKey {
+        section: "security",
+        key: "pinentry",
+        ty: "path",
+        default: "none",
+        doc: "pinentry program used the same way when no askpass program is set, e.g. pinentry-mac",
+    },
+    ConfigKey {
+        section: "security",
+        key: "allow_value_flag",
+        ty: "bool",
+        default: "true",
+        doc: "Accept `add --value`, which leaves the value in shell history and the process list; false refuses it",
+    },
+    ConfigKey {
+        section: "security",
+        key: "require_key_for_list",
+        ty: "bool",
+        default: "false",
+        doc: "Make `list` and `search` fetch the master key first, as other commands do; by default they read only metadata",
+    },
+    ConfigKey {
+        section: "yubikey",
+        key: "slot",
+        ty: "integer",
+        default: "2",
+        doc: "OTP slot (1 or 2) holding the HMAC-SHA1 challenge-response secret that `init --yubikey` binds the vault to",
+    },
+    ConfigKey {
+        section: "yubikey",
+        key: "program",
+        ty: "path",
+        default: "ykchalresp",
+        doc: "Program that asks the YubiKey: ykchalresp (yubikey-personalization) or ykman",
+    },
+    ConfigKey {
+        section: "pkcs11",
+        key: "module",
+        ty: "path",
+        default: "none",
+        doc: "The token vendor's PKCS#11 module, e.g. /usr/lib/softhsm/libsofthsm2.so; required for `init --pkcs11`",
+    },
+    ConfigKey {
+        section: "pkcs11",
+        key: "slot",
+        ty: "integer",
+        default: "first slot with a token",
+        doc: "PKCS#11 slot id of the token",
+    },
+    ConfigKey {
+        section: "pkcs11",
+        key: "key_label",
+        ty: "string",
+        default: "devinventory",
+        doc: "Label of the token key that wraps the vault's master key",
+    },
+    ConfigKey {
+        section: "pkcs11",
+        key: "key_id",
+        ty: "string",
+        default: "none",
+        doc: "Hex object id of the wrapping key, used instead of key_label",
+    },
+    ConfigKey {
+        section: "pkcs11",
+        key: "mechanism",
+        ty: "string",
+        default: "RSA-PKCS-OAEP",
+        doc: "pkcs11-tool mechanism that encrypts and decrypts with the wrapping key",
+    },
+    ConfigKey {
+        section: "pkcs11",
+        key: "program",
+        ty: "path",
+        default: "pkcs11-tool",
+        doc: "OpenSC's pkcs11-tool, which talks to the module; the PIN comes from DEVINVENTORY_PKCS11_PIN or a prompt",
+    },
+    ConfigKey {
+        section: "vaults.NAME",
+        key: "path",
+        ty: "path",
+        default: "required",
+        doc: "Vault file used with --vault NAME (or DEVINVENTORY_VAULT=NAME)",
+    },
+    ConfigKey {
+        section: "vaults.NAME",
+        key: "keyring_service",
+        ty: "string",
+        default: "[keyring] service",
+        doc: "Keyring service this vault's master key is stored under",
+    },
+    ConfigKey {
+        section: "vaults.NAME",
+        key: "keyring_account",
+        ty: "string",
+        default: "[keyring] account",
+        doc: "Keyring account this vault's master key is stored under",
+    },
+];
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct ConfigFile {
@@ -13,6 +264,33 @@ pub struct ConfigFile {
     pub keyring: KeyringConfig,
     #[serde(default)]
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub approval: ApprovalConfig,
+    #[serde(default)]
+    pub crypto: CryptoConfig,
+    #[serde(default)]
+    pub reveal: RevealConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub display: DisplayConfig,
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub agent: AgentConfig,
+    #[serde(default)]
+    pub backup: BackupConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    #[serde(default)]
+    pub yubikey: YubikeyConfig,
+    #[serde(default)]
+    pub pkcs11: Pkcs11Config,
+    /// Named vaults selected with `--vault`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vaults: BTreeMap<String, VaultProfile>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -32,48 +310,388 @@ pub struct LoggingConfig {
     pub level: Option<String>,
 }
 
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ApprovalConfig {
+    /// Deletes/exports of secrets under these prefixes need a second device's approval
+    #[serde(default)]
+    pub protected_prefixes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CryptoConfig {
+    /// Rewrite records still under a retired key version whenever they are read
+    #[serde(default)]
+    pub reencrypt_on_read: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RevealConfig {
+    /// Plaintext reveals of one secret per hour before re-confirmation is required
+    pub max_per_hour: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LimitsConfig {
+    /// Largest value stored, in KiB (default 8192, 0 = no limit)
+    pub max_value_kib: Option<u64>,
+    /// Values above this many KiB are stored with a warning (default 256, 0 = never)
+    pub warn_value_kib: Option<u64>,
+}
+
+impl LimitsConfig {
+    pub fn max_value_bytes(&self) -> Option<usize> {
+        kib(self.max_value_kib.unwrap_or(8192))
+    }
+
+    pub fn warn_value_bytes(&self) -> Option<usize> {
+        kib(self.warn_value_kib.unwrap_or(256))
+    }
+}
+
+fn kib(kib: u64) -> Option<usize> {
+    (kib > 0).then(|| usize::try_from(kib.saturating_mul(1024)).unwrap_or(usize::MAX))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DisplayConfig {
+    /// How tables show times: utc, local or relative
+    pub time: Option<TimeStyle>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ClipboardConfig {
+    /// Seconds a copied secret stays on the clipboard (default 20)
+    pub clear_after_secs: Option<u64>,
+}
+
+impl ClipboardConfig {
+    pub fn clear_after(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.clear_after_secs.unwrap_or(20))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AuditConfig {
+    /// Collector new audit entries are shipped to after every command:
+    /// `file:PATH`, `syslog` or an `http(s)://` URL
+    pub ship_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AgentConfig {
+    /// Minutes between background integrity checks (default 60, 0 = off)
+    pub integrity_check_mins: Option<u64>,
+    /// Records verified per check (default 16)
+    pub integrity_sample: Option<usize>,
+    /// Seconds the name list is cached for `names` (default 2, 0 = off)
+    pub names_cache_secs: Option<u64>,
+}
+
+impl AgentConfig {
+    pub fn integrity_check_every(&self) -> Option<std::time::Duration> {
+        match self.integrity_check_mins.unwrap_or(60) {
+            0 => None,
+            mins => Some(std::time::Duration::from_secs(mins * 60)),
+        }
+    }
+
+    pub fn integrity_sample(&self) -> usize {
+        self.integrity_sample.unwrap_or(16)
+    }
+
+    pub fn names_cache_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.names_cache_secs.unwrap_or(2))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BackupConfig {
+    /// Base64 X25519 public key exports and backups are sealed to
+    pub public_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SecurityConfig {
+    /// Program asked for passphrases and confirmations when there is no terminal
+    pub askpass: Option<String>,
+    /// pinentry program used when no askpass program is set
+    pub pinentry: Option<String>,
+    /// Accept `add --value` (default true)
+    pub allow_value_flag: Option<bool>,
+    /// Refuse `list` and `search` without the master key (default false)
+    pub require_key_for_list: Option<bool>,
+}
+
+impl SecurityConfig {
+    pub fn value_flag_allowed(&self) -> bool {
+        self.allow_value_flag.unwrap_or(true)
+    }
+
+    pub fn key_required_for_list(&self) -> bool {
+        self.require_key_for_list.unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct YubikeyConfig {
+    /// OTP slot for new YubiKey vaults (default 2)
+    pub slot: Option<u8>,
+    /// ykchalresp or ykman (default ykchalresp)
+    pub program: Option<String>,
+}
+
+impl YubikeyConfig {
+    pub fn device(&self) -> yubikey::Device {
+        let default = yubikey::Device::default();
+        yubikey::Device {
+            slot: self.slot.unwrap_or(default.slot),
+            program: self.program.clone().unwrap_or(default.program),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Pkcs11Config {
+    /// Vendor PKCS#11 module
+    pub module: Option<String>,
+    pub slot: Option<u64>,
+    /// Label of the wrapping key (default devinventory)
+    pub key_label: Option<String>,
+    /// Hex id of the wrapping key, instead of the label
+    pub key_id: Option<String>,
+    /// Wrapping mechanism (default RSA-PKCS-OAEP)
+    pub mechanism: Option<String>,
+    /// pkcs11-tool by name or path
+    pub program: Option<String>,
+}
+
+impl Pkcs11Config {
+    pub fn token(&self) -> pkcs11::Token {
+        let mut token = pkcs11::Token::default();
+        token.module = self.module.clone();
+        token.slot = self.slot;
+        if let Some(label) = &self.key_label {
+            token.key_label = label.clone();
+        }
+        token.key_id = self.key_id.clone();
+        if let Some(mechanism) = &self.mechanism {
+            token.mechanism = mechanism.clone();
+        }
+        if let Some(program) = &self.program {
+            token.program = program.clone();
+        }
+        token
+    }
+}
+
+impl ConfigFile {
+    /// The `[vaults.<name>]` profile, or an error listing the configured ones.
+    pub fn vault_profile(&self, name: &str) -> Result<&VaultProfile> {
+        self.vaults.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.vaults.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                anyhow::anyhow!(
+                    "unknown vault '{name}'; config.toml has no [vaults.{name}] section"
+                )
+            } else {
+                anyhow::anyhow!("unknown vault '{name}'; configured: {}", known.join(", "))
+            }
+        })
+    }
+}
+
+/// One `[vaults.<name>]` section: a vault file and where its master key lives.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct VaultProfile {
+    pub path: Option<String>,
+    pub keyring_service: Option<String>,
+    pub keyring_account: Option<String>,
+}
+
+/// Values supplied on the command line for this invocation; they win over env and file.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub db_path: Option<PathBuf>,
+    pub keyring_service: Option<String>,
+    pub keyring_account: Option<String>,
+    pub ephemeral: bool,
+    /// `[vaults.<name>]` profile to use
+    pub vault: Option<String>,
+}
+
 /// The runtime config (final config)
 pub struct Config {
+    /// Profile the vault was chosen by, if any
+    pub vault: Option<String>,
     pub db_path: PathBuf,
+    /// Run against an in-memory database; `db_path` is never opened
+    pub ephemeral: bool,
     pub master_key_source: MasterKeySource,
     pub keyring_service: String,
     pub keyring_account: String,
+    pub log_level: LevelFilter,
+    /// The config file as loaded (defaults when absent)
+    pub file: ConfigFile,
+}
+
+/// Portable bundle of everything needed to set up a vault elsewhere except the key itself.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigSnapshot {
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub config: ConfigFile,
+    pub vault: VaultSummary,
+    pub key_source: KeySourceDescription,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultSummary {
+    pub path: String,
+    pub secret_count: usize,
+    pub last_updated: Option<DateTime<Utc>>,
+}
+
+/// Where the master key is expected to come from; never contains key material.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeySourceDescription {
+    /// `keyring`, `key-file` (`--dmk-file`) or `manual` (inline `--dmk` / `--no-keyring`)
+    pub mode: String,
+    pub keyring_service: String,
+    pub keyring_account: String,
+}
+
+impl ConfigSnapshot {
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).context("Failed to read snapshot")?;
+        let snapshot: Self = serde_json::from_str(&content).context("Failed to parse snapshot")?;
+        if snapshot.format_version != SNAPSHOT_FORMAT_VERSION {
+            bail!(
+                "unsupported snapshot format version {} (expected {})",
+                snapshot.format_version,
+                SNAPSHOT_FORMAT_VERSION
+            );
+        }
+        Ok(snapshot)
+    }
+
+    /// Write the bundled config file to `config_path`, refusing to replace one unless `force`.
+    pub fn install_config(&self, config_path: &Path, force: bool) -> Result<()> {
+        if config_path.exists() && !force {
+            bail!(
+                "{} already exists; pass --force to replace it",
+                config_path.to_string_lossy()
+            );
+        }
+        if let Some(dir) = config_path.parent() {
+            perms::create_private_dir_all(dir)?;
+        }
+        let content = toml::to_string_pretty(&self.config)?;
+        std::fs::write(config_path, content).context("Failed to write config file")?;
+        perms::make_owner_only(config_path)
+    }
 }
 
 impl Config {
     /// Priority: CLI arg > env > config file > default value
-    pub fn build(cli_db_path: Option<PathBuf>, master_key_source: MasterKeySource) -> Result<Self> {
-        let config_file = Self::load_config_file()?;
-
-        let db_path = cli_db_path // CLI arguments
-            .or_else(|| {
-                std::env::var("DEVINVENTORY_DB_PATH") // environment variable
-                    .ok()
-                    .map(PathBuf::from)
-            })
+    pub fn build(overrides: ConfigOverrides, master_key_source: MasterKeySource) -> Result<Self> {
+        Self::resolve(
+            overrides,
+            master_key_source,
+            Self::load_config_file()?,
+            |var| std::env::var(var).ok(),
+        )
+    }
+
+    /// [`Config::build`] over a loaded config file, reading variables through `env`.
+    fn resolve(
+        overrides: ConfigOverrides,
+        master_key_source: MasterKeySource,
+        config_file: ConfigFile,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        // a chosen profile sits between the command line and everything else
+        let vault = overrides
+            .vault
+            .or_else(|| env(VAULT_VAR).filter(|v| !v.is_empty()));
+        let profile = match &vault {
+            Some(name) => config_file.vault_profile(name)?.clone(),
+            None => VaultProfile::default(),
+        };
+
+        let db_path = overrides
+            .db_path // CLI arguments
+            .or_else(|| profile.path.as_ref().map(PathBuf::from))
+            .or_else(|| env(DB_PATH_VAR).map(PathBuf::from)) // environment variable
             .or_else(
                 || config_file.database.path.as_ref().map(PathBuf::from), // config file
-            )
-            .unwrap_or_else(|| Self::default_db_path().unwrap());
+            );
+        let db_path = match db_path {
+            Some(path) => path,
+            None => Self::default_db_path()?,
+        };
 
-        let keyring_service = std::env::var("DEVINVENTORY_KEYRING_SERVICE")
-            .ok()
+        let keyring_service = overrides
+            .keyring_service
+            .or_else(|| profile.keyring_service.clone())
+            .or_else(|| env(KEYRING_SERVICE_VAR))
             .or_else(|| config_file.keyring.service.clone())
             .unwrap_or_else(|| "devinventory".to_string());
 
-        let keyring_account = std::env::var("DEVINVENTORY_KEYRING_ACCOUNT")
-            .ok()
+        let keyring_account = overrides
+            .keyring_account
+            .or_else(|| profile.keyring_account.clone())
+            .or_else(|| env(KEYRING_ACCOUNT_VAR))
             .or_else(|| config_file.keyring.account.clone())
             .unwrap_or_else(|| "dmk".to_string());
 
+        let log_level = config_file
+            .logging
+            .level
+            .as_deref()
+            .map(str::parse::<LevelFilter>)
+            .transpose()
+            .context("Invalid logging.level in config file")?
+            .unwrap_or(LevelFilter::Info);
+
         Ok(Self {
+            vault,
             db_path,
+            ephemeral: overrides.ephemeral,
             master_key_source,
             keyring_service,
             keyring_account,
+            log_level,
+            file: config_file,
         })
     }
 
+    /// Describe how this vault's key is sourced, for config snapshots.
+    pub fn key_source_description(&self) -> KeySourceDescription {
+        let source = &self.master_key_source;
+        let mode = if source.base64_inline.is_some() {
+            "manual"
+        } else if source.key_file.is_some() {
+            "key-file"
+        } else if !source.allow_keyring {
+            "manual"
+        } else {
+            "keyring"
+        };
+        KeySourceDescription {
+            mode: mode.to_string(),
+            keyring_service: self.keyring_service.clone(),
+            keyring_account: self.keyring_account.clone(),
+        }
+    }
+
+    /// Configured log level raised by one step per `-v`, capped at trace.
+    pub fn log_level_with_verbosity(&self, verbose: u8) -> LevelFilter {
+        LevelFilter::iter()
+            .skip_while(|l| *l < self.log_level)
+            .nth(verbose as usize)
+            .unwrap_or(LevelFilter::Trace)
+    }
+
     fn load_config_file() -> Result<ConfigFile> {
         let config_path = Self::config_file_path()?;
 
@@ -84,7 +702,7 @@ impl Config {
         let content =
             std::fs::read_to_string(&config_path).context("Failed to read config file")?;
 
-        toml::from_str(&content).context("Failed to parse config file")
+        parse_config_file(&config_path, &content)
     }
 
     pub fn config_file_path() -> Result<PathBuf> {
@@ -93,10 +711,24 @@ impl Config {
         Ok(config_dir.join("devinventory").join("config.toml"))
     }
 
-    fn default_db_path() -> Result<PathBuf> {
+    /// Keyring entries written by devinventory, for `key gc`.
+    pub fn keyring_index_path() -> Result<PathBuf> {
         let config_dir = dirs::config_dir().context("Cannot determine user config directory")?;
 
-        Ok(config_dir.join("devinventory").join("secrets.db"))
+        Ok(config_dir.join("devinventory").join("keyring.json"))
+    }
+
+    /// User-defined vault templates for `init --template`, one `<name>.toml` each.
+    pub fn templates_dir() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().context("Cannot determine user config directory")?;
+
+        Ok(config_dir.join("devinventory").join("templates"))
+    }
+
+    pub fn default_db_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().context("Cannot determine user config directory")?;
+
+        Ok(config_dir.join("devinventory").join("devinventory.db"))
     }
 
     pub fn generate_example_config() -> String {
@@ -111,8 +743,425 @@ impl Config {
             logging: LoggingConfig {
                 level: Some("info".to_string()),
             },
+            approval: ApprovalConfig {
+                protected_prefixes: vec!["prod/".to_string()],
+            },
+            crypto: CryptoConfig {
+                reencrypt_on_read: true,
+            },
+            reveal: RevealConfig {
+                max_per_hour: Some(5),
+            },
+            limits: LimitsConfig {
+                max_value_kib: Some(8192),
+                warn_value_kib: Some(256),
+            },
+            display: DisplayConfig {
+                time: Some(TimeStyle::Relative),
+            },
+            clipboard: ClipboardConfig {
+                clear_after_secs: Some(20),
+            },
+            audit: AuditConfig {
+                ship_to: Some("syslog".to_string()),
+            },
+            agent: AgentConfig {
+                integrity_check_mins: Some(60),
+                integrity_sample: Some(16),
+                names_cache_secs: Some(2),
+            },
+            backup: BackupConfig {
+                public_key: Some("Bdx0gD1u5S8XjLx0tnbO3vv0jYbS0hJ9YQ2j4Y5kxVg=".to_string()),
+            },
+            security: SecurityConfig {
+                askpass: Some("/usr/lib/ssh/ssh-askpass".to_string()),
+                pinentry: Some("pinentry-gnome3".to_string()),
+                allow_value_flag: Some(false),
+                require_key_for_list: Some(false),
+            },
+            yubikey: YubikeyConfig {
+                slot: Some(2),
+                program: Some("ykman".to_string()),
+            },
+            pkcs11: Pkcs11Config {
+                module: Some("/usr/lib/softhsm/libsofthsm2.so".to_string()),
+                slot: Some(0),
+                key_label: Some("devinventory".to_string()),
+                key_id: Some("01".to_string()),
+                mechanism: Some("RSA-PKCS-OAEP".to_string()),
+                program: Some("pkcs11-tool".to_string()),
+            },
+            vaults: BTreeMap::from([(
+                "work".to_string(),
+                VaultProfile {
+                    path: Some("/home/me/work/secrets.db".to_string()),
+                    keyring_service: Some("devinventory".to_string()),
+                    keyring_account: Some("work".to_string()),
+                },
+            )]),
         };
 
         toml::to_string_pretty(&example).unwrap()
     }
 }
+
+/// Parse config.toml strictly: unknown sections and keys, wrong types and invalid
+/// values are errors naming the file and line, with a suggestion for likely typos.
+pub fn parse_config_file(path: &Path, content: &str) -> Result<ConfigFile> {
+    let path = path.to_string_lossy();
+    let located = |err: &toml::de::Error| match err.span() {
+        Some(span) => {
+            let line = content[..span.start].matches('\n').count() + 1;
+            format!("{path}:{line}: {}", err.message().trim_end())
+        }
+        None => format!("{path}: {}", err.message().trim_end()),
+    };
+    let table: toml::Table = toml::from_str(content).map_err(|e| anyhow::anyhow!(located(&e)))?;
+
+    let at = |section: &str, key: Option<&str>| match line_of(content, section, key) {
+        Some(line) => format!("{path}:{line}"),
+        None => path.to_string(),
+    };
+    let mut problems = Vec::new();
+    // `vaults.NAME` keys belong to every `[vaults.<name>]` table
+    let mut sections: Vec<&str> = CONFIG_KEYS
+        .iter()
+        .map(|k| k.section.split('.').next().unwrap_or(k.section))
+        .collect();
+    sections.dedup();
+    let mut tables = Vec::new();
+    for (section, value) in &table {
+        if !sections.contains(&section.as_str()) {
+            let hint = match closest(section, &sections) {
+                Some(known) => format!("; did you mean [{known}]?"),
+                None => format!("; known sections: {}", sections.join(", ")),
+            };
+            problems.push(format!(
+                "{}: unknown section [{section}]{hint}",
+                at(section, None)
+            ));
+            continue;
+        }
+        let Some(keys) = value.as_table() else {
+            problems.push(format!(
+                "{}: [{section}] must be a table",
+                at(section, None)
+            ));
+            continue;
+        };
+        if section != "vaults" {
+            tables.push((section.clone(), section.as_str(), keys));
+            continue;
+        }
+        for (name, profile) in keys {
+            let section = format!("vaults.{name}");
+            match profile.as_table() {
+                Some(profile) if profile.contains_key("path") => {
+                    tables.push((section, "vaults.NAME", profile));
+                }
+                Some(_) => {
+                    problems.push(format!("{}: [{section}] needs a path", at(&section, None)))
+                }
+                None => problems.push(format!(
+                    "{}: vaults.{name} must be a [vaults.{name}] table",
+                    at("vaults", Some(name))
+                )),
+            }
+        }
+    }
+    for (section, schema, keys) in tables {
+        let known: Vec<&str> = CONFIG_KEYS
+            .iter()
+            .filter(|k| k.section == schema)
+            .map(|k| k.key)
+            .collect();
+        for key in keys.keys().filter(|k| !known.contains(&k.as_str())) {
+            let hint = match (
+                closest(key, &known),
+                CONFIG_KEYS.iter().find(|k| k.key == key),
+            ) {
+                (Some(near), _) => format!("; did you mean {near}?"),
+                (None, Some(other)) => format!("; {key} belongs in [{}]", other.section),
+                (None, None) => format!("; [{section}] takes {}", known.join(", ")),
+            };
+            problems.push(format!(
+                "{}: unknown key {key} in [{section}]{hint}",
+                at(&section, Some(key))
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        bail!(problems.join("\n"));
+    }
+
+    let file: ConfigFile = toml::from_str(content).map_err(|e| anyhow::anyhow!(located(&e)))?;
+    if let Some(level) = &file.logging.level
+        && level.parse::<LevelFilter>().is_err()
+    {
+        problems.push(format!(
+            "{}: invalid level \"{level}\"; use off, error, warn, info, debug or trace",
+            at("logging", Some("level"))
+        ));
+    }
+    if let Some(target) = &file.audit.ship_to
+        && let Err(e) = crate::audit::Sink::parse(target)
+    {
+        problems.push(format!("{}: {e}", at("audit", Some("ship_to"))));
+    }
+    if let (Some(max), Some(warn)) = (
+        file.limits.max_value_bytes(),
+        file.limits.warn_value_bytes(),
+    ) && warn > max
+    {
+        problems.push(format!(
+            "{}: warn_value_kib is above max_value_kib, so the warning can never show",
+            at("limits", Some("warn_value_kib"))
+        ));
+    }
+    if let Some(key) = &file.backup.public_key
+        && let Err(e) = crate::backup::parse_public_key(key)
+    {
+        problems.push(format!("{}: {e}", at("backup", Some("public_key"))));
+    }
+    if let Some(slot) = file.yubikey.slot
+        && !(1..=2).contains(&slot)
+    {
+        problems.push(format!(
+            "{}: YubiKey slot must be 1 or 2",
+            at("yubikey", Some("slot"))
+        ));
+    }
+    if let Some(id) = &file.pkcs11.key_id
+        && (id.is_empty()
+            || !id.len().is_multiple_of(2)
+            || !id.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        problems.push(format!(
+            "{}: key_id must be hex, e.g. 01",
+            at("pkcs11", Some("key_id"))
+        ));
+    }
+    if !problems.is_empty() {
+        bail!(problems.join("\n"));
+    }
+    Ok(file)
+}
+
+/// Line of `key` under `[section]`, or of the section header when `key` is `None`.
+fn line_of(content: &str, section: &str, key: Option<&str>) -> Option<usize> {
+    let mut current = "";
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.split_once(']')) {
+            current = header.0.trim();
+            if key.is_none() && current == section {
+                return Some(number + 1);
+            }
+        } else if let Some(key) = key
+            && current == section
+            && line
+                .split_once('=')
+                .is_some_and(|(k, _)| k.trim().trim_matches('"') == key)
+        {
+            return Some(number + 1);
+        }
+    }
+    None
+}
+
+/// The candidate within a small edit distance of `word`, if any.
+fn closest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let limit = (word.len() / 3).max(2);
+    candidates
+        .iter()
+        .map(|c| (edit_distance(word, c), *c))
+        .filter(|(d, _)| *d <= limit)
+        .min()
+        .map(|(_, c)| c)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_stacks_on_configured_level() {
+        let config = Config {
+            vault: None,
+            db_path: PathBuf::from("test.db"),
+            ephemeral: false,
+            master_key_source: MasterKeySource {
+                base64_inline: None,
+                key_file: None,
+                allow_keyring: false,
+            },
+            keyring_service: "devinventory".into(),
+            keyring_account: "dmk".into(),
+            log_level: LevelFilter::Warn,
+            file: ConfigFile::default(),
+        };
+        assert_eq!(config.log_level_with_verbosity(0), LevelFilter::Warn);
+        assert_eq!(config.log_level_with_verbosity(1), LevelFilter::Info);
+        assert_eq!(config.log_level_with_verbosity(9), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn snapshot_installs_config_without_clobbering() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot_path = dir.path().join("snapshot.json");
+        let snapshot = ConfigSnapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            exported_at: Utc::now(),
+            config: toml::from_str("[database]\npath = \"/srv/vault.db\"\n").unwrap(),
+            vault: VaultSummary {
+                path: "/srv/vault.db".into(),
+                secret_count: 3,
+                last_updated: None,
+            },
+            key_source: KeySourceDescription {
+                mode: "keyring".into(),
+                keyring_service: "devinventory".into(),
+                keyring_account: "dmk".into(),
+            },
+        };
+        std::fs::write(&snapshot_path, serde_json::to_string(&snapshot).unwrap()).unwrap();
+
+        let read = ConfigSnapshot::read(&snapshot_path).unwrap();
+        let config_path = dir.path().join("devinventory").join("config.toml");
+        read.install_config(&config_path, false).unwrap();
+        assert!(read.install_config(&config_path, false).is_err());
+        read.install_config(&config_path, true).unwrap();
+
+        let written: ConfigFile =
+            toml::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+        assert_eq!(written.database.path.as_deref(), Some("/srv/vault.db"));
+    }
+
+    #[test]
+    fn config_mistakes_point_at_the_line() {
+        let path = Path::new("config.toml");
+        let err = |content: &str| parse_config_file(path, content).unwrap_err().to_string();
+        assert_eq!(
+            err("[logging]\nlevel = \"debug\"\n\n[databse]\npath = \"x\"\n"),
+            "config.toml:4: unknown section [databse]; did you mean [database]?"
+        );
+        assert_eq!(
+            err("[agent]\nintegrity_sampel = 4\nship_to = \"syslog\"\n"),
+            "config.toml:2: unknown key integrity_sampel in [agent]; did you mean integrity_sample?\n\
+             config.toml:3: unknown key ship_to in [agent]; ship_to belongs in [audit]"
+        );
+        assert_eq!(
+            err("[logging]\nlevel = \"loud\"\n"),
+            "config.toml:2: invalid level \"loud\"; use off, error, warn, info, debug or trace"
+        );
+        assert!(
+            err("[crypto]\nreencrypt_on_read = \"yes\"\n")
+                .starts_with("config.toml:2: invalid type")
+        );
+        assert_eq!(
+            err(
+                "[vaults.work]\npath = \"w.db\"\nkeyring_acount = \"w\"\n\n[vaults.home]\nkeyring_account = \"h\"\n"
+            ),
+            "config.toml:5: [vaults.home] needs a path\n\
+             config.toml:3: unknown key keyring_acount in [vaults.work]; did you mean keyring_account?"
+        );
+        let file = parse_config_file(path, &Config::generate_example_config()).unwrap();
+        assert_eq!(file.agent.integrity_sample, Some(16));
+        assert_eq!(file.vaults["work"].keyring_account.as_deref(), Some("work"));
+    }
+
+    #[test]
+    fn vault_profiles_pick_the_file_and_keyring_entry() {
+        let file = parse_config_file(
+            Path::new("config.toml"),
+            "[keyring]\naccount = \"main\"\n\n\
+             [vaults.work]\npath = \"/srv/work.db\"\nkeyring_account = \"work\"\n\n\
+             [vaults.home]\npath = \"/srv/home.db\"\n",
+        )
+        .unwrap();
+        let key = MasterKeySource {
+            base64_inline: None,
+            key_file: None,
+            allow_keyring: true,
+        };
+        let resolve = |vault: Option<&str>, env: &[(&str, &str)]| {
+            let overrides = ConfigOverrides {
+                vault: vault.map(str::to_string),
+                ..Default::default()
+            };
+            Config::resolve(overrides, key.clone(), file.clone(), |var| {
+                env.iter()
+                    .find(|(name, _)| *name == var)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+
+        let work = resolve(Some("work"), &[(DB_PATH_VAR, "/tmp/env.db")]).unwrap();
+        assert_eq!(work.vault.as_deref(), Some("work"));
+        assert_eq!(work.db_path, PathBuf::from("/srv/work.db"));
+        assert_eq!(
+            (work.keyring_service.as_str(), work.keyring_account.as_str()),
+            ("devinventory", "work")
+        );
+        // a profile without a keyring entry falls back to the usual one
+        let home = resolve(None, &[(VAULT_VAR, "home")]).unwrap();
+        assert_eq!(home.db_path, PathBuf::from("/srv/home.db"));
+        assert_eq!(home.keyring_account, "main");
+        // no profile: the environment decides as before
+        let plain = resolve(None, &[(VAULT_VAR, ""), (DB_PATH_VAR, "/tmp/env.db")]).unwrap();
+        assert_eq!(plain.vault, None);
+        assert_eq!(plain.db_path, PathBuf::from("/tmp/env.db"));
+        assert_eq!(
+            resolve(Some("play"), &[]).err().unwrap().to_string(),
+            "unknown vault 'play'; configured: home, work"
+        );
+    }
+
+    #[test]
+    fn schema_lists_every_config_key() {
+        let example: toml::Table = toml::from_str(&Config::generate_example_config()).unwrap();
+        let mut keys: Vec<(String, String)> = example
+            .iter()
+            .flat_map(|(section, table)| {
+                let table = table.as_table().unwrap();
+                let tables: Vec<_> = if section == "vaults" {
+                    table
+                        .values()
+                        .map(|t| ("vaults.NAME", t.as_table().unwrap()))
+                        .collect()
+                } else {
+                    vec![(section.as_str(), table)]
+                };
+                tables.into_iter().flat_map(|(section, table)| {
+                    table
+                        .keys()
+                        .map(move |key| (section.to_string(), key.clone()))
+                })
+            })
+            .collect();
+        keys.sort();
+        let mut schema: Vec<(String, String)> = CONFIG_KEYS
+            .iter()
+            .map(|k| (k.section.to_string(), k.key.to_string()))
+            .collect();
+        schema.sort();
+        assert_eq!(keys, schema);
+    }
+}
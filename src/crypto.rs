@@ -1,8 +1,123 @@
-use anyhow::Result;
-use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, aead::Aead, aead::KeyInit};
+pub mod age;
+
+use aes_gcm::Aes256Gcm;
+use anyhow::{Result, anyhow};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, KeyInit, Payload},
+};
+use clap::ValueEnum;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
 use rand::RngCore;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
 use zeroize::Zeroize;
 
+const DEVICE_WRAP_INFO: &[u8] = b"devinventory device key wrap v1";
+const ATTESTATION_INFO: &[u8] = b"devinventory attestation v1";
+const SEAL_INFO: &[u8] = b"devinventory sealed box v1";
+const SYNTHETIC_NONCE_INFO: &[u8] = b"devinventory synthetic nonce v1";
+const HOST_BINDING_INFO: &[u8] = b"devinventory host binding v1";
+/// Prefixed to a record's associated data when wrapping its data key, so a wrapped
+/// key cannot be passed off as a ciphertext of the same record or the reverse.
+const DATA_KEY_LABEL: &str = "devinventory data key v1:";
+/// First byte of every ciphertext written since the header was introduced; the second
+/// is the [`Cipher`] id. Both are authenticated along with the caller's label.
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = 2;
+const NONCE_LEN: usize = 12;
+
+/// AEAD a vault encrypts new values with. Every ciphertext names its own, so values
+/// written under another one keep decrypting.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Cipher {
+    #[default]
+    #[value(name = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+    #[value(name = "aes-256-gcm")]
+    Aes256Gcm,
+}
+
+impl Cipher {
+    /// Id stored in the ciphertext header
+    fn id(self) -> u8 {
+        match self {
+            Cipher::ChaCha20Poly1305 => 1,
+            Cipher::Aes256Gcm => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .copied()
+            .find(|cipher| cipher.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Cipher::ChaCha20Poly1305 => "chacha20-poly1305",
+            Cipher::Aes256Gcm => "aes-256-gcm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .copied()
+            .find(|cipher| cipher.name() == name)
+    }
+
+    /// The cipher `blob` was written with; ciphertexts from before the header are
+    /// always ChaCha20-Poly1305.
+    pub fn of(blob: &[u8]) -> Self {
+        header_cipher(blob).unwrap_or(Cipher::ChaCha20Poly1305)
+    }
+
+    fn seal(
+        self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        let (key, nonce) = (Key::from_slice(key), Nonce::from_slice(nonce));
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        match self {
+            Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(key).encrypt(nonce, payload),
+            Cipher::Aes256Gcm => Aes256Gcm::new(key).encrypt(nonce, payload),
+        }
+        .map_err(|e| anyhow!(format!("encrypt failed: {e:?}")))
+    }
+
+    fn open(self, key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        let (key, nonce) = (Key::from_slice(key), Nonce::from_slice(nonce));
+        let payload = Payload { msg: sealed, aad };
+        match self {
+            Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(key).decrypt(nonce, payload),
+            Cipher::Aes256Gcm => Aes256Gcm::new(key).decrypt(nonce, payload),
+        }
+        .ok()
+    }
+}
+
+impl std::fmt::Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn header_cipher(blob: &[u8]) -> Option<Cipher> {
+    match blob {
+        [FORMAT_VERSION, id, ..] if blob.len() >= HEADER_LEN + NONCE_LEN => Cipher::from_id(*id),
+        _ => None,
+    }
+}
+
 #[derive(Clone)]
 pub struct MasterKey(pub(crate) [u8; 32]);
 
@@ -20,57 +135,306 @@ impl Drop for MasterKey {
 
 pub struct SecretCrypto {
     key: MasterKey,
+    cipher: Cipher,
+}
+
+/// A value sealed under its own random data key, with that data key wrapped by the
+/// master key. Rotating the master key rewraps `wrapped_key` and leaves `ciphertext`
+/// alone.
+pub struct Envelope {
+    pub wrapped_key: Vec<u8>,
+    pub ciphertext: Vec<u8>,
 }
 
 impl SecretCrypto {
     pub fn new(key: MasterKey) -> Self {
-        Self { key }
+        Self {
+            key,
+            cipher: Cipher::default(),
+        }
+    }
+
+    /// Encrypt with `cipher` instead of ChaCha20-Poly1305; decryption follows each
+    /// ciphertext's header either way.
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    pub fn cipher(&self) -> Cipher {
+        self.cipher
+    }
+
+    /// This key mixed with `machine` through HKDF, for values only that machine may
+    /// open: elsewhere the master key alone cannot compute it.
+    pub fn bound_to(&self, machine: &str) -> SecretCrypto {
+        SecretCrypto {
+            key: derive_key(&self.key.0, machine.as_bytes(), HOST_BINDING_INFO),
+            cipher: self.cipher,
+        }
     }
 
     pub fn encrypt(&self, aad_label: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
         let mut nonce_bytes = [0u8; 12];
         let mut rng = rand::rng();
         rng.fill_bytes(&mut nonce_bytes);
-        let nonce = Nonce::from_slice(&nonce_bytes);
-        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.key.0));
-        let mut aad = aad_label.as_bytes().to_vec();
-        let mut ciphertext = cipher
-            .encrypt(
-                nonce,
-                chacha20poly1305::aead::Payload {
-                    msg: plaintext,
-                    aad: &aad,
-                },
-            )
-            .map_err(|e| anyhow::anyhow!(format!("encrypt failed: {e:?}")))?;
-        // store nonce || ciphertext
-        let mut out = Vec::with_capacity(12 + ciphertext.len());
-        out.extend_from_slice(&nonce_bytes);
-        out.append(&mut ciphertext);
+        self.encrypt_with_nonce(nonce_bytes, aad_label, plaintext)
+    }
+
+    /// Like [`SecretCrypto::encrypt`], but the nonce is a MAC of the label and
+    /// plaintext, so the same input under the same key gives the same bytes. Only
+    /// for whole files where telling two identical copies apart is the point.
+    pub fn encrypt_deterministic(&self, aad_label: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut input = (aad_label.len() as u64).to_be_bytes().to_vec();
+        input.extend_from_slice(aad_label.as_bytes());
+        input.extend_from_slice(plaintext);
+        let digest = keyed_digest(&self.key, SYNTHETIC_NONCE_INFO, &input);
+        input.zeroize();
+        let mut nonce_bytes = [0u8; 12];
+        nonce_bytes.copy_from_slice(&digest[..12]);
+        self.encrypt_with_nonce(nonce_bytes, aad_label, plaintext)
+    }
+
+    fn encrypt_with_nonce(
+        &self,
+        nonce_bytes: [u8; 12],
+        aad_label: &str,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        let header = [FORMAT_VERSION, self.cipher.id()];
+        let mut aad = header_aad(&header, aad_label);
+        let ciphertext = self.cipher.seal(&self.key.0, &nonce_bytes, &aad, plaintext);
         aad.zeroize();
+        // store header || nonce || ciphertext
+        let mut out = Vec::with_capacity(HEADER_LEN + NONCE_LEN + plaintext.len() + 16);
+        out.extend_from_slice(&header);
+        out.extend_from_slice(&nonce_bytes);
+        out.extend(ciphertext?);
         Ok(out)
     }
 
+    /// Encrypt `plaintext` under a fresh data key and wrap that key with this one, both
+    /// bound to `aad_label`.
+    pub fn seal(&self, aad_label: &str, plaintext: &[u8]) -> Result<Envelope> {
+        let data_key = generate_data_key();
+        let ciphertext = SecretCrypto::new(data_key.clone())
+            .with_cipher(self.cipher)
+            .encrypt(aad_label, plaintext)?;
+        let wrapped_key = self.encrypt(&data_key_label(aad_label), &data_key.0)?;
+        Ok(Envelope {
+            wrapped_key,
+            ciphertext,
+        })
+    }
+
+    /// Decrypt a stored value: through its wrapped data key, or with this key directly
+    /// when there is none (records written before envelopes).
+    pub fn open(
+        &self,
+        aad_label: &str,
+        wrapped_key: Option<&[u8]>,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>> {
+        match wrapped_key {
+            Some(wrapped) => SecretCrypto::new(self.unwrap_data_key(aad_label, wrapped)?)
+                .decrypt(aad_label, ciphertext),
+            None => self.decrypt(aad_label, ciphertext),
+        }
+    }
+
+    /// Move a wrapped data key from this key to `to` without touching the value it
+    /// protects.
+    pub fn rewrap(
+        &self,
+        to: &SecretCrypto,
+        aad_label: &str,
+        wrapped_key: &[u8],
+    ) -> Result<Vec<u8>> {
+        let data_key = self.unwrap_data_key(aad_label, wrapped_key)?;
+        to.encrypt(&data_key_label(aad_label), &data_key.0)
+    }
+
+    fn unwrap_data_key(&self, aad_label: &str, wrapped_key: &[u8]) -> Result<MasterKey> {
+        let mut raw = self
+            .decrypt(&data_key_label(aad_label), wrapped_key)
+            .map_err(|_| anyhow::anyhow!("unwrapping data key failed"))?;
+        let key = <[u8; 32]>::try_from(raw.as_slice())
+            .map(MasterKey)
+            .map_err(|_| anyhow::anyhow!("data key has wrong length"));
+        raw.zeroize();
+        key
+    }
+
+    /// Decrypt with the cipher named in `blob`'s header. Ciphertexts from before the
+    /// header (`nonce || ciphertext` under ChaCha20-Poly1305) still open; one whose
+    /// random nonce happens to look like a header is retried that way.
     pub fn decrypt(&self, aad_label: &str, blob: &[u8]) -> Result<Vec<u8>> {
-        if blob.len() < 12 {
-            return Err(anyhow::anyhow!("ciphertext too short"));
+        if let Some(cipher) = header_cipher(blob) {
+            let (header, rest) = blob.split_at(HEADER_LEN);
+            let (nonce, ct) = rest.split_at(NONCE_LEN);
+            let aad = header_aad(header, aad_label);
+            let nonce = nonce.try_into().expect("nonce is 12 bytes");
+            if let Some(plaintext) = cipher.open(&self.key.0, nonce, &aad, ct) {
+                return Ok(plaintext);
+            }
         }
-        let (nonce_bytes, ct) = blob.split_at(12);
-        let nonce = Nonce::from_slice(nonce_bytes);
-        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.key.0));
-        let plaintext = cipher
-            .decrypt(
-                nonce,
-                chacha20poly1305::aead::Payload {
-                    msg: ct,
-                    aad: aad_label.as_bytes(),
-                },
-            )
-            .map_err(|e| anyhow::anyhow!(format!("decrypt failed: {e:?}")))?;
-        Ok(plaintext)
+        if blob.len() < NONCE_LEN {
+            return Err(anyhow!("ciphertext too short"));
+        }
+        let (nonce, ct) = blob.split_at(NONCE_LEN);
+        let nonce = nonce.try_into().expect("nonce is 12 bytes");
+        Cipher::ChaCha20Poly1305
+            .open(&self.key.0, nonce, aad_label.as_bytes(), ct)
+            .ok_or_else(|| anyhow!("decrypt failed"))
     }
 }
 
+fn header_aad(header: &[u8], aad_label: &str) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.extend_from_slice(aad_label.as_bytes());
+    aad
+}
+
+fn data_key_label(aad_label: &str) -> String {
+    format!("{DATA_KEY_LABEL}{aad_label}")
+}
+
+fn generate_data_key() -> MasterKey {
+    let mut key = [0u8; 32];
+    rand::rng().fill_bytes(&mut key);
+    MasterKey(key)
+}
+
+/// X25519 keypair identifying one enrolled device.
+pub struct DeviceKeypair {
+    pub secret: [u8; 32],
+    pub public: [u8; 32],
+}
+
+impl Drop for DeviceKeypair {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+pub fn generate_device_keypair() -> DeviceKeypair {
+    let mut secret = [0u8; 32];
+    rand::rng().fill_bytes(&mut secret);
+    let public = device_public_key(&secret);
+    DeviceKeypair { secret, public }
+}
+
+/// Public half of an X25519 secret key, e.g. one derived from a key slot's unlock key.
+pub fn device_public_key(secret: &[u8; 32]) -> [u8; 32] {
+    PublicKey::from(&StaticSecret::from(*secret)).to_bytes()
+}
+
+/// Wrap the master key for one device (ephemeral X25519 + HKDF-SHA256 + ChaCha20-Poly1305).
+/// Layout: ephemeral public key (32) || nonce (12) || ciphertext.
+pub fn wrap_for_device(key: &MasterKey, device_public: &[u8; 32], aad: &str) -> Result<Vec<u8>> {
+    seal_to(device_public, DEVICE_WRAP_INFO, aad, &key.0)
+}
+
+/// Recover a master key wrapped by [`wrap_for_device`] using the device's secret key.
+pub fn unwrap_with_device(device_secret: &[u8; 32], blob: &[u8], aad: &str) -> Result<MasterKey> {
+    let mut plaintext = open_from(device_secret, DEVICE_WRAP_INFO, aad, blob)?;
+    if plaintext.len() != 32 {
+        plaintext.zeroize();
+        return Err(anyhow::anyhow!("wrapped key has wrong length"));
+    }
+    let mut arr = [0u8; 32];
+    arr.copy_from_slice(&plaintext);
+    plaintext.zeroize();
+    Ok(MasterKey(arr))
+}
+
+/// Encrypt arbitrary bytes to an X25519 public key, the same construction as
+/// [`wrap_for_device`]; only the matching secret key can open the result.
+pub fn seal_for_recipient(public: &[u8; 32], aad: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    seal_to(public, SEAL_INFO, aad, plaintext)
+}
+
+/// Open a [`seal_for_recipient`] blob with the recipient's secret key.
+pub fn open_with_secret(secret: &[u8; 32], aad: &str, blob: &[u8]) -> Result<Vec<u8>> {
+    open_from(secret, SEAL_INFO, aad, blob)
+}
+
+fn seal_to(public: &[u8; 32], info: &[u8], aad: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut eph_bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut eph_bytes);
+    let eph_secret = StaticSecret::from(eph_bytes);
+    eph_bytes.zeroize();
+    let eph_public = PublicKey::from(&eph_secret);
+    let shared = eph_secret.diffie_hellman(&PublicKey::from(*public));
+    let wrap = SecretCrypto::new(derive_key(shared.as_bytes(), eph_public.as_bytes(), info));
+    let mut out = eph_public.to_bytes().to_vec();
+    out.extend(wrap.encrypt(aad, plaintext)?);
+    Ok(out)
+}
+
+fn open_from(secret: &[u8; 32], info: &[u8], aad: &str, blob: &[u8]) -> Result<Vec<u8>> {
+    if blob.len() < 32 {
+        return Err(anyhow::anyhow!("sealed data too short"));
+    }
+    let (eph, ct) = blob.split_at(32);
+    let eph: [u8; 32] = eph.try_into()?;
+    let shared = StaticSecret::from(*secret).diffie_hellman(&PublicKey::from(eph));
+    let wrap = SecretCrypto::new(derive_key(shared.as_bytes(), &eph, info));
+    wrap.decrypt(aad, ct)
+}
+
+/// Key derived from a passphrase with PBKDF2-HMAC-SHA256.
+pub fn passphrase_key(passphrase: &[u8], salt: &[u8], rounds: u32) -> MasterKey {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase, salt, rounds, &mut key);
+    MasterKey(key)
+}
+
+/// HMAC-SHA256 over an attestation payload, keyed from the master key.
+pub fn sign_attestation(key: &MasterKey, payload: &[u8]) -> Vec<u8> {
+    attestation_mac(key, payload)
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+/// Check a [`sign_attestation`] signature in constant time.
+pub fn verify_attestation(key: &MasterKey, payload: &[u8], signature: &[u8]) -> bool {
+    attestation_mac(key, payload)
+        .verify_slice(signature)
+        .is_ok()
+}
+
+/// HMAC-SHA256 of `data` under a key derived from the master key for `info`.
+pub fn keyed_digest(key: &MasterKey, info: &[u8], data: &[u8]) -> [u8; 32] {
+    keyed_mac(key, info, data).finalize().into_bytes().into()
+}
+
+fn attestation_mac(key: &MasterKey, payload: &[u8]) -> Hmac<Sha256> {
+    keyed_mac(key, ATTESTATION_INFO, payload)
+}
+
+fn keyed_mac(key: &MasterKey, info: &[u8], data: &[u8]) -> Hmac<Sha256> {
+    let mut okm = [0u8; 32];
+    Hkdf::<Sha256>::new(None, &key.0)
+        .expand(info, &mut okm)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(&okm).expect("HMAC takes keys of any length");
+    okm.zeroize();
+    mac.update(data);
+    mac
+}
+
+/// A 256-bit key from shared or hardware-held secret bytes with HKDF-SHA256.
+pub fn derive_key(shared: &[u8], salt: &[u8], info: &[u8]) -> MasterKey {
+    let mut okm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(salt), shared)
+        .expand(info, &mut okm)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    MasterKey(okm)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,5 +448,148 @@ mod tests {
         assert_ne!(ct, plaintext);
         let pt = crypto.decrypt("name", &ct).expect("decrypt");
         assert_eq!(pt, plaintext);
+
+        let stable = crypto.encrypt_deterministic("name", plaintext).unwrap();
+        assert_eq!(
+            stable,
+            crypto.encrypt_deterministic("name", plaintext).unwrap()
+        );
+        assert_ne!(
+            stable,
+            crypto.encrypt_deterministic("other", plaintext).unwrap()
+        );
+        assert_eq!(crypto.decrypt("name", &stable).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn envelopes_rewrap_without_touching_the_value() {
+        let (old, new) = (
+            SecretCrypto::new(MasterKey([3u8; 32])),
+            SecretCrypto::new(MasterKey([4u8; 32])),
+        );
+        let sealed = old.seal("name", b"value").unwrap();
+        assert_eq!(
+            old.open("name", Some(&sealed.wrapped_key), &sealed.ciphertext)
+                .unwrap(),
+            b"value"
+        );
+        assert!(
+            old.open("other", Some(&sealed.wrapped_key), &sealed.ciphertext)
+                .is_err()
+        );
+        assert!(old.open("name", None, &sealed.ciphertext).is_err());
+
+        let rewrapped = old.rewrap(&new, "name", &sealed.wrapped_key).unwrap();
+        assert_eq!(
+            new.open("name", Some(&rewrapped), &sealed.ciphertext)
+                .unwrap(),
+            b"value"
+        );
+        assert!(
+            old.open("name", Some(&rewrapped), &sealed.ciphertext)
+                .is_err()
+        );
+
+        let legacy = old.encrypt("name", b"value").unwrap();
+        assert_eq!(old.open("name", None, &legacy).unwrap(), b"value");
+    }
+
+    #[test]
+    fn ciphertexts_name_their_cipher() {
+        let key = MasterKey([6u8; 32]);
+        let chacha = SecretCrypto::new(key.clone());
+        let aes = SecretCrypto::new(key.clone()).with_cipher(Cipher::Aes256Gcm);
+        let a = chacha.encrypt("name", b"value").unwrap();
+        let b = aes.encrypt("name", b"value").unwrap();
+        assert_eq!(a[..2], [FORMAT_VERSION, 1]);
+        assert_eq!(b[..2], [FORMAT_VERSION, 2]);
+        assert_eq!(Cipher::of(&b), Cipher::Aes256Gcm);
+        // either instance reads both: the header picks the cipher
+        assert_eq!(chacha.decrypt("name", &b).unwrap(), b"value");
+        assert_eq!(aes.decrypt("name", &a).unwrap(), b"value");
+        assert!(aes.decrypt("other", &b).is_err());
+        let mut relabelled = b.clone();
+        relabelled[1] = 1;
+        assert!(chacha.decrypt("name", &relabelled).is_err());
+
+        let sealed = aes.seal("name", b"value").unwrap();
+        assert_eq!(Cipher::of(&sealed.ciphertext), Cipher::Aes256Gcm);
+        assert_eq!(
+            chacha
+                .open("name", Some(&sealed.wrapped_key), &sealed.ciphertext)
+                .unwrap(),
+            b"value"
+        );
+
+        // written before the header: nonce || ChaCha20-Poly1305 ciphertext
+        let nonce = [1u8; 12];
+        let mut legacy = nonce.to_vec();
+        legacy.extend(
+            Cipher::ChaCha20Poly1305
+                .seal(&key.0, &nonce, b"name", b"value")
+                .unwrap(),
+        );
+        assert_eq!(Cipher::of(&legacy), Cipher::ChaCha20Poly1305);
+        assert_eq!(aes.decrypt("name", &legacy).unwrap(), b"value");
+    }
+
+    fn unhex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn aes_gcm_matches_the_nist_test_vectors() {
+        // test case 16 of the GCM specification (McGrew & Viega)
+        let key: [u8; 32] =
+            unhex("feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308")
+                .try_into()
+                .unwrap();
+        let nonce: [u8; 12] = unhex("cafebabefacedbaddecaf888").try_into().unwrap();
+        let aad = unhex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let plaintext = unhex(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a721c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+        );
+        let gcm = Cipher::Aes256Gcm;
+        let sealed = gcm.seal(&key, &nonce, &aad, &plaintext).unwrap();
+        assert_eq!(
+            sealed,
+            unhex(
+                "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662\
+                 76fc6ece0f4e1768cddf8853bb2d551b"
+            )
+        );
+        assert_eq!(gcm.open(&key, &nonce, &aad, &sealed).unwrap(), plaintext);
+        assert!(gcm.open(&key, &nonce, b"", &sealed).is_none());
+        assert!(gcm.open(&key, &nonce, &aad, &sealed[..10]).is_none());
+
+        // test case 13: empty plaintext, the tag alone
+        assert_eq!(
+            gcm.seal(&[0u8; 32], &[0u8; 12], b"", b"").unwrap(),
+            unhex("530f8afbc74536b9a963b4f1c4cb738b")
+        );
+    }
+
+    #[test]
+    fn device_wrap_roundtrip() {
+        let key = MasterKey([5u8; 32]);
+        let device = generate_device_keypair();
+        let other = generate_device_keypair();
+        let wrapped = wrap_for_device(&key, &device.public, "dev-1").expect("wrap");
+
+        let unwrapped = unwrap_with_device(&device.secret, &wrapped, "dev-1").expect("unwrap");
+        assert_eq!(unwrapped.0, key.0);
+        assert!(unwrap_with_device(&other.secret, &wrapped, "dev-1").is_err());
+        assert!(unwrap_with_device(&device.secret, &wrapped, "dev-2").is_err());
+    }
+
+    #[test]
+    fn attestation_signature_is_keyed() {
+        let sig = sign_attestation(&MasterKey([1u8; 32]), b"root");
+        assert!(verify_attestation(&MasterKey([1u8; 32]), b"root", &sig));
+        assert!(!verify_attestation(&MasterKey([2u8; 32]), b"root", &sig));
+        assert!(!verify_attestation(&MasterKey([1u8; 32]), b"roots", &sig));
     }
 }
@@ -0,0 +1,177 @@
+//! age (age-encryption.org/v1) files to X25519 recipients through the `age` crate:
+//! exports that `age -d -i key.txt` opens, and reading such files back.
+
+use ::age::secrecy::ExposeSecret;
+use ::age::{Decryptor, Encryptor, x25519};
+use anyhow::{Context, Result, anyhow, bail};
+use std::io::{Read, Write};
+
+/// An X25519 recipient, written `age1...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient(x25519::Recipient);
+
+impl Recipient {
+    pub fn parse(text: &str) -> Result<Self> {
+        text.trim()
+            .parse()
+            .map(Self)
+            .map_err(|e| anyhow!("'{text}' is not an age recipient (age1...): {e}"))
+    }
+}
+
+impl std::fmt::Display for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// An X25519 identity, written `AGE-SECRET-KEY-1...`.
+pub struct Identity(x25519::Identity);
+
+impl Identity {
+    pub fn generate() -> Self {
+        Self(x25519::Identity::generate())
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        text.trim()
+            .parse()
+            .map(Self)
+            .map_err(|_| anyhow!("not an age identity (AGE-SECRET-KEY-1...)"))
+    }
+
+    pub fn to_recipient(&self) -> Recipient {
+        Recipient(self.0.to_public())
+    }
+
+    /// The `AGE-SECRET-KEY-1...` form age reads from an identity file.
+    pub fn to_secret_string(&self) -> String {
+        self.0.to_string().expose_secret().to_string()
+    }
+}
+
+/// Encrypt `plaintext` to every recipient; any one of their identities opens it.
+pub fn encrypt(recipients: &[Recipient], plaintext: &[u8]) -> Result<Vec<u8>> {
+    if recipients.is_empty() {
+        bail!("at least one age recipient is needed");
+    }
+    let encryptor = Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| &recipient.0 as &dyn ::age::Recipient),
+    )
+    .context("age encryption failed")?;
+    let mut out = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut out)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// Decrypt an age file addressed to `identity`.
+pub fn decrypt(identity: &Identity, data: &[u8]) -> Result<Vec<u8>> {
+    let decryptor = Decryptor::new(data).context("not an age file")?;
+    let mut reader = decryptor
+        .decrypt(std::iter::once(&identity.0 as &dyn ::age::Identity))
+        .map_err(|e| match e {
+            ::age::DecryptError::NoMatchingKeys => {
+                anyhow!("this age file is not addressed to the given identity")
+            }
+            e => anyhow!("cannot open the age file: {e}"),
+        })?;
+    let mut out = Vec::new();
+    reader
+        .read_to_end(&mut out)
+        .context("age payload is corrupt or truncated")?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    const CHUNK: usize = 64 * 1024;
+
+    fn unhex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// A file from the C2SP age test kit: `header`, then the binary payload.
+    fn testkit_file(header: &str, payload: &str) -> Vec<u8> {
+        let mut file = header.as_bytes().to_vec();
+        file.extend(unhex(payload));
+        file
+    }
+
+    #[test]
+    fn opens_files_written_by_the_reference_implementation() {
+        // testkit vector `x25519`
+        let identity = Identity::parse(
+            "AGE-SECRET-KEY-1XMWWC06LY3EE5RYTXM9MFLAZ2U56JJJ36S0MYPDRWSVLUL66MV4QX3S7F6",
+        )
+        .unwrap();
+        let file = testkit_file(
+            "age-encryption.org/v1\n\
+             -> X25519 TEiF0ypqr+bpvcqXNyCVJpL7OuwPdVwPL7KQEbFDOCc\n\
+             EmECAEcKN+n/Vs9SbWiV+Hu0r+E8R77DdWYyd83nw7U\n\
+             --- Vn+54jqiiUCE+WZcEVY3f1sqHjlu/z1LCQ/T7Xm7qI0\n",
+            "eecf62c7ce91b433274e68d4f2f9134cb74c5bfef7beaa52c8f0bc0e992c1e8331fb66",
+        );
+        let plaintext = decrypt(&identity, &file).unwrap();
+        assert_eq!(
+            Sha256::digest(&plaintext)[..],
+            unhex("013f54400c82da08037759ada907a8b864e97de81c088a182062c4b5622fd2ab")
+        );
+        assert!(decrypt(&Identity::generate(), &file).is_err());
+
+        // testkit vector `x25519_low_order`: a low-order share makes the shared secret
+        // all zeros, which the spec requires rejecting
+        let identity = Identity::parse(
+            "AGE-SECRET-KEY-1EGTZVFFV20835NWYV6270LXYVK2VKNX2MMDKWYKLMGR48UAWX40Q2P2LM0",
+        )
+        .unwrap();
+        let file = testkit_file(
+            "age-encryption.org/v1\n\
+             -> X25519 X5yVvKNQjCSx0LFVnIPvWwREXMRYHI6G2CJO3dCfEdc\n\
+             3E0NpFans/m0WLWF7+54ZBdNj3iqQqpraGDFiaRkvBA\n\
+             --- sXw327YMT1/ULXe+ZyRMbMY0Z2jnWHGgI9j1we6yQ8A\n",
+            "ac5d3f3706e55071d3a604204697b9090595c2f7f5dbaee80d7a018c2872058af3ce7c",
+        );
+        assert!(decrypt(&identity, &file).is_err());
+    }
+
+    #[test]
+    fn files_open_for_each_recipient_and_nobody_else() {
+        // from the age specification's examples
+        let spec = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+        assert_eq!(Recipient::parse(spec).unwrap().to_string(), spec);
+        assert!(Recipient::parse(&spec.replace('q', "p")).is_err());
+
+        let (alice, bob, eve) = (
+            Identity::generate(),
+            Identity::generate(),
+            Identity::generate(),
+        );
+        let alice_again = Identity::parse(&alice.to_secret_string()).unwrap();
+        assert_eq!(alice_again.to_recipient(), alice.to_recipient());
+        let recipients = [alice.to_recipient(), bob.to_recipient()];
+        let large = vec![7u8; CHUNK * 2 + 5];
+        for plaintext in [&b""[..], b"API_KEY=x\n", &large[..CHUNK], &large] {
+            let file = encrypt(&recipients, plaintext).unwrap();
+            assert!(file.starts_with(b"age-encryption.org/v1\n-> X25519 "));
+            assert_eq!(decrypt(&alice, &file).unwrap(), plaintext);
+            assert_eq!(decrypt(&bob, &file).unwrap(), plaintext);
+            assert!(decrypt(&eve, &file).is_err());
+        }
+
+        let mut file = encrypt(&recipients, b"API_KEY=x\n").unwrap();
+        let last = file.len() - 1;
+        file[last] ^= 1;
+        assert!(decrypt(&alice, &file).is_err());
+        assert!(decrypt(&alice, &file[..file.len() - 16]).is_err());
+    }
+}
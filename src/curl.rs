@@ -0,0 +1,72 @@
+//! Authorization headers for `curl`, handed over in a private header file
+//! (`-H @file`) so the credential never appears on a command line.
+
+use anyhow::{Result, bail};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use clap::ValueEnum;
+
+/// Placeholder for the secret value in `--header-template`.
+pub const VALUE_PLACEHOLDER: &str = "{value}";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthScheme {
+    /// `Authorization: Bearer <value>`
+    Bearer,
+    /// `Authorization: Basic <base64 of value>`; the value is `user:password`
+    Basic,
+    /// `Authorization: token <value>` (GitHub style)
+    Token,
+}
+
+/// The header line for `value`: `template` with `{value}` replaced, or the scheme's
+/// Authorization header. Values or templates spanning lines are refused, as they could
+/// smuggle extra headers into the request.
+pub fn header_line(scheme: AuthScheme, template: Option<&str>, value: &str) -> Result<String> {
+    if value.contains(['\r', '\n']) {
+        bail!("the secret spans several lines and cannot go into a header");
+    }
+    let line = match template {
+        Some(template) => {
+            if !template.contains(VALUE_PLACEHOLDER) {
+                bail!("--header-template needs a {VALUE_PLACEHOLDER} placeholder");
+            }
+            if template.contains(['\r', '\n']) {
+                bail!("--header-template must be a single line");
+            }
+            template.replace(VALUE_PLACEHOLDER, value)
+        }
+        None => match scheme {
+            AuthScheme::Bearer => format!("Authorization: Bearer {value}"),
+            AuthScheme::Basic => format!("Authorization: Basic {}", STANDARD.encode(value)),
+            AuthScheme::Token => format!("Authorization: token {value}"),
+        },
+    };
+    if !line.contains(':') {
+        // never echo the line: it holds the secret
+        bail!("--header-template must look like 'Name: ...{VALUE_PLACEHOLDER}'");
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_single_line_headers() {
+        assert_eq!(
+            header_line(AuthScheme::Bearer, None, "t0k").unwrap(),
+            "Authorization: Bearer t0k"
+        );
+        assert_eq!(
+            header_line(AuthScheme::Basic, None, "user:pw").unwrap(),
+            "Authorization: Basic dXNlcjpwdw=="
+        );
+        assert_eq!(
+            header_line(AuthScheme::Bearer, Some("X-Api-Key: {value}"), "k").unwrap(),
+            "X-Api-Key: k"
+        );
+        assert!(header_line(AuthScheme::Bearer, None, "a\r\nX-Evil: 1").is_err());
+        assert!(header_line(AuthScheme::Bearer, Some("X-Api-Key: fixed"), "k").is_err());
+    }
+}
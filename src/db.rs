@@ -1,18 +1,26 @@
-use crate::crypto::{MasterKey, SecretCrypto};
-use anyhow::{Context, Result};
+use crate::crypto::Envelope;
+use crate::domain::{
+    ApprovalRequest, AuditAction, AuditEntry, AuditFilter, CertificateInfo, Device, KeySlot, Lease,
+    MetadataPatch, Provenance, SecretAttributes, SecretLock, SlotKind,
+};
+use crate::oplog::{OpKind, Operation, VectorClock};
+use crate::perms;
+use anyhow::{Context, Result, bail};
 use chrono::{DateTime, Utc};
-use dirs::config_dir;
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Row, Sqlite, sqlite::SqlitePoolOptions};
-use std::{
-    fs,
-    fs::OpenOptions,
-    path::{Path, PathBuf},
+use sqlx::{
+    Pool, Row, Sqlite, Transaction, TransactionManager,
+    sqlite::{SqlitePoolOptions, SqliteRow, SqliteTransactionManager},
 };
+use std::{collections::BTreeMap, fs::OpenOptions, path::Path};
 use uuid::Uuid;
 
-const DEFAULT_DB_NAME: &str = "devinventory.db";
+/// Columns selected whenever a full `SecretRecord` is loaded.
+const RECORD_COLUMNS: &str = "id, name, kind, note, owner, contact, immutable, bind_host, allowed_commands, expires_at, certificate, fields, provenance, key_version, ciphertext, created_at, updated_at, data_key";
+/// Columns selected whenever an `AuditEntry` is loaded.
+const AUDIT_COLUMNS: &str = "id, at, action, secret_name, detail, host, user";
+const NAMES_SQL: &str = "SELECT name FROM secrets WHERE deleted_at IS NULL AND name >= ?1 AND name < ?2 ORDER BY name LIMIT ?3";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SecretRecord {
@@ -20,23 +28,90 @@ pub struct SecretRecord {
     pub name: String,
     pub kind: Option<String>,
     pub note: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub contact: Option<String>,
+    pub immutable: bool,
+    pub bind_host: bool,
+    pub allowed_commands: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub certificate: Option<CertificateInfo>,
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    /// Master key version the ciphertext was written under
+    #[serde(default = "first_key_version")]
+    pub key_version: i64,
     pub ciphertext: Vec<u8>,
+    /// Data key the ciphertext is sealed under, wrapped by the master key; `None`
+    /// for records encrypted with the master key directly
+    #[serde(default)]
+    pub data_key: Option<Vec<u8>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl SecretRecord {
+    fn from_row(r: &SqliteRow) -> Self {
+        Self {
+            id: Uuid::parse_str(r.get::<String, _>("id").as_str()).unwrap_or_else(|_| Uuid::nil()),
+            name: r.get("name"),
+            kind: r.get("kind"),
+            note: r.get("note"),
+            owner: r.get("owner"),
+            contact: r.get("contact"),
+            immutable: r.get("immutable"),
+            bind_host: r.get("bind_host"),
+            allowed_commands: r
+                .get::<Option<String>, _>("allowed_commands")
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+            expires_at: r.get("expires_at"),
+            certificate: r
+                .get::<Option<String>, _>("certificate")
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            fields: r
+                .get::<Option<String>, _>("fields")
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+            provenance: r
+                .get::<Option<String>, _>("provenance")
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            key_version: r.get("key_version"),
+            ciphertext: r.get("ciphertext"),
+            data_key: r.get("data_key"),
+            created_at: r.get("created_at"),
+            updated_at: r.get("updated_at"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Repository {
     pool: Pool<Sqlite>,
+    /// Where `begin_unit` opens its own connection; `None` for in-memory databases
+    url: Option<String>,
+    /// Set on the repository `begin_unit` hands out
+    in_unit: bool,
 }
 
 impl Repository {
     pub async fn connect(path: &Path) -> Result<Self> {
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+            perms::create_private_dir_all(parent)?;
         }
         if !path.exists() {
             // Touch the file so SQLite doesn't fail with code 14 on some sandboxed FS.
-            OpenOptions::new().create(true).write(true).open(path)?;
+            OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(path)?;
+            perms::make_owner_only(path)?;
             info!("created new database file at {}", path.to_string_lossy());
         }
         let url = format!("sqlite://{}", path.to_string_lossy());
@@ -46,7 +121,83 @@ impl Repository {
             .connect(&url)
             .await
             .context("connect sqlite")?;
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            url: Some(url),
+            in_unit: false,
+        })
+    }
+
+    /// A private in-memory database that disappears with the process.
+    pub async fn in_memory() -> Result<Self> {
+        // Every connection to `:memory:` is its own database, so keep exactly one alive.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .context("open in-memory sqlite")?;
+        Ok(Self {
+            pool,
+            url: None,
+            in_unit: false,
+        })
+    }
+
+    /// A repository whose calls all run inside one write transaction until
+    /// `finish_unit`; transactions its methods open become savepoints. Other
+    /// connections wait for the write lock meanwhile.
+    pub async fn begin_unit(&self) -> Result<Repository> {
+        if self.in_unit {
+            bail!("a unit of work is already open");
+        }
+        let pool = match &self.url {
+            // one connection, so every call lands in the open transaction
+            Some(url) => SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect(url)
+                .await
+                .context("connect sqlite")?,
+            // an in-memory pool already has exactly one connection
+            None => self.pool.clone(),
+        };
+        let mut conn = pool.acquire().await?;
+        SqliteTransactionManager::begin(&mut conn, Some("BEGIN IMMEDIATE".into())).await?;
+        debug!("unit of work started");
+        Ok(Self {
+            pool,
+            url: self.url.clone(),
+            in_unit: true,
+        })
+    }
+
+    /// Commit or roll back a unit opened by `begin_unit`.
+    pub async fn finish_unit(self, commit: bool) -> Result<()> {
+        if !self.in_unit {
+            bail!("no unit of work is open");
+        }
+        let mut conn = self.pool.acquire().await?;
+        if commit {
+            SqliteTransactionManager::commit(&mut conn).await?;
+        } else {
+            SqliteTransactionManager::rollback(&mut conn).await?;
+        }
+        drop(conn);
+        if self.url.is_some() {
+            self.pool.close().await;
+        }
+        debug!(
+            "unit of work {}",
+            if commit { "committed" } else { "rolled back" }
+        );
+        Ok(())
+    }
+
+    pub fn in_unit(&self) -> bool {
+        self.in_unit
     }
 
     pub async fn migrate(&self) -> Result<()> {
@@ -68,46 +219,287 @@ impl Repository {
         sqlx::query("CREATE INDEX IF NOT EXISTS idx_secrets_kind ON secrets(kind);")
             .execute(&self.pool)
             .await?;
+        self.ensure_column("secrets", "immutable", "INTEGER NOT NULL DEFAULT 0")
+            .await?;
+        self.ensure_column("secrets", "bind_host", "INTEGER NOT NULL DEFAULT 0")
+            .await?;
+        // JSON array of executable names/paths; NULL means unrestricted
+        self.ensure_column("secrets", "allowed_commands", "TEXT")
+            .await?;
+        self.ensure_column("secrets", "expires_at", "TEXT").await?;
+        self.ensure_column("secrets", "owner", "TEXT").await?;
+        self.ensure_column("secrets", "contact", "TEXT").await?;
+        self.ensure_column("secrets", "key_version", "INTEGER NOT NULL DEFAULT 1")
+            .await?;
+        // retired master keys, each wrapped under the current key; the current
+        // version is the row without a wrapped key
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS key_versions (
+                version     INTEGER PRIMARY KEY,
+                wrapped_key BLOB,
+                created_at  TEXT NOT NULL
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        // JSON CertificateInfo for kind=certificate
+        self.ensure_column("secrets", "certificate", "TEXT").await?;
+        // JSON object of free-form fields (e.g. attributes kept from an import)
+        self.ensure_column("secrets", "fields", "TEXT").await?;
+        // JSON Provenance of the current value
+        self.ensure_column("secrets", "provenance", "TEXT").await?;
+        // the record's data key wrapped by the master key; NULL for records written
+        // before envelope encryption, which rotation and re-encrypt-on-read upgrade
+        self.ensure_column("secrets", "data_key", "BLOB").await?;
+        // set when `rm` moves the secret to the trash; every live query skips such rows
+        self.ensure_column("secrets", "deleted_at", "TEXT").await?;
+        // folder part of the name (`prod/db` for `prod/db/password`), NULL at the top
+        // level; trimming every non-slash character off the end leaves the folder
+        self.ensure_column(
+            "secrets",
+            "namespace",
+            "TEXT GENERATED ALWAYS AS (NULLIF(rtrim(rtrim(name, replace(name, '/', '')), '/'), '')) VIRTUAL",
+        )
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_secrets_namespace ON secrets(namespace);")
+            .execute(&self.pool)
+            .await?;
+        // covers name lookups over live secrets, so completion never touches ciphertext;
+        // deleted_at rides along only so SQLite sees the index as covering
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_secrets_live_name ON secrets(name, deleted_at) WHERE deleted_at IS NULL;",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS devices (
+                id          TEXT PRIMARY KEY,
+                label       TEXT NOT NULL UNIQUE,
+                public_key  BLOB NOT NULL,
+                wrapped_key BLOB,
+                created_at  TEXT NOT NULL,
+                revoked_at  TEXT
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS key_slots (
+                id          INTEGER PRIMARY KEY,
+                kind        TEXT NOT NULL,
+                label       TEXT NOT NULL,
+                params      TEXT,
+                public_key  BLOB NOT NULL,
+                wrapped_key BLOB NOT NULL,
+                created_at  TEXT NOT NULL
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS vault_meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS oplog (
+                op_id       TEXT PRIMARY KEY,
+                replica     TEXT NOT NULL,
+                clock       TEXT NOT NULL,
+                kind        TEXT NOT NULL,
+                secret_name TEXT NOT NULL,
+                record      TEXT,
+                created_at  TEXT NOT NULL
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_oplog_name ON oplog(secret_name);")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                at          TEXT NOT NULL,
+                action      TEXT NOT NULL,
+                secret_name TEXT,
+                detail      TEXT
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.ensure_column("audit_log", "host", "TEXT").await?;
+        self.ensure_column("audit_log", "user", "TEXT").await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS approvals (
+                id           TEXT PRIMARY KEY,
+                action       TEXT NOT NULL,
+                target       TEXT NOT NULL,
+                requested_by TEXT NOT NULL,
+                created_at   TEXT NOT NULL,
+                approved_by  TEXT,
+                approved_at  TEXT,
+                consumed_at  TEXT
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        // environment variable a secret is injected as, instead of the derived name
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS env_mappings (secret_name TEXT PRIMARY KEY, var TEXT NOT NULL);",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS tags (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE);",
+        )
+        .execute(&self.pool)
+        .await?;
+        // keyed by secret id so tags survive renames and re-encryption
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS secret_tags (
+                secret_id   TEXT NOT NULL,
+                tag_id      INTEGER NOT NULL REFERENCES tags(id),
+                PRIMARY KEY (secret_id, tag_id)
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        // local soft locks; keyed by secret id like tags
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS secret_locks (
+                secret_id   TEXT PRIMARY KEY,
+                locked_by   TEXT NOT NULL,
+                locked_at   TEXT NOT NULL,
+                reason      TEXT
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS leases (
+                id          TEXT PRIMARY KEY,
+                token_hash  TEXT NOT NULL UNIQUE,
+                secret_name TEXT NOT NULL,
+                created_by  TEXT NOT NULL,
+                created_at  TEXT NOT NULL,
+                expires_at  TEXT NOT NULL,
+                redeemed_at TEXT
+            );
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
         debug!("database schema ensured");
         Ok(())
     }
 
+    /// Add `column` to `table` when an older database predates it.
+    async fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        // table_xinfo also lists generated columns
+        let rows = sqlx::query(&format!("PRAGMA table_xinfo({table})"))
+            .fetch_all(&self.pool)
+            .await?;
+        if rows.iter().any(|r| r.get::<String, _>("name") == column) {
+            return Ok(());
+        }
+        sqlx::query(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))
+        .execute(&self.pool)
+        .await?;
+        info!("migrated table '{}': added column '{}'", table, column);
+        Ok(())
+    }
+
     pub async fn upsert_secret(
         &self,
         name: &str,
-        kind: Option<String>,
-        note: Option<String>,
-        ciphertext: &[u8],
+        attrs: &SecretAttributes,
+        sealed: &Envelope,
+        key_version: i64,
     ) -> Result<()> {
         let now = Utc::now();
-        sqlx::query(
+        let allowed_commands = allowed_commands_json(&attrs.allowed_commands)?;
+        let mut tx = self.pool.begin().await?;
+        refuse_trashed(&mut tx, name).await?;
+        sqlx::query(&format!(
             r#"
-            INSERT INTO secrets (id, name, kind, note, ciphertext, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            INSERT INTO secrets ({RECORD_COLUMNS})
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
             ON CONFLICT(name) DO UPDATE SET
                 kind=excluded.kind,
                 note=excluded.note,
+                owner=excluded.owner,
+                contact=excluded.contact,
+                immutable=excluded.immutable,
+                bind_host=excluded.bind_host,
+                allowed_commands=excluded.allowed_commands,
+                expires_at=excluded.expires_at,
+                certificate=excluded.certificate,
+                fields=excluded.fields,
+                provenance=excluded.provenance,
+                key_version=excluded.key_version,
                 ciphertext=excluded.ciphertext,
+                data_key=excluded.data_key,
                 updated_at=excluded.updated_at;
-            "#,
-        )
+            "#
+        ))
         .bind(Uuid::new_v4().to_string())
         .bind(name)
-        .bind(kind)
-        .bind(note)
-        .bind(ciphertext)
+        .bind(&attrs.kind)
+        .bind(&attrs.note)
+        .bind(&attrs.owner)
+        .bind(&attrs.contact)
+        .bind(attrs.immutable)
+        .bind(attrs.bind_host)
+        .bind(allowed_commands)
+        .bind(attrs.expires_at)
+        .bind(certificate_json(attrs.certificate.as_ref())?)
+        .bind(fields_json(&attrs.fields)?)
+        .bind(serde_json::to_string(&attrs.provenance)?)
+        .bind(key_version)
+        .bind(&sealed.ciphertext)
         .bind(now)
         .bind(now)
-        .execute(&self.pool)
+        .bind(&sealed.wrapped_key)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
         info!("upserted secret '{}'", name);
         Ok(())
     }
 
+    pub async fn secret_name_by_id(&self, id: Uuid) -> Result<Option<String>> {
+        let name =
+            sqlx::query_scalar("SELECT name FROM secrets WHERE id = ?1 AND deleted_at IS NULL")
+                .bind(id.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(name)
+    }
+
     pub async fn fetch_secret(&self, name: &str) -> Result<Option<SecretRecord>> {
-        let row = sqlx::query(
-            r#"SELECT id, name, kind, note, ciphertext, created_at, updated_at FROM secrets WHERE name = ?1"#,
-        )
+        let row = sqlx::query(&format!(
+            "SELECT {RECORD_COLUMNS} FROM secrets WHERE name = ?1 AND deleted_at IS NULL"
+        ))
         .bind(name)
         .fetch_optional(&self.pool)
         .await?;
@@ -116,155 +508,1307 @@ impl Repository {
             name,
             if row.is_some() { "hit" } else { "miss" }
         );
-        Ok(row.map(|r| SecretRecord {
-            id: Uuid::parse_str(r.get::<String, _>("id").as_str()).unwrap_or_else(|_| Uuid::nil()),
-            name: r.get("name"),
-            kind: r.get("kind"),
-            note: r.get("note"),
-            ciphertext: r.get("ciphertext"),
-            created_at: r.get("created_at"),
-            updated_at: r.get("updated_at"),
-        }))
+        Ok(row.as_ref().map(SecretRecord::from_row))
     }
 
-    pub async fn list_secrets(&self) -> Result<Vec<SecretRecord>> {
-        let rows = sqlx::query(
-            r#"SELECT id, name, kind, note, ciphertext, created_at, updated_at FROM secrets ORDER BY name"#
-        )
+    /// Live records for any of `names` in one query, in no particular order; missing
+    /// names are simply absent.
+    pub async fn fetch_secrets(&self, names: &[String]) -> Result<Vec<SecretRecord>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {RECORD_COLUMNS} FROM secrets WHERE name IN (SELECT value FROM json_each(?1)) AND deleted_at IS NULL"
+        ))
+        .bind(serde_json::to_string(names)?)
         .fetch_all(&self.pool)
         .await?;
-        debug!("list_secrets returned {} rows", rows.len());
-        Ok(rows
-            .into_iter()
-            .map(|r| SecretRecord {
-                id: Uuid::parse_str(r.get::<String, _>("id").as_str())
-                    .unwrap_or_else(|_| Uuid::nil()),
-                name: r.get("name"),
-                kind: r.get("kind"),
-                note: r.get("note"),
-                ciphertext: r.get("ciphertext"),
-                created_at: r.get("created_at"),
-                updated_at: r.get("updated_at"),
-            })
-            .collect())
+        debug!("fetch {} secrets -> {} hits", names.len(), rows.len());
+        Ok(rows.iter().map(SecretRecord::from_row).collect())
     }
 
-    /// Search name/kind/note with a case-insensitive substring match.
-    pub async fn search_secrets(&self, query: &str) -> Result<Vec<SecretRecord>> {
-        let pattern = format!("%{}%", query.to_lowercase());
+    /// Load `name`; when it was written under another key version, store `upgrade`'s
+    /// envelope under `current_version` in the same transaction and return the new record.
+    pub async fn fetch_secret_upgrading<F>(
+        &self,
+        name: &str,
+        current_version: i64,
+        upgrade: F,
+    ) -> Result<Option<SecretRecord>>
+    where
+        F: FnOnce(&SecretRecord) -> Result<Envelope>,
+    {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(&format!(
+            "SELECT {RECORD_COLUMNS} FROM secrets WHERE name = ?1 AND deleted_at IS NULL"
+        ))
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(mut record) = row.as_ref().map(SecretRecord::from_row) else {
+            return Ok(None);
+        };
+        if record.key_version != current_version {
+            let sealed = upgrade(&record)?;
+            sqlx::query(
+                "UPDATE secrets SET ciphertext = ?1, data_key = ?2, key_version = ?3 WHERE id = ?4",
+            )
+            .bind(&sealed.ciphertext)
+            .bind(&sealed.wrapped_key)
+            .bind(current_version)
+            .bind(record.id.to_string())
+            .execute(&mut *tx)
+            .await?;
+            debug!(
+                "re-encrypted '{}' from key version {} to {}",
+                name, record.key_version, current_version
+            );
+            record.ciphertext = sealed.ciphertext;
+            record.data_key = Some(sealed.wrapped_key);
+            record.key_version = current_version;
+        }
+        tx.commit().await?;
+        Ok(Some(record))
+    }
+
+    /// Number of secrets written under a key version other than `current_version`.
+    pub async fn count_stale(&self, current_version: i64) -> Result<(i64, i64)> {
+        let row = sqlx::query(
+            r#"SELECT COALESCE(SUM(deleted_at IS NULL), 0) AS live,
+                      COALESCE(SUM(deleted_at IS NOT NULL), 0) AS trashed
+               FROM secrets WHERE key_version != ?1"#,
+        )
+        .bind(current_version)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok((row.get("live"), row.get("trashed")))
+    }
+
+    /// Current master key version (1 for vaults that were never rotated lazily).
+    pub async fn current_key_version(&self) -> Result<i64> {
+        let row = sqlx::query("SELECT MAX(version) AS v FROM key_versions")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get::<Option<i64>, _>("v").unwrap_or(1))
+    }
+
+    /// Retired key versions with their wrapped keys.
+    pub async fn retired_keys(&self) -> Result<Vec<(i64, Vec<u8>)>> {
         let rows = sqlx::query(
-            r#"SELECT id, name, kind, note, ciphertext, created_at, updated_at
-               FROM secrets
-               WHERE lower(name) LIKE ?1 OR lower(kind) LIKE ?1 OR lower(note) LIKE ?1
-               ORDER BY name"#,
+            "SELECT version, wrapped_key FROM key_versions WHERE wrapped_key IS NOT NULL ORDER BY version",
         )
-        .bind(pattern)
         .fetch_all(&self.pool)
         .await?;
-        info!("search_secrets '{}' -> {} rows", query, rows.len());
         Ok(rows
-            .into_iter()
-            .map(|r| SecretRecord {
-                id: Uuid::parse_str(r.get::<String, _>("id").as_str())
-                    .unwrap_or_else(|_| Uuid::nil()),
-                name: r.get("name"),
-                kind: r.get("kind"),
-                note: r.get("note"),
-                ciphertext: r.get("ciphertext"),
-                created_at: r.get("created_at"),
-                updated_at: r.get("updated_at"),
-            })
+            .iter()
+            .map(|r| (r.get("version"), r.get("wrapped_key")))
             .collect())
     }
 
-    pub async fn delete_secret(&self, name: &str) -> Result<bool> {
-        let res = sqlx::query("DELETE FROM secrets WHERE name = ?1")
-            .bind(name)
-            .execute(&self.pool)
-            .await?;
-        debug!("delete_secret '{}' -> {}", name, res.rows_affected());
-        Ok(res.rows_affected() > 0)
-    }
-
-    pub async fn reencrypt_all(
-        &self,
-        old_crypto: &SecretCrypto,
-        new_key: &MasterKey,
-    ) -> Result<()> {
+    /// Replace the key chain: `current` becomes the active version, `retired` the wrapped older keys.
+    pub async fn set_key_chain(&self, current: i64, retired: &[(i64, Vec<u8>)]) -> Result<()> {
         let mut tx = self.pool.begin().await?;
-        let rows = sqlx::query(r#"SELECT id, name, ciphertext FROM secrets"#)
-            .fetch_all(&mut *tx)
+        sqlx::query("DELETE FROM key_versions")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO key_versions (version, created_at) VALUES (?1, ?2)")
+            .bind(current)
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await?;
+        for (version, wrapped) in retired {
+            sqlx::query(
+                "INSERT INTO key_versions (version, wrapped_key, created_at) VALUES (?1, ?2, ?3)",
+            )
+            .bind(version)
+            .bind(wrapped)
+            .bind(Utc::now())
+            .execute(&mut *tx)
             .await?;
-        let total = rows.len();
-
-        let new_crypto = SecretCrypto::new(new_key.clone());
-        for row in rows {
-            let name: String = row.get("name");
-            let ct: Vec<u8> = row.get("ciphertext");
-            let id: String = row.get("id");
-            let plaintext = old_crypto.decrypt(&name, &ct)?;
-            let new_ct = new_crypto.encrypt(&name, &plaintext)?;
-            sqlx::query("UPDATE secrets SET ciphertext = ?1, updated_at = ?2 WHERE id = ?3")
-                .bind(new_ct)
-                .bind(Utc::now())
-                .bind(id)
-                .execute(&mut *tx)
-                .await?;
         }
         tx.commit().await?;
-        info!("re-encrypted {} secrets with new master key", total);
         Ok(())
     }
-}
 
-pub fn resolve_db_path(override_path: Option<&PathBuf>) -> Result<PathBuf> {
-    if let Some(p) = override_path {
-        return Ok(p.clone());
+    pub async fn list_secrets(&self) -> Result<Vec<SecretRecord>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {RECORD_COLUMNS} FROM secrets WHERE deleted_at IS NULL ORDER BY name"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        debug!("list_secrets returned {} rows", rows.len());
+        Ok(rows.iter().map(SecretRecord::from_row).collect())
     }
-    // Default location: $XDG_CONFIG_HOME/devinventory/devinventory.db
-    let base = config_dir()
-        .context("cannot find config dir")?
-        .join("devinventory");
-    Ok(base.join(DEFAULT_DB_NAME))
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::crypto::SecretCrypto;
 
-    #[tokio::test]
-    async fn repo_crud_and_rotate() {
-        // use in-memory sqlite to avoid filesystem writes in tests
-        let db_path = PathBuf::from(":memory:");
+    /// Up to `limit` records picked at random, trashed ones included.
+    pub async fn sample_records(&self, limit: i64) -> Result<Vec<SecretRecord>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {RECORD_COLUMNS} FROM secrets ORDER BY random() LIMIT ?1"
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(SecretRecord::from_row).collect())
+    }
 
-        let repo = Repository::connect(&db_path).await.unwrap();
-        repo.migrate().await.unwrap();
+    /// The `limit` secrets with the largest ciphertexts, largest first, with the bytes
+    /// their copies in the operation log take up.
+    pub async fn largest_secrets(&self, limit: i64) -> Result<Vec<(String, i64, i64)>> {
+        let rows = sqlx::query(
+            r#"SELECT s.name, length(s.ciphertext) AS size,
+                      (SELECT COALESCE(SUM(length(o.record)), 0) FROM oplog o
+                       WHERE o.secret_name = s.name) AS history
+               FROM secrets s WHERE s.deleted_at IS NULL ORDER BY size DESC, s.name LIMIT ?1"#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|r| (r.get("name"), r.get("size"), r.get("history")))
+            .collect())
+    }
 
-        let key1 = MasterKey([1u8; 32]);
-        let crypto1 = SecretCrypto::new(key1.clone());
+    /// Live secrets per kind (`None` for secrets without one), most common first.
+    pub async fn kind_counts(&self) -> Result<Vec<(Option<String>, i64)>> {
+        let rows = sqlx::query(
+            r#"SELECT kind, COUNT(*) AS n FROM secrets WHERE deleted_at IS NULL
+               GROUP BY kind ORDER BY n DESC, kind"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(|r| (r.get("kind"), r.get("n"))).collect())
+    }
 
-        // create
-        let ct = crypto1.encrypt("api", b"secret-token").unwrap();
-        repo.upsert_secret("api", Some("token".into()), None, &ct)
-            .await
-            .unwrap();
+    /// Live secrets, trashed secrets and the bytes of all their ciphertexts.
+    pub async fn secret_totals(&self) -> Result<(i64, i64, i64)> {
+        let row = sqlx::query(
+            r#"SELECT COALESCE(SUM(deleted_at IS NULL), 0) AS live,
+                      COALESCE(SUM(deleted_at IS NOT NULL), 0) AS trashed,
+                      COALESCE(SUM(length(ciphertext)), 0) AS bytes
+               FROM secrets"#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok((row.get("live"), row.get("trashed"), row.get("bytes")))
+    }
 
-        // read
-        let rec = repo.fetch_secret("api").await.unwrap().unwrap();
-        let pt = crypto1.decrypt(&rec.name, &rec.ciphertext).unwrap();
-        assert_eq!(pt, b"secret-token");
+    /// The `limit` live secrets changed longest ago, oldest first.
+    pub async fn least_recently_updated(&self, limit: i64) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let rows = sqlx::query(
+            "SELECT name, updated_at FROM secrets WHERE deleted_at IS NULL ORDER BY updated_at, name LIMIT ?1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|r| (r.get("name"), r.get("updated_at")))
+            .collect())
+    }
 
-        // rotate
-        let key2 = MasterKey([2u8; 32]);
-        repo.reencrypt_all(&crypto1, &key2).await.unwrap();
-        let crypto2 = SecretCrypto::new(key2.clone());
-        let rec2 = repo.fetch_secret("api").await.unwrap().unwrap();
-        let pt2 = crypto2.decrypt(&rec2.name, &rec2.ciphertext).unwrap();
-        assert_eq!(pt2, b"secret-token");
+    /// Bytes the database occupies (page count times page size).
+    pub async fn database_size(&self) -> Result<i64> {
+        let pages: i64 = sqlx::query_scalar("PRAGMA page_count")
+            .fetch_one(&self.pool)
+            .await?;
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(pages * page_size)
+    }
 
-        // delete
+    /// Secrets whose name starts with `prefix`. A folder prefix (ending in `/`) is
+    /// answered from the namespace index: the folder itself and everything below it.
+    pub async fn list_secrets_with_prefix(&self, prefix: &str) -> Result<Vec<SecretRecord>> {
+        let (filter, bound) = match prefix.strip_suffix('/') {
+            // '0' sorts right after '/', bounding the range of subfolders
+            Some(folder) => (
+                "namespace = ?1 OR (namespace >= ?1 || '/' AND namespace < ?1 || '0')",
+                folder,
+            ),
+            None => ("substr(name, 1, length(?1)) = ?1", prefix),
+        };
+        let sql = format!(
+            "SELECT {RECORD_COLUMNS} FROM secrets WHERE deleted_at IS NULL AND ({filter}) ORDER BY name"
+        );
+        let query = sqlx::query(&sql).bind(bound);
+        let rows = query.fetch_all(&self.pool).await?;
+        debug!(
+            "list_secrets_with_prefix '{}' -> {} rows",
+            prefix,
+            rows.len()
+        );
+        Ok(rows.iter().map(SecretRecord::from_row).collect())
+    }
+
+    /// Names of live secrets starting with `prefix`, in order, read from
+    /// `idx_secrets_live_name` alone.
+    pub async fn secret_names(&self, prefix: &str, limit: Option<u32>) -> Result<Vec<String>> {
+        // a range on `name` lets SQLite seek the index; U+10FFFF sorts after any
+        // character a name continues with
+        let rows = sqlx::query(NAMES_SQL)
+            .bind(prefix)
+            .bind(format!("{prefix}\u{10FFFF}"))
+            .bind(limit.map_or(-1, i64::from))
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(|row| row.get("name")).collect())
+    }
+
+    /// Search name/kind/note/owner with a case-insensitive substring match.
+    pub async fn search_secrets(&self, query: &str) -> Result<Vec<SecretRecord>> {
+        let pattern = format!("%{}%", query.to_lowercase());
+        let rows = sqlx::query(&format!(
+            r#"SELECT {RECORD_COLUMNS}
+               FROM secrets
+               WHERE deleted_at IS NULL
+                 AND (lower(name) LIKE ?1 OR lower(kind) LIKE ?1 OR lower(note) LIKE ?1 OR lower(owner) LIKE ?1)
+               ORDER BY name"#
+        ))
+        .bind(pattern)
+        .fetch_all(&self.pool)
+        .await?;
+        info!("search_secrets '{}' -> {} rows", query, rows.len());
+        Ok(rows.iter().map(SecretRecord::from_row).collect())
+    }
+
+    /// Move `old` to `new` with `sealed` (sealed for the new name), keeping its id,
+    /// metadata and env mapping.
+    pub async fn rename_secret(
+        &self,
+        old: &str,
+        new: &str,
+        sealed: &Envelope,
+        key_version: i64,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        refuse_trashed(&mut tx, new).await?;
+        let res = sqlx::query(
+            "UPDATE secrets SET name = ?1, ciphertext = ?2, data_key = ?3, key_version = ?4, updated_at = ?5 WHERE name = ?6 AND deleted_at IS NULL",
+        )
+        .bind(new)
+        .bind(&sealed.ciphertext)
+        .bind(&sealed.wrapped_key)
+        .bind(key_version)
+        .bind(Utc::now())
+        .bind(old)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE env_mappings SET secret_name = ?1 WHERE secret_name = ?2")
+            .bind(new)
+            .bind(old)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE leases SET secret_name = ?1 WHERE secret_name = ?2")
+            .bind(new)
+            .bind(old)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        debug!(
+            "rename_secret '{}' -> '{}': {}",
+            old,
+            new,
+            res.rows_affected()
+        );
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Move a live secret to the trash. Its tags stay for `restore_secret`; locks and
+    /// leases go with it.
+    pub async fn delete_secret(&self, name: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM secret_locks WHERE secret_id IN (SELECT id FROM secrets WHERE name = ?1)",
+        )
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+        // an open lease must not hand out a later secret of the same name
+        sqlx::query("DELETE FROM leases WHERE secret_name = ?1")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        let res = sqlx::query(
+            "UPDATE secrets SET deleted_at = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+        )
+        .bind(Utc::now())
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        debug!("delete_secret '{}' -> {}", name, res.rows_affected());
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Secrets in the trash with when they were deleted, most recent first.
+    pub async fn list_trash(&self) -> Result<Vec<(SecretRecord, DateTime<Utc>)>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {RECORD_COLUMNS}, deleted_at FROM secrets WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, name"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|r| (SecretRecord::from_row(r), r.get("deleted_at")))
+            .collect())
+    }
+
+    /// Take `name` out of the trash; `false` when it is not there.
+    pub async fn restore_secret(&self, name: &str) -> Result<bool> {
+        let res = sqlx::query(
+            "UPDATE secrets SET deleted_at = NULL, updated_at = ?1 WHERE name = ?2 AND deleted_at IS NOT NULL",
+        )
+        .bind(Utc::now())
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+        debug!("restore_secret '{}' -> {}", name, res.rows_affected());
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Remove `name` from the trash for good; `false` when it is not there.
+    pub async fn purge_secret(&self, name: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let purged = purge_trashed(&mut tx, name).await?;
+        tx.commit().await?;
+        debug!("purge_secret '{}' -> {}", name, purged);
+        Ok(purged)
+    }
+
+    /// Toggle the write-once flag without touching the ciphertext.
+    pub async fn set_immutable(&self, name: &str, immutable: bool) -> Result<bool> {
+        let res = sqlx::query("UPDATE secrets SET immutable = ?1, updated_at = ?2 WHERE name = ?3 AND deleted_at IS NULL")
+            .bind(immutable)
+            .bind(Utc::now())
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        debug!(
+            "set_immutable '{}' = {} -> {}",
+            name,
+            immutable,
+            res.rows_affected()
+        );
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn meta_value(&self, key: &str) -> Result<Option<String>> {
+        let row = sqlx::query("SELECT value FROM vault_meta WHERE key = ?1")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get("value")))
+    }
+
+    pub async fn set_meta_value(&self, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO vault_meta (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        )
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_meta_value(&self, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM vault_meta WHERE key = ?1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_env_mapping(&self, name: &str, var: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO env_mappings (secret_name, var) VALUES (?1, ?2) ON CONFLICT(secret_name) DO UPDATE SET var = excluded.var",
+        )
+        .bind(name)
+        .bind(var)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_env_mapping(&self, name: &str) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM env_mappings WHERE secret_name = ?1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Secret name to variable, in name order.
+    pub async fn list_env_mappings(&self) -> Result<Vec<(String, String)>> {
+        let rows = sqlx::query("SELECT secret_name, var FROM env_mappings ORDER BY secret_name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|r| (r.get("secret_name"), r.get("var")))
+            .collect())
+    }
+
+    /// Attach `tags` to the secret, creating tags on first use. Returns how many were new
+    /// to it.
+    pub async fn add_tags(&self, secret_id: Uuid, tags: &[String]) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let added = insert_tags(&mut tx, secret_id, tags).await?;
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    /// Apply `patch` to every secret in `ids` inside one transaction.
+    pub async fn update_metadata(&self, ids: &[Uuid], patch: &MetadataPatch) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for id in ids {
+            sqlx::query(
+                r#"UPDATE secrets SET note = COALESCE(?1, note), owner = COALESCE(?2, owner),
+                   contact = COALESCE(?3, contact), expires_at = COALESCE(?4, expires_at),
+                   updated_at = ?5 WHERE id = ?6"#,
+            )
+            .bind(&patch.note)
+            .bind(&patch.owner)
+            .bind(&patch.contact)
+            .bind(patch.expires_at)
+            .bind(Utc::now())
+            .bind(id.to_string())
+            .execute(&mut *tx)
+            .await?;
+            insert_tags(&mut tx, *id, &patch.tags).await?;
+        }
+        tx.commit().await?;
+        debug!("update_metadata on {} secret(s)", ids.len());
+        Ok(())
+    }
+
+    /// Lock the secret; `false` when it already was.
+    pub async fn insert_lock(&self, secret_id: Uuid, lock: &SecretLock) -> Result<bool> {
+        let res = sqlx::query(
+            r#"INSERT OR IGNORE INTO secret_locks (secret_id, locked_by, locked_at, reason)
+               VALUES (?1, ?2, ?3, ?4)"#,
+        )
+        .bind(secret_id.to_string())
+        .bind(&lock.locked_by)
+        .bind(lock.locked_at)
+        .bind(&lock.reason)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn delete_lock(&self, secret_id: Uuid) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM secret_locks WHERE secret_id = ?1")
+            .bind(secret_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn fetch_lock(&self, secret_id: Uuid) -> Result<Option<SecretLock>> {
+        let row = sqlx::query(
+            "SELECT locked_by, locked_at, reason FROM secret_locks WHERE secret_id = ?1",
+        )
+        .bind(secret_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|r| SecretLock {
+            locked_by: r.get("locked_by"),
+            locked_at: r.get("locked_at"),
+            reason: r.get("reason"),
+        }))
+    }
+
+    /// Detach `tags` from the secret and drop tags no secret uses any more.
+    pub async fn remove_tags(&self, secret_id: Uuid, tags: &[String]) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let mut removed = 0;
+        for tag in tags {
+            let res = sqlx::query(
+                "DELETE FROM secret_tags WHERE secret_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+            )
+            .bind(secret_id.to_string())
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+            removed += res.rows_affected() as usize;
+        }
+        sqlx::query("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM secret_tags)")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    /// Tags of every tagged secret, by secret id, each list sorted.
+    pub async fn tags_by_secret(&self) -> Result<BTreeMap<Uuid, Vec<String>>> {
+        let rows = sqlx::query(
+            "SELECT st.secret_id, t.name FROM secret_tags st JOIN tags t ON t.id = st.tag_id ORDER BY t.name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut tags: BTreeMap<Uuid, Vec<String>> = BTreeMap::new();
+        for row in rows {
+            if let Ok(id) = Uuid::parse_str(row.get::<String, _>("secret_id").as_str()) {
+                tags.entry(id).or_default().push(row.get("name"));
+            }
+        }
+        Ok(tags)
+    }
+
+    /// Every tag with the number of secrets carrying it, by name.
+    pub async fn tag_counts(&self) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query(
+            r#"SELECT t.name, COUNT(*) AS uses FROM tags t
+               JOIN secret_tags st ON st.tag_id = t.id
+               JOIN secrets s ON s.id = st.secret_id AND s.deleted_at IS NULL
+               GROUP BY t.name ORDER BY t.name"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|r| (r.get("name"), r.get("uses")))
+            .collect())
+    }
+
+    /// Store an operation; returns `false` when it was already present.
+    pub async fn insert_op(&self, op: &Operation) -> Result<bool> {
+        let res = sqlx::query(
+            r#"INSERT OR IGNORE INTO oplog (op_id, replica, clock, kind, secret_name, record, created_at)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"#,
+        )
+        .bind(op.op_id.to_string())
+        .bind(&op.replica)
+        .bind(serde_json::to_string(&op.clock)?)
+        .bind(op.kind.as_str())
+        .bind(&op.secret_name)
+        .bind(op.record.as_ref().map(serde_json::to_string).transpose()?)
+        .bind(op.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Operations, optionally only those touching `name`, in insertion order.
+    pub async fn list_ops(&self, name: Option<&str>) -> Result<Vec<Operation>> {
+        let rows = sqlx::query(
+            r#"SELECT op_id, replica, clock, kind, secret_name, record, created_at
+               FROM oplog WHERE ?1 IS NULL OR secret_name = ?1 ORDER BY rowid"#,
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(op_from_row).collect()
+    }
+
+    /// Write a record exactly as given (id and timestamps included), replacing any row with its name.
+    pub async fn put_record(&self, record: &SecretRecord) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM secrets WHERE name = ?1")
+            .bind(&record.name)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!(
+            "INSERT INTO secrets ({RECORD_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)"
+        ))
+        .bind(record.id.to_string())
+        .bind(&record.name)
+        .bind(&record.kind)
+        .bind(&record.note)
+        .bind(&record.owner)
+        .bind(&record.contact)
+        .bind(record.immutable)
+        .bind(record.bind_host)
+        .bind(allowed_commands_json(&record.allowed_commands)?)
+        .bind(record.expires_at)
+        .bind(certificate_json(record.certificate.as_ref())?)
+        .bind(fields_json(&record.fields)?)
+        .bind(
+            record
+                .provenance
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        )
+        .bind(record.key_version)
+        .bind(&record.ciphertext)
+        .bind(record.created_at)
+        .bind(record.updated_at)
+        .bind(&record.data_key)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn insert_audit(
+        &self,
+        action: AuditAction,
+        secret_name: Option<&str>,
+        detail: Option<&str>,
+        host: Option<&str>,
+        user: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO audit_log (at, action, secret_name, detail, host, user) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(Utc::now())
+        .bind(action.as_str())
+        .bind(secret_name)
+        .bind(detail)
+        .bind(host)
+        .bind(user)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// How often `action` was recorded for `secret_name` at or after `since`.
+    pub async fn count_audit(
+        &self,
+        action: AuditAction,
+        secret_name: &str,
+        since: DateTime<Utc>,
+    ) -> Result<i64> {
+        let count = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM audit_log WHERE action = ?1 AND secret_name = ?2 AND at >= ?3",
+        )
+        .bind(action.as_str())
+        .bind(secret_name)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// Audit entries recorded at or after `since`, oldest first.
+    pub async fn list_audit(&self, since: DateTime<Utc>) -> Result<Vec<AuditEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {AUDIT_COLUMNS} FROM audit_log WHERE at >= ?1 ORDER BY id"
+        ))
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(audit_from_row).collect())
+    }
+
+    /// Audit entries matching `filter`, newest first.
+    pub async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let rows = sqlx::query(&format!(
+            r#"SELECT {AUDIT_COLUMNS} FROM audit_log
+               WHERE (?1 IS NULL OR secret_name = ?1) AND (?2 IS NULL OR action = ?2)
+                 AND (?3 IS NULL OR host = ?3) AND (?4 IS NULL OR at >= ?4)
+               ORDER BY id DESC LIMIT ?5"#
+        ))
+        .bind(&filter.secret_name)
+        .bind(filter.action.map(|a| a.as_str()))
+        .bind(&filter.host)
+        .bind(filter.since)
+        .bind(filter.limit.unwrap_or(-1))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(audit_from_row).collect())
+    }
+
+    /// Audit entries with an id above `after`, oldest first.
+    pub async fn list_audit_after(&self, after: i64) -> Result<Vec<AuditEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {AUDIT_COLUMNS} FROM audit_log WHERE id > ?1 ORDER BY id"
+        ))
+        .bind(after)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(audit_from_row).collect())
+    }
+
+    pub async fn insert_approval(&self, request: &ApprovalRequest) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO approvals (id, action, target, requested_by, created_at)
+               VALUES (?1, ?2, ?3, ?4, ?5)"#,
+        )
+        .bind(request.id.to_string())
+        .bind(&request.action)
+        .bind(&request.target)
+        .bind(&request.requested_by)
+        .bind(request.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Requests not yet consumed, optionally only those for `action` on `target`, oldest first.
+    pub async fn open_approvals(
+        &self,
+        action_target: Option<(&str, &str)>,
+    ) -> Result<Vec<ApprovalRequest>> {
+        let (action, target) = action_target.unzip();
+        let rows = sqlx::query(
+            r#"SELECT id, action, target, requested_by, created_at, approved_by, approved_at
+               FROM approvals
+               WHERE consumed_at IS NULL AND (?1 IS NULL OR (action = ?1 AND target = ?2))
+               ORDER BY created_at"#,
+        )
+        .bind(action)
+        .bind(target)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(approval_from_row).collect()
+    }
+
+    pub async fn fetch_approval(&self, id: Uuid) -> Result<Option<ApprovalRequest>> {
+        let row = sqlx::query(
+            r#"SELECT id, action, target, requested_by, created_at, approved_by, approved_at
+               FROM approvals WHERE id = ?1 AND consumed_at IS NULL"#,
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(approval_from_row).transpose()
+    }
+
+    pub async fn set_approved(&self, id: Uuid, approved_by: &str) -> Result<()> {
+        sqlx::query("UPDATE approvals SET approved_by = ?1, approved_at = ?2 WHERE id = ?3")
+            .bind(approved_by)
+            .bind(Utc::now())
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Mark an approved request as used so it cannot authorize a second run.
+    pub async fn consume_approval(&self, id: Uuid) -> Result<()> {
+        sqlx::query("UPDATE approvals SET consumed_at = ?1 WHERE id = ?2")
+            .bind(Utc::now())
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn insert_lease(&self, lease: &Lease, token_hash: &str) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO leases (id, token_hash, secret_name, created_by, created_at, expires_at)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6)"#,
+        )
+        .bind(lease.id.to_string())
+        .bind(token_hash)
+        .bind(&lease.secret_name)
+        .bind(&lease.created_by)
+        .bind(lease.created_at)
+        .bind(lease.expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Leases neither redeemed nor expired, soonest expiry first.
+    pub async fn open_leases(&self) -> Result<Vec<Lease>> {
+        let rows = sqlx::query(
+            r#"SELECT id, secret_name, created_by, created_at, expires_at, redeemed_at
+               FROM leases WHERE redeemed_at IS NULL AND expires_at > ?1
+               ORDER BY expires_at"#,
+        )
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(lease_from_row).collect()
+    }
+
+    /// Mark the open lease with `token_hash` redeemed and return it; a single statement,
+    /// so two concurrent redeemers cannot both win.
+    pub async fn redeem_lease(&self, token_hash: &str) -> Result<Option<Lease>> {
+        let now = Utc::now();
+        let row = sqlx::query(
+            r#"UPDATE leases SET redeemed_at = ?1
+               WHERE token_hash = ?2 AND redeemed_at IS NULL AND expires_at > ?1
+               RETURNING id, secret_name, created_by, created_at, expires_at, redeemed_at"#,
+        )
+        .bind(now)
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(lease_from_row).transpose()
+    }
+
+    /// Drop a lease; returns the secret it was for.
+    pub async fn delete_lease(&self, id: Uuid) -> Result<Option<String>> {
+        let row = sqlx::query("DELETE FROM leases WHERE id = ?1 RETURNING secret_name")
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get("secret_name")))
+    }
+
+    pub async fn insert_device(&self, device: &Device, wrapped_key: &[u8]) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO devices (id, label, public_key, wrapped_key, created_at)
+               VALUES (?1, ?2, ?3, ?4, ?5)"#,
+        )
+        .bind(device.id.to_string())
+        .bind(&device.label)
+        .bind(device.public_key.as_slice())
+        .bind(wrapped_key)
+        .bind(device.created_at)
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("enrolling device '{}'", device.label))?;
+        info!("enrolled device '{}'", device.label);
+        Ok(())
+    }
+
+    pub async fn list_devices(&self) -> Result<Vec<Device>> {
+        let rows = sqlx::query(
+            "SELECT id, label, public_key, created_at, revoked_at FROM devices ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(device_from_row).collect()
+    }
+
+    /// Wrapped master key of an active (non-revoked) device.
+    pub async fn fetch_device_wrap(&self, id: Uuid) -> Result<Option<Vec<u8>>> {
+        let row =
+            sqlx::query("SELECT wrapped_key FROM devices WHERE id = ?1 AND revoked_at IS NULL")
+                .bind(id.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.and_then(|r| r.get("wrapped_key")))
+    }
+
+    pub async fn update_device_wrap(&self, id: Uuid, wrapped_key: &[u8]) -> Result<()> {
+        sqlx::query("UPDATE devices SET wrapped_key = ?1 WHERE id = ?2")
+            .bind(wrapped_key)
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Revoke a device by id or label, discarding its wrapped key.
+    pub async fn revoke_device(&self, id_or_label: &str) -> Result<bool> {
+        let res = sqlx::query(
+            r#"UPDATE devices SET wrapped_key = NULL, revoked_at = ?1
+               WHERE (id = ?2 OR label = ?2) AND revoked_at IS NULL"#,
+        )
+        .bind(Utc::now())
+        .bind(id_or_label)
+        .execute(&self.pool)
+        .await?;
+        debug!("revoke_device '{}' -> {}", id_or_label, res.rows_affected());
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// The lowest free slot number.
+    pub async fn next_key_slot(&self) -> Result<i64> {
+        let rows = sqlx::query("SELECT id FROM key_slots ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+        let mut free = 0;
+        for row in rows {
+            if row.get::<i64, _>("id") != free {
+                break;
+            }
+            free += 1;
+        }
+        Ok(free)
+    }
+
+    pub async fn insert_key_slot(&self, slot: &KeySlot, wrapped_key: &[u8]) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO key_slots (id, kind, label, params, public_key, wrapped_key, created_at)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"#,
+        )
+        .bind(slot.id)
+        .bind(slot.kind.as_str())
+        .bind(&slot.label)
+        .bind(&slot.params)
+        .bind(slot.public_key.as_slice())
+        .bind(wrapped_key)
+        .bind(slot.created_at)
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("adding key slot {}", slot.id))?;
+        Ok(())
+    }
+
+    pub async fn list_key_slots(&self) -> Result<Vec<KeySlot>> {
+        let rows = sqlx::query(
+            "SELECT id, kind, label, params, public_key, created_at FROM key_slots ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(key_slot_from_row).collect()
+    }
+
+    /// Master key wrapped for one slot.
+    pub async fn fetch_slot_wrap(&self, id: i64) -> Result<Option<Vec<u8>>> {
+        let row = sqlx::query("SELECT wrapped_key FROM key_slots WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get("wrapped_key")))
+    }
+
+    pub async fn update_slot_wrap(&self, id: i64, wrapped_key: &[u8]) -> Result<()> {
+        sqlx::query("UPDATE key_slots SET wrapped_key = ?1 WHERE id = ?2")
+            .bind(wrapped_key)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_key_slot(&self, id: i64) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM key_slots WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Replace every record's envelope inside one transaction; `reencrypt` maps a record
+    /// to its envelope under `new_version`, which becomes the only key version.
+    pub async fn reencrypt_all<F>(&self, new_version: i64, mut reencrypt: F) -> Result<()>
+    where
+        F: FnMut(&SecretRecord) -> Result<Envelope>,
+    {
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(&format!("SELECT {RECORD_COLUMNS} FROM secrets"))
+            .fetch_all(&mut *tx)
+            .await?;
+        let total = rows.len();
+
+        for row in rows {
+            let record = SecretRecord::from_row(&row);
+            let sealed = reencrypt(&record)?;
+            sqlx::query(
+                "UPDATE secrets SET ciphertext = ?1, data_key = ?2, key_version = ?3, updated_at = ?4 WHERE id = ?5",
+            )
+            .bind(sealed.ciphertext)
+            .bind(sealed.wrapped_key)
+            .bind(new_version)
+            .bind(Utc::now())
+            .bind(record.id.to_string())
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // logged records carry ciphertext too; keep them decryptable for merges
+        let logged = sqlx::query("SELECT op_id, record FROM oplog WHERE record IS NOT NULL")
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in logged {
+            let mut record: SecretRecord = serde_json::from_str(row.get("record"))?;
+            let sealed = reencrypt(&record)?;
+            record.ciphertext = sealed.ciphertext;
+            record.data_key = Some(sealed.wrapped_key);
+            record.key_version = new_version;
+            sqlx::query("UPDATE oplog SET record = ?1 WHERE op_id = ?2")
+                .bind(serde_json::to_string(&record)?)
+                .bind(row.get::<String, _>("op_id"))
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM key_versions")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO key_versions (version, created_at) VALUES (?1, ?2)")
+            .bind(new_version)
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        info!("re-encrypted {} secrets with new master key", total);
+        Ok(())
+    }
+}
+
+fn first_key_version() -> i64 {
+    1
+}
+
+/// Stored as a JSON array; NULL when unrestricted.
+fn allowed_commands_json(allowed: &[String]) -> Result<Option<String>> {
+    if allowed.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(allowed)?))
+}
+
+fn certificate_json(info: Option<&CertificateInfo>) -> Result<Option<String>> {
+    Ok(info.map(serde_json::to_string).transpose()?)
+}
+
+fn fields_json(fields: &BTreeMap<String, String>) -> Result<Option<String>> {
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(fields)?))
+}
+
+fn op_from_row(r: &SqliteRow) -> Result<Operation> {
+    let kind = match r.get::<String, _>("kind").as_str() {
+        "put" => OpKind::Put,
+        "delete" => OpKind::Delete,
+        other => return Err(anyhow::anyhow!("unknown operation kind '{other}'")),
+    };
+    let clock: VectorClock = serde_json::from_str(r.get("clock"))?;
+    let record = r
+        .get::<Option<String>, _>("record")
+        .map(|json| serde_json::from_str(&json))
+        .transpose()?;
+    Ok(Operation {
+        op_id: Uuid::parse_str(r.get::<String, _>("op_id").as_str())?,
+        replica: r.get("replica"),
+        clock,
+        kind,
+        secret_name: r.get("secret_name"),
+        record,
+        created_at: r.get("created_at"),
+    })
+}
+
+fn approval_from_row(r: &SqliteRow) -> Result<ApprovalRequest> {
+    Ok(ApprovalRequest {
+        id: Uuid::parse_str(r.get::<String, _>("id").as_str())?,
+        action: r.get("action"),
+        target: r.get("target"),
+        requested_by: r.get("requested_by"),
+        created_at: r.get("created_at"),
+        approved_by: r.get("approved_by"),
+        approved_at: r.get("approved_at"),
+    })
+}
+
+/// `add_tags` inside a caller's transaction.
+async fn insert_tags(
+    tx: &mut Transaction<'_, Sqlite>,
+    secret_id: Uuid,
+    tags: &[String],
+) -> Result<usize> {
+    let mut added = 0;
+    for tag in tags {
+        sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?1)")
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+        let res = sqlx::query(
+            "INSERT OR IGNORE INTO secret_tags (secret_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+        )
+        .bind(secret_id.to_string())
+        .bind(tag)
+        .execute(&mut **tx)
+        .await?;
+        added += res.rows_affected() as usize;
+    }
+    Ok(added)
+}
+
+/// Fail when the trash holds a secret named `name`: writing the name anew would
+/// silently destroy it.
+async fn refuse_trashed(tx: &mut Transaction<'_, Sqlite>, name: &str) -> Result<()> {
+    let trashed: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM secrets WHERE name = ?1 AND deleted_at IS NOT NULL)",
+    )
+    .bind(name)
+    .fetch_one(&mut **tx)
+    .await?;
+    if trashed {
+        bail!("'{name}' is in the trash; restore or purge it first");
+    }
+    Ok(())
+}
+
+/// Delete the trashed row named `name` with its tags and locks, so the name can be
+/// reused; `false` when the trash holds no such secret.
+async fn purge_trashed(tx: &mut Transaction<'_, Sqlite>, name: &str) -> Result<bool> {
+    let trashed = "SELECT id FROM secrets WHERE name = ?1 AND deleted_at IS NOT NULL";
+    for table in ["secret_tags", "secret_locks"] {
+        sqlx::query(&format!(
+            "DELETE FROM {table} WHERE secret_id IN ({trashed})"
+        ))
+        .bind(name)
+        .execute(&mut **tx)
+        .await?;
+    }
+    sqlx::query("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM secret_tags)")
+        .execute(&mut **tx)
+        .await?;
+    let res = sqlx::query("DELETE FROM secrets WHERE name = ?1 AND deleted_at IS NOT NULL")
+        .bind(name)
+        .execute(&mut **tx)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+fn lease_from_row(r: &SqliteRow) -> Result<Lease> {
+    Ok(Lease {
+        id: Uuid::parse_str(r.get::<String, _>("id").as_str())?,
+        secret_name: r.get("secret_name"),
+        created_by: r.get("created_by"),
+        created_at: r.get("created_at"),
+        expires_at: r.get("expires_at"),
+        redeemed_at: r.get("redeemed_at"),
+    })
+}
+
+fn audit_from_row(r: &SqliteRow) -> AuditEntry {
+    AuditEntry {
+        id: r.get("id"),
+        at: r.get("at"),
+        action: r.get("action"),
+        secret_name: r.get("secret_name"),
+        detail: r.get("detail"),
+        host: r.get("host"),
+        user: r.get("user"),
+    }
+}
+
+fn device_from_row(r: &SqliteRow) -> Result<Device> {
+    let public_key: Vec<u8> = r.get("public_key");
+    Ok(Device {
+        id: Uuid::parse_str(r.get::<String, _>("id").as_str())?,
+        label: r.get("label"),
+        public_key: public_key
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("device public key must be 32 bytes"))?,
+        created_at: r.get("created_at"),
+        revoked_at: r.get("revoked_at"),
+    })
+}
+
+fn key_slot_from_row(r: &SqliteRow) -> Result<KeySlot> {
+    let kind: String = r.get("kind");
+    let public_key: Vec<u8> = r.get("public_key");
+    Ok(KeySlot {
+        id: r.get("id"),
+        kind: SlotKind::parse(&kind)
+            .ok_or_else(|| anyhow::anyhow!("unknown key slot kind '{kind}'"))?,
+        label: r.get("label"),
+        params: r.get("params"),
+        public_key: public_key
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("key slot public key must be 32 bytes"))?,
+        created_at: r.get("created_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{MasterKey, SecretCrypto};
+
+    #[tokio::test]
+    async fn repo_crud_and_rotate() {
+        // use in-memory sqlite to avoid filesystem writes in tests
+        let repo = Repository::in_memory().await.unwrap();
+        repo.migrate().await.unwrap();
+
+        let key1 = MasterKey([1u8; 32]);
+        let crypto1 = SecretCrypto::new(key1.clone());
+
+        // create
+        let sealed = crypto1.seal("api", b"secret-token").unwrap();
+        let attrs = SecretAttributes {
+            kind: Some("token".into()),
+            ..Default::default()
+        };
+        repo.upsert_secret("api", &attrs, &sealed, 1).await.unwrap();
+
+        // read
+        let rec = repo.fetch_secret("api").await.unwrap().unwrap();
+        let pt = crypto1
+            .open(&rec.name, rec.data_key.as_deref(), &rec.ciphertext)
+            .unwrap();
+        assert_eq!(pt, b"secret-token");
+
+        // rotate
+        let key2 = MasterKey([2u8; 32]);
+        let crypto2 = SecretCrypto::new(key2.clone());
+        repo.reencrypt_all(2, |r| {
+            Ok(Envelope {
+                wrapped_key: crypto1.rewrap(&crypto2, &r.name, r.data_key.as_deref().unwrap())?,
+                ciphertext: r.ciphertext.clone(),
+            })
+        })
+        .await
+        .unwrap();
+        let rec2 = repo.fetch_secret("api").await.unwrap().unwrap();
+        assert_eq!(rec2.ciphertext, rec.ciphertext);
+        let pt2 = crypto2
+            .open(&rec2.name, rec2.data_key.as_deref(), &rec2.ciphertext)
+            .unwrap();
+        assert_eq!(pt2, b"secret-token");
+        assert_eq!(rec2.key_version, 2);
+        assert_eq!(repo.current_key_version().await.unwrap(), 2);
+
+        // delete
         assert!(repo.delete_secret("api").await.unwrap());
         assert!(repo.fetch_secret("api").await.unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn prefix_listing_uses_namespaces_for_folders() {
+        let repo = Repository::in_memory().await.unwrap();
+        repo.migrate().await.unwrap();
+        // migrating twice must not re-add the generated column
+        repo.migrate().await.unwrap();
+        let names = [
+            "prod",
+            "prod/api",
+            "prod/db/password",
+            "prod-old/key",
+            "prodx/db/a",
+        ];
+        let sealed = Envelope {
+            wrapped_key: b"key".to_vec(),
+            ciphertext: b"ct".to_vec(),
+        };
+        for name in names {
+            repo.upsert_secret(name, &SecretAttributes::default(), &sealed, 1)
+                .await
+                .unwrap();
+        }
+        let listed = |records: Vec<SecretRecord>| -> Vec<String> {
+            records.into_iter().map(|r| r.name).collect()
+        };
+        assert_eq!(
+            listed(repo.list_secrets_with_prefix("prod/").await.unwrap()),
+            ["prod/api", "prod/db/password"]
+        );
+        assert_eq!(
+            listed(repo.list_secrets_with_prefix("prod-").await.unwrap()),
+            ["prod-old/key"]
+        );
+        assert_eq!(
+            repo.secret_names("prod/", Some(1)).await.unwrap(),
+            ["prod/api"]
+        );
+        assert_eq!(repo.secret_names("prod", None).await.unwrap().len(), 5);
+        repo.delete_secret("prod/api").await.unwrap();
+        assert_eq!(
+            repo.secret_names("prod/", None).await.unwrap(),
+            ["prod/db/password"]
+        );
+        let plan: Vec<String> = sqlx::query(&format!("EXPLAIN QUERY PLAN {NAMES_SQL}"))
+            .bind("prod/")
+            .bind("prod/\u{10FFFF}")
+            .bind(-1)
+            .fetch_all(&repo.pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("detail"))
+            .collect();
+        assert_eq!(
+            plan,
+            ["SEARCH secrets USING COVERING INDEX idx_secrets_live_name (name>? AND name<?)"]
+        );
+        let row = sqlx::query("SELECT namespace FROM secrets WHERE name = 'prod/db/password'")
+            .fetch_one(&repo.pool)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("namespace"), "prod/db");
+    }
 }
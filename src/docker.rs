@@ -0,0 +1,50 @@
+//! Docker credential helper protocol (`docker-credential-<name> get|store|erase|list`).
+
+use serde::{Deserialize, Serialize};
+
+/// Executable name docker looks up for `"credsStore": "devinventory"`.
+pub const HELPER_BINARY: &str = "docker-credential-devinventory";
+
+/// Kind given to secrets stored through the helper.
+pub const CREDENTIAL_KIND: &str = "docker-credential";
+
+/// Message docker recognises as "no credentials" on `get`.
+pub const NOT_FOUND: &str = "credentials not found in native keychain";
+
+const NAME_PREFIX: &str = "docker/";
+
+/// Payload of `store` and reply of `get`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Credentials {
+    #[serde(rename = "ServerURL")]
+    pub server_url: String,
+    #[serde(rename = "Username")]
+    pub username: String,
+    #[serde(rename = "Secret")]
+    pub secret: String,
+}
+
+/// Vault name holding the credentials of `server_url`.
+pub fn secret_name(server_url: &str) -> String {
+    format!("{NAME_PREFIX}{}", server_url.trim())
+}
+
+/// Server URL of a helper-managed secret name.
+pub fn server_url(secret_name: &str) -> Option<&str> {
+    secret_name.strip_prefix(NAME_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_payload_roundtrips_and_names_map_back() {
+        let json = r#"{"ServerURL":"https://index.docker.io/v1/","Username":"me","Secret":"pw"}"#;
+        let creds: Credentials = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&creds).unwrap(), json);
+        let name = secret_name(&creds.server_url);
+        assert_eq!(name, "docker/https://index.docker.io/v1/");
+        assert_eq!(server_url(&name), Some("https://index.docker.io/v1/"));
+    }
+}
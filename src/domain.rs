@@ -1,4 +1,6 @@
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
 // Data after decryption
@@ -8,22 +10,319 @@ pub struct Secret {
     pub name: String,
     pub kind: Option<String>,
     pub note: Option<String>,
+    pub owner: Option<String>,
+    pub contact: Option<String>,
+    pub immutable: bool,
+    pub bind_host: bool,
+    pub allowed_commands: Vec<String>,
     pub plaintext: Vec<u8>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub certificate: Option<CertificateInfo>,
+    pub fields: BTreeMap<String, String>,
+    /// How the current value entered the vault; `None` for values stored before it was kept
+    pub provenance: Option<Provenance>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
 // Metadata without secretion
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SecretMetadata {
     pub id: Uuid,
     pub name: String,
     pub kind: Option<String>,
     pub note: Option<String>,
+    pub owner: Option<String>,
+    pub contact: Option<String>,
+    pub immutable: bool,
+    pub bind_host: bool,
+    pub allowed_commands: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub certificate: Option<CertificateInfo>,
+    pub fields: BTreeMap<String, String>,
+    /// How the current value entered the vault; `None` for values stored before it was kept
+    pub provenance: Option<Provenance>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Attributes supplied by the user when adding or replacing a secret
+#[derive(Debug, Clone, Default)]
+pub struct SecretAttributes {
+    pub kind: Option<String>,
+    pub note: Option<String>,
+    /// Team or person responsible for rotating the secret
+    pub owner: Option<String>,
+    /// How to reach the owner (email, chat handle, ...)
+    pub contact: Option<String>,
+    /// Write-once: reject later overwrites and deletes
+    pub immutable: bool,
+    /// Only decryptable on the machine that stored it
+    pub bind_host: bool,
+    /// Executables allowed to receive the value via `exec`; empty means any
+    pub allowed_commands: Vec<String>,
+    /// When the value stops being valid (filled from the PEM for certificates)
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Parsed details of `kind=certificate` values
+    pub certificate: Option<CertificateInfo>,
+    /// Free-form extra fields, e.g. attributes kept from an import
+    pub fields: BTreeMap<String, String>,
+    /// Where the value comes from (manual entry unless set)
+    pub provenance: Provenance,
+}
+
+/// Path by which a value entered the vault
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// Typed, piped or edited by hand
+    #[default]
+    Manual,
+    /// Created by `generate`
+    Generated,
+    /// Read from another store or an export file
+    Import,
+    /// Merged from another replica
+    Sync,
+    /// Replaced by a rotation provider or hook
+    Rotation,
+    /// Copied from another environment by `promote`
+    Promotion,
+    /// Copied from another vault by `transfer`
+    Transfer,
+}
+
+impl Origin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Origin::Manual => "manual",
+            Origin::Generated => "generated",
+            Origin::Import => "import",
+            Origin::Sync => "sync",
+            Origin::Rotation => "rotation",
+            Origin::Promotion => "promotion",
+            Origin::Transfer => "transfer",
+        }
+    }
+}
+
+/// Origin of a value plus the file, replica, hook or secret it came from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub origin: Origin,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl Provenance {
+    pub fn new(origin: Origin, source: impl Into<String>) -> Self {
+        Self {
+            origin,
+            source: Some(source.into()),
+        }
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{} ({source})", self.origin.as_str()),
+            None => f.write_str(self.origin.as_str()),
+        }
+    }
+}
+
+/// Metadata `set` applies to many secrets at once; unset fields are left alone
+#[derive(Debug, Clone, Default)]
+pub struct MetadataPatch {
+    pub note: Option<String>,
+    pub owner: Option<String>,
+    pub contact: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Tags to attach, on top of those already there
+    pub tags: Vec<String>,
+}
+
+impl MetadataPatch {
+    /// Names of the fields this patch changes, e.g. `note, tags`.
+    pub fn changed_fields(&self) -> Vec<&'static str> {
+        [
+            ("note", self.note.is_some()),
+            ("owner", self.owner.is_some()),
+            ("contact", self.contact.is_some()),
+            ("expires", self.expires_at.is_some()),
+            ("tags", !self.tags.is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(field, set)| set.then_some(field))
+        .collect()
+    }
+}
+
+/// Details extracted from a stored X.509 certificate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    /// Subject alternative names, prefixed by type (`DNS:`, `IP:`, ...)
+    pub sans: Vec<String>,
+    pub not_after: DateTime<Utc>,
+}
+
+/// A machine enrolled to unlock the vault with its own keypair
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub id: Uuid,
+    pub label: String,
+    pub public_key: [u8; 32],
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// What unlocks a key slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SlotKind {
+    /// A passphrase, stretched with Argon2id
+    Passphrase,
+    /// A random key printed once, to write down and keep offline
+    Recovery,
+    /// A random key kept in the OS keyring
+    Keyring,
+}
+
+impl SlotKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SlotKind::Passphrase => "passphrase",
+            SlotKind::Recovery => "recovery",
+            SlotKind::Keyring => "keyring",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        [SlotKind::Passphrase, SlotKind::Recovery, SlotKind::Keyring]
+            .into_iter()
+            .find(|kind| kind.as_str() == text)
+    }
+}
+
+/// A second way to unlock the vault: the master key wrapped to a keypair derived
+/// from the slot's own unlock key, so rotation can rewrap it without that key
+#[derive(Debug, Clone)]
+pub struct KeySlot {
+    pub id: i64,
+    pub kind: SlotKind,
+    pub label: String,
+    /// Argon2id settings of a passphrase slot, as JSON
+    pub params: Option<String>,
+    pub public_key: [u8; 32],
+    pub created_at: DateTime<Utc>,
+}
+
+/// A destructive operation on a protected secret waiting for a second approver
+#[derive(Debug, Clone)]
+pub struct ApprovalRequest {
+    pub id: Uuid,
+    /// `delete` or `export`
+    pub action: String,
+    /// Secret name (delete) or name prefix (export)
+    pub target: String,
+    pub requested_by: String,
+    pub created_at: DateTime<Utc>,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<DateTime<Utc>>,
+}
+
+/// A soft lock holding a secret's value and name in place until it is unlocked
+#[derive(Debug, Clone)]
+pub struct SecretLock {
+    pub locked_by: String,
+    pub locked_at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+/// A one-time grant to read one secret's value, redeemable once before it expires.
+/// Only a hash of its token is stored.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub id: Uuid,
+    pub secret_name: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub redeemed_at: Option<DateTime<Utc>>,
+}
+
+/// Kind of activity recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum AuditAction {
+    Add,
+    Update,
+    Read,
+    Delete,
+    Rotate,
+    Import,
+    Export,
+    Device,
+    Approve,
+    Reveal,
+    Lease,
+    KeySlot,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Add => "add",
+            AuditAction::Update => "update",
+            AuditAction::Read => "read",
+            AuditAction::Delete => "delete",
+            AuditAction::Rotate => "rotate",
+            AuditAction::Import => "import",
+            AuditAction::Export => "export",
+            AuditAction::Device => "device",
+            AuditAction::Approve => "approve",
+            AuditAction::Reveal => "reveal",
+            AuditAction::Lease => "lease",
+            AuditAction::KeySlot => "key-slot",
+        }
+    }
+}
+
+/// One row of the local audit log
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub at: DateTime<Utc>,
+    /// `AuditAction::as_str` of the recorded action
+    pub action: String,
+    pub secret_name: Option<String>,
+    pub detail: Option<String>,
+    /// Machine the CLI ran on; `None` for entries older than the column
+    pub host: Option<String>,
+    /// Login name of whoever ran it
+    pub user: Option<String>,
+}
+
+/// Outcome of verifying a sample of records, kept for `doctor`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub checked_at: DateTime<Utc>,
+    pub checked: usize,
+    /// `name: error` for every record that failed to authenticate or decrypt
+    pub failures: Vec<String>,
+}
+
+/// Which audit entries `audit list` shows; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub secret_name: Option<String>,
+    pub action: Option<AuditAction>,
+    pub host: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Newest entries kept when more match
+    pub limit: Option<i64>,
+}
+
 impl Secret {
     /// Convert to metadata
     pub fn to_metadata(&self) -> SecretMetadata {
@@ -32,8 +331,150 @@ impl Secret {
             name: self.name.clone(),
             kind: self.kind.clone(),
             note: self.note.clone(),
+            owner: self.owner.clone(),
+            contact: self.contact.clone(),
+            immutable: self.immutable,
+            bind_host: self.bind_host,
+            allowed_commands: self.allowed_commands.clone(),
+            expires_at: self.expires_at,
+            certificate: self.certificate.clone(),
+            fields: self.fields.clone(),
+            provenance: self.provenance.clone(),
             created_at: self.created_at,
             updated_at: self.updated_at,
         }
     }
+
+    /// Attributes to store a new value under, keeping everything but the value
+    pub fn to_attributes(&self) -> SecretAttributes {
+        SecretAttributes {
+            kind: self.kind.clone(),
+            note: self.note.clone(),
+            owner: self.owner.clone(),
+            contact: self.contact.clone(),
+            immutable: self.immutable,
+            bind_host: self.bind_host,
+            allowed_commands: self.allowed_commands.clone(),
+            expires_at: self.expires_at,
+            certificate: self.certificate.clone(),
+            fields: self.fields.clone(),
+            // the new value is a manual one unless the caller says otherwise
+            provenance: Provenance::default(),
+        }
+    }
+}
+
+/// Derive an environment variable name from a secret name (`db/prod-pass` -> `DB_PROD_PASS`).
+pub fn env_var_name(name: &str) -> String {
+    let mut var: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if var.is_empty() || var.starts_with(|c: char| c.is_ascii_digit()) {
+        var.insert(0, '_');
+    }
+    var
+}
+
+/// Whether `var` is usable as an environment variable name in a shell.
+pub fn is_env_var_name(var: &str) -> bool {
+    var.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Tags are single words: no whitespace or commas, so lists of them stay unambiguous.
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && !tag.contains(|c: char| c.is_whitespace() || c == ',')
+}
+
+/// A secret addressed by name, or by the id it keeps across renames and syncs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretRef {
+    Name(String),
+    Id(Uuid),
+}
+
+impl std::fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretRef::Name(name) => f.write_str(name),
+            SecretRef::Id(id) => write!(f, "id {id}"),
+        }
+    }
+}
+
+impl From<&str> for SecretRef {
+    fn from(name: &str) -> Self {
+        SecretRef::Name(name.to_string())
+    }
+}
+
+impl From<&String> for SecretRef {
+    fn from(name: &String) -> Self {
+        SecretRef::Name(name.clone())
+    }
+}
+
+impl From<String> for SecretRef {
+    fn from(name: String) -> Self {
+        SecretRef::Name(name)
+    }
+}
+
+impl From<Uuid> for SecretRef {
+    fn from(id: Uuid) -> Self {
+        SecretRef::Id(id)
+    }
+}
+
+impl From<&SecretRef> for SecretRef {
+    fn from(target: &SecretRef) -> Self {
+        target.clone()
+    }
+}
+
+/// Shell-style match of a secret name against `pattern` (`*` any run, `?` one character).
+pub fn name_matches(pattern: &str, name: &str) -> bool {
+    fn matches(p: &[char], n: &[char]) -> bool {
+        match (p.first(), n.first()) {
+            (None, None) => true,
+            (Some('*'), _) => matches(&p[1..], n) || (!n.is_empty() && matches(p, &n[1..])),
+            (Some('?'), Some(_)) => matches(&p[1..], &n[1..]),
+            (Some(pc), Some(nc)) if pc == nc => matches(&p[1..], &n[1..]),
+            _ => false,
+        }
+    }
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    matches(&p, &n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_name_globs() {
+        assert!(name_matches("app/*", "app/db-pass"));
+        assert!(name_matches("*/token", "gh/token"));
+        assert!(name_matches("db?", "db1"));
+        assert!(!name_matches("app/*", "web/app/key"));
+        assert!(name_matches("exact", "exact"));
+    }
+
+    #[test]
+    fn derives_env_var_names() {
+        assert_eq!(env_var_name("db/prod-pass"), "DB_PROD_PASS");
+        assert_eq!(env_var_name("gh.token"), "GH_TOKEN");
+        assert_eq!(env_var_name("1password"), "_1PASSWORD");
+        assert!(is_env_var_name("DATABASE_PASSWORD"));
+        assert!(is_env_var_name(&env_var_name("1password")));
+        assert!(!is_env_var_name("9LIVES") && !is_env_var_name("A-B") && !is_env_var_name(""));
+    }
 }
@@ -0,0 +1,117 @@
+//! Comparison of deployed credentials (a rendered .env, a Kubernetes Secret) with
+//! the vault, which is the source of truth.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftStatus {
+    /// Deployed value equals the vault's
+    Current,
+    /// Deployed value differs from the vault's
+    Stale,
+    /// Deployed key no vault secret exports as
+    Untracked,
+    /// Vault secret the target does not carry
+    Missing,
+}
+
+impl DriftStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DriftStatus::Current => "current",
+            DriftStatus::Stale => "stale",
+            DriftStatus::Untracked => "untracked",
+            DriftStatus::Missing => "missing",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriftItem {
+    pub key: String,
+    /// Vault secret exported under `key`, if any
+    pub secret: Option<String>,
+    pub status: DriftStatus,
+}
+
+/// Match `deployed` values by key against `vault`, given as (key, secret name, value).
+/// Items come in key order.
+pub fn compare(
+    deployed: &BTreeMap<String, Vec<u8>>,
+    vault: &[(String, String, Vec<u8>)],
+) -> Vec<DriftItem> {
+    let vault: BTreeMap<&str, (&str, &[u8])> = vault
+        .iter()
+        .map(|(key, name, value)| (key.as_str(), (name.as_str(), value.as_slice())))
+        .collect();
+    let mut items: Vec<DriftItem> = deployed
+        .iter()
+        .map(|(key, value)| match vault.get(key.as_str()) {
+            Some((name, expected)) => DriftItem {
+                key: key.clone(),
+                secret: Some(name.to_string()),
+                status: if value.as_slice() == *expected {
+                    DriftStatus::Current
+                } else {
+                    DriftStatus::Stale
+                },
+            },
+            None => DriftItem {
+                key: key.clone(),
+                secret: None,
+                status: DriftStatus::Untracked,
+            },
+        })
+        .collect();
+    items.extend(
+        vault
+            .iter()
+            .filter(|(key, _)| !deployed.contains_key(**key))
+            .map(|(key, (name, _))| DriftItem {
+                key: key.to_string(),
+                secret: Some(name.to_string()),
+                status: DriftStatus::Missing,
+            }),
+    );
+    items.sort_by(|a, b| a.key.cmp(&b.key));
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_each_key() {
+        let deployed = BTreeMap::from([
+            ("API_KEY".to_string(), b"k1".to_vec()),
+            ("DB_PASS".to_string(), b"old".to_vec()),
+            ("LOG_LEVEL".to_string(), b"debug".to_vec()),
+        ]);
+        let vault = [
+            (
+                "API_KEY".to_string(),
+                "app/api-key".to_string(),
+                b"k1".to_vec(),
+            ),
+            (
+                "DB_PASS".to_string(),
+                "app/db-pass".to_string(),
+                b"new".to_vec(),
+            ),
+            ("SMTP".to_string(), "app/smtp".to_string(), b"s".to_vec()),
+        ];
+        let items = compare(&deployed, &vault);
+        let statuses: Vec<(&str, DriftStatus)> =
+            items.iter().map(|i| (i.key.as_str(), i.status)).collect();
+        assert_eq!(
+            statuses,
+            [
+                ("API_KEY", DriftStatus::Current),
+                ("DB_PASS", DriftStatus::Stale),
+                ("LOG_LEVEL", DriftStatus::Untracked),
+                ("SMTP", DriftStatus::Missing),
+            ]
+        );
+    }
+}
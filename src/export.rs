@@ -0,0 +1,197 @@
+//! Writers that turn decrypted secrets into files other tools consume.
+
+use crate::attest::hex;
+use aes::Aes256;
+use anyhow::{Result, anyhow};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+const ANSIBLE_VAULT_HEADER: &str = "$ANSIBLE_VAULT;1.1;AES256";
+const ANSIBLE_PBKDF2_ROUNDS: u32 = 10_000;
+
+/// Render `name: "value"` pairs as a YAML mapping; values use JSON string
+/// escaping, which is valid YAML double-quoted scalar syntax.
+pub fn yaml_vars(vars: &[(String, String)]) -> Result<String> {
+    let mut out = String::from("---\n");
+    for (key, value) in vars {
+        out.push_str(&format!("{}: {}\n", key, serde_json::to_string(value)?));
+    }
+    Ok(out)
+}
+
+/// Render `KEY=value` lines as a .env file. Values are single-quoted, which dotenv
+/// parsers take literally, unless they hold a single quote or a line break; those are
+/// double-quoted with `\\`, `\"` and `\n` escapes.
+pub fn dotenv(vars: &[(String, String)]) -> String {
+    let mut out = String::new();
+    for (key, value) in vars {
+        if value.contains(['\'', '\n', '\r']) {
+            let escaped = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\r', "\\r")
+                .replace('\n', "\\n");
+            out.push_str(&format!("{key}=\"{escaped}\"\n"));
+        } else {
+            out.push_str(&format!("{key}='{value}'\n"));
+        }
+    }
+    out
+}
+
+/// Read a .env file: `KEY=value` lines with an optional `export `, blank lines and `#`
+/// comments skipped. Single-quoted values are literal, double-quoted ones take the
+/// escapes [`dotenv`] writes, and bare values end at ` #`.
+pub fn parse_dotenv(text: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, raw) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected KEY=value", number + 1))?;
+        let raw = raw.trim();
+        let value = if let Some(quoted) = raw.strip_prefix('\'') {
+            quoted
+                .strip_suffix('\'')
+                .ok_or_else(|| anyhow!("line {}: unterminated quote", number + 1))?
+                .to_string()
+        } else if let Some(quoted) = raw.strip_prefix('"') {
+            let quoted = quoted
+                .strip_suffix('"')
+                .ok_or_else(|| anyhow!("line {}: unterminated quote", number + 1))?;
+            let mut value = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    value.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some(other) => value.push(other),
+                    None => value.push('\\'),
+                }
+            }
+            value
+        } else {
+            raw.split(" #")
+                .next()
+                .unwrap_or_default()
+                .trim_end()
+                .to_string()
+        };
+        vars.push((key.trim().to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// Encrypt `plaintext` in the Ansible Vault 1.1 AES256 format, as `ansible-vault encrypt` does.
+/// The salt is a MAC of the plaintext under the password instead of random, so the
+/// same variables and password give the same file.
+pub fn ansible_vault_encrypt(plaintext: &[u8], password: &[u8]) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(password).map_err(|e| anyhow!("hmac: {e}"))?;
+    mac.update(plaintext);
+    let salt = mac.finalize().into_bytes();
+    ansible_vault_encrypt_with_salt(plaintext, password, &salt)
+}
+
+fn ansible_vault_encrypt_with_salt(
+    plaintext: &[u8],
+    password: &[u8],
+    salt: &[u8],
+) -> Result<String> {
+    // 32-byte AES key || 32-byte HMAC key || 16-byte CTR IV
+    let mut derived = [0u8; 80];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, ANSIBLE_PBKDF2_ROUNDS, &mut derived);
+    let (aes_key, rest) = derived.split_at(32);
+    let (hmac_key, iv) = rest.split_at(32);
+
+    // PKCS#7 padding to the AES block size, then AES-256-CTR
+    let pad = 16 - plaintext.len() % 16;
+    let mut buffer = plaintext.to_vec();
+    buffer.extend(std::iter::repeat_n(pad as u8, pad));
+    let mut cipher = ctr::Ctr128BE::<Aes256>::new(aes_key.into(), iv.into());
+    cipher.apply_keystream(&mut buffer);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_key).map_err(|e| anyhow!("hmac: {e}"))?;
+    mac.update(&buffer);
+    let tag = mac.finalize().into_bytes();
+    derived.zeroize();
+
+    let inner = format!("{}\n{}\n{}", hex(salt), hex(&tag), hex(&buffer));
+    let outer = hex(inner.as_bytes());
+    let mut out = String::from(ANSIBLE_VAULT_HEADER);
+    for chunk in outer.as_bytes().chunks(80) {
+        out.push('\n');
+        out.push_str(std::str::from_utf8(chunk)?);
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ansible_vault_matches_reference_layout() {
+        let vault =
+            ansible_vault_encrypt_with_salt(b"db_password: \"x\"\n", b"pw", &[0u8; 32]).unwrap();
+        let mut lines = vault.lines();
+        assert_eq!(lines.next(), Some(ANSIBLE_VAULT_HEADER));
+        let body: String = lines.collect();
+        assert!(body.len().is_multiple_of(2));
+        let inner: Vec<u8> = (0..body.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&body[i..i + 2], 16).unwrap())
+            .collect();
+        let inner = String::from_utf8(inner).unwrap();
+        let parts: Vec<&str> = inner.split('\n').collect();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], "0".repeat(64));
+        assert_eq!(parts[1].len(), 64);
+        // 17 bytes of plaintext pad to two AES blocks
+        assert_eq!(parts[2].len(), 64);
+
+        let stable = ansible_vault_encrypt(b"a: \"1\"\n", b"pw").unwrap();
+        assert_eq!(stable, ansible_vault_encrypt(b"a: \"1\"\n", b"pw").unwrap());
+        assert_ne!(stable, ansible_vault_encrypt(b"a: \"2\"\n", b"pw").unwrap());
+    }
+
+    #[test]
+    fn yaml_vars_quotes_values() {
+        let vars = vec![("token".to_string(), "a\"b\nc".to_string())];
+        assert_eq!(yaml_vars(&vars).unwrap(), "---\ntoken: \"a\\\"b\\nc\"\n");
+    }
+
+    #[test]
+    fn dotenv_quotes_values_parsers_read_back() {
+        let vars = [
+            ("A".to_string(), "plain $HOME".to_string()),
+            ("B".to_string(), "it's".to_string()),
+            ("C".to_string(), "l1\nl2 \\ \"q\"".to_string()),
+        ];
+        assert_eq!(
+            dotenv(&vars),
+            "A='plain $HOME'\nB=\"it's\"\nC=\"l1\\nl2 \\\\ \\\"q\\\"\"\n"
+        );
+        assert_eq!(parse_dotenv(&dotenv(&vars)).unwrap(), vars);
+        let file = "# deployed\nexport D=bare value # note\n\nE=\n";
+        assert_eq!(
+            parse_dotenv(file).unwrap(),
+            [
+                ("D".to_string(), "bare value".to_string()),
+                ("E".to_string(), String::new())
+            ]
+        );
+        assert!(parse_dotenv("F='open").is_err());
+    }
+}
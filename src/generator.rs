@@ -0,0 +1,94 @@
+//! Random passwords and passphrases, generated where they are stored.
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use rand::Rng;
+use serde::Deserialize;
+
+/// BIP-39 English list (CC0): 2048 words, so 11 bits of entropy per word.
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Charset {
+    /// Letters and digits
+    Alnum,
+    /// Letters, digits and ASCII punctuation
+    Symbols,
+    /// Lower-case hex digits
+    Hex,
+    /// Digits only (PINs)
+    Digits,
+}
+
+impl Charset {
+    fn alphabet(self) -> &'static [u8] {
+        match self {
+            Charset::Alnum => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+            Charset::Symbols => {
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!#$%&()*+,-./:;<=>?@[]^_{|}~"
+            }
+            Charset::Hex => b"0123456789abcdef",
+            Charset::Digits => b"0123456789",
+        }
+    }
+
+    /// Entropy of a `length`-character password, in bits.
+    pub fn bits(self, length: usize) -> f64 {
+        length as f64 * (self.alphabet().len() as f64).log2()
+    }
+}
+
+/// `length` characters drawn uniformly from `charset`.
+pub fn password(length: usize, charset: Charset) -> Result<String> {
+    if length == 0 {
+        bail!("password length must be at least 1");
+    }
+    let alphabet = charset.alphabet();
+    let mut rng = rand::rng();
+    Ok((0..length)
+        .map(|_| alphabet[rng.random_range(0..alphabet.len())] as char)
+        .collect())
+}
+
+/// `words` words drawn uniformly from the word list, joined by `separator`.
+pub fn passphrase(words: usize, separator: &str) -> Result<String> {
+    if words == 0 {
+        bail!("a passphrase needs at least 1 word");
+    }
+    let list: Vec<&str> = WORDLIST.lines().collect();
+    let mut rng = rand::rng();
+    Ok((0..words)
+        .map(|_| list[rng.random_range(0..list.len())])
+        .collect::<Vec<_>>()
+        .join(separator))
+}
+
+/// Entropy of a `words`-word passphrase, in bits.
+pub fn passphrase_bits(words: usize) -> f64 {
+    words as f64 * (WORDLIST.lines().count() as f64).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_from_the_requested_alphabet() {
+        assert_eq!(WORDLIST.lines().count(), 2048);
+        assert_eq!(passphrase_bits(6), 66.0);
+        let hex = password(64, Charset::Hex).unwrap();
+        assert_eq!(hex.len(), 64);
+        assert!(hex.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert!(
+            password(40, Charset::Alnum)
+                .unwrap()
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric())
+        );
+        let phrase = passphrase(5, " ").unwrap();
+        assert_eq!(phrase.split(' ').count(), 5);
+        assert!(password(0, Charset::Digits).is_err());
+        assert!(passphrase(0, "-").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Identification of the machine the CLI is running on.
+
+use anyhow::{Context, Result, anyhow};
+use std::path::{Path, PathBuf};
+
+/// Stable identifier of this machine, used to bind secrets to the host that created them.
+pub fn machine_id() -> Result<String> {
+    let id = platform_machine_id()?;
+    let id = id.trim();
+    if id.is_empty() {
+        return Err(anyhow!("machine id is empty"));
+    }
+    Ok(id.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn platform_machine_id() -> Result<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .find_map(|p| std::fs::read_to_string(p).ok())
+        .context("cannot read /etc/machine-id")
+}
+
+#[cfg(target_os = "macos")]
+fn platform_machine_id() -> Result<String> {
+    let out = std::process::Command::new("ioreg")
+        .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+        .output()
+        .context("running ioreg")?;
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .find(|l| l.contains("IOPlatformUUID"))
+        .and_then(|l| l.split('"').nth(3))
+        .map(str::to_string)
+        .context("IOPlatformUUID not found")
+}
+
+#[cfg(target_os = "windows")]
+fn platform_machine_id() -> Result<String> {
+    let out = std::process::Command::new("reg")
+        .args([
+            "query",
+            r"HKLM\SOFTWARE\Microsoft\Cryptography",
+            "/v",
+            "MachineGuid",
+        ])
+        .output()
+        .context("running reg query")?;
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .find(|l| l.contains("MachineGuid"))
+        .and_then(|l| l.split_whitespace().last())
+        .map(str::to_string)
+        .context("MachineGuid not found")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_machine_id() -> Result<String> {
+    let out = std::process::Command::new("hostid")
+        .output()
+        .context("running hostid")?;
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Network name of this machine, for telling apart the hosts sharing a vault.
+pub fn hostname() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    if let Ok(name) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        return Some(name.trim().to_string()).filter(|n| !n.is_empty());
+    }
+    if let Ok(name) = std::env::var("COMPUTERNAME") {
+        return Some(name);
+    }
+    let out = std::process::Command::new("hostname").output().ok()?;
+    let name = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (out.status.success() && !name.is_empty()).then_some(name)
+}
+
+/// Login name of the user running the CLI, `unknown` when the environment lacks one.
+pub fn login_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Resolve `program` the way a shell would: paths are taken as-is, bare names are looked up in `PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let candidate = Path::new(program);
+    if candidate.components().count() > 1 {
+        return std::path::absolute(candidate).ok().filter(|p| p.is_file());
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| {
+        let full = dir.join(program);
+        if full.is_file() {
+            return Some(full);
+        }
+        let exe = full.with_extension(std::env::consts::EXE_EXTENSION);
+        exe.is_file().then_some(exe)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn machine_id_is_stable() {
+        let Ok(id) = machine_id() else {
+            // containers often have no /etc/machine-id
+            return;
+        };
+        assert!(!id.is_empty() && id.trim() == id);
+        assert_eq!(machine_id().unwrap(), id);
+    }
+
+    #[test]
+    fn bare_names_are_looked_up_in_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("tool");
+        std::fs::write(&file, "").unwrap();
+        assert_eq!(find_program(file.to_str().unwrap()), Some(file));
+        assert!(find_program(dir.path().to_str().unwrap()).is_none());
+        assert!(find_program("devinventory-no-such-program").is_none());
+    }
+}
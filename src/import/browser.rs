@@ -0,0 +1,123 @@
+//! Password exports of Chrome/Chromium and Firefox (CSV).
+
+use super::{ImportItem, parse_csv, vault_name};
+use crate::domain::SecretAttributes;
+use anyhow::{Result, bail};
+use std::collections::{BTreeMap, HashMap};
+
+/// Kind given to imported browser logins.
+pub const WEB_LOGIN_KIND: &str = "web-login";
+
+/// Logins from an export, plus how many exact duplicates were dropped.
+#[derive(Debug, Default)]
+pub struct BrowserImport {
+    pub items: Vec<ImportItem>,
+    pub duplicates: usize,
+}
+
+/// Parse a browser export: Chrome writes `name,url,username,password[,note]`,
+/// Firefox `url,username,password,httpRealm,...`; columns are found by header.
+/// Logins are named `<name_prefix><domain>/<username>`; the same login exported
+/// twice is kept once, and different passwords for one name get `-2`, `-3`, ...
+pub fn parse(text: &str, name_prefix: &str) -> Result<BrowserImport> {
+    let mut rows = parse_csv(text).into_iter();
+    let Some(header) = rows.next() else {
+        return Ok(BrowserImport::default());
+    };
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+    };
+    let (Some(url_col), Some(user_col), Some(pass_col)) =
+        (column("url"), column("username"), column("password"))
+    else {
+        bail!("not a browser password export: needs url, username and password columns");
+    };
+    let note_col = column("note");
+
+    let mut import = BrowserImport::default();
+    let mut seen: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        let cell = |col: usize| row.get(col).map(String::as_str).unwrap_or_default();
+        let (url, username, password) = (cell(url_col), cell(user_col), cell(pass_col));
+        if password.is_empty() {
+            continue;
+        }
+        let base = vault_name(name_prefix, &[&domain(url), username]);
+        let passwords = seen.entry(base.clone()).or_default();
+        if passwords.iter().any(|p| p == password) {
+            import.duplicates += 1;
+            continue;
+        }
+        passwords.push(password.to_string());
+        let name = match passwords.len() {
+            1 => base,
+            n => format!("{base}-{n}"),
+        };
+        let mut fields = BTreeMap::from([("url".to_string(), url.to_string())]);
+        if !username.is_empty() {
+            fields.insert("username".to_string(), username.to_string());
+        }
+        import.items.push(ImportItem {
+            name,
+            value: password.as_bytes().to_vec(),
+            attrs: SecretAttributes {
+                kind: Some(WEB_LOGIN_KIND.to_string()),
+                note: note_col
+                    .map(cell)
+                    .filter(|n| !n.is_empty())
+                    .map(str::to_string),
+                fields,
+                ..Default::default()
+            },
+        });
+    }
+    Ok(import)
+}
+
+/// Host of `url` without `www.`; Android app entries keep their package name.
+fn domain(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.rsplit_once('@').map_or(rest, |(_, host)| host);
+    let host = rest.split(['/', ':', '?', '#']).next().unwrap_or_default();
+    host.strip_prefix("www.").unwrap_or(host).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chrome_export_dedupes_and_names_by_domain_and_user() {
+        let csv = "name,url,username,password,note\n\
+                   github.com,https://www.github.com/login,me,pw1,\n\
+                   github.com,https://github.com/session,me,pw1,\n\
+                   github.com,https://github.com/,me,pw2,\"old, unused\"\n\
+                   ,https://example.com:8443/,,pw3,\n";
+        let import = parse(csv, "web/").unwrap();
+        assert_eq!(import.duplicates, 1);
+        let names: Vec<&str> = import.items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "web/github.com/me",
+                "web/github.com/me-2",
+                "web/example.com"
+            ]
+        );
+        assert_eq!(import.items[1].attrs.note.as_deref(), Some("old, unused"));
+        assert_eq!(import.items[0].attrs.fields["username"], "me");
+        assert_eq!(import.items[0].attrs.kind.as_deref(), Some(WEB_LOGIN_KIND));
+    }
+
+    #[test]
+    fn firefox_export_columns_are_found_by_header() {
+        let csv = "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\"\n\
+                   \"https://mail.example.org\",\"me@example.org\",\"p\"\"w\",,\"\",\"{1}\"\n";
+        let import = parse(csv, "web/").unwrap();
+        assert_eq!(import.items[0].name, "web/mail.example.org/me@example.org");
+        assert_eq!(import.items[0].value, b"p\"w");
+        assert!(parse("a,b\n1,2\n", "web/").is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! macOS login keychain, read through the `security` tool.
+
+use super::{ImportItem, browser::WEB_LOGIN_KIND, vault_name};
+use crate::domain::SecretAttributes;
+use anyhow::{Context, Result, bail};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemClass {
+    /// `genp`: application passwords, keyed by service
+    Generic,
+    /// `inet`: website/server passwords, keyed by server
+    Internet,
+}
+
+/// Attributes of one keychain item, as listed by `security dump-keychain`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeychainEntry {
+    pub class: ItemClass,
+    /// `svce` for generic items, `srvr` for internet items
+    pub service: String,
+    pub account: String,
+    pub label: Option<String>,
+}
+
+/// Parse `security dump-keychain` output (without `-d`, so no secrets).
+pub fn parse_dump(text: &str) -> Vec<KeychainEntry> {
+    let mut entries = Vec::new();
+    let mut current: Option<KeychainEntry> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(class) = line.strip_prefix("class: ") {
+            entries.extend(current.take().filter(|e| !e.service.is_empty()));
+            let class = match class.trim_matches('"') {
+                "genp" => ItemClass::Generic,
+                "inet" => ItemClass::Internet,
+                _ => continue,
+            };
+            current = Some(KeychainEntry {
+                class,
+                service: String::new(),
+                account: String::new(),
+                label: None,
+            });
+            continue;
+        }
+        if line.starts_with("keychain: ") {
+            entries.extend(current.take().filter(|e| !e.service.is_empty()));
+            continue;
+        }
+        let Some(entry) = current.as_mut() else {
+            continue;
+        };
+        let Some((key, value)) = parse_attribute(line) else {
+            continue;
+        };
+        match (key, entry.class) {
+            ("svce", ItemClass::Generic) | ("srvr", ItemClass::Internet) => entry.service = value,
+            ("acct", _) => entry.account = value,
+            ("0x00000007", _) => entry.label = Some(value),
+            _ => {}
+        }
+    }
+    entries.extend(current.filter(|e| !e.service.is_empty()));
+    entries
+}
+
+/// `"svce"<blob>="github"` or `0x00000007 <blob>=0x6869  "hi"`; `<NULL>` yields nothing.
+fn parse_attribute(line: &str) -> Option<(&str, String)> {
+    let (key, rest) = line.split_once('<')?;
+    let key = key.trim().trim_matches('"');
+    let (_, value) = rest.split_once(">=")?;
+    let quoted = if value.starts_with('"') {
+        value
+    } else if value.starts_with("0x") {
+        &value[value.find('"')?..]
+    } else {
+        return None;
+    };
+    Some((key, quoted.trim_matches('"').to_string()))
+}
+
+/// Items of the login keychain whose service/server starts with `service_prefix`,
+/// named `<name_prefix><service>/<account>`. macOS may ask to allow each read.
+pub fn read_items(service_prefix: Option<&str>, name_prefix: &str) -> Result<Vec<ImportItem>> {
+    if !cfg!(target_os = "macos") {
+        bail!("the macOS keychain is only available on macOS");
+    }
+    let out = Command::new("security")
+        .arg("dump-keychain")
+        .output()
+        .context("running `security dump-keychain`")?;
+    if !out.status.success() {
+        bail!(
+            "`security dump-keychain` failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    let mut items = Vec::new();
+    for entry in parse_dump(&String::from_utf8_lossy(&out.stdout)) {
+        if service_prefix.is_some_and(|p| !entry.service.starts_with(p)) {
+            continue;
+        }
+        let value = password(&entry)?;
+        items.push(ImportItem {
+            name: vault_name(name_prefix, &[&entry.service, &entry.account]),
+            value,
+            attrs: SecretAttributes {
+                kind: Some(
+                    match entry.class {
+                        ItemClass::Generic => "password",
+                        ItemClass::Internet => WEB_LOGIN_KIND,
+                    }
+                    .to_string(),
+                ),
+                note: entry.label.clone(),
+                ..Default::default()
+            },
+        });
+    }
+    Ok(items)
+}
+
+fn password(entry: &KeychainEntry) -> Result<Vec<u8>> {
+    let subcommand = match entry.class {
+        ItemClass::Generic => "find-generic-password",
+        ItemClass::Internet => "find-internet-password",
+    };
+    let out = Command::new("security")
+        .args([subcommand, "-s", &entry.service, "-a", &entry.account, "-w"])
+        .output()
+        .with_context(|| format!("running `security {subcommand}`"))?;
+    if !out.status.success() {
+        bail!(
+            "cannot read keychain item {}/{}: {}",
+            entry.service,
+            entry.account,
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    let mut value = out.stdout;
+    if value.last() == Some(&b'\n') {
+        value.pop();
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_generic_and_internet_items() {
+        let dump = r#"keychain: "/Users/me/Library/Keychains/login.keychain-db"
+version: 512
+class: "genp"
+attributes:
+    0x00000007 <blob>="GitHub CLI"
+    0x00000008 <blob>=<NULL>
+    "acct"<blob>="me"
+    "cdat"<timedate>=0x32303233  "20231101120000Z\000"
+    "svce"<blob>="gh:github.com"
+keychain: "/Users/me/Library/Keychains/login.keychain-db"
+class: "inet"
+attributes:
+    "acct"<blob>=0x6D65  "me"
+    "ptcl"<uint32>="htps"
+    "srvr"<blob>="example.com"
+keychain: "/Users/me/Library/Keychains/login.keychain-db"
+class: 0x80001000
+attributes:
+    "alis"<blob>="cert"
+"#;
+        assert_eq!(
+            parse_dump(dump),
+            vec![
+                KeychainEntry {
+                    class: ItemClass::Generic,
+                    service: "gh:github.com".into(),
+                    account: "me".into(),
+                    label: Some("GitHub CLI".into()),
+                },
+                KeychainEntry {
+                    class: ItemClass::Internet,
+                    service: "example.com".into(),
+                    account: "me".into(),
+                    label: None,
+                },
+            ]
+        );
+    }
+}
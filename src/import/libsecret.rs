@@ -0,0 +1,118 @@
+//! libsecret / GNOME Keyring items, read through `secret-tool`.
+
+use super::{ImportItem, vault_name};
+use crate::domain::SecretAttributes;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::process::Command;
+
+/// One item printed by `secret-tool search --all`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Item {
+    /// D-Bus object path, e.g. `/org/freedesktop/secrets/collection/login/3`
+    pub path: String,
+    pub label: String,
+    pub secret: String,
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Item {
+    /// Collection the item lives in, from its object path.
+    pub fn collection(&self) -> Option<&str> {
+        self.path.split("/collection/").nth(1)?.split('/').next()
+    }
+}
+
+/// Parse `secret-tool search --all` output.
+pub fn parse_search(text: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut current: Option<Item> = None;
+    for line in text.lines() {
+        if let Some(path) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            items.extend(current.take());
+            current = Some(Item {
+                path: path.to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let (Some(item), Some((key, value))) = (current.as_mut(), line.split_once(" = ")) else {
+            continue;
+        };
+        match key {
+            "label" => item.label = value.to_string(),
+            "secret" => item.secret = value.to_string(),
+            _ => {
+                if let Some(attr) = key.strip_prefix("attribute.") {
+                    item.attributes.insert(attr.to_string(), value.to_string());
+                }
+            }
+        }
+    }
+    items.extend(current);
+    items
+}
+
+/// Items matching every `key=value` attribute (secret-tool needs at least one),
+/// optionally only from `collection`, named `<name_prefix><label>`. Attributes
+/// are kept as the secret's fields.
+pub fn read_items(
+    collection: Option<&str>,
+    attributes: &[(String, String)],
+    name_prefix: &str,
+) -> Result<Vec<ImportItem>> {
+    if attributes.is_empty() {
+        bail!("secret-tool searches by attribute; pass at least one --attribute key=value");
+    }
+    let mut command = Command::new("secret-tool");
+    command.args(["search", "--all", "--unlock"]);
+    for (key, value) in attributes {
+        command.args([key, value]);
+    }
+    let out = command.output().context("running `secret-tool search`")?;
+    if !out.status.success() {
+        bail!(
+            "`secret-tool search` failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(parse_search(&String::from_utf8_lossy(&out.stdout))
+        .into_iter()
+        .filter(|item| collection.is_none() || item.collection() == collection)
+        .map(|item| ImportItem {
+            name: vault_name(name_prefix, &[&item.label]),
+            value: item.secret.into_bytes(),
+            attrs: SecretAttributes {
+                kind: Some("password".to_string()),
+                fields: item.attributes,
+                ..Default::default()
+            },
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_items_with_attributes_and_collection() {
+        let out = "[/org/freedesktop/secrets/collection/login/3]\n\
+                   label = GitHub token\n\
+                   secret = ghp_abc = def\n\
+                   created = 2024-01-01 10:00:00\n\
+                   schema = org.freedesktop.Secret.Generic\n\
+                   attribute.app = gh\n\
+                   attribute.user = me\n\
+                   [/org/freedesktop/secrets/collection/session/1]\n\
+                   label = other\n\
+                   secret = x\n";
+        let items = parse_search(out);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].collection(), Some("login"));
+        assert_eq!(items[0].secret, "ghp_abc = def");
+        assert_eq!(items[0].attributes["app"], "gh");
+        assert_eq!(items[0].attributes.len(), 2);
+        assert_eq!(items[1].collection(), Some("session"));
+    }
+}
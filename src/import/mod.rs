@@ -0,0 +1,82 @@
+//! Pulling credentials out of other stores (OS keychains, browser exports, plain
+//! CSV/JSON files) into the vault.
+
+pub mod browser;
+pub mod keychain;
+pub mod libsecret;
+pub mod table;
+pub mod wincred;
+
+use crate::domain::SecretAttributes;
+
+/// One credential read from an external store, ready to be saved.
+#[derive(Debug, Clone)]
+pub struct ImportItem {
+    pub name: String,
+    pub value: Vec<u8>,
+    pub attrs: SecretAttributes,
+}
+
+/// `prefix` followed by the non-empty `parts` joined with `/`.
+pub fn vault_name(prefix: &str, parts: &[&str]) -> String {
+    let parts: Vec<&str> = parts
+        .iter()
+        .map(|p| p.trim().trim_matches('/'))
+        .filter(|p| !p.is_empty())
+        .collect();
+    format!("{prefix}{}", parts.join("/"))
+}
+
+/// Rows of RFC 4180 CSV: quoted fields may hold commas, `""` and line breaks.
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vault_names_skip_empty_parts() {
+        assert_eq!(
+            vault_name("keychain/", &["github.com", "me"]),
+            "keychain/github.com/me"
+        );
+        assert_eq!(vault_name("", &["/svc/", " "]), "svc");
+    }
+
+    #[test]
+    fn csv_handles_quotes_and_embedded_newlines() {
+        let rows = parse_csv("a,\"b,c\",\"say \"\"hi\"\"\"\r\n1,\"two\nlines\",\n");
+        assert_eq!(
+            rows,
+            vec![vec!["a", "b,c", "say \"hi\""], vec!["1", "two\nlines", ""],]
+        );
+    }
+}
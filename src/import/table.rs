@@ -0,0 +1,147 @@
+//! Plain CSV and JSON files with one secret per row, e.g. a spreadsheet being migrated.
+
+use super::{ImportItem, parse_csv};
+use crate::domain::SecretAttributes;
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+/// Columns mapped onto secret attributes; any other column is kept as a field.
+const KNOWN: &[&str] = &[
+    "name", "value", "kind", "note", "owner", "contact", "expires",
+];
+
+/// CSV with a header row holding at least `name` and `value`.
+pub fn parse_csv_file(text: &str, name_prefix: &str) -> Result<Vec<ImportItem>> {
+    let mut rows = parse_csv(text).into_iter();
+    let Some(header) = rows.next() else {
+        return Ok(Vec::new());
+    };
+    let header: Vec<String> = header.iter().map(|h| h.trim().to_lowercase()).collect();
+    if !header.iter().any(|h| h == "name") || !header.iter().any(|h| h == "value") {
+        bail!("the header row needs name and value columns");
+    }
+    let mut items = Vec::new();
+    for (number, row) in rows.enumerate() {
+        if row.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        let cells = header.iter().cloned().zip(row).collect();
+        items.push(item(cells, name_prefix).with_context(|| format!("row {}", number + 2))?);
+    }
+    Ok(items)
+}
+
+/// A JSON array of objects with the same keys as the CSV columns, or one object
+/// mapping names to values.
+pub fn parse_json_file(text: &str, name_prefix: &str) -> Result<Vec<ImportItem>> {
+    let cells = |object: serde_json::Map<String, serde_json::Value>| -> Result<_> {
+        object
+            .into_iter()
+            .map(|(key, value)| Ok((key.to_lowercase(), scalar(value)?)))
+            .collect::<Result<BTreeMap<_, _>>>()
+    };
+    match serde_json::from_str(text).context("not valid JSON")? {
+        serde_json::Value::Array(rows) => rows
+            .into_iter()
+            .enumerate()
+            .map(|(index, row)| {
+                let serde_json::Value::Object(object) = row else {
+                    bail!("entry {}: expected an object", index + 1);
+                };
+                item(cells(object)?, name_prefix).with_context(|| format!("entry {}", index + 1))
+            })
+            .collect(),
+        serde_json::Value::Object(object) => object
+            .into_iter()
+            .map(|(name, value)| {
+                let cells = BTreeMap::from([
+                    ("name".to_string(), name.clone()),
+                    ("value".to_string(), scalar(value).context(name)?),
+                ]);
+                item(cells, name_prefix)
+            })
+            .collect(),
+        _ => bail!("expected an array of objects or an object of name/value pairs"),
+    }
+}
+
+/// Strings as they are, numbers and booleans as written; nested values are rejected.
+fn scalar(value: serde_json::Value) -> Result<String> {
+    match value {
+        serde_json::Value::String(s) => Ok(s),
+        serde_json::Value::Null => Ok(String::new()),
+        serde_json::Value::Number(_) | serde_json::Value::Bool(_) => Ok(value.to_string()),
+        _ => bail!("values must be strings, numbers or booleans"),
+    }
+}
+
+fn item(mut cells: BTreeMap<String, String>, name_prefix: &str) -> Result<ImportItem> {
+    let mut take = |key: &str| cells.remove(key).filter(|v| !v.trim().is_empty());
+    let name = take("name").ok_or_else(|| anyhow!("name is empty"))?;
+    let value = take("value").ok_or_else(|| anyhow!("'{}' has no value", name.trim()))?;
+    let expires_at = take("expires").map(|e| parse_time(&e)).transpose()?;
+    let attrs = SecretAttributes {
+        kind: take("kind"),
+        note: take("note"),
+        owner: take("owner"),
+        contact: take("contact"),
+        expires_at,
+        ..Default::default()
+    };
+    let fields = cells
+        .into_iter()
+        .filter(|(key, value)| !KNOWN.contains(&key.as_str()) && !value.is_empty())
+        .collect();
+    Ok(ImportItem {
+        name: format!("{name_prefix}{}", name.trim()),
+        value: value.into_bytes(),
+        attrs: SecretAttributes { fields, ..attrs },
+    })
+}
+
+/// `expires` as an RFC 3339 time or a `YYYY-MM-DD` date (midnight UTC).
+fn parse_time(text: &str) -> Result<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map(|d| d.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc())
+        .map_err(|_| anyhow!("expires '{text}' is not a date (YYYY-MM-DD) or RFC 3339 time"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_become_secrets_with_attributes() {
+        let csv = "Name,Value,Kind,Expires,Env\n\
+                   db/pass,\"p,w\",password,2030-01-31,prod\n\
+                   ,,,,\n\
+                   api/key,k1,,,\n";
+        let items = parse_csv_file(csv, "team/").unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name, "team/db/pass");
+        assert_eq!(items[0].value, b"p,w");
+        assert_eq!(items[0].attrs.kind.as_deref(), Some("password"));
+        assert_eq!(
+            items[0].attrs.expires_at.unwrap().to_rfc3339(),
+            "2030-01-31T00:00:00+00:00"
+        );
+        assert_eq!(items[0].attrs.fields["env"], "prod");
+        assert!(items[1].attrs.fields.is_empty());
+        assert!(parse_csv_file("name,secret\na,b\n", "").is_err());
+        let err = parse_csv_file("name,value\na,\n", "").unwrap_err();
+        assert_eq!(format!("{err:#}"), "row 2: 'a' has no value");
+
+        let json = r#"[{"name": "db/pass", "value": "pw", "owner": "ops"}, {"name": "port", "value": 5432}]"#;
+        let items = parse_json_file(json, "").unwrap();
+        assert_eq!(items[0].attrs.owner.as_deref(), Some("ops"));
+        assert_eq!(items[1].value, b"5432");
+        let items = parse_json_file(r#"{"a": "1", "b": "2"}"#, "x/").unwrap();
+        assert_eq!(items[1].name, "x/b");
+        assert!(parse_json_file(r#"[{"name": "a", "value": {"nested": 1}}]"#, "").is_err());
+    }
+}
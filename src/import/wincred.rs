@@ -0,0 +1,160 @@
+//! Windows Credential Manager (generic credentials).
+
+use super::{ImportItem, vault_name};
+use crate::domain::SecretAttributes;
+use anyhow::Result;
+
+/// One generic credential as stored by Windows.
+#[derive(Debug, Clone)]
+pub struct Credential {
+    pub target: String,
+    pub user: String,
+    pub comment: Option<String>,
+    pub blob: Vec<u8>,
+}
+
+/// Generic credentials whose target starts with `target_prefix`, named from their
+/// target and user (see [`target_name`]).
+pub fn read_items(target_prefix: Option<&str>, name_prefix: &str) -> Result<Vec<ImportItem>> {
+    let filter = target_prefix.map(|p| format!("{p}*"));
+    Ok(enumerate(filter.as_deref())?
+        .into_iter()
+        .map(|cred| ImportItem {
+            name: target_name(name_prefix, &cred.target, &cred.user),
+            value: decode_blob(&cred.blob),
+            attrs: SecretAttributes {
+                kind: Some("password".to_string()),
+                note: cred.comment,
+                ..Default::default()
+            },
+        })
+        .collect())
+}
+
+/// `git:https://github.com` + `me` -> `<prefix>git/github.com/me`; the
+/// `LegacyGeneric:target=` wrapper Windows adds is dropped.
+pub fn target_name(prefix: &str, target: &str, user: &str) -> String {
+    let target = target
+        .strip_prefix("LegacyGeneric:target=")
+        .unwrap_or(target)
+        .replace("https://", "")
+        .replace("http://", "")
+        .replace(':', "/");
+    vault_name(prefix, &[&target, user])
+}
+
+/// Passwords saved through Windows dialogs are UTF-16LE; apps often store UTF-8.
+pub fn decode_blob(blob: &[u8]) -> Vec<u8> {
+    let looks_utf16 = blob.len() >= 2
+        && blob.len().is_multiple_of(2)
+        && blob.chunks(2).all(|pair| pair[1] == 0 && pair[0] != 0);
+    if !looks_utf16 {
+        return blob.to_vec();
+    }
+    let units: Vec<u16> = blob
+        .chunks(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units).into_bytes()
+}
+
+#[cfg(windows)]
+fn enumerate(filter: Option<&str>) -> Result<Vec<Credential>> {
+    use windows_sys::Win32::Security::Credentials::{
+        CRED_TYPE_GENERIC, CREDENTIALW, CredEnumerateW, CredFree,
+    };
+    // ERROR_NOT_FOUND: nothing matches the filter
+    const NOT_FOUND: i32 = 1168;
+
+    let filter: Option<Vec<u16>> = filter.map(|f| f.encode_utf16().chain(Some(0)).collect());
+    let mut count = 0u32;
+    let mut list: *mut *mut CREDENTIALW = std::ptr::null_mut();
+    // SAFETY: the filter is NUL-terminated and outlives the call; count/list are
+    // out-parameters that Windows fills in.
+    let ok = unsafe {
+        CredEnumerateW(
+            filter.as_ref().map_or(std::ptr::null(), |f| f.as_ptr()),
+            0,
+            &mut count,
+            &mut list,
+        )
+    };
+    if ok == 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(NOT_FOUND) {
+            return Ok(Vec::new());
+        }
+        return Err(anyhow::anyhow!("CredEnumerateW failed: {err}"));
+    }
+    let mut creds = Vec::new();
+    for i in 0..count as usize {
+        // SAFETY: Windows returned `count` valid credential pointers, freed below.
+        let cred = unsafe { &**list.add(i) };
+        if cred.Type != CRED_TYPE_GENERIC {
+            continue;
+        }
+        // SAFETY: string fields are NUL-terminated UTF-16 or null; the blob is
+        // `CredentialBlobSize` bytes.
+        unsafe {
+            creds.push(Credential {
+                target: wide_string(cred.TargetName).unwrap_or_default(),
+                user: wide_string(cred.UserName).unwrap_or_default(),
+                comment: wide_string(cred.Comment),
+                blob: if cred.CredentialBlob.is_null() {
+                    Vec::new()
+                } else {
+                    std::slice::from_raw_parts(
+                        cred.CredentialBlob,
+                        cred.CredentialBlobSize as usize,
+                    )
+                    .to_vec()
+                },
+            });
+        }
+    }
+    // SAFETY: `list` came from CredEnumerateW and is freed exactly once.
+    unsafe { CredFree(list as *const core::ffi::c_void) };
+    Ok(creds)
+}
+
+#[cfg(windows)]
+unsafe fn wide_string(ptr: *const u16) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let mut len = 0;
+    // SAFETY: caller guarantees a NUL-terminated string.
+    unsafe {
+        while *ptr.add(len) != 0 {
+            len += 1;
+        }
+        Some(String::from_utf16_lossy(std::slice::from_raw_parts(
+            ptr, len,
+        )))
+    }
+}
+
+#[cfg(not(windows))]
+fn enumerate(_filter: Option<&str>) -> Result<Vec<Credential>> {
+    anyhow::bail!("Windows Credential Manager is only available on Windows")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_targets_and_decodes_utf16_blobs() {
+        assert_eq!(
+            target_name("wincred/", "git:https://github.com", "me"),
+            "wincred/git/github.com/me"
+        );
+        assert_eq!(
+            target_name("wincred/", "LegacyGeneric:target=backup-nas", ""),
+            "wincred/backup-nas"
+        );
+        let utf16: Vec<u8> = "pw1".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(decode_blob(&utf16), b"pw1");
+        assert_eq!(decode_blob(b"ghp_token"), b"ghp_token");
+    }
+}
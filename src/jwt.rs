@@ -0,0 +1,61 @@
+//! Decoding (never verifying) JSON Web Tokens for inspection.
+
+use anyhow::{Result, anyhow, bail};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// Header and claims of a compact-serialized JWT.
+#[derive(Debug, Clone)]
+pub struct JwtParts {
+    pub header: Value,
+    pub claims: Value,
+}
+
+impl JwtParts {
+    /// A NumericDate claim (`exp`, `iat`, `nbf`) as a timestamp.
+    pub fn time_claim(&self, claim: &str) -> Option<DateTime<Utc>> {
+        let secs = self.claims.get(claim)?.as_f64()?;
+        DateTime::from_timestamp(secs as i64, 0)
+    }
+}
+
+/// Split and decode `header.payload.signature`; the signature is not checked.
+pub fn decode(token: &str) -> Result<JwtParts> {
+    let token = token.trim();
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        bail!("not a JWT: expected three dot-separated segments");
+    }
+    Ok(JwtParts {
+        header: decode_segment(parts[0], "header")?,
+        claims: decode_segment(parts[1], "claims")?,
+    })
+}
+
+fn decode_segment(segment: &str, what: &str) -> Result<Value> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(segment.trim_end_matches('='))
+        .map_err(|_| anyhow!("not a JWT: {what} is not base64url"))?;
+    serde_json::from_slice(&bytes).map_err(|_| anyhow!("not a JWT: {what} is not JSON"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_header_and_time_claims() {
+        // {"alg":"HS256","typ":"JWT"}.{"sub":"ci","iat":1700000000,"exp":1700003600}
+        let token = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJzdWIiOiJjaSIsImlhdCI6MTcwMDAwMDAwMCwiZXhwIjoxNzAwMDAzNjAwfQ.c2ln";
+        let jwt = decode(token).unwrap();
+        assert_eq!(jwt.header["alg"], "HS256");
+        assert_eq!(jwt.claims["sub"], "ci");
+        assert_eq!(
+            jwt.time_claim("exp").unwrap().to_rfc3339(),
+            "2023-11-14T23:13:20+00:00"
+        );
+        assert!(jwt.time_claim("nbf").is_none());
+        assert!(decode("ghp_plainPersonalAccessToken").is_err());
+    }
+}
@@ -1,26 +1,143 @@
-use crate::crypto::MasterKey;
-use anyhow::{Context, Result, anyhow};
+pub mod pkcs11;
+
+use crate::crypto::{self, MasterKey, SecretCrypto};
+use crate::perms;
+use crate::yubikey::{self, YubikeyChallenge};
+use anyhow::{Context, Result, anyhow, bail};
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Utc};
 use keyring::Entry;
 use log::{debug, info, warn};
+use pkcs11::WrappedKey;
 use rand::RngCore;
-use zeroize::Zeroize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Argon2id costs for new passphrase-derived keys: 64 MiB, three passes, one lane.
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_LANES: u32 = 1;
+const PASSPHRASE_CHECK_INFO: &[u8] = b"devinventory passphrase check v1";
+/// Associated data of a master key wrapped under a passphrase-derived key.
+const PASSPHRASE_WRAP_LABEL: &str = "devinventory passphrase wrap v1";
+/// Wrong passphrases accepted before giving up.
+const PASSPHRASE_ATTEMPTS: usize = 3;
+const TOUCH_YUBIKEY: &str = "Touch your YubiKey if it blinks...";
 
-const SERVICE: &str = "devinventory";
-const ACCOUNT: &str = "dmk";
+/// Asks someone for a passphrase, PIN or recovery key. The UI supplies it; without
+/// one, anything that needs an answer fails instead of prompting.
+pub trait Prompter: Send + Sync {
+    /// Whether anyone can be asked right now.
+    fn can_ask(&self) -> bool;
+    /// A hidden answer to `prompt`.
+    fn secret(&self, prompt: &str) -> Result<String>;
+    /// Tell whoever is asked about something they have to do, e.g. touch a key.
+    fn notice(&self, _message: &str) {}
+}
 
+#[derive(Clone)]
 pub struct MasterKeySource {
     pub base64_inline: Option<String>,
+    /// File holding the key, e.g. on a removable drive or a container secret mount
+    pub key_file: Option<PathBuf>,
     pub allow_keyring: bool,
 }
 
+#[derive(Clone)]
 pub struct MasterKeyProvider {
     src: MasterKeySource,
+    keyring_service: String,
+    keyring_account: String,
+    /// Index file and vault to record keyring writes under
+    index: Option<(PathBuf, PathBuf)>,
+    /// Key derived from the vault passphrase, so it is asked once per process
+    derived: Arc<Mutex<Option<MasterKey>>>,
+    /// YubiKey answering the challenge of a YubiKey vault
+    yubikey: yubikey::Device,
+    /// Token holding the key that wraps a PKCS#11 vault's master key
+    pkcs11: pkcs11::Token,
+    /// Who is asked for passphrases; nobody when unset
+    prompter: Option<Arc<dyn Prompter>>,
 }
 
 impl MasterKeyProvider {
-    pub fn new(src: MasterKeySource) -> Self {
-        Self { src }
+    pub fn new(src: MasterKeySource, keyring_service: String, keyring_account: String) -> Self {
+        Self {
+            src,
+            keyring_service,
+            keyring_account,
+            index: None,
+            derived: Arc::default(),
+            yubikey: yubikey::Device::default(),
+            pkcs11: pkcs11::Token::default(),
+            prompter: None,
+        }
+    }
+
+    /// Talk to the YubiKey through `device` (`[yubikey]` in config.toml).
+    pub fn with_yubikey(mut self, device: yubikey::Device) -> Self {
+        self.yubikey = device;
+        self
+    }
+
+    /// Unwrap the master key of a PKCS#11 vault with `token` (`[pkcs11]` in config.toml).
+    pub fn with_pkcs11(mut self, token: pkcs11::Token) -> Self {
+        self.pkcs11 = match &self.prompter {
+            Some(prompter) => token.with_prompter(prompter.clone()),
+            None => token,
+        };
+        self
+    }
+
+    /// Ask `prompter` for passphrases, recovery keys and token PINs.
+    pub fn with_prompter(mut self, prompter: Arc<dyn Prompter>) -> Self {
+        self.pkcs11 = self.pkcs11.with_prompter(prompter.clone());
+        self.prompter = Some(prompter);
+        self
+    }
+
+    /// Whether a passphrase or recovery key can be asked for.
+    pub fn can_ask(&self) -> bool {
+        self.prompter.as_ref().is_some_and(|p| p.can_ask())
+    }
+
+    /// A hidden answer to `prompt`; an error when there is nobody to ask.
+    pub fn ask(&self, prompt: &str) -> Result<String> {
+        match &self.prompter {
+            Some(prompter) => prompter.secret(prompt),
+            None => bail!(
+                "nobody to ask '{}'; provide the key with --dmk",
+                prompt.trim_end_matches([':', ' '])
+            ),
+        }
+    }
+
+    /// Ask for a new passphrase twice, then wrap a fresh key under it with new Argon2id
+    /// settings.
+    pub fn ask_new_passphrase(&self, prompt_text: &str) -> Result<(PassphraseKdf, MasterKey)> {
+        match &self.prompter {
+            Some(prompter) => {
+                PassphraseKdf::create(&ask_passphrase_twice(prompter.as_ref(), prompt_text)?)
+            }
+            None => bail!("nobody to ask for a new passphrase"),
+        }
+    }
+
+    fn notice(&self, message: &str) {
+        if let Some(prompter) = &self.prompter {
+            prompter.notice(message);
+        }
+    }
+
+    /// Note every keyring entry written for `vault` in the index at `index_path`.
+    pub fn with_index(mut self, index_path: PathBuf, vault: PathBuf) -> Self {
+        self.index = Some((index_path, vault));
+        self
     }
 
     /// Obtain existing master key. If `generate_if_missing` is true, will create a new key.
@@ -35,6 +152,21 @@ impl MasterKeyProvider {
             return Ok(k);
         }
 
+        if let Some(path) = &self.src.key_file {
+            if !generate_if_missing || path.exists() {
+                let key = read_key_file(path)?;
+                info!("master key read from {}", path.to_string_lossy());
+                return Ok(key);
+            }
+            let key = generate_key();
+            write_key_file(path, &key)?;
+            println!(
+                "Generated new master key in {}. Keep a copy of that file somewhere safe.",
+                path.to_string_lossy()
+            );
+            return Ok(key);
+        }
+
         if self.src.allow_keyring
             && let Some(k) = self.read_keyring().unwrap_or_else(|e| {
                 warn!("keyring unavailable ({}); cannot load stored key", e);
@@ -59,7 +191,10 @@ impl MasterKeyProvider {
             match self.write_keyring(&encoded) {
                 Ok(_) => {
                     info!("new master key written to keyring");
-                    println!("Stored in OS keyring under service '{SERVICE}' account '{ACCOUNT}'.");
+                    println!(
+                        "Stored in OS keyring under service '{}' account '{}'.",
+                        self.keyring_service, self.keyring_account
+                    );
                 }
                 Err(e) => {
                     warn!("cannot write keyring: {e}; you must store the key manually");
@@ -74,6 +209,11 @@ impl MasterKeyProvider {
 
     pub async fn rotate(&self) -> Result<MasterKey> {
         let key = generate_key();
+        if let Some(path) = &self.src.key_file {
+            write_key_file(path, &key)?;
+            println!("Key file {} updated.", path.to_string_lossy());
+            return Ok(key);
+        }
         let encoded = general_purpose::STANDARD.encode(&key.0);
         println!("New master key (base64). Save immediately: {}", encoded);
         if self.src.allow_keyring {
@@ -91,8 +231,245 @@ impl MasterKeyProvider {
         Ok(key)
     }
 
+    /// The key of a vault whose master key comes from a passphrase: `--dmk` or the key
+    /// file when it holds that key, else unlocked by the passphrase (asked up to three
+    /// times) and kept for the rest of the process. The keyring is not consulted.
+    pub fn obtain_with_passphrase(&self, kdf: &PassphraseKdf) -> Result<MasterKey> {
+        if let Some(inline) = &self.src.base64_inline {
+            let key = decode_key(inline)?;
+            if !kdf.matches(&key) {
+                bail!("the --dmk key is not this vault's passphrase-protected key");
+            }
+            info!("master key provided inline");
+            return Ok(key);
+        }
+        if let Some(path) = &self.src.key_file {
+            let key = read_key_file(path)?;
+            if !kdf.matches(&key) {
+                bail!(
+                    "{} does not hold this vault's passphrase-protected key",
+                    path.to_string_lossy()
+                );
+            }
+            return Ok(key);
+        }
+        let cached = self.derived.lock().expect("key cache poisoned").clone();
+        if let Some(key) = cached.filter(|key| kdf.matches(key)) {
+            return Ok(key);
+        }
+        for attempt in 1..=PASSPHRASE_ATTEMPTS {
+            let mut passphrase = self.ask("Vault passphrase: ")?;
+            let derived = kdf.derive(&passphrase);
+            passphrase.zeroize();
+            match derived {
+                Ok(key) => {
+                    info!("master key unlocked by the vault passphrase");
+                    self.remember(&key);
+                    return Ok(key);
+                }
+                Err(e) if attempt < PASSPHRASE_ATTEMPTS => warn!("{e}; try again"),
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!("the last attempt returns")
+    }
+
+    /// The key of a vault whose master key comes from a YubiKey: `--dmk` or the key file
+    /// when it holds that key, else derived from the YubiKey's answer to the vault's
+    /// challenge and kept for the rest of the process. The keyring is not consulted.
+    pub fn obtain_with_yubikey(&self, challenge: &YubikeyChallenge) -> Result<MasterKey> {
+        if let Some(inline) = &self.src.base64_inline {
+            let key = decode_key(inline)?;
+            if !challenge.matches(&key) {
+                bail!("the --dmk key is not this vault's YubiKey-derived key");
+            }
+            info!("master key provided inline");
+            return Ok(key);
+        }
+        if let Some(path) = &self.src.key_file {
+            let key = read_key_file(path)?;
+            if !challenge.matches(&key) {
+                bail!(
+                    "{} does not hold this vault's YubiKey-derived key",
+                    path.to_string_lossy()
+                );
+            }
+            return Ok(key);
+        }
+        let cached = self.derived.lock().expect("key cache poisoned").clone();
+        if let Some(key) = cached.filter(|key| challenge.matches(key)) {
+            return Ok(key);
+        }
+        self.notice(TOUCH_YUBIKEY);
+        let key = challenge.derive(&self.yubikey)?;
+        info!(
+            "master key derived from the YubiKey in slot {}",
+            challenge.slot
+        );
+        self.remember(&key);
+        Ok(key)
+    }
+
+    /// A fresh challenge for the configured YubiKey and the key its answer derives.
+    pub fn new_yubikey_key(&self) -> Result<(YubikeyChallenge, MasterKey)> {
+        self.notice(TOUCH_YUBIKEY);
+        let (challenge, key) = YubikeyChallenge::create(&self.yubikey)?;
+        self.remember(&key);
+        Ok((challenge, key))
+    }
+
+    /// The key of a vault whose master key is wrapped by a PKCS#11 token: `--dmk` or the
+    /// key file when it holds that key, else unwrapped by the token and kept for the
+    /// rest of the process. The keyring is not consulted.
+    pub fn obtain_with_pkcs11(&self, wrapped: &WrappedKey) -> Result<MasterKey> {
+        if let Some(inline) = &self.src.base64_inline {
+            let key = decode_key(inline)?;
+            if !wrapped.matches(&key) {
+                bail!("the --dmk key is not this vault's token-wrapped key");
+            }
+            info!("master key provided inline");
+            return Ok(key);
+        }
+        if let Some(path) = &self.src.key_file {
+            let key = read_key_file(path)?;
+            if !wrapped.matches(&key) {
+                bail!(
+                    "{} does not hold this vault's token-wrapped key",
+                    path.to_string_lossy()
+                );
+            }
+            return Ok(key);
+        }
+        let cached = self.derived.lock().expect("key cache poisoned").clone();
+        if let Some(key) = cached.filter(|key| wrapped.matches(key)) {
+            return Ok(key);
+        }
+        let key = wrapped.unwrap(&self.pkcs11)?;
+        info!("master key unwrapped by the PKCS#11 token");
+        self.remember(&key);
+        Ok(key)
+    }
+
+    /// A fresh master key wrapped by the configured PKCS#11 token key.
+    pub fn new_pkcs11_key(&self) -> Result<(WrappedKey, MasterKey)> {
+        let (wrapped, key) = WrappedKey::create(&self.pkcs11)?;
+        self.remember(&key);
+        Ok((wrapped, key))
+    }
+
+    /// Ask for a new vault passphrase twice and wrap a fresh key under it.
+    pub fn new_passphrase_key(&self) -> Result<(PassphraseKdf, MasterKey)> {
+        let (kdf, key) = self.ask_new_passphrase("New vault passphrase: ")?;
+        self.remember(&key);
+        Ok((kdf, key))
+    }
+
+    /// Keep a key unlocked interactively (passphrase or key slot) for the rest of the process.
+    pub fn remember(&self, key: &MasterKey) {
+        *self.derived.lock().expect("key cache poisoned") = Some(key.clone());
+    }
+
+    /// The key kept by [`remember`](Self::remember), if any.
+    pub fn remembered(&self) -> Option<MasterKey> {
+        self.derived.lock().expect("key cache poisoned").clone()
+    }
+
+    /// Keep the unlock key of keyring slot `slot` next to the master key entry.
+    pub fn store_slot_key(&self, slot: i64, secret: &[u8; 32]) -> Result<()> {
+        if !self.src.allow_keyring {
+            bail!("keyring slots live in the OS keyring; drop --no-keyring to add one");
+        }
+        let account = self.slot_account(slot);
+        let mut encoded = general_purpose::STANDARD.encode(secret);
+        let result = Entry::new(&self.keyring_service, &account)?
+            .set_password(&encoded)
+            .context("writing key slot to keyring");
+        encoded.zeroize();
+        result?;
+        self.record_in_index(&account);
+        Ok(())
+    }
+
+    /// The unlock key of keyring slot `slot` on this machine, if its entry exists.
+    pub fn read_slot_key(&self, slot: i64) -> Result<Option<[u8; 32]>> {
+        if !self.src.allow_keyring {
+            return Ok(None);
+        }
+        match Entry::new(&self.keyring_service, &self.slot_account(slot))?.get_password() {
+            Ok(mut value) => {
+                let key = decode_key(&value);
+                value.zeroize();
+                Ok(Some(key?.0))
+            }
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(anyhow!(e)).context("reading key slot"),
+        }
+    }
+
+    /// Drop the keyring entry of slot `slot`; `false` when there was none.
+    pub fn delete_slot_key(&self, slot: i64) -> Result<bool> {
+        delete_keyring_entry(&self.keyring_service, &self.slot_account(slot))
+    }
+
+    fn slot_account(&self, slot: i64) -> String {
+        format!("{}-slot{slot}", self.keyring_account)
+    }
+
+    /// Validate a base64 master key and store it in the keyring entry of this provider.
+    pub fn store_in_keyring(&self, encoded: &str) -> Result<()> {
+        decode_key(encoded)?;
+        self.write_keyring(encoded.trim())?;
+        info!("master key stored in keyring");
+        Ok(())
+    }
+
+    /// Keep this machine's device secret key in the keyring, next to the master key entry.
+    pub fn store_device_key(&self, device_id: Uuid, secret: &[u8; 32]) -> Result<()> {
+        if !self.src.allow_keyring {
+            return Err(anyhow!(
+                "device keys are stored in the OS keyring; drop --no-keyring to enroll"
+            ));
+        }
+        let mut encoded = format!("{}:{}", device_id, general_purpose::STANDARD.encode(secret));
+        let result = self
+            .device_entry()?
+            .set_password(&encoded)
+            .context("writing device key to keyring");
+        encoded.zeroize();
+        result?;
+        self.record_in_index(&self.device_account());
+        Ok(())
+    }
+
+    /// This machine's enrolled device id and secret key, if any.
+    pub fn read_device_key(&self) -> Result<Option<(Uuid, [u8; 32])>> {
+        if !self.src.allow_keyring {
+            return Ok(None);
+        }
+        let mut value = match self.device_entry()?.get_password() {
+            Ok(v) => v,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(e) => return Err(anyhow!(e)).context("reading device key"),
+        };
+        let parsed = value
+            .split_once(':')
+            .ok_or_else(|| anyhow!("malformed device key entry"))
+            .and_then(|(id, key)| Ok((Uuid::parse_str(id)?, decode_key(key)?)));
+        value.zeroize();
+        let (id, key) = parsed?;
+        Ok(Some((id, key.0)))
+    }
+
+    fn device_account(&self) -> String {
+        format!("{}-device", self.keyring_account)
+    }
+
+    fn device_entry(&self) -> Result<Entry> {
+        Ok(Entry::new(&self.keyring_service, &self.device_account())?)
+    }
+
     fn read_keyring(&self) -> Result<Option<MasterKey>> {
-        let entry = Entry::new(SERVICE, ACCOUNT)?;
+        let entry = Entry::new(&self.keyring_service, &self.keyring_account)?;
         match entry.get_password() {
             Ok(value) => decode_key(&value).map(Some),
             Err(keyring::Error::NoEntry) => Ok(None),
@@ -104,10 +481,285 @@ impl MasterKeyProvider {
     }
 
     fn write_keyring(&self, encoded: &str) -> Result<()> {
-        let entry = Entry::new(SERVICE, ACCOUNT)?;
+        let entry = Entry::new(&self.keyring_service, &self.keyring_account)?;
         entry.set_password(encoded).context("writing keyring")?;
+        self.record_in_index(&self.keyring_account);
         Ok(())
     }
+
+    /// Best effort: a failure only means `key gc` will not know about the entry.
+    fn record_in_index(&self, account: &str) {
+        let Some((index_path, vault)) = &self.index else {
+            return;
+        };
+        let result = KeyringIndex::load(index_path).and_then(|mut index| {
+            index.record(&self.keyring_service, account, vault);
+            index.save(index_path)
+        });
+        if let Err(e) = result {
+            warn!("cannot update keyring index: {e}");
+        }
+    }
+}
+
+/// A new passphrase, asked for twice so a typo cannot lock anyone out.
+pub fn ask_passphrase_twice(
+    prompter: &dyn Prompter,
+    prompt_text: &str,
+) -> Result<Zeroizing<String>> {
+    let first = Zeroizing::new(prompter.secret(prompt_text)?);
+    if first.is_empty() {
+        bail!("the passphrase cannot be empty");
+    }
+    let second = Zeroizing::new(prompter.secret("Repeat passphrase: ")?);
+    if first != second {
+        bail!("passphrases do not match");
+    }
+    Ok(first)
+}
+
+/// A random recovery key and its printed form: 64 hex digits in groups of four.
+pub fn new_recovery_key() -> ([u8; 32], String) {
+    let key = generate_key().0;
+    let text = key
+        .chunks(2)
+        .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join("-");
+    (key, text)
+}
+
+/// A recovery key as printed by [`new_recovery_key`]; dashes, spaces and case are ignored.
+pub fn parse_recovery_key(text: &str) -> Option<[u8; 32]> {
+    let digits: Vec<u8> = text
+        .bytes()
+        .filter(|b| !matches!(b, b'-' | b' '))
+        .map(|b| (b as char).to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    if digits.len() != 64 {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (byte, pair) in key.iter_mut().zip(digits.chunks(2)) {
+        *byte = pair[0] << 4 | pair[1];
+    }
+    Some(key)
+}
+
+/// How a vault's master key is recovered from its passphrase: Argon2id derives a
+/// key-encryption key, which unwraps the master key. Kept in the vault: none of it is
+/// secret without the passphrase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PassphraseKdf {
+    /// Base64 of the random 16-byte salt
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub lanes: u32,
+    /// Base64 of the master key encrypted under the derived key; absent in vaults from
+    /// before wrapping, whose master key is the derived key itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapped: Option<String>,
+    /// Base64 of a digest of the master key, telling a mistyped passphrase apart
+    /// from a damaged vault
+    pub check: String,
+}
+
+impl PassphraseKdf {
+    /// A fresh random key wrapped under `passphrase` with a new salt and the default
+    /// costs.
+    pub fn create(passphrase: &str) -> Result<(PassphraseKdf, MasterKey)> {
+        Self::create_with_costs(passphrase, ARGON2_MEMORY_KIB, ARGON2_ITERATIONS)
+    }
+
+    pub(crate) fn create_with_costs(
+        passphrase: &str,
+        memory_kib: u32,
+        iterations: u32,
+    ) -> Result<(PassphraseKdf, MasterKey)> {
+        let key = generate_key();
+        let kdf = Self::wrap(&key, passphrase, memory_kib, iterations)?;
+        Ok((kdf, key))
+    }
+
+    /// The same master key wrapped under `passphrase` instead, with a new salt and
+    /// these costs. Nothing encrypted under the master key changes.
+    pub fn rewrap(&self, key: &MasterKey, passphrase: &str) -> Result<PassphraseKdf> {
+        if !self.matches(key) {
+            bail!("that is not the key these passphrase settings protect");
+        }
+        Self::wrap(key, passphrase, self.memory_kib, self.iterations)
+    }
+
+    pub(crate) fn wrap(
+        key: &MasterKey,
+        passphrase: &str,
+        memory_kib: u32,
+        iterations: u32,
+    ) -> Result<PassphraseKdf> {
+        let mut salt = [0u8; 16];
+        rand::rng().fill_bytes(&mut salt);
+        let mut kdf = PassphraseKdf {
+            salt: general_purpose::STANDARD.encode(salt),
+            memory_kib,
+            iterations,
+            lanes: ARGON2_LANES,
+            wrapped: None,
+            check: general_purpose::STANDARD.encode(check_digest(key)),
+        };
+        let wrapped =
+            SecretCrypto::new(kdf.stretch(passphrase)?).encrypt(PASSPHRASE_WRAP_LABEL, &key.0)?;
+        kdf.wrapped = Some(general_purpose::STANDARD.encode(wrapped));
+        Ok(kdf)
+    }
+
+    /// The master key `passphrase` unlocks; an error when it is not this vault's
+    /// passphrase.
+    pub fn derive(&self, passphrase: &str) -> Result<MasterKey> {
+        let derived = self.stretch(passphrase)?;
+        let key = match &self.wrapped {
+            None => derived,
+            Some(wrapped) => {
+                let wrapped = general_purpose::STANDARD
+                    .decode(wrapped)
+                    .context("wrapped passphrase key is not base64")?;
+                let Ok(mut raw) =
+                    SecretCrypto::new(derived).decrypt(PASSPHRASE_WRAP_LABEL, &wrapped)
+                else {
+                    bail!("wrong vault passphrase");
+                };
+                let key = <[u8; 32]>::try_from(raw.as_slice()).map(MasterKey);
+                raw.zeroize();
+                key.map_err(|_| anyhow!("wrapped passphrase key has the wrong length"))?
+            }
+        };
+        if !self.matches(&key) {
+            bail!("wrong vault passphrase");
+        }
+        Ok(key)
+    }
+
+    /// Whether `key` is the one these settings derive.
+    pub fn matches(&self, key: &MasterKey) -> bool {
+        general_purpose::STANDARD.encode(check_digest(key)) == self.check
+    }
+
+    fn stretch(&self, passphrase: &str) -> Result<MasterKey> {
+        let salt = general_purpose::STANDARD
+            .decode(&self.salt)
+            .context("passphrase salt is not base64")?;
+        let params = Params::new(self.memory_kib, self.iterations, self.lanes, Some(32))
+            .map_err(|e| anyhow!("invalid Argon2id settings: {e}"))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow!("deriving the master key: {e}"))?;
+        Ok(MasterKey(key))
+    }
+}
+
+fn check_digest(key: &MasterKey) -> [u8; 16] {
+    let digest = crypto::keyed_digest(key, PASSPHRASE_CHECK_INFO, b"");
+    digest[..16].try_into().expect("digest is 32 bytes")
+}
+
+/// A keyring entry written for one vault
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedEntry {
+    pub service: String,
+    pub account: String,
+    pub vault: PathBuf,
+    pub stored_at: DateTime<Utc>,
+}
+
+/// Keyring entries written by devinventory. OS keyrings cannot be listed portably, so
+/// this is how `key gc` finds them again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KeyringIndex {
+    pub entries: Vec<IndexedEntry>,
+}
+
+impl KeyringIndex {
+    /// The index at `path`; empty when there is none yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.to_string_lossy()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("{} is not a keyring index", path.to_string_lossy()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            perms::create_private_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        perms::make_owner_only(path)
+    }
+
+    pub fn record(&mut self, service: &str, account: &str, vault: &Path) {
+        self.entries
+            .retain(|e| !(e.service == service && e.account == account && e.vault == vault));
+        self.entries.push(IndexedEntry {
+            service: service.to_string(),
+            account: account.to_string(),
+            vault: vault.to_path_buf(),
+            stored_at: Utc::now(),
+        });
+    }
+
+    /// Entries (service, account), sorted, none of whose vaults exist any more. Vaults share an
+    /// entry when they use the same service and account, so one live vault keeps it.
+    pub fn orphans(&self) -> Vec<(String, String)> {
+        let entries: BTreeSet<(&str, &str)> = self
+            .entries
+            .iter()
+            .map(|e| (e.service.as_str(), e.account.as_str()))
+            .collect();
+        entries
+            .into_iter()
+            .filter(|(service, account)| {
+                !self
+                    .entries
+                    .iter()
+                    .any(|e| e.service == *service && e.account == *account && e.vault.exists())
+            })
+            .map(|(service, account)| (service.to_string(), account.to_string()))
+            .collect()
+    }
+
+    pub fn forget(&mut self, service: &str, account: &str) {
+        self.entries
+            .retain(|e| !(e.service == service && e.account == account));
+    }
+}
+
+/// Whether the keyring holds an entry for `service`/`account`.
+pub fn keyring_entry_exists(service: &str, account: &str) -> Result<bool> {
+    match Entry::new(service, account)?.get_password() {
+        Ok(mut value) => {
+            value.zeroize();
+            Ok(true)
+        }
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(anyhow!(e)).context("reading keyring"),
+    }
+}
+
+/// Remove the keyring entry for `service`/`account`; `false` when there was none.
+pub fn delete_keyring_entry(service: &str, account: &str) -> Result<bool> {
+    match Entry::new(service, account)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(anyhow!(e)).context("deleting keyring entry"),
+    }
+}
+
+/// `key` in the base64 form `--dmk` takes.
+pub fn encode_key(key: &MasterKey) -> String {
+    general_purpose::STANDARD.encode(key.0)
 }
 
 fn decode_key(b64: &str) -> Result<MasterKey> {
@@ -123,9 +775,156 @@ fn decode_key(b64: &str) -> Result<MasterKey> {
     Ok(MasterKey(arr))
 }
 
-fn generate_key() -> MasterKey {
+/// A key file's key: base64 text (as `--dmk` takes it) or the 32 raw bytes. Refused
+/// when anyone but the owner may read it.
+fn read_key_file(path: &Path) -> Result<MasterKey> {
+    if !path.exists() {
+        bail!(
+            "key file {} not found; run `init` to create it",
+            path.to_string_lossy()
+        );
+    }
+    if let Some(mode) = perms::exposed_mode(path)? {
+        bail!(
+            "key file {} is accessible to other users (mode {mode:o}); restrict it with chmod 600",
+            path.to_string_lossy()
+        );
+    }
+    let mut bytes = std::fs::read(path)
+        .with_context(|| format!("reading key file {}", path.to_string_lossy()))?;
+    let key = if bytes.len() == 32 {
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(&bytes);
+        Ok(MasterKey(raw))
+    } else {
+        std::str::from_utf8(&bytes)
+            .map_err(|_| anyhow!("invalid base64 master key"))
+            .and_then(decode_key)
+    };
+    bytes.zeroize();
+    key.with_context(|| format!("key file {}", path.to_string_lossy()))
+}
+
+/// Replace `path` with `key` in base64, owner-only and in one step, so a crash never
+/// leaves half a key behind.
+pub fn write_key_file(path: &Path, key: &MasterKey) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut encoded = general_purpose::STANDARD.encode(key.0);
+    encoded.push('\n');
+    let mut file = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("creating a key file in {}", dir.to_string_lossy()))?;
+    let written = std::io::Write::write_all(&mut file, encoded.as_bytes());
+    encoded.zeroize();
+    written?;
+    file.persist(path)
+        .with_context(|| format!("writing key file {}", path.to_string_lossy()))?;
+    perms::make_owner_only(path)
+}
+
+/// A fresh random master key.
+pub fn generate_key() -> MasterKey {
     let mut key = [0u8; 32];
     let mut rng = rand::rng();
     rng.fill_bytes(&mut key);
     MasterKey(key)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_files_round_trip_and_must_be_private() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dmk");
+        let key = generate_key();
+        write_key_file(&path, &key).unwrap();
+        assert_eq!(read_key_file(&path).unwrap().0, key.0);
+        std::fs::write(&path, key.0).unwrap();
+        perms::make_owner_only(&path).unwrap();
+        assert_eq!(read_key_file(&path).unwrap().0, key.0);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+            assert!(read_key_file(&path).is_err());
+        }
+        assert!(read_key_file(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn recovery_keys_read_back_as_printed() {
+        let (key, text) = new_recovery_key();
+        assert_eq!(text.len(), 79);
+        assert_eq!(parse_recovery_key(&text), Some(key));
+        let retyped = text.to_uppercase().replace('-', " ");
+        assert_eq!(parse_recovery_key(&retyped), Some(key));
+        assert_eq!(parse_recovery_key(&text[..70]), None);
+        assert_eq!(parse_recovery_key("correct horse battery staple"), None);
+    }
+
+    #[test]
+    fn changing_the_passphrase_keeps_the_master_key() {
+        let (kdf, key) = PassphraseKdf::create_with_costs("old", 64, 1).unwrap();
+        let changed = kdf.rewrap(&key, "new").unwrap();
+        assert_eq!(changed.derive("new").unwrap().0, key.0);
+        assert_eq!(changed.check, kdf.check);
+        assert_ne!(changed.salt, kdf.salt);
+        assert!(changed.derive("old").is_err());
+        assert!(kdf.rewrap(&generate_key(), "new").is_err());
+
+        // vaults from before wrapping: the derived key is the master key
+        let mut legacy = kdf.clone();
+        legacy.wrapped = None;
+        let derived = legacy.stretch("old").unwrap();
+        legacy.check = general_purpose::STANDARD.encode(check_digest(&derived));
+        assert_eq!(legacy.derive("old").unwrap().0, derived.0);
+        let upgraded = legacy.rewrap(&derived, "new").unwrap();
+        assert_eq!(upgraded.derive("new").unwrap().0, derived.0);
+    }
+
+    #[test]
+    fn passphrases_derive_the_same_key_under_stored_settings() {
+        let (kdf, key) = PassphraseKdf::create_with_costs("correct horse", 64, 1).unwrap();
+        let stored: PassphraseKdf =
+            serde_json::from_str(&serde_json::to_string(&kdf).unwrap()).unwrap();
+        assert_eq!(stored.derive("correct horse").unwrap().0, key.0);
+        assert!(stored.matches(&key));
+        let err = stored.derive("correct horse ").err().unwrap();
+        assert_eq!(err.to_string(), "wrong vault passphrase");
+        let (other, other_key) = PassphraseKdf::create_with_costs("correct horse", 64, 1).unwrap();
+        assert_ne!(other.salt, kdf.salt);
+        assert_ne!(other_key.0, key.0);
+    }
+
+    #[test]
+    fn entries_are_orphaned_once_every_vault_is_gone() {
+        let dir = tempfile::tempdir().unwrap();
+        let live = dir.path().join("live.db");
+        std::fs::write(&live, b"").unwrap();
+        let gone = dir.path().join("gone.db");
+
+        let mut index = KeyringIndex::default();
+        index.record("devinventory", "dmk", &gone);
+        index.record("devinventory", "dmk", &live);
+        index.record("devinventory", "old", &gone);
+        index.record("devinventory", "old-device", &gone);
+        index.record("devinventory", "old", &gone);
+        assert_eq!(index.entries.len(), 4);
+        assert_eq!(
+            index.orphans(),
+            [
+                ("devinventory".to_string(), "old".to_string()),
+                ("devinventory".to_string(), "old-device".to_string())
+            ]
+        );
+
+        let path = dir.path().join("keyring.json");
+        index.forget("devinventory", "old");
+        index.save(&path).unwrap();
+        assert_eq!(KeyringIndex::load(&path).unwrap().entries.len(), 3);
+    }
+}
@@ -0,0 +1,248 @@
+//! Vault keys wrapped by a key that never leaves a PKCS#11 token (an HSM or smart
+//! card), used through OpenSC's `pkcs11-tool` with the vendor's module.
+
+use super::Prompter;
+use crate::crypto::{self, MasterKey};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Program used when `[pkcs11] program` is not set.
+pub const DEFAULT_PROGRAM: &str = "pkcs11-tool";
+/// Label of the wrapping key when `[pkcs11] key_label` is not set.
+pub const DEFAULT_KEY_LABEL: &str = "devinventory";
+/// Mechanism used when `[pkcs11] mechanism` is not set.
+pub const DEFAULT_MECHANISM: &str = "RSA-PKCS-OAEP";
+/// Token PIN; asked for when unset.
+pub const PIN_VAR: &str = "DEVINVENTORY_PKCS11_PIN";
+const CHECK_INFO: &[u8] = b"devinventory pkcs11 check v1";
+
+/// How to reach the token and which of its keys wraps new vault keys, from `[pkcs11]`.
+#[derive(Clone)]
+pub struct Token {
+    /// The vendor's PKCS#11 module, e.g. `/usr/lib/softhsm/libsofthsm2.so`
+    pub module: Option<String>,
+    /// Slot id; the first slot holding a token when unset
+    pub slot: Option<u64>,
+    pub key_label: String,
+    /// Hex object id, used instead of the label when set
+    pub key_id: Option<String>,
+    pub mechanism: String,
+    pub program: String,
+    /// PIN once asked, so a run asks at most once
+    pin: Arc<Mutex<Option<Zeroizing<String>>>>,
+    /// Who is asked for the PIN; without one it must come from `DEVINVENTORY_PKCS11_PIN`
+    prompter: Option<Arc<dyn Prompter>>,
+}
+
+impl Default for Token {
+    fn default() -> Self {
+        Self {
+            module: None,
+            slot: None,
+            key_label: DEFAULT_KEY_LABEL.to_string(),
+            key_id: None,
+            mechanism: DEFAULT_MECHANISM.to_string(),
+            program: DEFAULT_PROGRAM.to_string(),
+            pin: Arc::default(),
+            prompter: None,
+        }
+    }
+}
+
+impl Token {
+    /// Log in with `pin` instead of asking for it.
+    pub fn with_pin(self, pin: String) -> Self {
+        *self.pin.lock().expect("pin cache poisoned") = Some(Zeroizing::new(pin));
+        self
+    }
+
+    /// Ask `prompter` for the PIN when it is neither set nor in the environment.
+    pub fn with_prompter(mut self, prompter: Arc<dyn Prompter>) -> Self {
+        self.prompter = Some(prompter);
+        self
+    }
+
+    fn pin(&self) -> Result<Zeroizing<String>> {
+        if let Ok(pin) = std::env::var(PIN_VAR) {
+            return Ok(Zeroizing::new(pin));
+        }
+        let mut cached = self.pin.lock().expect("pin cache poisoned");
+        if let Some(pin) = cached.as_ref() {
+            return Ok(pin.clone());
+        }
+        let Some(prompter) = &self.prompter else {
+            bail!("no PKCS#11 token PIN; set ${PIN_VAR}");
+        };
+        let pin = Zeroizing::new(prompter.secret("PKCS#11 token PIN: ")?);
+        *cached = Some(pin.clone());
+        Ok(pin)
+    }
+
+    /// `input` encrypted or decrypted (`operation` is `--encrypt` or `--decrypt`) by the
+    /// token key `wrapping`.
+    fn run(&self, operation: &str, wrapping: &KeyRef, input: &[u8]) -> Result<Vec<u8>> {
+        let module = self
+            .module
+            .as_deref()
+            .ok_or_else(|| anyhow!("no PKCS#11 module configured; set [pkcs11] module"))?;
+        let mut command = Command::new(&self.program);
+        command.args(["--module", module]);
+        if let Some(slot) = self.slot {
+            command.args(["--slot", &slot.to_string()]);
+        }
+        match (&wrapping.key_id, &wrapping.key_label) {
+            (Some(id), _) => command.args(["--id", id]),
+            (None, Some(label)) => command.args(["--label", label]),
+            (None, None) => bail!("the PKCS#11 key has neither an id nor a label"),
+        };
+        let pin = self.pin()?;
+        let mut child = command
+            .args(["--login", "--pin", &format!("env:{PIN_VAR}")])
+            .args(["--mechanism", &wrapping.mechanism, operation])
+            .env(PIN_VAR, pin.as_str())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("running '{}'; is OpenSC installed?", self.program))?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(input)
+            .with_context(|| format!("writing to {}", self.program))?;
+        let mut output = child.wait_with_output()?;
+        if !output.status.success() {
+            output.stdout.zeroize();
+            bail!(
+                "{} failed: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+
+    fn key_ref(&self) -> KeyRef {
+        KeyRef {
+            key_label: self.key_id.is_none().then(|| self.key_label.clone()),
+            key_id: self.key_id.clone(),
+            mechanism: self.mechanism.clone(),
+        }
+    }
+}
+
+/// The token key a vault key was wrapped by and how.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRef {
+    pub key_label: Option<String>,
+    pub key_id: Option<String>,
+    pub mechanism: String,
+}
+
+/// A vault master key wrapped by a token key. Kept in the vault: it is useless
+/// without the token and its PIN.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    #[serde(flatten)]
+    pub wrapping: KeyRef,
+    /// Base64 of the master key as the token encrypted it
+    pub wrapped: String,
+    /// Base64 of a digest of the master key, telling another token apart from a
+    /// damaged vault
+    pub check: String,
+}
+
+impl WrappedKey {
+    /// A fresh random master key, wrapped by `token`'s configured key.
+    pub fn create(token: &Token) -> Result<(Self, MasterKey)> {
+        let key = super::generate_key();
+        let wrapping = token.key_ref();
+        let wrapped = token.run("--encrypt", &wrapping, &key.0)?;
+        let settings = Self {
+            wrapping,
+            wrapped: general_purpose::STANDARD.encode(wrapped),
+            check: general_purpose::STANDARD.encode(check_digest(&key)),
+        };
+        Ok((settings, key))
+    }
+
+    /// The master key as `token` unwraps it; an error when that is not this vault's key.
+    pub fn unwrap(&self, token: &Token) -> Result<MasterKey> {
+        let wrapped = general_purpose::STANDARD
+            .decode(&self.wrapped)
+            .context("wrapped PKCS#11 key is not base64")?;
+        let mut plain = token.run("--decrypt", &self.wrapping, &wrapped)?;
+        let key = <[u8; 32]>::try_from(plain.as_slice()).map(MasterKey);
+        plain.zeroize();
+        match key {
+            Ok(key) if self.matches(&key) => Ok(key),
+            _ => bail!("the PKCS#11 token did not unwrap this vault's key"),
+        }
+    }
+
+    /// Whether `key` is the one wrapped here.
+    pub fn matches(&self, key: &MasterKey) -> bool {
+        general_purpose::STANDARD.encode(check_digest(key)) == self.check
+    }
+}
+
+fn check_digest(key: &MasterKey) -> [u8; 16] {
+    let digest = crypto::keyed_digest(key, CHECK_INFO, b"");
+    digest[..16].try_into().expect("digest is 32 bytes")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// A stand-in for `pkcs11-tool` that wants PIN 1234 and "encrypts" by prefixing
+    /// its input, answering `--decrypt` with `unwrap`.
+    fn fake(dir: &tempfile::TempDir, name: &str, unwrap: &str) -> Token {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.path().join(name);
+        std::fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\n[ \"${PIN_VAR}\" = 1234 ] || {{ echo CKR_PIN_INCORRECT >&2; exit 1; }}\n\
+                 case \" $* \" in\n*\" --encrypt\"*) printf tok:; cat ;;\n*\" --decrypt\"*) {unwrap} ;;\nesac\n"
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700)).unwrap();
+        Token {
+            module: Some("/usr/lib/fake-pkcs11.so".into()),
+            program: path.to_string_lossy().into_owned(),
+            ..Token::default()
+        }
+    }
+
+    #[test]
+    fn keys_unwrap_only_on_their_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let token = fake(&dir, "pkcs11-tool", "tail -c +5").with_pin("1234".into());
+        let (wrapped, key) = WrappedKey::create(&token).unwrap();
+        assert_eq!(wrapped.wrapping.key_label.as_deref(), Some("devinventory"));
+        assert_ne!(general_purpose::STANDARD.encode(key.0), wrapped.wrapped);
+        assert_eq!(wrapped.unwrap(&token).unwrap().0, key.0);
+
+        let other = fake(&dir, "other", "head -c 32 /dev/zero").with_pin("1234".into());
+        let err = wrapped.unwrap(&other).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "the PKCS#11 token did not unwrap this vault's key"
+        );
+        let wrong_pin = fake(&dir, "pkcs11-tool", "tail -c +5").with_pin("0000".into());
+        assert!(wrapped.unwrap(&wrong_pin).is_err());
+        let unconfigured = Token {
+            module: None,
+            ..token
+        };
+        assert!(WrappedKey::create(&unconfigured).is_err());
+    }
+}
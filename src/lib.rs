@@ -0,0 +1,38 @@
+//! Core of DevInventory: local, encrypted storage for infrastructure secrets.
+
+pub mod agent;
+pub mod attest;
+pub mod audit;
+pub mod autostart;
+pub mod aws;
+pub mod backup;
+pub mod blueprint;
+pub mod cert;
+pub mod config;
+pub mod crypto;
+pub mod curl;
+pub mod db;
+pub mod docker;
+pub mod domain;
+pub mod drift;
+pub mod export;
+pub mod generator;
+pub mod host;
+pub mod import;
+pub mod jwt;
+pub mod keymgr;
+pub mod kube;
+pub mod oplog;
+pub mod perms;
+pub mod registry;
+pub mod report;
+pub mod rotation;
+pub mod scan;
+pub mod service;
+pub mod template;
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod testing;
+pub mod totp;
+pub mod transform;
+pub mod ui;
+pub mod yubikey;
@@ -1,21 +1,94 @@
-mod cli;
-mod config;
-mod crypto;
-mod db;
-mod domain;
-mod keymgr;
-
 use anyhow::Result;
-use env_logger::Env;
-use log::info;
+use devinventory::{
+    audit, config::Config, db::Repository, keymgr::MasterKeyProvider, service::SecretService, ui,
+    ui::cli::Cli,
+};
+use log::{debug, info, warn};
+use std::sync::Arc;
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
-    // Initialize logger early; default to info level but allow RUST_LOG override.
-    env_logger::Builder::from_env(Env::default().default_filter_or("info"))
-        .format_timestamp_secs()
-        .init();
+    let cli = Cli::from_env();
+    // reference pages must work without a vault or even a valid config file
+    if let ui::cli::Commands::Explain { topic } = cli.command {
+        print!("{}", ui::explain::render(topic));
+        return Ok(());
+    }
+    if let ui::cli::Commands::Completions { shell } = cli.command {
+        return ui::cli::print_completions(shell);
+    }
+    let config = Config::build(cli.config_overrides(), cli.master_key_source())?;
+    init_logger(&config, cli.verbose);
+    ui::prompt::init(&config.file.security);
 
     info!("starting devinventory CLI");
-    cli::run().await
+
+    let repo = if config.ephemeral {
+        info!("using an in-memory ephemeral vault");
+        Repository::in_memory().await?
+    } else {
+        info!("opening database at {}", config.db_path.to_string_lossy());
+        Repository::connect(&config.db_path).await?
+    };
+    repo.migrate().await?;
+    debug!("database migrations ensured");
+
+    // nothing here reads the key: the service asks the provider the first time a
+    // command decrypts, so metadata-only commands never touch the keyring
+    let mut key_provider = MasterKeyProvider::new(
+        config.master_key_source.clone(),
+        config.keyring_service.clone(),
+        config.keyring_account.clone(),
+    )
+    .with_yubikey(config.file.yubikey.device())
+    .with_pkcs11(config.file.pkcs11.token())
+    .with_prompter(Arc::new(ui::prompt::Terminal));
+    if !config.ephemeral {
+        key_provider = key_provider.with_index(
+            Config::keyring_index_path()?,
+            std::path::absolute(&config.db_path)?,
+        );
+    }
+    let service = SecretService::new(repo, key_provider)
+        .with_protected_prefixes(config.file.approval.protected_prefixes.clone())
+        .with_reencrypt_on_read(config.file.crypto.reencrypt_on_read)
+        .with_reveal_limit(config.file.reveal.max_per_hour)
+        .with_value_limits(
+            config.file.limits.warn_value_bytes(),
+            config.file.limits.max_value_bytes(),
+        );
+
+    if let Some(seed) = &cli.seed {
+        let summary = service.merge_operations(ui::cli::read_oplog(seed)?).await?;
+        info!(
+            "seeded ephemeral vault with {} operations from {}",
+            summary.new_ops,
+            seed.to_string_lossy()
+        );
+    }
+    let output = cli.output(&config);
+    let result = ui::cli::run_cli(cli.command, output, &config, &service).await;
+    if let Some(dump) = &cli.dump {
+        let count = ui::cli::write_oplog(&service, dump).await?;
+        info!("dumped {} operations to {}", count, dump.to_string_lossy());
+    }
+    if let Some(target) = &config.file.audit.ship_to {
+        // best effort: entries left behind go out with the next command
+        if let Err(e) = async { service.ship_audit(&audit::Sink::parse(target)?).await }.await {
+            warn!("audit entries not shipped to {target}: {e:#}");
+        }
+    }
+    result
+}
+
+/// Level comes from `[logging] level` (default info) raised by `-v`; RUST_LOG, when set, wins.
+fn init_logger(config: &Config, verbose: u8) {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(config.log_level_with_verbosity(verbose))
+        .format_timestamp_secs();
+    if let Ok(spec) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&spec);
+    }
+    builder.init();
 }
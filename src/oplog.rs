@@ -0,0 +1,112 @@
+//! Append-only operation log with vector clocks, so offline replicas can merge deterministically.
+
+use crate::db::SecretRecord;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Per-replica operation counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VectorClock(pub BTreeMap<String, u64>);
+
+impl VectorClock {
+    /// Advance this replica's counter.
+    pub fn tick(&mut self, replica: &str) {
+        *self.0.entry(replica.to_string()).or_default() += 1;
+    }
+
+    /// Pointwise maximum with `other`.
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (replica, &count) in &other.0 {
+            let entry = self.0.entry(replica.clone()).or_default();
+            *entry = (*entry).max(count);
+        }
+    }
+
+    /// True when `self` has seen everything `other` has, and more.
+    pub fn dominates(&self, other: &VectorClock) -> bool {
+        let get = |c: &VectorClock, r: &str| c.0.get(r).copied().unwrap_or(0);
+        let covers = other.0.keys().all(|r| get(self, r) >= get(other, r));
+        covers && self != other
+    }
+
+    fn total(&self) -> u64 {
+        self.0.values().sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpKind {
+    Put,
+    Delete,
+}
+
+impl OpKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpKind::Put => "put",
+            OpKind::Delete => "delete",
+        }
+    }
+}
+
+/// One mutation of one secret. `Put` carries the full resulting record (ciphertext included).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub op_id: Uuid,
+    pub replica: String,
+    pub clock: VectorClock,
+    pub kind: OpKind,
+    pub secret_name: String,
+    pub record: Option<SecretRecord>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The operation that defines a secret's current state: one no other operation
+/// dominates, with concurrent ones ordered by clock total, replica id, then op id.
+pub fn winner(ops: &[Operation]) -> Option<&Operation> {
+    ops.iter()
+        .filter(|op| !ops.iter().any(|o| o.clock.dominates(&op.clock)))
+        .max_by(|a, b| {
+            (a.clock.total(), &a.replica, a.op_id).cmp(&(b.clock.total(), &b.replica, b.op_id))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(replica: &str, clock: &[(&str, u64)], kind: OpKind) -> Operation {
+        Operation {
+            op_id: Uuid::new_v4(),
+            replica: replica.into(),
+            clock: VectorClock(clock.iter().map(|(r, c)| (r.to_string(), *c)).collect()),
+            kind,
+            secret_name: "api".into(),
+            record: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn later_operation_wins_over_what_it_saw() {
+        let first = op("a", &[("a", 1)], OpKind::Put);
+        let delete = op("b", &[("a", 1), ("b", 1)], OpKind::Delete);
+        let ops = vec![delete.clone(), first];
+        assert_eq!(winner(&ops).unwrap().op_id, delete.op_id);
+    }
+
+    #[test]
+    fn concurrent_operations_resolve_independent_of_order() {
+        let a = op("a", &[("a", 2)], OpKind::Put);
+        let b = op("b", &[("a", 1), ("b", 1)], OpKind::Put);
+        let forward = vec![a.clone(), b.clone()];
+        let backward = vec![b, a];
+        assert_eq!(
+            winner(&forward).unwrap().op_id,
+            winner(&backward).unwrap().op_id
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! File permission helpers that keep vault files private to the current user.
+
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+/// Create `dir` and any missing parents; new directories get mode 0700 on unix.
+pub fn create_private_dir_all(dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder
+        .create(dir)
+        .with_context(|| format!("creating directory {}", dir.to_string_lossy()))
+}
+
+/// Restrict `path` to owner read/write (0600) on unix; no-op elsewhere.
+pub fn make_owner_only(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("setting permissions on {}", path.to_string_lossy()))?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// The unix mode of `path` when it grants any group/world access, `None` otherwise.
+pub fn exposed_mode(path: &Path) -> Result<Option<u32>> {
+    #[cfg(unix)]
+    {
+        let mode = fs::metadata(path)
+            .with_context(|| format!("reading metadata of {}", path.to_string_lossy()))?
+            .permissions()
+            .mode()
+            & 0o777;
+        Ok((mode & 0o077 != 0).then_some(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(None)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_and_fixes_exposed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("vault.db");
+        fs::write(&file, b"").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(exposed_mode(&file).unwrap(), Some(0o644));
+
+        make_owner_only(&file).unwrap();
+        assert_eq!(exposed_mode(&file).unwrap(), None);
+    }
+}
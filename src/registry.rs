@@ -0,0 +1,68 @@
+//! Publish-token injection for package registries (cargo, npm).
+
+/// Default npm registry, used when no `--registry` is given.
+pub const NPM_DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
+
+/// Variable holding the npm token; the generated npmrc refers to it instead of the value.
+pub const NPM_TOKEN_VAR: &str = "NPM_TOKEN";
+
+/// Environment variable cargo reads the token of `registry` from (`None` = crates.io).
+pub fn cargo_token_var(registry: Option<&str>) -> String {
+    match registry {
+        None | Some("crates-io") => "CARGO_REGISTRY_TOKEN".to_string(),
+        Some(name) => {
+            let name: String = name
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() {
+                        c.to_ascii_uppercase()
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            format!("CARGO_REGISTRIES_{name}_TOKEN")
+        }
+    }
+}
+
+/// npmrc contents authenticating `registry_url` through `${NPM_TOKEN}`.
+pub fn npmrc(registry_url: &str) -> String {
+    let host_path = registry_url
+        .trim_start_matches("https:")
+        .trim_start_matches("http:");
+    let host_path = if host_path.ends_with('/') {
+        host_path.to_string()
+    } else {
+        format!("{host_path}/")
+    };
+    format!("registry={registry_url}\n{host_path}:_authToken=${{{NPM_TOKEN_VAR}}}\n")
+}
+
+/// `export VAR='value'` with POSIX single-quote escaping.
+pub fn shell_export(var: &str, value: &str) -> String {
+    format!("export {var}='{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cargo_vars_follow_registry_names() {
+        assert_eq!(cargo_token_var(None), "CARGO_REGISTRY_TOKEN");
+        assert_eq!(
+            cargo_token_var(Some("my-registry")),
+            "CARGO_REGISTRIES_MY_REGISTRY_TOKEN"
+        );
+    }
+
+    #[test]
+    fn npmrc_references_token_variable() {
+        assert_eq!(
+            npmrc("https://npm.example.com/repo"),
+            "registry=https://npm.example.com/repo\n//npm.example.com/repo/:_authToken=${NPM_TOKEN}\n"
+        );
+        assert_eq!(shell_export("T", "a'b"), r"export T='a'\''b'");
+    }
+}
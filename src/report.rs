@@ -0,0 +1,165 @@
+//! Shareable inventory reports: metadata only, never values.
+
+use crate::domain::SecretMetadata;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Markdown table
+    Md,
+    /// Standalone HTML page
+    Html,
+    /// CSV with a header row
+    Csv,
+}
+
+const HEADERS: [&str; 6] = [
+    "name",
+    "kind",
+    "owner",
+    "contact",
+    "expires",
+    "last rotated",
+];
+
+/// Render the inventory of `secrets` as of `generated_at`.
+pub fn inventory(
+    secrets: &[SecretMetadata],
+    format: Format,
+    generated_at: DateTime<Utc>,
+) -> String {
+    let rows: Vec<[String; 6]> = secrets
+        .iter()
+        .map(|s| {
+            [
+                s.name.clone(),
+                s.kind.clone().unwrap_or_default(),
+                s.owner.clone().unwrap_or_default(),
+                s.contact.clone().unwrap_or_default(),
+                s.expires_at.map(day).unwrap_or_default(),
+                day(s.updated_at),
+            ]
+        })
+        .collect();
+    let generated = generated_at.format("%Y-%m-%d %H:%M UTC");
+    match format {
+        Format::Md => {
+            let mut out = format!(
+                "# Secret inventory\n\nGenerated {generated}; {} secret(s). Values are never included.\n\n",
+                rows.len()
+            );
+            out.push_str(&md_row(&HEADERS.map(String::from)));
+            out.push_str(&format!("|{}\n", "---|".repeat(HEADERS.len())));
+            for row in &rows {
+                out.push_str(&md_row(row));
+            }
+            out
+        }
+        Format::Html => {
+            let cells = |tag: &str, row: &[String]| -> String {
+                row.iter()
+                    .map(|c| format!("<{tag}>{}</{tag}>", html_escape(c)))
+                    .collect()
+            };
+            let body: String = rows
+                .iter()
+                .map(|row| format!("<tr>{}</tr>\n", cells("td", row)))
+                .collect();
+            format!(
+                "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Secret inventory</title></head>\n<body>\n<h1>Secret inventory</h1>\n<p>Generated {generated}; {} secret(s). Values are never included.</p>\n<table>\n<tr>{}</tr>\n{body}</table>\n</body></html>\n",
+                rows.len(),
+                cells("th", &HEADERS.map(String::from))
+            )
+        }
+        Format::Csv => {
+            let mut out = csv_row(&HEADERS.map(String::from));
+            for row in &rows {
+                out.push_str(&csv_row(row));
+            }
+            out
+        }
+    }
+}
+
+fn day(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d").to_string()
+}
+
+fn md_row(cells: &[String]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|c| c.replace('|', "\\|").replace('\n', " "))
+        .collect();
+    format!("| {} |\n", cells.join(" | "))
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn csv_row(cells: &[String]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|c| {
+            if c.contains([',', '"', '\n']) {
+                format!("\"{}\"", c.replace('"', "\"\""))
+            } else {
+                c.clone()
+            }
+        })
+        .collect();
+    format!("{}\n", cells.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn secret(name: &str, owner: Option<&str>) -> SecretMetadata {
+        let at = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+        SecretMetadata {
+            id: uuid::Uuid::nil(),
+            name: name.into(),
+            kind: Some("token".into()),
+            note: Some("never shown".into()),
+            owner: owner.map(str::to_string),
+            contact: None,
+            immutable: false,
+            bind_host: false,
+            allowed_commands: Vec::new(),
+            expires_at: None,
+            certificate: None,
+            fields: Default::default(),
+            provenance: None,
+            created_at: at,
+            updated_at: at,
+        }
+    }
+
+    #[test]
+    fn renders_each_format_with_escaping() {
+        let secrets = [
+            secret("ci/token", Some("a|b")),
+            secret("x", Some("<ops>, \"core\"")),
+        ];
+        let at = Utc.with_ymd_and_hms(2025, 3, 2, 8, 30, 0).unwrap();
+
+        let md = inventory(&secrets, Format::Md, at);
+        assert!(md.contains("| ci/token | token | a\\|b |  |  | 2025-03-01 |\n"));
+        let html = inventory(&secrets, Format::Html, at);
+        assert!(html.contains("<td>&lt;ops&gt;, &quot;core&quot;</td>"));
+        let csv = inventory(&secrets, Format::Csv, at);
+        assert_eq!(
+            csv,
+            "name,kind,owner,contact,expires,last rotated\n\
+             ci/token,token,a|b,,,2025-03-01\n\
+             x,token,\"<ops>, \"\"core\"\"\",,,2025-03-01\n"
+        );
+        assert!(!md.contains("never shown"));
+    }
+}
@@ -0,0 +1,180 @@
+//! Batch rotation of secret values: which secrets to rotate and where new values
+//! come from.
+
+use crate::domain::{Origin, Provenance, Secret, SecretMetadata};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::RngCore;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Environment variable telling a rotation hook which secret it is rotating.
+pub const HOOK_NAME_VAR: &str = "DEVINVENTORY_SECRET_NAME";
+
+/// Secrets a batch rotation applies to; every given criterion must match.
+#[derive(Debug, Clone, Default)]
+pub struct Selector {
+    pub prefix: Option<String>,
+    pub kind: Option<String>,
+    pub tag: Option<String>,
+    /// Custom fields the secret must carry with exactly these values
+    pub fields: Vec<(String, String)>,
+}
+
+impl Selector {
+    pub fn is_empty(&self) -> bool {
+        self.prefix.is_none() && self.kind.is_none() && self.tag.is_none() && self.fields.is_empty()
+    }
+
+    /// Whether `secret`, carrying `tags`, meets every criterion.
+    pub fn matches(&self, secret: &SecretMetadata, tags: &[String]) -> bool {
+        self.prefix
+            .as_deref()
+            .is_none_or(|prefix| secret.name.starts_with(prefix))
+            && self
+                .kind
+                .as_deref()
+                .is_none_or(|kind| secret.kind.as_deref() == Some(kind))
+            && self.tag.as_ref().is_none_or(|tag| tags.contains(tag))
+            && self
+                .fields
+                .iter()
+                .all(|(key, value)| secret.fields.get(key) == Some(value))
+    }
+}
+
+/// Where a rotated secret's new value comes from.
+#[derive(Debug, Clone)]
+pub enum Provider {
+    /// 32 random bytes, base64url encoded
+    Random,
+    /// An external hook: gets the current value on stdin and the secret name in
+    /// `DEVINVENTORY_SECRET_NAME`, prints the new value on stdout
+    Command(Vec<String>),
+}
+
+impl Provider {
+    pub fn new_value(&self, current: &Secret) -> Result<Vec<u8>> {
+        match self {
+            Provider::Random => Ok(random_value().into_bytes()),
+            Provider::Command(argv) => run_hook(argv, current),
+        }
+    }
+
+    /// Provenance of the values this provider hands out.
+    pub fn provenance(&self) -> Provenance {
+        match self {
+            Provider::Random => Provenance::new(Origin::Rotation, "random"),
+            // the program only; arguments may carry credentials
+            Provider::Command(argv) => {
+                Provenance::new(Origin::Rotation, argv.first().cloned().unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// Outcome of a batch rotation; one failure does not stop the others.
+#[derive(Debug, Default)]
+pub struct RotationSummary {
+    pub rotated: Vec<String>,
+    /// Names with the reason they kept their old value
+    pub failed: Vec<(String, String)>,
+}
+
+/// 32 random bytes, base64url encoded.
+pub fn random_value() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn run_hook(argv: &[String], current: &Secret) -> Result<Vec<u8>> {
+    let (program, args) = argv
+        .split_first()
+        .ok_or_else(|| anyhow!("no rotation command given"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .env(HOOK_NAME_VAR, &current.name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("starting rotation hook '{program}'"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a hook that ignores the old value may exit before reading it
+        let _ = stdin.write_all(&current.plaintext);
+    }
+    let out = child.wait_with_output()?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let reason = stderr.lines().last().unwrap_or("").trim();
+        bail!("rotation hook failed ({}): {reason}", out.status);
+    }
+    let mut value = out.stdout;
+    while value.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+        value.pop();
+    }
+    if value.is_empty() {
+        bail!("rotation hook printed no new value");
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SecretAttributes;
+    use crate::testing::TempVault;
+
+    #[tokio::test]
+    async fn selects_by_every_criterion_and_runs_hooks() {
+        let aws = SecretAttributes {
+            kind: Some("token".into()),
+            fields: [("provider".to_string(), "aws".to_string())].into(),
+            ..Default::default()
+        };
+        let vault = TempVault::builder()
+            .with_sample_data()
+            .with_secret_attrs("prod/aws/key", "old", aws)
+            .build()
+            .await
+            .unwrap();
+        let secrets = vault.service.list_secrets().await.unwrap();
+        let names = |selector: &Selector| -> Vec<String> {
+            secrets
+                .iter()
+                .filter(|s| selector.matches(s, &[]))
+                .map(|s| s.name.clone())
+                .collect()
+        };
+        let by_field = Selector {
+            fields: vec![("provider".into(), "aws".into())],
+            ..Default::default()
+        };
+        assert_eq!(names(&by_field), ["prod/aws/key"]);
+        let prod_tokens = Selector {
+            prefix: Some("prod/".into()),
+            kind: Some("token".into()),
+            ..Default::default()
+        };
+        assert_eq!(names(&prod_tokens), ["prod/api/token", "prod/aws/key"]);
+        let tagged = Selector {
+            tag: Some("aws".into()),
+            ..Default::default()
+        };
+        assert!(names(&tagged).is_empty());
+        assert!(tagged.matches(&secrets[0], &["aws".into()]));
+
+        let current = vault.service.get_secret("prod/aws/key").await.unwrap();
+        let hook = |script: &str| Provider::Command(vec!["sh".into(), "-c".into(), script.into()]);
+        let value = hook("printf '%s-%s\\n' \"$DEVINVENTORY_SECRET_NAME\" \"$(cat)\"")
+            .new_value(&current)
+            .unwrap();
+        assert_eq!(value, b"prod/aws/key-old");
+        let err = hook("echo denied >&2; exit 3")
+            .new_value(&current)
+            .unwrap_err();
+        assert!(err.to_string().contains("denied"));
+        assert_eq!(Provider::Random.new_value(&current).unwrap().len(), 43);
+    }
+}
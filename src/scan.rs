@@ -0,0 +1,132 @@
+//! Leak scanning: look for vault values in files about to be committed.
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Values shorter than this are skipped; they match too much unrelated text.
+pub const MIN_SCAN_LEN: usize = 8;
+
+/// Per-repo allow-list of secret names, one per line, `#` comments.
+pub const ALLOW_FILE: &str = ".devinventory-allow";
+
+/// Marker line identifying hooks written by `hooks install`.
+pub const HOOK_MARKER: &str = "# installed by devinventory hooks install";
+
+/// A vault value found in scanned content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub path: String,
+    pub secret_name: String,
+}
+
+/// Names of the secrets whose value occurs in `content`.
+pub fn find_leaks<'a>(content: &[u8], secrets: &'a [(String, Vec<u8>)]) -> Vec<&'a str> {
+    secrets
+        .iter()
+        .filter(|(_, value)| value.len() >= MIN_SCAN_LEN)
+        .filter(|(_, value)| content.windows(value.len()).any(|w| w == value.as_slice()))
+        .map(|(name, _)| name.as_str())
+        .collect()
+}
+
+/// Parse the allow-list file format.
+pub fn parse_allow_list(text: &str) -> HashSet<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Read the allow-list at the root of `repo`, empty when absent.
+pub fn read_allow_list(repo: &Path) -> Result<HashSet<String>> {
+    let path = repo.join(ALLOW_FILE);
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("reading {}", path.to_string_lossy()))?;
+    Ok(parse_allow_list(&text))
+}
+
+/// Top-level directory of the git work tree containing the current directory.
+pub fn git_toplevel() -> Result<PathBuf> {
+    Ok(PathBuf::from(
+        git(&["rev-parse", "--show-toplevel"])?.trim(),
+    ))
+}
+
+/// Directory git runs hooks from (honors `core.hooksPath`).
+pub fn git_hooks_dir() -> Result<PathBuf> {
+    let dir = PathBuf::from(git(&["rev-parse", "--git-path", "hooks"])?.trim());
+    Ok(std::path::absolute(dir)?)
+}
+
+/// Staged (added, copied, modified, renamed) paths with their index contents.
+pub fn staged_files() -> Result<Vec<(String, Vec<u8>)>> {
+    let names = git(&[
+        "diff",
+        "--cached",
+        "--name-only",
+        "-z",
+        "--diff-filter=ACMR",
+    ])?;
+    let mut files = Vec::new();
+    for path in names.split('\0').filter(|p| !p.is_empty()) {
+        let out = Command::new("git")
+            .args(["show", &format!(":{path}")])
+            .output()
+            .context("running git show")?;
+        if !out.status.success() {
+            bail!("git show :{path} failed");
+        }
+        files.push((path.to_string(), out.stdout));
+    }
+    Ok(files)
+}
+
+/// Content of the git pre-commit hook.
+pub fn pre_commit_hook() -> String {
+    format!("#!/bin/sh\n{HOOK_MARKER}\nexec devinventory scan --staged\n")
+}
+
+fn git(args: &[&str]) -> Result<String> {
+    let out = Command::new("git")
+        .args(args)
+        .output()
+        .context("running git")?;
+    if !out.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_long_values_only() {
+        let secrets = vec![
+            ("db/pass".to_string(), b"hunter2-long".to_vec()),
+            ("pin".to_string(), b"1234".to_vec()),
+        ];
+        let content = b"password = hunter2-long\npin = 1234\n";
+        assert_eq!(find_leaks(content, &secrets), vec!["db/pass"]);
+        assert!(find_leaks(b"nothing here", &secrets).is_empty());
+    }
+
+    #[test]
+    fn allow_list_skips_comments_and_blanks() {
+        let allow = parse_allow_list("# fixtures\ntest/dummy\n\n  ci/token  \n");
+        assert_eq!(allow.len(), 2);
+        assert!(allow.contains("test/dummy"));
+        assert!(allow.contains("ci/token"));
+    }
+}
@@ -0,0 +1,4846 @@
+use crate::{
+    attest::{self, Snapshot},
+    audit,
+    backup::{BackupEntry, BackupFile, LostSecret, Protection, RestoreStrategy, SalvageManifest},
+    blueprint::Blueprint,
+    cert,
+    crypto::{self, Cipher, Envelope, MasterKey, SecretCrypto},
+    db::{Repository, SecretRecord},
+    domain::{
+        self, ApprovalRequest, AuditAction, AuditEntry, AuditFilter, Device, IntegrityReport,
+        KeySlot, Lease, MetadataPatch, Origin, Provenance, Secret, SecretAttributes, SecretLock,
+        SecretMetadata, SecretRef, SlotKind,
+    },
+    host,
+    import::ImportItem,
+    keymgr::{self, MasterKeyProvider, PassphraseKdf, pkcs11::WrappedKey},
+    oplog::{self, OpKind, Operation, VectorClock},
+    rotation::{self, Provider, RotationSummary, Selector},
+    totp::{self, Totp},
+    yubikey::YubikeyChallenge,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, SubsecRound, Utc};
+use log::{debug, info, warn};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+const REPLICA_KEY: &str = "replica_id";
+const CLOCK_KEY: &str = "vector_clock";
+const INTEGRITY_KEY: &str = "integrity_check";
+const PASSPHRASE_KDF_KEY: &str = "passphrase_kdf";
+const YUBIKEY_CHALLENGE_KEY: &str = "yubikey_challenge";
+const PKCS11_KEY_KEY: &str = "pkcs11_wrapped_key";
+const CIPHER_KEY: &str = "cipher";
+const VALUE_FINGERPRINT_INFO: &[u8] = b"devinventory value fingerprint v1";
+/// Marks lease tokens so they are recognisable in logs and secret scanners.
+const LEASE_TOKEN_PREFIX: &str = "dvl_";
+
+/// Result of merging another replica's operation log
+#[derive(Debug, Default)]
+pub struct MergeSummary {
+    pub received: usize,
+    pub new_ops: usize,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+/// Storage used by one secret
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretSize {
+    pub name: String,
+    /// Bytes of the current ciphertext
+    pub ciphertext: i64,
+    /// Bytes of the records kept for it in the operation log
+    pub history: i64,
+}
+
+/// Aggregates for periodic hygiene reviews
+#[derive(Debug, Clone)]
+pub struct VaultStats {
+    pub secrets: i64,
+    pub trashed: i64,
+    /// Bytes of every stored ciphertext, trash included
+    pub ciphertext_bytes: i64,
+    pub database_bytes: i64,
+    /// Secrets per kind, most common first; `None` counts secrets without a kind
+    pub by_kind: Vec<(Option<String>, i64)>,
+    /// Secrets per tag, by tag name
+    pub by_tag: Vec<(String, i64)>,
+    /// Secrets changed longest ago with when that was, oldest first
+    pub oldest: Vec<(String, DateTime<Utc>)>,
+}
+
+/// What an import did with each item
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    /// Names already in the vault, left alone without `overwrite`
+    pub skipped: Vec<String>,
+}
+
+/// What seeding a vault template created
+#[derive(Debug, Default)]
+pub struct TemplateSummary {
+    /// Names created and the entropy of their values, in bits
+    pub created: Vec<(String, f64)>,
+    /// Names already in the vault, left alone
+    pub skipped: Vec<String>,
+}
+
+/// What a backup restore did with each entry
+#[derive(Debug, Default)]
+pub struct RestoreSummary {
+    pub added: Vec<String>,
+    pub overwritten: Vec<String>,
+    /// Names already in the vault, left alone
+    pub skipped: Vec<String>,
+    /// Backup name and the name it was restored under
+    pub renamed: Vec<(String, String)>,
+}
+
+/// How a promoted secret compares with what is already at the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromoteStatus {
+    New,
+    Changed,
+    Unchanged,
+}
+
+/// One secret of a `promote` plan
+#[derive(Debug, Clone)]
+pub struct PromoteItem {
+    pub source: String,
+    pub target: String,
+    pub status: PromoteStatus,
+}
+
+/// How a secret in this vault compares with the same name in another vault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultDiffStatus {
+    /// Only in this vault
+    Added,
+    /// Only in the other vault
+    Removed,
+    /// In both, last updated at different times
+    Changed,
+}
+
+/// One name that differs between two vaults, compared by `updated_at`
+#[derive(Debug, Clone)]
+pub struct VaultDiffItem {
+    pub name: String,
+    pub status: VaultDiffStatus,
+    /// Last update in this vault
+    pub here: Option<DateTime<Utc>>,
+    /// Last update in the other vault
+    pub there: Option<DateTime<Utc>>,
+    /// Bound to this machine, so never copied
+    pub host_bound: bool,
+}
+
+impl VaultDiffItem {
+    /// Whether a sync from this vault would copy it.
+    pub fn newer_here(&self) -> bool {
+        match (self.here, self.there) {
+            (Some(_), None) => true,
+            (Some(here), Some(there)) => here > there,
+            _ => false,
+        }
+    }
+}
+
+/// What a one-way sync did with each differing name
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    pub copied: Vec<String>,
+    /// Names left alone in the target, with why
+    pub skipped: Vec<(String, String)>,
+    /// Names only in the target, moved to its trash
+    pub trashed: Vec<String>,
+}
+
+/// What a transfer of several secrets to another vault did with each
+#[derive(Debug, Default)]
+pub struct TransferSummary {
+    pub copied: Vec<String>,
+    /// Names left alone, with why
+    pub skipped: Vec<(String, String)>,
+}
+
+/// A name an import or sync would write over, with both versions for a person to
+/// choose between. Values are only shown as keyed fingerprints.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub name: String,
+    /// The version in the vault being written to
+    pub existing: SecretMetadata,
+    pub incoming: SecretAttributes,
+    /// Last update of the incoming version, when its source keeps one
+    pub incoming_updated: Option<DateTime<Utc>>,
+    /// Equal fingerprints mean equal values
+    pub existing_fingerprint: String,
+    pub incoming_fingerprint: String,
+    pub existing_size: usize,
+    pub incoming_size: usize,
+}
+
+/// Where a vault's master key comes from when it is not kept in the keyring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyOrigin {
+    /// Random, wrapped under a key derived from a passphrase with Argon2id
+    Passphrase,
+    /// Derived from a YubiKey's HMAC-SHA1 answer to a stored challenge
+    Yubikey,
+    /// Random, wrapped by a key inside a PKCS#11 token
+    Pkcs11,
+}
+
+impl KeyOrigin {
+    const ALL: [KeyOrigin; 3] = [KeyOrigin::Passphrase, KeyOrigin::Yubikey, KeyOrigin::Pkcs11];
+
+    /// Vault meta entry holding what the key is derived or unwrapped from
+    fn meta_key(self) -> &'static str {
+        match self {
+            KeyOrigin::Passphrase => PASSPHRASE_KDF_KEY,
+            KeyOrigin::Yubikey => YUBIKEY_CHALLENGE_KEY,
+            KeyOrigin::Pkcs11 => PKCS11_KEY_KEY,
+        }
+    }
+
+    /// `init`/`rotate` flag selecting it
+    pub fn flag(self) -> &'static str {
+        match self {
+            KeyOrigin::Passphrase => "--passphrase",
+            KeyOrigin::Yubikey => "--yubikey",
+            KeyOrigin::Pkcs11 => "--pkcs11",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            KeyOrigin::Passphrase => "a passphrase",
+            KeyOrigin::Yubikey => "a YubiKey",
+            KeyOrigin::Pkcs11 => "a PKCS#11 token",
+        }
+    }
+}
+
+/// What to do with one [`Conflict`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    KeepExisting,
+    TakeIncoming,
+    /// Keep the existing version and store the incoming one under this name
+    KeepBoth(String),
+}
+
+/// Outcome for one name of [`SecretService::get_many`]
+#[derive(Debug)]
+pub enum Fetched {
+    Found(Box<Secret>),
+    Missing,
+    /// Stored, but the value could not be decrypted; holds why
+    Unreadable(String),
+}
+
+/// One error listing every secret that could not be used and why, e.g. for `exec`.
+pub fn secrets_error(problems: &[(String, String)]) -> anyhow::Error {
+    let mut message = match problems.len() {
+        1 => "1 secret is unavailable:".to_string(),
+        n => format!("{n} secrets are unavailable:"),
+    };
+    for (name, problem) in problems {
+        message.push_str(&format!("\n  {name}: {problem}"));
+    }
+    anyhow!(message)
+}
+
+/// Business logic shared by every UI: coordinates keys, crypto and storage.
+#[derive(Clone)]
+pub struct SecretService {
+    repo: Repository,
+    key_provider: MasterKeyProvider,
+    /// Name prefixes whose deletes/exports need a second approver
+    protected_prefixes: Vec<String>,
+    /// Rewrite records found under a retired key version when they are read
+    reencrypt_on_read: bool,
+    /// Plaintext reveals of one secret allowed per hour without re-confirmation
+    reveal_limit: Option<u32>,
+    /// Values above this many bytes are stored with a warning
+    warn_value_bytes: Option<usize>,
+    /// Values above this many bytes are refused
+    max_value_bytes: Option<usize>,
+}
+
+/// The current master key plus every retired key version still in the vault.
+struct KeyChain {
+    current: i64,
+    keys: BTreeMap<i64, MasterKey>,
+    /// What new ciphertexts are written with
+    cipher: Cipher,
+}
+
+impl KeyChain {
+    fn crypto(&self, version: i64) -> Result<SecretCrypto> {
+        let key = self
+            .keys
+            .get(&version)
+            .ok_or_else(|| anyhow!("master key version {version} is not available"))?;
+        Ok(SecretCrypto::new(key.clone()).with_cipher(self.cipher))
+    }
+
+    fn current_crypto(&self) -> Result<SecretCrypto> {
+        self.crypto(self.current)
+    }
+}
+
+impl SecretService {
+    pub fn new(repo: Repository, key_provider: MasterKeyProvider) -> Self {
+        Self {
+            repo,
+            key_provider,
+            protected_prefixes: Vec::new(),
+            reencrypt_on_read: false,
+            reveal_limit: None,
+            warn_value_bytes: None,
+            max_value_bytes: None,
+        }
+    }
+
+    /// Require re-confirmation once a secret was revealed `limit` times in an hour.
+    pub fn with_reveal_limit(mut self, limit: Option<u32>) -> Self {
+        self.reveal_limit = limit;
+        self
+    }
+
+    /// Warn about values over `warn` bytes and refuse ones over `max` when storing.
+    pub fn with_value_limits(mut self, warn: Option<usize>, max: Option<usize>) -> Self {
+        self.warn_value_bytes = warn;
+        self.max_value_bytes = max;
+        self
+    }
+
+    /// Opt in to upgrading records written under retired key versions as they are read.
+    pub fn with_reencrypt_on_read(mut self, enabled: bool) -> Self {
+        self.reencrypt_on_read = enabled;
+        self
+    }
+
+    /// Enable the two-person rule for secrets under these prefixes.
+    pub fn with_protected_prefixes(mut self, prefixes: Vec<String>) -> Self {
+        self.protected_prefixes = prefixes;
+        self
+    }
+
+    /// Run `work` as one unit: every call it makes on the service it is handed commits
+    /// together when it returns `Ok`, or not at all. Calls on `self` from elsewhere wait
+    /// for the write lock meanwhile; a nested `transaction` joins the open one.
+    ///
+    /// ```no_run
+    /// # async fn bump(service: &devinventory::service::SecretService) -> anyhow::Result<()> {
+    /// service
+    ///     .transaction(async |tx| {
+    ///         let counter = tx.get_secret("deploy/counter").await?.plaintext;
+    ///         let next = String::from_utf8(counter)?.parse::<u64>()? + 1;
+    ///         tx.add_secret("deploy/counter", next.to_string().as_bytes(), Default::default())
+    ///             .await?;
+    ///         Ok(())
+    ///     })
+    ///     .await
+    /// # }
+    /// ```
+    pub async fn transaction<T>(
+        &self,
+        work: impl AsyncFnOnce(&SecretService) -> Result<T>,
+    ) -> Result<T> {
+        if self.repo.in_unit() {
+            return work(self).await;
+        }
+        let unit = SecretService {
+            repo: self.repo.begin_unit().await?,
+            key_provider: self.key_provider.clone(),
+            protected_prefixes: self.protected_prefixes.clone(),
+            reencrypt_on_read: self.reencrypt_on_read,
+            reveal_limit: self.reveal_limit,
+            warn_value_bytes: self.warn_value_bytes,
+            max_value_bytes: self.max_value_bytes,
+        };
+        let result = work(&unit).await;
+        unit.repo.finish_unit(result.is_ok()).await?;
+        result
+    }
+
+    /// The same keys and settings over another database, e.g. a second copy of the vault.
+    pub fn with_repository(&self, repo: Repository) -> SecretService {
+        SecretService {
+            repo,
+            key_provider: self.key_provider.clone(),
+            protected_prefixes: self.protected_prefixes.clone(),
+            reencrypt_on_read: self.reencrypt_on_read,
+            reveal_limit: self.reveal_limit,
+            warn_value_bytes: self.warn_value_bytes,
+            max_value_bytes: self.max_value_bytes,
+        }
+    }
+
+    /// Generate (or load) the master key, storing it in the keyring when allowed. A
+    /// passphrase, YubiKey or PKCS#11 vault only checks that it unlocks.
+    pub async fn init_master_key(&self) -> Result<()> {
+        if self.key_origin().await?.is_some() {
+            return self.ensure_master_key().await;
+        }
+        let master_key = self.key_provider.obtain(true).await?;
+        let crypto = SecretCrypto::new(master_key);
+        // quick touch to ensure key material used and zeroized after scope
+        let _ = crypto.encrypt("init", b"").ok();
+        Ok(())
+    }
+
+    /// Start a vault whose master key comes from `origin` instead of the keyring.
+    /// Refused once secrets exist under another key; `rotate` with the origin's flag
+    /// moves those over.
+    pub async fn init_with(&self, origin: KeyOrigin) -> Result<()> {
+        match self.key_origin().await? {
+            Some(current) if current == origin => bail!(
+                "this vault already uses {}; give it a new key with 'rotate'",
+                origin.describe()
+            ),
+            Some(current) => bail!(
+                "this vault's key comes from {}; switch with 'rotate {}'",
+                current.describe(),
+                origin.flag()
+            ),
+            None => {}
+        }
+        if !self.repo.list_secrets().await?.is_empty()
+            || !self.repo.retired_keys().await?.is_empty()
+        {
+            bail!(
+                "this vault already holds secrets; switch it over with 'rotate {}'",
+                origin.flag()
+            );
+        }
+        let (settings, _) = self.new_origin_key(origin)?;
+        self.repo
+            .set_meta_value(origin.meta_key(), &settings)
+            .await?;
+        info!("vault master key now comes from {}", origin.describe());
+        Ok(())
+    }
+
+    /// Where this vault's master key comes from; `None` for a keyring or key-file vault.
+    pub async fn key_origin(&self) -> Result<Option<KeyOrigin>> {
+        for origin in KeyOrigin::ALL {
+            if self.repo.meta_value(origin.meta_key()).await?.is_some() {
+                return Ok(Some(origin));
+            }
+        }
+        Ok(None)
+    }
+
+    /// A new master key from `origin` and the JSON settings that get it back.
+    fn new_origin_key(&self, origin: KeyOrigin) -> Result<(String, MasterKey)> {
+        Ok(match origin {
+            KeyOrigin::Passphrase => {
+                let (kdf, key) = self.key_provider.new_passphrase_key()?;
+                (serde_json::to_string(&kdf)?, key)
+            }
+            KeyOrigin::Yubikey => {
+                let (challenge, key) = self.key_provider.new_yubikey_key()?;
+                (serde_json::to_string(&challenge)?, key)
+            }
+            KeyOrigin::Pkcs11 => {
+                let (wrapped, key) = self.key_provider.new_pkcs11_key()?;
+                (serde_json::to_string(&wrapped)?, key)
+            }
+        })
+    }
+
+    /// Cipher new values are encrypted with; ChaCha20-Poly1305 unless chosen otherwise.
+    pub async fn cipher(&self) -> Result<Cipher> {
+        match self.repo.meta_value(CIPHER_KEY).await? {
+            Some(name) => Cipher::from_name(&name)
+                .ok_or_else(|| anyhow!("this vault uses cipher '{name}', unknown to this version")),
+            None => Ok(Cipher::default()),
+        }
+    }
+
+    /// Encrypt new values with `cipher` from now on. Existing ones keep theirs and
+    /// still decrypt; a `rotate` moves them over.
+    pub async fn set_cipher(&self, cipher: Cipher) -> Result<()> {
+        self.repo.set_meta_value(CIPHER_KEY, cipher.name()).await?;
+        info!("new values will be encrypted with {cipher}");
+        Ok(())
+    }
+
+    /// Token-wrapped key of a PKCS#11 vault; `None` when the key comes from elsewhere.
+    pub async fn pkcs11_key(&self) -> Result<Option<WrappedKey>> {
+        self.repo
+            .meta_value(PKCS11_KEY_KEY)
+            .await?
+            .map(|json| serde_json::from_str(&json).context("reading the PKCS#11 wrapped key"))
+            .transpose()
+    }
+
+    /// Challenge of a YubiKey vault; `None` when the key comes from elsewhere.
+    pub async fn yubikey_challenge(&self) -> Result<Option<YubikeyChallenge>> {
+        self.repo
+            .meta_value(YUBIKEY_CHALLENGE_KEY)
+            .await?
+            .map(|json| serde_json::from_str(&json).context("reading the YubiKey challenge"))
+            .transpose()
+    }
+
+    /// Argon2id settings of a passphrase vault; `None` when the key is kept elsewhere.
+    pub async fn passphrase_kdf(&self) -> Result<Option<PassphraseKdf>> {
+        self.repo
+            .meta_value(PASSPHRASE_KDF_KEY)
+            .await?
+            .map(|json| serde_json::from_str(&json).context("reading the passphrase settings"))
+            .transpose()
+    }
+
+    /// Protect a passphrase vault's master key with `passphrase` instead. Only the
+    /// wrapped master key changes, so this is instant however many secrets there are;
+    /// `rotate` replaces the master key itself.
+    pub async fn change_passphrase(&self, passphrase: &str) -> Result<()> {
+        let kdf = self.passphrase_kdf().await?.ok_or_else(|| {
+            anyhow!("this vault's key does not come from a passphrase; see 'rotate --passphrase'")
+        })?;
+        let key = self.master_key().await?;
+        let changed = kdf.rewrap(&key, passphrase)?;
+        self.repo
+            .set_meta_value(PASSPHRASE_KDF_KEY, &serde_json::to_string(&changed)?)
+            .await?;
+        self.key_provider.remember(&key);
+        info!("vault passphrase changed");
+        Ok(())
+    }
+
+    /// Fail early when no master key is available, without decrypting anything.
+    pub async fn ensure_master_key(&self) -> Result<()> {
+        self.master_key().await.map(|_| ())
+    }
+
+    /// The master key from the configured source, or the vault passphrase, YubiKey or
+    /// PKCS#11 token for vaults keyed that way, falling back to this machine's device
+    /// key, then key slots.
+    async fn master_key(&self) -> Result<MasterKey> {
+        let obtained = if let Some(kdf) = self.passphrase_kdf().await? {
+            self.key_provider.obtain_with_passphrase(&kdf)
+        } else if let Some(challenge) = self.yubikey_challenge().await? {
+            self.key_provider.obtain_with_yubikey(&challenge)
+        } else if let Some(wrapped) = self.pkcs11_key().await? {
+            self.key_provider.obtain_with_pkcs11(&wrapped)
+        } else {
+            self.key_provider.obtain(false).await
+        };
+        let err = match obtained {
+            Ok(key) => return Ok(key),
+            Err(err) => err,
+        };
+        if let Some(key) = self.unlock_with_device().await? {
+            return Ok(key);
+        }
+        match self.unlock_with_slot().await? {
+            Some(key) => Ok(key),
+            None => Err(err),
+        }
+    }
+
+    /// The master key from a key slot: keyring slots with an entry on this machine
+    /// first, then a passphrase or recovery key asked for (three tries) when someone
+    /// can answer.
+    async fn unlock_with_slot(&self) -> Result<Option<MasterKey>> {
+        let slots = self.repo.list_key_slots().await?;
+        if slots.is_empty() {
+            return Ok(None);
+        }
+        if let Some(key) = self.key_provider.remembered() {
+            return Ok(Some(key));
+        }
+        for slot in slots.iter().filter(|s| s.kind == SlotKind::Keyring) {
+            let unlock_key = self
+                .key_provider
+                .read_slot_key(slot.id)
+                .unwrap_or_else(|e| {
+                    debug!("key slot {} unavailable: {e}", slot.id);
+                    None
+                });
+            if let Some(unlock_key) = unlock_key {
+                return self.open_slot(slot, &unlock_key).await.map(Some);
+            }
+        }
+        let asked: Vec<&KeySlot> = slots
+            .iter()
+            .filter(|s| s.kind != SlotKind::Keyring)
+            .collect();
+        if asked.is_empty() || !self.key_provider.can_ask() {
+            return Ok(None);
+        }
+        for _ in 0..3 {
+            let mut answer = self
+                .key_provider
+                .ask("Key slot passphrase or recovery key: ")?;
+            let mut opened = None;
+            for slot in &asked {
+                let unlock_key = match slot.kind {
+                    SlotKind::Recovery => keymgr::parse_recovery_key(&answer),
+                    _ => slot_kdf(slot)?.derive(&answer).ok().map(|key| key.0),
+                };
+                if let Some(unlock_key) = unlock_key {
+                    opened = Some(self.open_slot(slot, &unlock_key).await?);
+                    break;
+                }
+            }
+            answer.zeroize();
+            if let Some(key) = opened {
+                return Ok(Some(key));
+            }
+            warn!("no key slot accepts that; try again");
+        }
+        bail!("no key slot accepts that passphrase or recovery key")
+    }
+
+    async fn open_slot(&self, slot: &KeySlot, unlock_key: &[u8; 32]) -> Result<MasterKey> {
+        let wrapped = self
+            .repo
+            .fetch_slot_wrap(slot.id)
+            .await?
+            .ok_or_else(|| anyhow!("key slot {} is gone", slot.id))?;
+        let key = crypto::unwrap_with_device(unlock_key, &wrapped, &key_slot_label(slot.id))
+            .with_context(|| format!("opening key slot {}", slot.id))?;
+        info!(
+            "master key unlocked with key slot {} ({})",
+            slot.id, slot.label
+        );
+        self.key_provider.remember(&key);
+        Ok(key)
+    }
+
+    /// Wrap the master key in a new slot of `kind`. A passphrase slot asks for its
+    /// passphrase, a keyring slot stores its key in the keyring, and a recovery slot
+    /// returns the recovery key to hand to the user; it is not kept anywhere.
+    pub async fn add_key_slot(
+        &self,
+        kind: SlotKind,
+        label: &str,
+    ) -> Result<(KeySlot, Option<String>)> {
+        let master_key = self.master_key().await?;
+        let id = self.repo.next_key_slot().await?;
+        let (unlock_key, params, recovery) = match kind {
+            SlotKind::Passphrase => {
+                let (kdf, key) = self
+                    .key_provider
+                    .ask_new_passphrase("Passphrase for the new slot: ")?;
+                (key.0, Some(serde_json::to_string(&kdf)?), None)
+            }
+            SlotKind::Recovery => {
+                let (key, text) = keymgr::new_recovery_key();
+                (key, None, Some(text))
+            }
+            SlotKind::Keyring => {
+                let key = crypto::generate_device_keypair().secret;
+                self.key_provider.store_slot_key(id, &key)?;
+                (key, None, None)
+            }
+        };
+        let slot = KeySlot {
+            id,
+            kind,
+            label: label.to_string(),
+            params,
+            public_key: crypto::device_public_key(&unlock_key),
+            created_at: Utc::now(),
+        };
+        let wrapped =
+            crypto::wrap_for_device(&master_key, &slot.public_key, &key_slot_label(slot.id))?;
+        self.repo.insert_key_slot(&slot, &wrapped).await?;
+        self.audit(
+            AuditAction::KeySlot,
+            None,
+            Some(&format!("added {} slot {id} ({label})", kind.as_str())),
+        )
+        .await?;
+        Ok((slot, recovery))
+    }
+
+    pub async fn list_key_slots(&self) -> Result<Vec<KeySlot>> {
+        self.repo.list_key_slots().await
+    }
+
+    /// Remove slot `id`, and its keyring entry for a keyring slot.
+    pub async fn remove_key_slot(&self, id: i64) -> Result<KeySlot> {
+        let slot = self
+            .repo
+            .list_key_slots()
+            .await?
+            .into_iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| anyhow!("no key slot {id}"))?;
+        self.repo.delete_key_slot(id).await?;
+        if slot.kind == SlotKind::Keyring
+            && let Err(e) = self.key_provider.delete_slot_key(id)
+        {
+            warn!("key slot {id} removed, but its keyring entry stays: {e:#}");
+        }
+        self.audit(
+            AuditAction::KeySlot,
+            None,
+            Some(&format!(
+                "removed {} slot {id} ({})",
+                slot.kind.as_str(),
+                slot.label
+            )),
+        )
+        .await?;
+        Ok(slot)
+    }
+
+    async fn unlock_with_device(&self) -> Result<Option<MasterKey>> {
+        let Some((device_id, secret)) = self.key_provider.read_device_key().unwrap_or_else(|e| {
+            debug!("no device key available: {e}");
+            None
+        }) else {
+            return Ok(None);
+        };
+        let Some(wrapped) = self.repo.fetch_device_wrap(device_id).await? else {
+            warn!("device {} is revoked or unknown to this vault", device_id);
+            return Ok(None);
+        };
+        let key = crypto::unwrap_with_device(&secret, &wrapped, &device_id.to_string())
+            .context("unwrapping master key with device key")?;
+        info!("master key unlocked with device key {}", device_id);
+        Ok(Some(key))
+    }
+
+    /// Current and retired master keys; retired ones are stored wrapped under the current key.
+    async fn key_chain(&self) -> Result<KeyChain> {
+        self.load_key_chain(false).await
+    }
+
+    /// With `lossy`, retired keys that no longer unwrap are left out with a warning.
+    async fn load_key_chain(&self, lossy: bool) -> Result<KeyChain> {
+        let current_key = self.master_key().await?;
+        let current = self.repo.current_key_version().await?;
+        let wrapper = SecretCrypto::new(current_key.clone());
+        let mut keys = BTreeMap::from([(current, current_key)]);
+        for (version, wrapped) in self.repo.retired_keys().await? {
+            let unwrapped = wrapper
+                .decrypt(&key_version_label(version), &wrapped)
+                .with_context(|| format!("unwrapping master key version {version}"))
+                .and_then(|raw| {
+                    <[u8; 32]>::try_from(raw)
+                        .map_err(|_| anyhow!("master key version {version} is not 32 bytes"))
+                });
+            match unwrapped {
+                Ok(raw) => {
+                    keys.insert(version, MasterKey(raw));
+                }
+                Err(e) if lossy => warn!("{e:#}"),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(KeyChain {
+            current,
+            keys,
+            cipher: self.cipher().await?,
+        })
+    }
+
+    /// Enroll this machine: create a device keypair, wrap the master key for it and
+    /// keep the device secret in the keyring.
+    pub async fn enroll_device(&self, label: &str) -> Result<Device> {
+        let master_key = self.master_key().await?;
+        let keypair = crypto::generate_device_keypair();
+        let device = Device {
+            id: Uuid::new_v4(),
+            label: label.to_string(),
+            public_key: keypair.public,
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        let wrapped =
+            crypto::wrap_for_device(&master_key, &device.public_key, &device.id.to_string())?;
+        self.key_provider
+            .store_device_key(device.id, &keypair.secret)?;
+        self.repo.insert_device(&device, &wrapped).await?;
+        self.audit(
+            AuditAction::Device,
+            None,
+            Some(&format!("enrolled {label}")),
+        )
+        .await?;
+        Ok(device)
+    }
+
+    pub async fn list_devices(&self) -> Result<Vec<Device>> {
+        self.repo.list_devices().await
+    }
+
+    /// Revoke a device by id or label so its key no longer unlocks the vault.
+    pub async fn revoke_device(&self, id_or_label: &str) -> Result<()> {
+        if !self.repo.revoke_device(id_or_label).await? {
+            bail!("no active device '{id_or_label}'");
+        }
+        self.audit(
+            AuditAction::Device,
+            None,
+            Some(&format!("revoked {id_or_label}")),
+        )
+        .await?;
+        info!("revoked device '{}'", id_or_label);
+        Ok(())
+    }
+
+    /// Encrypt and store a secret, replacing any mutable secret with the same name.
+    pub async fn add_secret(
+        &self,
+        name: &str,
+        value: &[u8],
+        mut attrs: SecretAttributes,
+    ) -> Result<SecretMetadata> {
+        let chain = self.key_chain().await?;
+        if attrs.kind.as_deref() == Some(cert::CERTIFICATE_KIND) {
+            let info = cert::parse_certificate(value)
+                .with_context(|| format!("'{name}' has kind=certificate but no valid PEM"))?;
+            attrs.expires_at = Some(info.not_after);
+            attrs.certificate = Some(info);
+        }
+        if attrs.kind.as_deref() == Some(totp::TOTP_KIND) {
+            Totp::parse(value).with_context(|| {
+                format!("'{name}' has kind=totp but no Base32 seed or otpauth:// URI")
+            })?;
+        }
+        self.check_value_size(name, value)?;
+        let existing = self.repo.fetch_secret(name).await?;
+        if existing.as_ref().is_some_and(|e| e.immutable) {
+            bail!("secret '{name}' is immutable; run `clear-immutable {name}` first");
+        }
+        if let Some(existing) = &existing {
+            self.ensure_unlocked(existing).await?;
+        }
+        let sealed = Binding::of(name, attrs.bind_host)?.seal(&chain.current_crypto()?, value)?;
+        // seed the log with the pre-change state before logging this change
+        self.replica_id().await?;
+        self.repo
+            .upsert_secret(name, &attrs, &sealed, chain.current)
+            .await?;
+        self.record_op(OpKind::Put, name).await?;
+        let action = if existing.is_some() {
+            AuditAction::Update
+        } else {
+            AuditAction::Add
+        };
+        self.audit(action, Some(name), None).await?;
+        info!("saved/updated secret: {}", name);
+        let record = self
+            .repo
+            .fetch_secret(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret vanished after save"))?;
+        Ok(to_metadata(record))
+    }
+
+    /// Refuse a value over the hard size limit; warn about one over the soft limit.
+    fn check_value_size(&self, name: &str, value: &[u8]) -> Result<()> {
+        let size = value.len();
+        if let Some(max) = self.max_value_bytes
+            && size > max
+        {
+            bail!(
+                "'{name}' is {size} bytes, over the {max}-byte limit ([limits] max_value_kib); \
+                 keep large files outside the vault and store their path and checksum instead"
+            );
+        }
+        if let Some(limit) = self.warn_value_bytes
+            && size > limit
+        {
+            warn!(
+                "'{name}' is {size} bytes, over {limit} ([limits] warn_value_kib); large values \
+                 slow down every backup and sync of the vault"
+            );
+        }
+        Ok(())
+    }
+
+    /// Current name of `target`; an unknown id is an error, a name is taken as given.
+    pub async fn resolve(&self, target: impl Into<SecretRef>) -> Result<String> {
+        match target.into() {
+            SecretRef::Name(name) => Ok(name),
+            SecretRef::Id(id) => self
+                .repo
+                .secret_name_by_id(id)
+                .await?
+                .ok_or_else(|| anyhow!("no secret with id {id}")),
+        }
+    }
+
+    /// Like `resolve`, but an unknown id is `Ok(None)`.
+    async fn lookup(&self, target: SecretRef) -> Result<Option<String>> {
+        match target {
+            SecretRef::Name(name) => Ok(Some(name)),
+            SecretRef::Id(id) => self.repo.secret_name_by_id(id).await,
+        }
+    }
+
+    pub async fn get_secret(&self, target: impl Into<SecretRef>) -> Result<Secret> {
+        self.find_secret(target)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))
+    }
+
+    /// Like `get_secret`, but a missing secret is `Ok(None)` rather than an error.
+    pub async fn find_secret(&self, target: impl Into<SecretRef>) -> Result<Option<Secret>> {
+        let Some(name) = self.lookup(target.into()).await? else {
+            return Ok(None);
+        };
+        let name = name.as_str();
+        let chain = self.key_chain().await?;
+        let record = if self.reencrypt_on_read {
+            self.repo
+                .fetch_secret_upgrading(name, chain.current, |record| {
+                    reseal(&chain, &chain.current_crypto()?, record)
+                })
+                .await?
+        } else {
+            self.repo.fetch_secret(name).await?
+        };
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        let secret = decrypt_record(&chain, record)?;
+        self.audit(AuditAction::Read, Some(name), None).await?;
+        Ok(Some(secret))
+    }
+
+    /// Metadata of `target` without decrypting it (so no read is audited).
+    pub async fn secret_metadata(
+        &self,
+        target: impl Into<SecretRef>,
+    ) -> Result<Option<SecretMetadata>> {
+        let Some(name) = self.lookup(target.into()).await? else {
+            return Ok(None);
+        };
+        Ok(self.repo.fetch_secret(&name).await?.map(to_metadata))
+    }
+
+    /// The current one-time code of a `kind=totp` secret and the seconds it stays valid.
+    pub async fn totp_code(&self, name: &str) -> Result<(String, u64)> {
+        let secret = self.get_secret(name).await?;
+        if secret.kind.as_deref() != Some(totp::TOTP_KIND) {
+            bail!("secret '{name}' is not of kind={}", totp::TOTP_KIND);
+        }
+        let totp = Totp::parse(&secret.plaintext)?;
+        let now = Utc::now().timestamp().max(0) as u64;
+        Ok((totp.code_at(now), totp.remaining(now)))
+    }
+
+    /// Reveals of `name` in the past hour, when they have reached the configured limit.
+    pub async fn reveal_cooldown(&self, name: &str) -> Result<Option<i64>> {
+        let Some(limit) = self.reveal_limit else {
+            return Ok(None);
+        };
+        let since = Utc::now() - chrono::Duration::hours(1);
+        let count = self
+            .repo
+            .count_audit(AuditAction::Reveal, name, since)
+            .await?;
+        Ok((count >= i64::from(limit)).then_some(count))
+    }
+
+    /// Fetch a secret to show its plaintext to a person, counting the reveal.
+    pub async fn reveal_secret(&self, name: &str) -> Result<Secret> {
+        let secret = self.get_secret(name).await?;
+        self.audit(AuditAction::Reveal, Some(name), None).await?;
+        Ok(secret)
+    }
+
+    /// Each of `names` in order with whether it was found and decrypted, read in one
+    /// query. Only the master key being unavailable fails the whole call.
+    pub async fn get_many(&self, names: &[String]) -> Result<Vec<(String, Fetched)>> {
+        let chain = self.key_chain().await?;
+        let records: BTreeMap<String, SecretRecord> = self
+            .repo
+            .fetch_secrets(names)
+            .await?
+            .into_iter()
+            .map(|r| (r.name.clone(), r))
+            .collect();
+        let mut results = Vec::with_capacity(names.len());
+        for name in names {
+            let fetched = match records.get(name) {
+                None => Fetched::Missing,
+                Some(record) => match decrypt_record(&chain, record.clone()) {
+                    Ok(secret) => {
+                        self.audit(AuditAction::Read, Some(name), None).await?;
+                        Fetched::Found(Box::new(secret))
+                    }
+                    Err(e) => Fetched::Unreadable(format!("{e:#}")),
+                },
+            };
+            results.push((name.clone(), fetched));
+        }
+        Ok(results)
+    }
+
+    /// Every one of `names`, in order, or one error listing all that are missing or
+    /// cannot be decrypted.
+    pub async fn get_all(&self, names: &[String]) -> Result<Vec<Secret>> {
+        self.get_all_checked(names, |_| None).await
+    }
+
+    /// Like [`SecretService::get_all`], with `check` adding a problem for a secret
+    /// that was read but may not be used here.
+    pub async fn get_all_checked(
+        &self,
+        names: &[String],
+        check: impl Fn(&Secret) -> Option<String>,
+    ) -> Result<Vec<Secret>> {
+        let mut secrets = Vec::with_capacity(names.len());
+        let mut problems = Vec::new();
+        for (name, fetched) in self.get_many(names).await? {
+            match fetched {
+                Fetched::Found(secret) => match check(&secret) {
+                    Some(problem) => problems.push((name, problem)),
+                    None => secrets.push(*secret),
+                },
+                Fetched::Missing => problems.push((name, "not found".to_string())),
+                Fetched::Unreadable(why) => problems.push((name, why)),
+            }
+        }
+        if !problems.is_empty() {
+            return Err(secrets_error(&problems));
+        }
+        Ok(secrets)
+    }
+
+    /// Decrypt secrets for injection into `program`, enforcing each secret's exec allow-list.
+    pub async fn secrets_for_exec(&self, names: &[String], program: &Path) -> Result<Vec<Secret>> {
+        self.get_all_checked(names, |secret| {
+            if exec_allowed(&secret.allowed_commands, program) {
+                return None;
+            }
+            warn!(
+                "refused to pass secret '{}' to {}",
+                secret.name,
+                program.to_string_lossy()
+            );
+            Some(format!(
+                "may not be passed to '{}' (allowed: {})",
+                program.to_string_lossy(),
+                secret.allowed_commands.join(", ")
+            ))
+        })
+        .await
+    }
+
+    /// Decrypt every secret whose name starts with `prefix`, ordered by name.
+    pub async fn secrets_with_prefix(&self, prefix: &str) -> Result<Vec<Secret>> {
+        let chain = self.key_chain().await?;
+        let secrets = self
+            .repo
+            .list_secrets_with_prefix(prefix)
+            .await?
+            .into_iter()
+            .map(|record| decrypt_record(&chain, record))
+            .collect::<Result<Vec<_>>>()?;
+        let detail = format!("{} secrets with prefix '{prefix}'", secrets.len());
+        self.audit(AuditAction::Read, None, Some(&detail)).await?;
+        Ok(secrets)
+    }
+
+    /// Metadata of the secrets whose name starts with `prefix`; `prod/` means the
+    /// `prod` folder and everything below it.
+    pub async fn list_secrets_with_prefix(&self, prefix: &str) -> Result<Vec<SecretMetadata>> {
+        let rows = self.repo.list_secrets_with_prefix(prefix).await?;
+        Ok(rows.into_iter().map(to_metadata).collect())
+    }
+
+    /// Secrets with an expiry at or before now + `window`, soonest first.
+    pub async fn expiring_within(&self, window: chrono::Duration) -> Result<Vec<SecretMetadata>> {
+        let cutoff = Utc::now() + window;
+        let mut due: Vec<SecretMetadata> = self
+            .repo
+            .list_secrets()
+            .await?
+            .into_iter()
+            .filter(|r| r.expires_at.is_some_and(|at| at <= cutoff))
+            .map(to_metadata)
+            .collect();
+        due.sort_by_key(|m| m.expires_at);
+        Ok(due)
+    }
+
+    /// Names starting with `prefix` for completion; needs no master key.
+    pub async fn secret_names(&self, prefix: &str, limit: Option<u32>) -> Result<Vec<String>> {
+        self.repo.secret_names(prefix, limit).await
+    }
+
+    pub async fn list_secrets(&self) -> Result<Vec<SecretMetadata>> {
+        let rows = self.repo.list_secrets().await?;
+        Ok(rows.into_iter().map(to_metadata).collect())
+    }
+
+    pub async fn search_secrets(&self, query: &str) -> Result<Vec<SecretMetadata>> {
+        let rows = self.repo.search_secrets(query).await?;
+        Ok(rows.into_iter().map(to_metadata).collect())
+    }
+
+    /// Move a secret to the trash, from where `restore_secret` brings it back.
+    /// Returns `false` when it did not exist.
+    pub async fn delete_secret(&self, target: impl Into<SecretRef>) -> Result<bool> {
+        let Some(name) = self.lookup(target.into()).await? else {
+            return Ok(false);
+        };
+        let name = name.as_str();
+        if let Some(existing) = self.repo.fetch_secret(name).await? {
+            if existing.immutable {
+                bail!("secret '{name}' is immutable; run `clear-immutable {name}` first");
+            }
+            self.ensure_unlocked(&existing).await?;
+            self.require_approval("delete", name).await?;
+        }
+        let deleted = self.repo.delete_secret(name).await?;
+        if deleted {
+            self.repo.delete_env_mapping(name).await?;
+            self.record_op(OpKind::Delete, name).await?;
+            self.audit(AuditAction::Delete, Some(name), Some("moved to trash"))
+                .await?;
+        }
+        Ok(deleted)
+    }
+
+    /// Deleted secrets with when they were deleted, most recent first.
+    pub async fn trash(&self) -> Result<Vec<(SecretMetadata, DateTime<Utc>)>> {
+        let rows = self.repo.list_trash().await?;
+        Ok(rows
+            .into_iter()
+            .map(|(record, deleted_at)| (to_metadata(record), deleted_at))
+            .collect())
+    }
+
+    /// Bring a secret back from the trash with its value, metadata and tags.
+    pub async fn restore_secret(&self, name: &str) -> Result<SecretMetadata> {
+        if !self.repo.restore_secret(name).await? {
+            bail!("'{name}' is not in the trash");
+        }
+        self.record_op(OpKind::Put, name).await?;
+        self.audit(AuditAction::Add, Some(name), Some("restored from trash"))
+            .await?;
+        self.secret_metadata(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))
+    }
+
+    /// Delete a secret in the trash for good. Returns `false` when it is not there.
+    pub async fn purge_secret(&self, name: &str) -> Result<bool> {
+        let purged = self.repo.purge_secret(name).await?;
+        if purged {
+            self.audit(AuditAction::Delete, Some(name), Some("purged from trash"))
+                .await?;
+        }
+        Ok(purged)
+    }
+
+    /// Attach tags to a secret. Returns how many it did not carry yet.
+    pub async fn tag_secret(&self, name: &str, tags: &[String]) -> Result<usize> {
+        if let Some(bad) = tags.iter().find(|t| !domain::is_valid_tag(t)) {
+            bail!("'{bad}' is not a valid tag (no spaces or commas)");
+        }
+        let record = self
+            .repo
+            .fetch_secret(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))?;
+        let added = self.repo.add_tags(record.id, tags).await?;
+        if added > 0 {
+            let detail = format!("tagged {}", tags.join(", "));
+            self.audit(AuditAction::Update, Some(name), Some(&detail))
+                .await?;
+        }
+        Ok(added)
+    }
+
+    /// Detach tags from a secret. Returns how many it carried.
+    pub async fn untag_secret(&self, name: &str, tags: &[String]) -> Result<usize> {
+        let record = self
+            .repo
+            .fetch_secret(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))?;
+        let removed = self.repo.remove_tags(record.id, tags).await?;
+        if removed > 0 {
+            let detail = format!("untagged {}", tags.join(", "));
+            self.audit(AuditAction::Update, Some(name), Some(&detail))
+                .await?;
+        }
+        Ok(removed)
+    }
+
+    /// Tags of one secret, sorted.
+    pub async fn secret_tags(&self, name: &str) -> Result<Vec<String>> {
+        let record = self
+            .repo
+            .fetch_secret(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))?;
+        let mut tags = self.repo.tags_by_secret().await?;
+        Ok(tags.remove(&record.id).unwrap_or_default())
+    }
+
+    /// The `limit` secrets taking the most space, largest ciphertext first.
+    pub async fn largest_secrets(&self, limit: usize) -> Result<Vec<SecretSize>> {
+        Ok(self
+            .repo
+            .largest_secrets(limit as i64)
+            .await?
+            .into_iter()
+            .map(|(name, ciphertext, history)| SecretSize {
+                name,
+                ciphertext,
+                history,
+            })
+            .collect())
+    }
+
+    pub async fn database_size(&self) -> Result<i64> {
+        self.repo.database_size().await
+    }
+
+    /// Counts and sizes across the vault, with the `oldest` least recently changed secrets.
+    pub async fn stats(&self, oldest: usize) -> Result<VaultStats> {
+        let (secrets, trashed, ciphertext_bytes) = self.repo.secret_totals().await?;
+        Ok(VaultStats {
+            secrets,
+            trashed,
+            ciphertext_bytes,
+            database_bytes: self.repo.database_size().await?,
+            by_kind: self.repo.kind_counts().await?,
+            by_tag: self.repo.tag_counts().await?,
+            oldest: self.repo.least_recently_updated(oldest as i64).await?,
+        })
+    }
+
+    /// Every live secret in one backup file, encrypted under the master key or, given
+    /// `passphrase`, a key derived from it. Host-bound secrets cannot leave this machine
+    /// and are left out; their names come back with the file.
+    pub async fn backup(&self, passphrase: Option<&str>) -> Result<(BackupFile, Vec<String>)> {
+        let chain = self.key_chain().await?;
+        let mut tags = self.repo.tags_by_secret().await?;
+        let mut entries = Vec::new();
+        let mut host_bound = Vec::new();
+        for record in self.repo.list_secrets().await? {
+            if record.bind_host {
+                host_bound.push(record.name);
+                continue;
+            }
+            let tags = tags.remove(&record.id).unwrap_or_default();
+            entries.push(BackupEntry::new(decrypt_record(&chain, record)?, tags));
+        }
+        let file = backup_file(&chain, &entries, passphrase)?;
+        let detail = format!("backup of {} secrets", entries.len());
+        self.audit(AuditAction::Export, None, Some(&detail)).await?;
+        Ok((file, host_bound))
+    }
+
+    /// Like [`Self::backup`] for a damaged vault: every secret that still decrypts
+    /// goes into the file and the others, host-bound ones included, are listed in the
+    /// manifest. A retired key that no longer unwraps only costs the secrets under it.
+    pub async fn salvage(&self, passphrase: Option<&str>) -> Result<(BackupFile, SalvageManifest)> {
+        let chain = self.load_key_chain(true).await?;
+        let mut tags = self.repo.tags_by_secret().await.unwrap_or_else(|e| {
+            warn!("salvaging without tags: {e:#}");
+            BTreeMap::new()
+        });
+        let mut entries = Vec::new();
+        let mut unrecoverable = Vec::new();
+        for record in self.repo.list_secrets().await? {
+            let mut lost = LostSecret {
+                id: record.id,
+                name: record.name.clone(),
+                key_version: record.key_version,
+                updated_at: record.updated_at,
+                reason: "bound to this machine".to_string(),
+            };
+            if record.bind_host {
+                unrecoverable.push(lost);
+                continue;
+            }
+            let tags = tags.remove(&record.id).unwrap_or_default();
+            match decrypt_record(&chain, record) {
+                Ok(secret) => entries.push(BackupEntry::new(secret, tags)),
+                Err(e) => {
+                    warn!("salvage: {e:#}");
+                    lost.reason = format!("{e:#}");
+                    unrecoverable.push(lost);
+                }
+            }
+        }
+        let file = backup_file(&chain, &entries, passphrase)?;
+        let detail = format!(
+            "salvage of {} secrets, {} unrecoverable",
+            entries.len(),
+            unrecoverable.len()
+        );
+        // the vault may be too damaged to write to; the export matters more
+        if let Err(e) = self.audit(AuditAction::Export, None, Some(&detail)).await {
+            warn!("could not record the salvage in the audit log: {e:#}");
+        }
+        let manifest = SalvageManifest {
+            created_at: Utc::now().trunc_subsecs(0),
+            exported: entries.len(),
+            unrecoverable,
+        };
+        Ok((file, manifest))
+    }
+
+    /// Decrypt `file` with `passphrase` or, for master-key backups, any key this vault
+    /// has had, and store its entries under the current key in one transaction.
+    pub async fn restore_backup(
+        &self,
+        file: &BackupFile,
+        passphrase: Option<&str>,
+        strategy: RestoreStrategy,
+    ) -> Result<RestoreSummary> {
+        let entries = match (&file.protection, passphrase) {
+            (Protection::MasterKey, _) => {
+                let chain = self.key_chain().await?;
+                let mut keys = chain.keys.values().rev();
+                loop {
+                    let Some(key) = keys.next() else {
+                        bail!("backup cannot be decrypted with this vault's master key");
+                    };
+                    if let Ok(entries) = file.entries(key) {
+                        break entries;
+                    }
+                }
+            }
+            (protection, Some(passphrase)) => {
+                let key = protection
+                    .passphrase_key(passphrase)?
+                    .expect("passphrase protection derives a key");
+                file.entries(&key)?
+            }
+            (_, None) => bail!("backup is protected by a passphrase"),
+        };
+        self.transaction(async |tx| {
+            let mut summary = RestoreSummary::default();
+            for entry in entries {
+                let mut name = entry.name.clone();
+                let exists = tx.repo.fetch_secret(&name).await?.is_some();
+                if exists {
+                    match strategy {
+                        RestoreStrategy::Skip => {
+                            summary.skipped.push(name);
+                            continue;
+                        }
+                        RestoreStrategy::Overwrite => {}
+                        RestoreStrategy::Rename => {
+                            name = format!("{}-restored", entry.name);
+                            let mut n = 2;
+                            while tx.repo.fetch_secret(&name).await?.is_some() {
+                                name = format!("{}-restored-{n}", entry.name);
+                                n += 1;
+                            }
+                        }
+                    }
+                }
+                tx.add_secret(&name, &entry.value()?, entry.attributes())
+                    .await
+                    .with_context(|| format!("restoring '{name}'"))?;
+                if !entry.tags.is_empty() {
+                    tx.tag_secret(&name, &entry.tags).await?;
+                }
+                match (exists, strategy) {
+                    (false, _) => summary.added.push(name),
+                    (true, RestoreStrategy::Rename) => summary.renamed.push((entry.name, name)),
+                    (true, _) => summary.overwritten.push(name),
+                }
+            }
+            let detail = format!(
+                "backup from {}: {} added, {} overwritten, {} renamed, {} skipped",
+                file.created_at.format("%Y-%m-%d %H:%M"),
+                summary.added.len(),
+                summary.overwritten.len(),
+                summary.renamed.len(),
+                summary.skipped.len()
+            );
+            tx.audit(AuditAction::Import, None, Some(&detail)).await?;
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Secrets whose name matches the shell-style `glob`, ordered by name.
+    pub async fn secrets_matching(&self, glob: &str) -> Result<Vec<SecretMetadata>> {
+        Ok(self
+            .list_secrets()
+            .await?
+            .into_iter()
+            .filter(|m| domain::name_matches(glob, &m.name))
+            .collect())
+    }
+
+    /// Apply `patch` to every secret matching `glob` in one transaction; returns the
+    /// names changed. Values are not touched, so immutable and locked secrets qualify.
+    pub async fn bulk_set(&self, glob: &str, patch: &MetadataPatch) -> Result<Vec<String>> {
+        let targets = self.secrets_matching(glob).await?;
+        self.set_metadata(&targets, patch).await
+    }
+
+    /// Apply `patch` to `targets` in one transaction, as `bulk_set` does.
+    pub async fn set_metadata(
+        &self,
+        targets: &[SecretMetadata],
+        patch: &MetadataPatch,
+    ) -> Result<Vec<String>> {
+        let changed = patch.changed_fields();
+        if changed.is_empty() {
+            bail!("nothing to set; give --note, --owner, --contact, --expires or --tag");
+        }
+        if let Some(bad) = patch.tags.iter().find(|t| !domain::is_valid_tag(t)) {
+            bail!("'{bad}' is not a valid tag (no spaces or commas)");
+        }
+        let ids: Vec<Uuid> = targets.iter().map(|m| m.id).collect();
+        self.replica_id().await?;
+        self.repo.update_metadata(&ids, patch).await?;
+        let detail = format!("set {}", changed.join(", "));
+        for target in targets {
+            self.record_op(OpKind::Put, &target.name).await?;
+            self.audit(AuditAction::Update, Some(&target.name), Some(&detail))
+                .await?;
+        }
+        info!("bulk set {} on {} secret(s)", detail, targets.len());
+        Ok(targets.iter().map(|m| m.name.clone()).collect())
+    }
+
+    /// Every tag in use with how many secrets carry it.
+    pub async fn tag_counts(&self) -> Result<Vec<(String, i64)>> {
+        self.repo.tag_counts().await
+    }
+
+    /// Inject `name` as `var` wherever secrets become environment variables.
+    pub async fn set_env_mapping(&self, name: &str, var: &str) -> Result<()> {
+        if !domain::is_env_var_name(var) {
+            bail!("'{var}' is not a valid environment variable name");
+        }
+        if self.repo.fetch_secret(name).await?.is_none() {
+            bail!("secret not found");
+        }
+        self.repo.set_env_mapping(name, var).await?;
+        self.audit(
+            AuditAction::Update,
+            Some(name),
+            Some(&format!("env var {var}")),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Go back to the derived variable name. Returns `false` when there was no mapping.
+    pub async fn remove_env_mapping(&self, name: &str) -> Result<bool> {
+        let removed = self.repo.delete_env_mapping(name).await?;
+        if removed {
+            self.audit(AuditAction::Update, Some(name), Some("env var reset"))
+                .await?;
+        }
+        Ok(removed)
+    }
+
+    pub async fn env_mappings(&self) -> Result<BTreeMap<String, String>> {
+        Ok(self.repo.list_env_mappings().await?.into_iter().collect())
+    }
+
+    /// Variable each secret is injected as: its mapping, else the derived name.
+    pub async fn env_vars_for(&self, names: &[String]) -> Result<Vec<String>> {
+        let mappings = self.env_mappings().await?;
+        Ok(names
+            .iter()
+            .map(|name| {
+                mappings
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_else(|| domain::env_var_name(name))
+            })
+            .collect())
+    }
+
+    /// Secrets under `prefix` (carrying `tag`, if given) with the variable each is
+    /// exported as: its mapping, else the name without `prefix` made env-safe. Two
+    /// secrets landing on the same variable is an error, not a silent overwrite.
+    pub async fn env_export(
+        &self,
+        prefix: &str,
+        tag: Option<&str>,
+    ) -> Result<Vec<(String, Secret)>> {
+        let tags = self.repo.tags_by_secret().await?;
+        let mut secrets = self.secrets_with_prefix(prefix).await?;
+        if let Some(tag) = tag {
+            secrets.retain(|s| tags.get(&s.id).is_some_and(|t| t.iter().any(|t| t == tag)));
+        }
+        let mappings = self.env_mappings().await?;
+        let mut seen: BTreeMap<String, String> = BTreeMap::new();
+        let mut vars = Vec::with_capacity(secrets.len());
+        for secret in secrets {
+            let var = mappings.get(&secret.name).cloned().unwrap_or_else(|| {
+                domain::env_var_name(secret.name.strip_prefix(prefix).unwrap_or(&secret.name))
+            });
+            if let Some(other) = seen.insert(var.clone(), secret.name.clone()) {
+                bail!(
+                    "'{other}' and '{}' both export as {var}; rename one or `map set` it",
+                    secret.name
+                );
+            }
+            vars.push((var, secret));
+        }
+        Ok(vars)
+    }
+
+    /// Move a secret to a new name. The name is part of the associated data, so the
+    /// value is decrypted and sealed again for `new`; id and metadata stay as they were.
+    pub async fn rename_secret(&self, old: &str, new: &str) -> Result<SecretMetadata> {
+        if old == new {
+            bail!("'{old}' already has that name");
+        }
+        let chain = self.key_chain().await?;
+        let record = self
+            .repo
+            .fetch_secret(old)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))?;
+        if record.immutable {
+            bail!("secret '{old}' is immutable; run `clear-immutable {old}` first");
+        }
+        self.ensure_unlocked(&record).await?;
+        if self.repo.fetch_secret(new).await?.is_some() {
+            bail!("secret '{new}' already exists");
+        }
+        self.require_approval("delete", old).await?;
+        let bind_host = record.bind_host;
+        let secret = decrypt_record(&chain, record)?;
+        let sealed =
+            Binding::of(new, bind_host)?.seal(&chain.current_crypto()?, &secret.plaintext)?;
+        self.replica_id().await?;
+        if !self
+            .repo
+            .rename_secret(old, new, &sealed, chain.current)
+            .await?
+        {
+            bail!("secret not found");
+        }
+        self.record_op(OpKind::Delete, old).await?;
+        self.record_op(OpKind::Put, new).await?;
+        self.audit(
+            AuditAction::Update,
+            Some(new),
+            Some(&format!("renamed from {old}")),
+        )
+        .await?;
+        info!("renamed secret '{}' to '{}'", old, new);
+        let record = self
+            .repo
+            .fetch_secret(new)
+            .await?
+            .ok_or_else(|| anyhow!("secret vanished after rename"))?;
+        Ok(to_metadata(record))
+    }
+
+    /// Rename every secret under `old_prefix` to live under `new_prefix` instead, in one
+    /// transaction. Nothing is renamed when any target name is already taken or any
+    /// secret is locked, immutable or protected without an approval.
+    pub async fn rename_prefix(
+        &self,
+        old_prefix: &str,
+        new_prefix: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let records = self.repo.list_secrets_with_prefix(old_prefix).await?;
+        for record in &records {
+            if record.immutable {
+                bail!(
+                    "secret '{}' is immutable; run `clear-immutable {}` first",
+                    record.name,
+                    record.name
+                );
+            }
+            self.ensure_unlocked(record).await?;
+        }
+        let moves: Vec<(String, String)> = records
+            .into_iter()
+            .map(|r| {
+                let new = format!("{new_prefix}{}", &r.name[old_prefix.len()..]);
+                (r.name, new)
+            })
+            .collect();
+        for (old, new) in &moves {
+            if self.repo.fetch_secret(new).await?.is_some() {
+                bail!("cannot move '{old}': '{new}' already exists");
+            }
+        }
+        // approvals are only consumed inside the transaction, so a failed move keeps them
+        for (old, _) in &moves {
+            if !self.has_approval("delete", old).await? {
+                self.require_approval("delete", old).await?;
+            }
+        }
+        self.transaction(async |tx| {
+            for (old, new) in &moves {
+                tx.rename_secret(old, new).await?;
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(moves)
+    }
+
+    /// Hold a secret in place: overwriting, renaming and removing it fail until
+    /// `unlock_secret`. Returns `false` when it was already locked.
+    pub async fn lock_secret(&self, name: &str, reason: Option<&str>) -> Result<bool> {
+        let record = self
+            .repo
+            .fetch_secret(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))?;
+        let lock = SecretLock {
+            locked_by: self.current_identity(false).await?,
+            locked_at: Utc::now(),
+            reason: reason.map(str::to_string),
+        };
+        if !self.repo.insert_lock(record.id, &lock).await? {
+            return Ok(false);
+        }
+        let detail = match reason {
+            Some(reason) => format!("locked: {reason}"),
+            None => "locked".to_string(),
+        };
+        self.audit(AuditAction::Update, Some(name), Some(&detail))
+            .await?;
+        info!("locked secret: {}", name);
+        Ok(true)
+    }
+
+    /// Lift a lock; `false` when the secret was not locked.
+    pub async fn unlock_secret(&self, name: &str) -> Result<bool> {
+        let record = self
+            .repo
+            .fetch_secret(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))?;
+        if !self.repo.delete_lock(record.id).await? {
+            return Ok(false);
+        }
+        self.audit(AuditAction::Update, Some(name), Some("unlocked"))
+            .await?;
+        info!("unlocked secret: {}", name);
+        Ok(true)
+    }
+
+    pub async fn secret_lock(&self, name: &str) -> Result<Option<SecretLock>> {
+        let record = self
+            .repo
+            .fetch_secret(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))?;
+        self.repo.fetch_lock(record.id).await
+    }
+
+    async fn ensure_unlocked(&self, record: &SecretRecord) -> Result<()> {
+        if let Some(lock) = self.repo.fetch_lock(record.id).await? {
+            let name = &record.name;
+            let reason = lock.reason.map(|r| format!(" ({r})")).unwrap_or_default();
+            bail!(
+                "secret '{name}' is locked by {}{reason}; run `unlock {name}` first",
+                lock.locked_by
+            );
+        }
+        Ok(())
+    }
+
+    /// Drop the write-once flag so the secret can be changed or removed again.
+    pub async fn clear_immutable(&self, name: &str) -> Result<()> {
+        self.replica_id().await?;
+        if !self.repo.set_immutable(name, false).await? {
+            bail!("secret not found");
+        }
+        self.record_op(OpKind::Put, name).await?;
+        self.audit(AuditAction::Update, Some(name), Some("cleared immutable"))
+            .await?;
+        info!("cleared immutable flag on secret: {}", name);
+        Ok(())
+    }
+
+    /// This vault's replica id; created on first use, seeding the log with existing secrets.
+    pub async fn replica_id(&self) -> Result<String> {
+        if let Some(id) = self.repo.meta_value(REPLICA_KEY).await? {
+            return Ok(id);
+        }
+        let id = Uuid::new_v4().to_string();
+        self.repo.set_meta_value(REPLICA_KEY, &id).await?;
+        info!("created replica id {}", id);
+        for record in self.repo.list_secrets().await? {
+            let name = record.name.clone();
+            self.append_op(&id, OpKind::Put, &name, Some(record))
+                .await?;
+        }
+        Ok(id)
+    }
+
+    pub async fn vector_clock(&self) -> Result<VectorClock> {
+        match self.repo.meta_value(CLOCK_KEY).await? {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Ok(VectorClock::default()),
+        }
+    }
+
+    /// Every logged operation, oldest first.
+    pub async fn operations(&self) -> Result<Vec<Operation>> {
+        self.replica_id().await?;
+        self.repo.list_ops(None).await
+    }
+
+    async fn record_op(&self, kind: OpKind, name: &str) -> Result<()> {
+        let replica = self.replica_id().await?;
+        let record = match kind {
+            OpKind::Put => self.repo.fetch_secret(name).await?,
+            OpKind::Delete => None,
+        };
+        self.append_op(&replica, kind, name, record).await
+    }
+
+    async fn append_op(
+        &self,
+        replica: &str,
+        kind: OpKind,
+        name: &str,
+        record: Option<SecretRecord>,
+    ) -> Result<()> {
+        let mut clock = self.vector_clock().await?;
+        clock.tick(replica);
+        let op = Operation {
+            op_id: Uuid::new_v4(),
+            replica: replica.to_string(),
+            clock: clock.clone(),
+            kind,
+            secret_name: name.to_string(),
+            record,
+            created_at: Utc::now(),
+        };
+        self.repo.insert_op(&op).await?;
+        self.repo
+            .set_meta_value(CLOCK_KEY, &serde_json::to_string(&clock)?)
+            .await?;
+        debug!("logged {} of '{}'", kind.as_str(), name);
+        Ok(())
+    }
+
+    /// Save credentials read from another store in one transaction, so a failing item
+    /// leaves the vault untouched; existing names are skipped unless `overwrite`.
+    /// Recorded in the audit log as one import from `source`.
+    pub async fn import_secrets(
+        &self,
+        source: &str,
+        items: Vec<ImportItem>,
+        overwrite: bool,
+    ) -> Result<ImportSummary> {
+        self.transaction(async |tx| {
+            let mut summary = ImportSummary::default();
+            for item in items {
+                let exists = tx.repo.fetch_secret(&item.name).await?.is_some();
+                if exists && !overwrite {
+                    summary.skipped.push(item.name);
+                    continue;
+                }
+                let attrs = SecretAttributes {
+                    provenance: Provenance::new(Origin::Import, source),
+                    ..item.attrs
+                };
+                tx.add_secret(&item.name, &item.value, attrs)
+                    .await
+                    .with_context(|| format!("importing '{}'", item.name))?;
+                if exists {
+                    summary.updated.push(item.name);
+                } else {
+                    summary.added.push(item.name);
+                }
+            }
+            let detail = format!(
+                "{source}: {} added, {} updated, {} skipped",
+                summary.added.len(),
+                summary.updated.len(),
+                summary.skipped.len()
+            );
+            tx.audit(AuditAction::Import, None, Some(&detail)).await?;
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Generate every secret of the template `name` under `prefix`, all or nothing.
+    /// Secrets that already exist keep their values.
+    pub async fn apply_template(
+        &self,
+        name: &str,
+        blueprint: &Blueprint,
+        prefix: &str,
+    ) -> Result<TemplateSummary> {
+        self.transaction(async |tx| {
+            let mut summary = TemplateSummary::default();
+            for secret in &blueprint.secrets {
+                let full_name = format!("{prefix}{}", secret.name);
+                if tx.repo.fetch_secret(&full_name).await?.is_some() {
+                    summary.skipped.push(full_name);
+                    continue;
+                }
+                let (value, bits) = secret.generate()?;
+                let attrs = SecretAttributes {
+                    kind: secret.kind.clone(),
+                    note: secret.note.clone(),
+                    provenance: Provenance::new(Origin::Generated, format!("template {name}")),
+                    ..Default::default()
+                };
+                tx.add_secret(&full_name, value.as_bytes(), attrs)
+                    .await
+                    .with_context(|| format!("creating '{full_name}'"))?;
+                tx.tag_secret(&full_name, &secret.tags).await?;
+                summary.created.push((full_name, bits));
+            }
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Merge operations from another replica and re-materialize every secret they touch,
+    /// in one transaction: a failure leaves the vault and its clock as they were.
+    /// Both replicas must share the master key for merged ciphertexts to decrypt.
+    pub async fn merge_operations(&self, ops: Vec<Operation>) -> Result<MergeSummary> {
+        let summary = self
+            .transaction(async |tx| tx.apply_operations(ops).await)
+            .await?;
+        info!(
+            "merged {} new operations ({} updated, {} deleted)",
+            summary.new_ops,
+            summary.updated.len(),
+            summary.deleted.len()
+        );
+        Ok(summary)
+    }
+
+    async fn apply_operations(&self, ops: Vec<Operation>) -> Result<MergeSummary> {
+        let local = self.replica_id().await?;
+        let mut summary = MergeSummary {
+            received: ops.len(),
+            ..Default::default()
+        };
+        let mut clock = self.vector_clock().await?;
+        let mut touched = BTreeSet::new();
+        for op in ops {
+            if self.repo.insert_op(&op).await? {
+                summary.new_ops += 1;
+                clock.merge(&op.clock);
+                touched.insert(op.secret_name);
+            }
+        }
+        self.repo
+            .set_meta_value(CLOCK_KEY, &serde_json::to_string(&clock)?)
+            .await?;
+
+        for name in touched {
+            let ops = self.repo.list_ops(Some(&name)).await?;
+            let Some(winner) = oplog::winner(&ops) else {
+                continue;
+            };
+            match (&winner.kind, &winner.record) {
+                (OpKind::Put, Some(record)) if winner.replica != local => {
+                    let record = SecretRecord {
+                        provenance: Some(Provenance::new(Origin::Sync, &winner.replica)),
+                        ..record.clone()
+                    };
+                    self.repo.put_record(&record).await?;
+                    summary.updated.push(name);
+                }
+                (OpKind::Put, Some(record)) => {
+                    self.repo.put_record(record).await?;
+                    summary.updated.push(name);
+                }
+                _ => {
+                    if self.repo.delete_secret(&name).await? {
+                        summary.deleted.push(name);
+                    }
+                }
+            }
+        }
+        if summary.new_ops > 0 {
+            let detail = format!(
+                "merged {} operations ({} updated, {} deleted)",
+                summary.new_ops,
+                summary.updated.len(),
+                summary.deleted.len()
+            );
+            self.audit(AuditAction::Import, None, Some(&detail)).await?;
+        }
+        Ok(summary)
+    }
+
+    /// Switch to a new master key. Eagerly rewraps every record's data key (values
+    /// stay as they are; records from before envelope encryption or under a cipher
+    /// other than the vault's are re-encrypted once), or with `lazy` only retires the old key (wrapped under the new one) so
+    /// records move over as they are rewritten or read with re-encrypt-on-read enabled.
+    ///
+    /// A vault whose key comes from a passphrase, YubiKey or PKCS#11 token gets its new
+    /// key the same way: a newly chosen passphrase (which is how it is changed), a
+    /// fresh challenge or a freshly wrapped random key. `to` switches any vault over.
+    pub async fn rotate_master_key(&self, lazy: bool, to: Option<KeyOrigin>) -> Result<()> {
+        let chain = self.key_chain().await?;
+        let Some(origin) = to.or(self.key_origin().await?) else {
+            let new_key = self.key_provider.rotate().await?;
+            return self.rotate_to(chain, new_key, lazy).await;
+        };
+        let (settings, new_key) = self.new_origin_key(origin)?;
+        // records and the settings giving their key change together
+        self.transaction(async |tx| {
+            tx.rotate_to(chain, new_key, lazy).await?;
+            for other in KeyOrigin::ALL {
+                tx.repo.delete_meta_value(other.meta_key()).await?;
+            }
+            tx.repo.set_meta_value(origin.meta_key(), &settings).await
+        })
+        .await
+    }
+
+    async fn rotate_to(&self, chain: KeyChain, new_key: MasterKey, lazy: bool) -> Result<()> {
+        let new_crypto = SecretCrypto::new(new_key.clone()).with_cipher(chain.cipher);
+        let new_version = chain.current + 1;
+        if lazy {
+            let mut retired = Vec::new();
+            for (version, key) in &chain.keys {
+                let wrapped = new_crypto.encrypt(&key_version_label(*version), &key.0)?;
+                retired.push((*version, wrapped));
+            }
+            self.repo.set_key_chain(new_version, &retired).await?;
+        } else {
+            self.repo
+                .reencrypt_all(new_version, |record| reseal(&chain, &new_crypto, record))
+                .await?;
+        }
+        for device in self.repo.list_devices().await? {
+            if device.revoked_at.is_none() {
+                let wrapped =
+                    crypto::wrap_for_device(&new_key, &device.public_key, &device.id.to_string())?;
+                self.repo.update_device_wrap(device.id, &wrapped).await?;
+            }
+        }
+        for slot in self.repo.list_key_slots().await? {
+            let wrapped =
+                crypto::wrap_for_device(&new_key, &slot.public_key, &key_slot_label(slot.id))?;
+            self.repo.update_slot_wrap(slot.id, &wrapped).await?;
+        }
+        let detail = format!(
+            "master key v{new_version}{}",
+            if lazy { " (lazy)" } else { "" }
+        );
+        self.audit(AuditAction::Rotate, None, Some(&detail)).await?;
+        info!("master key rotated to version {}", new_version);
+        Ok(())
+    }
+
+    /// Live and trashed secrets still encrypted under a retired key version.
+    pub async fn stale_secret_counts(&self) -> Result<(i64, i64)> {
+        let current = self.repo.current_key_version().await?;
+        self.repo.count_stale(current).await
+    }
+
+    /// Sign a snapshot of the vault's current records with the master key.
+    pub async fn attest(&self) -> Result<Snapshot> {
+        let chain = self.key_chain().await?;
+        let mut snapshot = self.current_snapshot(chain.current).await?;
+        let key = &chain.keys[&chain.current];
+        let signature = crypto::sign_attestation(key, snapshot.signing_payload().as_bytes());
+        snapshot.signature = attest::hex(&signature);
+        Ok(snapshot)
+    }
+
+    /// Check the signature of an earlier snapshot and return the vault's current
+    /// state to compare it with.
+    pub async fn verify_attestation(&self, snapshot: &Snapshot) -> Result<Snapshot> {
+        let chain = self.key_chain().await?;
+        let key = chain.keys.get(&snapshot.key_version).ok_or_else(|| {
+            anyhow!(
+                "snapshot was signed with master key version {}, which this vault no longer holds",
+                snapshot.key_version
+            )
+        })?;
+        let signature = attest::unhex(&snapshot.signature)
+            .ok_or_else(|| anyhow!("snapshot signature is not hex"))?;
+        if !crypto::verify_attestation(key, snapshot.signing_payload().as_bytes(), &signature) {
+            bail!("snapshot signature does not match; it was altered or made for another vault");
+        }
+        self.current_snapshot(chain.current).await
+    }
+
+    async fn current_snapshot(&self, key_version: i64) -> Result<Snapshot> {
+        let records = self.repo.list_secrets().await?;
+        let leaves: Vec<[u8; 32]> = records
+            .iter()
+            .map(|r| attest::leaf(&r.name, &r.ciphertext))
+            .collect();
+        let devices = self
+            .repo
+            .list_devices()
+            .await?
+            .iter()
+            .filter(|d| d.revoked_at.is_none())
+            .count();
+        Ok(Snapshot {
+            version: 1,
+            created_at: Utc::now().trunc_subsecs(0),
+            secrets: records.len(),
+            devices,
+            key_version,
+            root: attest::hex(&attest::merkle_root(&leaves)),
+            signature: String::new(),
+        })
+    }
+
+    /// Compare secrets under `from` (optionally only relative names matching `only`)
+    /// with their counterparts under `to`.
+    pub async fn plan_promotion(
+        &self,
+        from: &str,
+        to: &str,
+        only: Option<&str>,
+    ) -> Result<Vec<PromoteItem>> {
+        let chain = self.key_chain().await?;
+        let mut plan = Vec::new();
+        for source in self.secrets_with_prefix(from).await? {
+            let relative = &source.name[from.len()..];
+            if only.is_some_and(|pattern| !domain::name_matches(pattern, relative)) {
+                continue;
+            }
+            let target = format!("{to}{relative}");
+            let status = match self.repo.fetch_secret(&target).await? {
+                None => PromoteStatus::New,
+                Some(record) => {
+                    if decrypt_record(&chain, record)?.plaintext == source.plaintext {
+                        PromoteStatus::Unchanged
+                    } else {
+                        PromoteStatus::Changed
+                    }
+                }
+            };
+            plan.push(PromoteItem {
+                source: source.name,
+                target,
+                status,
+            });
+        }
+        Ok(plan)
+    }
+
+    /// Copy every new or changed item of `plan` with its attributes, in one transaction.
+    /// With `fresh_value` each target gets a new random value instead of the source's,
+    /// so the environments stop sharing it; the source is never changed.
+    pub async fn promote(&self, plan: &[PromoteItem], fresh_value: bool) -> Result<usize> {
+        let copied = self
+            .transaction(async |tx| {
+                let mut copied = 0;
+                for item in plan {
+                    if item.status == PromoteStatus::Unchanged {
+                        continue;
+                    }
+                    let source = tx.get_secret(&item.source).await?;
+                    let attrs = SecretAttributes {
+                        provenance: Provenance::new(Origin::Promotion, &item.source),
+                        ..source.to_attributes()
+                    };
+                    let value = if fresh_value {
+                        rotation::random_value().into_bytes()
+                    } else {
+                        source.plaintext.clone()
+                    };
+                    tx.add_secret(&item.target, &value, attrs).await?;
+                    copied += 1;
+                }
+                Ok(copied)
+            })
+            .await?;
+        info!("promoted {} secrets", copied);
+        Ok(copied)
+    }
+
+    /// Names whose live secrets differ between this vault and `other`, in name order.
+    /// Only metadata is compared, so no key is needed.
+    pub async fn diff_vault(&self, other: &SecretService) -> Result<Vec<VaultDiffItem>> {
+        let mut there: BTreeMap<String, SecretRecord> = other
+            .repo
+            .list_secrets()
+            .await?
+            .into_iter()
+            .map(|r| (r.name.clone(), r))
+            .collect();
+        let mut diff = Vec::new();
+        for record in self.repo.list_secrets().await? {
+            let theirs = there.remove(&record.name);
+            let status = match &theirs {
+                None => VaultDiffStatus::Added,
+                Some(theirs) if theirs.updated_at != record.updated_at => VaultDiffStatus::Changed,
+                Some(_) => continue,
+            };
+            diff.push(VaultDiffItem {
+                host_bound: record.bind_host || theirs.as_ref().is_some_and(|t| t.bind_host),
+                there: theirs.map(|t| t.updated_at),
+                here: Some(record.updated_at),
+                name: record.name,
+                status,
+            });
+        }
+        diff.extend(there.into_values().map(|theirs| VaultDiffItem {
+            name: theirs.name,
+            status: VaultDiffStatus::Removed,
+            here: None,
+            there: Some(theirs.updated_at),
+            host_bound: theirs.bind_host,
+        }));
+        diff.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(diff)
+    }
+
+    /// Items of an import from `source` whose names this vault already holds, in
+    /// import order.
+    pub async fn import_conflicts(
+        &self,
+        source: &str,
+        items: &[ImportItem],
+    ) -> Result<Vec<Conflict>> {
+        let chain = self.key_chain().await?;
+        let key = &chain.keys[&chain.current];
+        let mut conflicts = Vec::new();
+        for item in items {
+            let Some(record) = self.repo.fetch_secret(&item.name).await? else {
+                continue;
+            };
+            let existing = decrypt_record(&chain, record)?;
+            conflicts.push(Conflict {
+                name: item.name.clone(),
+                existing_fingerprint: value_fingerprint(key, &existing.plaintext),
+                existing_size: existing.plaintext.len(),
+                existing: existing.to_metadata(),
+                incoming: SecretAttributes {
+                    provenance: Provenance::new(Origin::Import, source),
+                    ..item.attrs.clone()
+                },
+                incoming_updated: None,
+                incoming_fingerprint: value_fingerprint(key, &item.value),
+                incoming_size: item.value.len(),
+            });
+        }
+        Ok(conflicts)
+    }
+
+    /// Names a sync into `target` would have to decide between, in name order:
+    /// every changed secret that is not bound to a machine.
+    pub async fn sync_conflicts(&self, target: &SecretService) -> Result<Vec<Conflict>> {
+        let chain = self.key_chain().await?;
+        let target_chain = target.key_chain().await?;
+        let key = &chain.keys[&chain.current];
+        let mut conflicts = Vec::new();
+        for item in self.diff_vault(target).await? {
+            if item.status != VaultDiffStatus::Changed || item.host_bound {
+                continue;
+            }
+            let fetch = async |service: &SecretService, chain: &KeyChain| -> Result<Secret> {
+                let record = service
+                    .repo
+                    .fetch_secret(&item.name)
+                    .await?
+                    .ok_or_else(|| anyhow!("'{}' vanished during the sync", item.name))?;
+                decrypt_record(chain, record)
+            };
+            let incoming = fetch(self, &chain).await?;
+            let existing = fetch(target, &target_chain)
+                .await
+                .context("the other vault uses a different master key")?;
+            conflicts.push(Conflict {
+                name: item.name,
+                existing_fingerprint: value_fingerprint(key, &existing.plaintext),
+                existing_size: existing.plaintext.len(),
+                existing: existing.to_metadata(),
+                incoming_fingerprint: value_fingerprint(key, &incoming.plaintext),
+                incoming_size: incoming.plaintext.len(),
+                incoming_updated: Some(incoming.updated_at),
+                incoming: SecretAttributes {
+                    provenance: incoming.provenance.clone().unwrap_or_default(),
+                    ..incoming.to_attributes()
+                },
+            });
+        }
+        Ok(conflicts)
+    }
+
+    /// Copy secrets that are new or newer here into `target`, keeping their timestamps
+    /// so both vaults then agree. Secrets newer in the target stay unless `resolutions`
+    /// (from [`Self::sync_conflicts`]) say otherwise; with `delete`, secrets only in the
+    /// target go to its trash. One transaction in the target.
+    pub async fn sync_into(
+        &self,
+        target: &SecretService,
+        delete: bool,
+        resolutions: &BTreeMap<String, Resolution>,
+    ) -> Result<SyncSummary> {
+        let diff = self.diff_vault(target).await?;
+        let chain = self.key_chain().await?;
+        let replica = self.replica_id().await?;
+        let tags = self.repo.tags_by_secret().await?;
+        let summary = target
+            .transaction(async |tx| {
+                let target_chain = tx.key_chain().await?;
+                if let Some(sample) = tx
+                    .repo
+                    .list_secrets()
+                    .await?
+                    .into_iter()
+                    .find(|r| !r.bind_host)
+                {
+                    decrypt_record(&target_chain, sample)
+                        .context("the other vault uses a different master key")?;
+                }
+                let mut summary = SyncSummary::default();
+                for item in diff {
+                    if item.status == VaultDiffStatus::Removed {
+                        if delete {
+                            tx.delete_secret(item.name.as_str())
+                                .await
+                                .with_context(|| format!("trashing '{}'", item.name))?;
+                            summary.trashed.push(item.name);
+                        }
+                        continue;
+                    }
+                    let resolution = resolutions.get(&item.name);
+                    let name = match resolution {
+                        Some(Resolution::KeepBoth(name)) => name.clone(),
+                        _ => item.name.clone(),
+                    };
+                    let existing = tx.repo.fetch_secret(&name).await?;
+                    if existing.is_some() && name != item.name {
+                        bail!("'{name}' already exists in the target");
+                    }
+                    let skip = if item.host_bound {
+                        Some("bound to this machine")
+                    } else if resolution == Some(&Resolution::KeepExisting) {
+                        Some("kept the target's version")
+                    } else if resolution.is_none() && !item.newer_here() {
+                        Some("newer in the target")
+                    } else if existing.as_ref().is_some_and(|e| e.immutable) {
+                        Some("immutable in the target")
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = skip {
+                        summary.skipped.push((item.name, reason.to_string()));
+                        continue;
+                    }
+                    if let Some(existing) = &existing {
+                        tx.ensure_unlocked(existing).await?;
+                    }
+                    let record = self
+                        .repo
+                        .fetch_secret(&item.name)
+                        .await?
+                        .ok_or_else(|| anyhow!("'{}' vanished during the sync", item.name))?;
+                    let secret = decrypt_record(&chain, record)?;
+                    let sealed = target_chain
+                        .current_crypto()?
+                        .seal(&name, &secret.plaintext)?;
+                    tx.replica_id().await?;
+                    tx.repo
+                        .put_record(&SecretRecord {
+                            id: existing.map_or_else(Uuid::new_v4, |e| e.id),
+                            name: name.clone(),
+                            kind: secret.kind,
+                            note: secret.note,
+                            owner: secret.owner,
+                            contact: secret.contact,
+                            immutable: secret.immutable,
+                            bind_host: false,
+                            allowed_commands: secret.allowed_commands,
+                            expires_at: secret.expires_at,
+                            certificate: secret.certificate,
+                            fields: secret.fields,
+                            provenance: Some(Provenance::new(Origin::Sync, &replica)),
+                            key_version: target_chain.current,
+                            ciphertext: sealed.ciphertext,
+                            data_key: Some(sealed.wrapped_key),
+                            created_at: secret.created_at,
+                            updated_at: secret.updated_at,
+                        })
+                        .await?;
+                    if let Some(tags) = tags.get(&secret.id) {
+                        tx.tag_secret(&name, tags).await?;
+                    }
+                    tx.record_op(OpKind::Put, &name).await?;
+                    summary.copied.push(name);
+                }
+                let detail = format!(
+                    "sync from replica {replica}: {} copied, {} skipped, {} trashed",
+                    summary.copied.len(),
+                    summary.skipped.len(),
+                    summary.trashed.len()
+                );
+                tx.audit(AuditAction::Import, None, Some(&detail)).await?;
+                Ok(summary)
+            })
+            .await?;
+        if !summary.copied.is_empty() {
+            let detail = format!("synced {} secrets to another vault", summary.copied.len());
+            self.audit(AuditAction::Export, None, Some(&detail)).await?;
+        }
+        info!(
+            "synced {} secrets ({} skipped, {} trashed)",
+            summary.copied.len(),
+            summary.skipped.len(),
+            summary.trashed.len()
+        );
+        Ok(summary)
+    }
+
+    /// Copy `name` into `target`, a vault with its own key, with its attributes and
+    /// tags; with `remove` it then goes to this vault's trash. A name the target
+    /// already holds is only replaced with `overwrite`.
+    pub async fn transfer(
+        &self,
+        name: &str,
+        target: &SecretService,
+        overwrite: bool,
+        remove: bool,
+    ) -> Result<SecretMetadata> {
+        let secret = self.get_secret(name).await?;
+        if secret.bind_host {
+            bail!("'{name}' is bound to this machine and cannot leave this vault");
+        }
+        if !overwrite && target.repo.fetch_secret(name).await?.is_some() {
+            bail!("the target vault already has '{name}'; pass --overwrite to replace it");
+        }
+        let tags = self.secret_tags(name).await?;
+        let attrs = SecretAttributes {
+            provenance: Provenance::new(Origin::Transfer, &self.replica_id().await?),
+            ..secret.to_attributes()
+        };
+        let copied = target
+            .transaction(async |tx| {
+                let copied = tx.add_secret(name, &secret.plaintext, attrs).await?;
+                if !tags.is_empty() {
+                    tx.tag_secret(name, &tags).await?;
+                }
+                Ok(copied)
+            })
+            .await?;
+        if remove {
+            self.delete_secret(name)
+                .await
+                .with_context(|| format!("'{name}' was copied but stays in this vault"))?;
+        }
+        let detail = if remove {
+            "moved to another vault"
+        } else {
+            "copied to another vault"
+        };
+        self.audit(AuditAction::Export, Some(name), Some(detail))
+            .await?;
+        info!("transferred secret '{}' (moved: {})", name, remove);
+        Ok(copied)
+    }
+
+    /// Copy `names` into `target` like `transfer`, in one transaction per vault. Names
+    /// the target holds (without `overwrite`) and machine-bound secrets are skipped
+    /// rather than failing the batch; with `remove` only the copied ones go to the trash.
+    pub async fn transfer_many(
+        &self,
+        names: &[String],
+        target: &SecretService,
+        overwrite: bool,
+        remove: bool,
+    ) -> Result<TransferSummary> {
+        let source = Provenance::new(Origin::Transfer, &self.replica_id().await?);
+        let mut secrets = Vec::new();
+        for name in names {
+            let secret = self.get_secret(name).await?;
+            let tags = self.secret_tags(name).await?;
+            secrets.push((secret, tags));
+        }
+        let summary = target
+            .transaction(async |tx| {
+                let mut summary = TransferSummary::default();
+                for (secret, tags) in &secrets {
+                    let name = &secret.name;
+                    if secret.bind_host {
+                        summary
+                            .skipped
+                            .push((name.clone(), "bound to this machine".into()));
+                        continue;
+                    }
+                    if !overwrite && tx.repo.fetch_secret(name).await?.is_some() {
+                        summary
+                            .skipped
+                            .push((name.clone(), "the target vault already has it".into()));
+                        continue;
+                    }
+                    let attrs = SecretAttributes {
+                        provenance: source.clone(),
+                        ..secret.to_attributes()
+                    };
+                    tx.add_secret(name, &secret.plaintext, attrs).await?;
+                    if !tags.is_empty() {
+                        tx.tag_secret(name, tags).await?;
+                    }
+                    summary.copied.push(name.clone());
+                }
+                Ok(summary)
+            })
+            .await?;
+        let detail = if remove {
+            "moved to another vault"
+        } else {
+            "copied to another vault"
+        };
+        self.transaction(async |tx| {
+            for name in &summary.copied {
+                if remove {
+                    tx.delete_secret(name).await?;
+                }
+                tx.audit(AuditAction::Export, Some(name), Some(detail))
+                    .await?;
+            }
+            Ok(())
+        })
+        .await
+        .with_context(|| {
+            format!(
+                "{} secrets were copied but stay in this vault",
+                summary.copied.len()
+            )
+        })?;
+        info!(
+            "transferred {} secrets (moved: {})",
+            summary.copied.len(),
+            remove
+        );
+        Ok(summary)
+    }
+
+    /// Fill the empty vault `target` with the secrets under `prefix`, re-encrypted
+    /// under its key, so it can be handed to someone who should see only those.
+    /// Revoking it later means rotating the same secrets here.
+    pub async fn export_subvault(
+        &self,
+        prefix: &str,
+        target: &SecretService,
+    ) -> Result<TransferSummary> {
+        if !target.repo.list_secrets().await?.is_empty() {
+            bail!("the sub-vault must start empty");
+        }
+        let names: Vec<String> = self
+            .list_secrets()
+            .await?
+            .into_iter()
+            .map(|s| s.name)
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        if names.is_empty() {
+            bail!("no secrets under '{prefix}'");
+        }
+        self.transfer_many(&names, target, false, false).await
+    }
+
+    /// Metadata of the secrets matching `selector`, in name order.
+    pub async fn select_secrets(&self, selector: &Selector) -> Result<Vec<SecretMetadata>> {
+        let tags = self.repo.tags_by_secret().await?;
+        let mut secrets = self.list_secrets().await?;
+        secrets.retain(|s| {
+            let tags = tags.get(&s.id).map(Vec::as_slice).unwrap_or_default();
+            selector.matches(s, tags)
+        });
+        Ok(secrets)
+    }
+
+    /// Give every secret matching `selector` a new value from `provider`, keeping its
+    /// attributes. A secret that fails keeps its old value and the rest still rotate.
+    pub async fn rotate_secrets(
+        &self,
+        selector: &Selector,
+        provider: &Provider,
+    ) -> Result<RotationSummary> {
+        let mut summary = RotationSummary::default();
+        for meta in self.select_secrets(selector).await? {
+            let rotated = async {
+                let current = self.get_secret(&meta.name).await?;
+                let value = provider.new_value(&current)?;
+                let attrs = SecretAttributes {
+                    provenance: provider.provenance(),
+                    ..current.to_attributes()
+                };
+                self.add_secret(&meta.name, &value, attrs).await
+            }
+            .await;
+            match rotated {
+                Ok(_) => summary.rotated.push(meta.name),
+                Err(e) => {
+                    debug!("rotating '{}' failed: {e:#}", meta.name);
+                    summary.failed.push((meta.name, format!("{e:#}")));
+                }
+            }
+        }
+        info!(
+            "rotated {} secrets, {} failed",
+            summary.rotated.len(),
+            summary.failed.len()
+        );
+        Ok(summary)
+    }
+
+    /// Whether the secret `name` lies under a protected prefix.
+    pub fn is_protected_name(&self, name: &str) -> bool {
+        self.protected_prefixes
+            .iter()
+            .any(|p| name.starts_with(p.as_str()))
+    }
+
+    /// Whether exporting everything under `prefix` reaches a protected prefix, either
+    /// inside it or by containing it (`pr` contains `prod/`).
+    pub fn is_protected_prefix(&self, prefix: &str) -> bool {
+        self.protected_prefixes
+            .iter()
+            .any(|p| prefix.starts_with(p.as_str()) || p.starts_with(prefix))
+    }
+
+    /// Whether `action` on `target` may go ahead now, without consuming the approval.
+    async fn has_approval(&self, action: &str, target: &str) -> Result<bool> {
+        if !self.is_protected_name(target) {
+            return Ok(true);
+        }
+        let open = self.repo.open_approvals(Some((action, target))).await?;
+        Ok(open.iter().any(|r| r.approved_by.is_some()))
+    }
+
+    /// Two-person rule: pass when the secret `target` is unprotected or an approved
+    /// request for `action` on it exists (consuming it); otherwise file or point at a
+    /// pending request.
+    pub async fn require_approval(&self, action: &str, target: &str) -> Result<()> {
+        if !self.is_protected_name(target) {
+            return Ok(());
+        }
+        self.approval(action, target).await
+    }
+
+    /// The two-person rule for exporting everything under `prefix`.
+    pub async fn require_export_approval(&self, prefix: &str) -> Result<()> {
+        if !self.is_protected_prefix(prefix) {
+            return Ok(());
+        }
+        self.approval("export", prefix).await
+    }
+
+    async fn approval(&self, action: &str, target: &str) -> Result<()> {
+        let open = self.repo.open_approvals(Some((action, target))).await?;
+        if let Some(approved) = open.iter().find(|r| r.approved_by.is_some()) {
+            self.repo.consume_approval(approved.id).await?;
+            info!(
+                "using approval {} for {} of '{}'",
+                approved.id, action, target
+            );
+            return Ok(());
+        }
+        let id = match open.first() {
+            Some(pending) => pending.id,
+            None => {
+                let request = ApprovalRequest {
+                    id: Uuid::new_v4(),
+                    action: action.to_string(),
+                    target: target.to_string(),
+                    requested_by: self.current_identity(false).await?,
+                    created_at: Utc::now(),
+                    approved_by: None,
+                    approved_at: None,
+                };
+                self.repo.insert_approval(&request).await?;
+                request.id
+            }
+        };
+        bail!(
+            "'{target}' is protected: {action} needs approval; another device must run `approve {id}`, then retry"
+        );
+    }
+
+    pub async fn pending_approvals(&self) -> Result<Vec<ApprovalRequest>> {
+        self.repo.open_approvals(None).await
+    }
+
+    /// Approve a request as this machine's enrolled device.
+    pub async fn approve(&self, id: Uuid) -> Result<ApprovalRequest> {
+        let approver = self.current_identity(true).await?;
+        self.approve_as(id, &approver).await
+    }
+
+    /// Record `approver`'s approval; the requester can never approve their own request.
+    pub async fn approve_as(&self, id: Uuid, approver: &str) -> Result<ApprovalRequest> {
+        let Some(request) = self.repo.fetch_approval(id).await? else {
+            bail!("no open approval request {id}");
+        };
+        if request.approved_by.is_some() {
+            bail!("request {id} is already approved");
+        }
+        if request.requested_by == approver {
+            bail!("request {id} was filed by {approver}; a second token holder must approve it");
+        }
+        self.repo.set_approved(id, approver).await?;
+        let detail = format!(
+            "{} of '{}' for {}",
+            request.action, request.target, request.requested_by
+        );
+        self.audit(AuditAction::Approve, None, Some(&detail))
+            .await?;
+        Ok(request)
+    }
+
+    /// Grant one read of `name` within `ttl`; returns the lease and its token, which is
+    /// shown only now. Secrets restricted to certain programs cannot be leased.
+    pub async fn create_lease(&self, name: &str, ttl: chrono::Duration) -> Result<(Lease, String)> {
+        if ttl <= chrono::Duration::zero() {
+            bail!("lease ttl must be positive");
+        }
+        let record = self
+            .repo
+            .fetch_secret(name)
+            .await?
+            .ok_or_else(|| anyhow!("secret not found"))?;
+        if !record.allowed_commands.is_empty() {
+            bail!("secret '{name}' is restricted to certain programs and cannot be leased");
+        }
+        let token = format!("{LEASE_TOKEN_PREFIX}{}", rotation::random_value());
+        let created_at = Utc::now().trunc_subsecs(0);
+        let lease = Lease {
+            id: Uuid::new_v4(),
+            secret_name: name.to_string(),
+            created_by: self.current_identity(false).await?,
+            created_at,
+            expires_at: created_at + ttl,
+            redeemed_at: None,
+        };
+        self.repo
+            .insert_lease(&lease, &lease_token_hash(&token))
+            .await?;
+        let detail = format!(
+            "created {} until {}",
+            lease.id,
+            lease.expires_at.to_rfc3339()
+        );
+        self.audit(AuditAction::Lease, Some(name), Some(&detail))
+            .await?;
+        Ok((lease, token))
+    }
+
+    /// Exchange a lease token for the secret; each token works once, before it expires.
+    pub async fn redeem_lease(&self, token: &str) -> Result<Secret> {
+        let Some(lease) = self
+            .repo
+            .redeem_lease(&lease_token_hash(token.trim()))
+            .await?
+        else {
+            bail!("lease token is unknown, expired or already redeemed");
+        };
+        let detail = format!("redeemed {}", lease.id);
+        self.audit(AuditAction::Lease, Some(&lease.secret_name), Some(&detail))
+            .await?;
+        self.get_secret(&lease.secret_name).await
+    }
+
+    pub async fn open_leases(&self) -> Result<Vec<Lease>> {
+        self.repo.open_leases().await
+    }
+
+    pub async fn revoke_lease(&self, id: Uuid) -> Result<bool> {
+        let Some(name) = self.repo.delete_lease(id).await? else {
+            return Ok(false);
+        };
+        let detail = format!("revoked {id}");
+        self.audit(AuditAction::Lease, Some(&name), Some(&detail))
+            .await?;
+        Ok(true)
+    }
+
+    /// `device:<label>` when this machine holds an active device key, else `user:<login>`
+    /// (rejected when `require_device`).
+    async fn current_identity(&self, require_device: bool) -> Result<String> {
+        if let Some((device_id, secret)) = self.key_provider.read_device_key().unwrap_or(None)
+            && let Some(wrapped) = self.repo.fetch_device_wrap(device_id).await?
+            && crypto::unwrap_with_device(&secret, &wrapped, &device_id.to_string()).is_ok()
+            && let Some(device) = self
+                .repo
+                .list_devices()
+                .await?
+                .into_iter()
+                .find(|d| d.id == device_id)
+        {
+            return Ok(format!("device:{}", device.label));
+        }
+        if require_device {
+            bail!("approving needs an enrolled device key on this machine (`device enroll`)");
+        }
+        Ok(format!("user:{}", host::login_name()))
+    }
+
+    /// Decrypt up to `sample` random records (the AEAD tag check catches altered
+    /// bytes), pausing `pause` before each so a background caller stays light on the
+    /// disk. The report is kept for `last_integrity_check`.
+    pub async fn check_integrity(
+        &self,
+        sample: usize,
+        pause: std::time::Duration,
+    ) -> Result<IntegrityReport> {
+        let chain = self.key_chain().await?;
+        let records = self.repo.sample_records(sample as i64).await?;
+        let mut report = IntegrityReport {
+            checked_at: Utc::now(),
+            checked: records.len(),
+            failures: Vec::new(),
+        };
+        for record in records {
+            tokio::time::sleep(pause).await;
+            let name = record.name.clone();
+            if let Err(e) = decrypt_record(&chain, record) {
+                warn!("integrity check: '{name}' failed: {e:#}");
+                report.failures.push(format!("{name}: {e:#}"));
+            }
+        }
+        self.repo
+            .set_meta_value(INTEGRITY_KEY, &serde_json::to_string(&report)?)
+            .await?;
+        Ok(report)
+    }
+
+    /// Report of the most recent `check_integrity`, if one ever ran.
+    pub async fn last_integrity_check(&self) -> Result<Option<IntegrityReport>> {
+        self.repo
+            .meta_value(INTEGRITY_KEY)
+            .await?
+            .map(|json| serde_json::from_str(&json).context("reading the integrity report"))
+            .transpose()
+    }
+
+    /// Append to the local audit log, stamped with this machine and user.
+    pub async fn audit(
+        &self,
+        action: AuditAction,
+        secret_name: Option<&str>,
+        detail: Option<&str>,
+    ) -> Result<()> {
+        let hostname = host::hostname();
+        self.repo
+            .insert_audit(
+                action,
+                secret_name,
+                detail,
+                hostname.as_deref(),
+                &host::login_name(),
+            )
+            .await
+    }
+
+    /// Audit entries matching `filter`, newest first.
+    pub async fn audit_entries(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        self.repo.query_audit(filter).await
+    }
+
+    /// Audit log entries from the last `window`, oldest first.
+    pub async fn audit_since(&self, window: chrono::Duration) -> Result<Vec<AuditEntry>> {
+        self.repo.list_audit(Utc::now() - window).await
+    }
+
+    /// Audit log entries recorded at or after `since`, oldest first.
+    pub async fn audit_from(&self, since: DateTime<Utc>) -> Result<Vec<AuditEntry>> {
+        self.repo.list_audit(since).await
+    }
+
+    /// Send `sink` the audit entries it has not received yet. The cursor only moves
+    /// once delivery succeeded, so a failed attempt is retried in full next time.
+    pub async fn ship_audit(&self, sink: &audit::Sink) -> Result<usize> {
+        let key = sink.cursor_key();
+        let after = match self.repo.meta_value(&key).await? {
+            Some(id) => id.parse()?,
+            None => 0,
+        };
+        let entries = self.repo.list_audit_after(after).await?;
+        let Some(last) = entries.last() else {
+            return Ok(0);
+        };
+        audit::ship(sink, &entries)?;
+        self.repo.set_meta_value(&key, &last.id.to_string()).await?;
+        info!("shipped {} audit entries", entries.len());
+        Ok(entries.len())
+    }
+}
+
+/// Only this digest is stored, so a copy of the vault does not yield usable tokens.
+fn lease_token_hash(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    attest::hex(&Sha256::digest(token.as_bytes()))
+}
+
+/// What a record's value is bound to: its name, and for host-bound secrets the machine
+/// id, which goes into the associated data and also keys the wrapping of the data key.
+struct Binding {
+    aad: String,
+    machine: Option<String>,
+}
+
+impl Binding {
+    /// The binding of `name` on this machine.
+    fn of(name: &str, bind_host: bool) -> Result<Self> {
+        let machine = match bind_host {
+            true => Some(host::machine_id().context("host binding needs a machine id")?),
+            false => None,
+        };
+        Ok(Self::on(name, machine))
+    }
+
+    fn on(name: &str, machine: Option<String>) -> Self {
+        let aad = match &machine {
+            Some(machine) => format!("{name}\x1fhost:{machine}"),
+            None => name.to_string(),
+        };
+        Self { aad, machine }
+    }
+
+    /// `crypto` with the machine id mixed in, when the value is host-bound.
+    fn key(&self, crypto: &SecretCrypto) -> Option<SecretCrypto> {
+        self.machine
+            .as_deref()
+            .map(|machine| crypto.bound_to(machine))
+    }
+
+    fn seal(&self, crypto: &SecretCrypto, plaintext: &[u8]) -> Result<Envelope> {
+        self.key(crypto)
+            .as_ref()
+            .unwrap_or(crypto)
+            .seal(&self.aad, plaintext)
+    }
+
+    /// Host-bound values written before their data keys were wrapped under the
+    /// machine-keyed key still open with `crypto` itself.
+    fn open(
+        &self,
+        crypto: &SecretCrypto,
+        wrapped_key: Option<&[u8]>,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>> {
+        match (self.key(crypto), wrapped_key) {
+            (Some(bound), Some(wrapped)) => bound
+                .open(&self.aad, Some(wrapped), ciphertext)
+                .or_else(|_| crypto.open(&self.aad, Some(wrapped), ciphertext)),
+            _ => crypto.open(&self.aad, wrapped_key, ciphertext),
+        }
+    }
+
+    /// The data key moved from `from` to `to`; a host-bound one from before the
+    /// machine-keyed wrapping gets it on the way.
+    fn rewrap(
+        &self,
+        from: &SecretCrypto,
+        to: &SecretCrypto,
+        wrapped_key: &[u8],
+    ) -> Result<Vec<u8>> {
+        match (self.key(from), self.key(to)) {
+            (Some(from_bound), Some(to_bound)) => from_bound
+                .rewrap(&to_bound, &self.aad, wrapped_key)
+                .or_else(|_| from.rewrap(&to_bound, &self.aad, wrapped_key)),
+            _ => from.rewrap(to, &self.aad, wrapped_key),
+        }
+    }
+}
+
+/// `entries` encrypted under the current master key, or under `passphrase` with a
+/// salt derived from it.
+fn backup_file(
+    chain: &KeyChain,
+    entries: &[BackupEntry],
+    passphrase: Option<&str>,
+) -> Result<BackupFile> {
+    let key = &chain.keys[&chain.current];
+    match passphrase {
+        Some(passphrase) => {
+            let protection = Protection::passphrase_for(key);
+            let key = protection
+                .passphrase_key(passphrase)?
+                .expect("passphrase protection derives a key");
+            BackupFile::create(entries, protection, &key)
+        }
+        None => BackupFile::create(entries, Protection::MasterKey, key),
+    }
+}
+
+/// Associated data for a retired master key wrapped under the current one.
+fn key_version_label(version: i64) -> String {
+    format!("devinventory key version {version}")
+}
+
+fn key_slot_label(id: i64) -> String {
+    format!("devinventory key slot {id}")
+}
+
+fn slot_kdf(slot: &KeySlot) -> Result<PassphraseKdf> {
+    let params = slot
+        .params
+        .as_deref()
+        .ok_or_else(|| anyhow!("key slot {} has no passphrase settings", slot.id))?;
+    serde_json::from_str(params).with_context(|| format!("reading key slot {}", slot.id))
+}
+
+/// Short keyed digest telling values apart without revealing them.
+fn value_fingerprint(key: &MasterKey, value: &[u8]) -> String {
+    attest::hex(&crypto::keyed_digest(key, VALUE_FINGERPRINT_INFO, value)[..6])
+}
+
+/// `record`'s envelope under `to`: its data key rewrapped, or for a record sealed
+/// directly under a master key or with another cipher, the value decrypted and sealed
+/// anew.
+fn reseal(chain: &KeyChain, to: &SecretCrypto, record: &SecretRecord) -> Result<Envelope> {
+    let binding = Binding::of(&record.name, record.bind_host)?;
+    let from = chain.crypto(record.key_version)?;
+    match &record.data_key {
+        Some(wrapped) if Cipher::of(&record.ciphertext) == to.cipher() => Ok(Envelope {
+            wrapped_key: binding.rewrap(&from, to, wrapped)?,
+            ciphertext: record.ciphertext.clone(),
+        }),
+        data_key => {
+            let mut plaintext = binding.open(&from, data_key.as_deref(), &record.ciphertext)?;
+            let sealed = binding.seal(to, &plaintext);
+            plaintext.zeroize();
+            sealed
+        }
+    }
+}
+
+fn decrypt_record(chain: &KeyChain, record: SecretRecord) -> Result<Secret> {
+    let name = &record.name;
+    let plaintext = Binding::of(name, record.bind_host)?
+        .open(
+            &chain.crypto(record.key_version)?,
+            record.data_key.as_deref(),
+            &record.ciphertext,
+        )
+        .with_context(|| {
+            if record.bind_host {
+                format!("'{name}' is bound to the machine it was created on")
+            } else {
+                format!("cannot decrypt '{name}'")
+            }
+        })?;
+    Ok(Secret {
+        id: record.id,
+        name: record.name,
+        kind: record.kind,
+        note: record.note,
+        owner: record.owner,
+        contact: record.contact,
+        immutable: record.immutable,
+        bind_host: record.bind_host,
+        allowed_commands: record.allowed_commands,
+        expires_at: record.expires_at,
+        certificate: record.certificate,
+        fields: record.fields,
+        provenance: record.provenance,
+        plaintext,
+        created_at: record.created_at,
+        updated_at: record.updated_at,
+    })
+}
+
+/// Allow-list entries containing a path separator must match the resolved
+/// program path exactly; bare entries match the executable's file name exactly,
+/// apart from the platform's executable suffix (`psql` allows `psql.exe` on Windows,
+/// never `psql.sh`).
+fn exec_allowed(allowed: &[String], program: &Path) -> bool {
+    if allowed.is_empty() {
+        return true;
+    }
+    let file_name = program.file_name().map(|s| s.to_string_lossy());
+    let file_name = file_name.as_deref().map(|name| {
+        name.strip_suffix(std::env::consts::EXE_SUFFIX)
+            .unwrap_or(name)
+    });
+    allowed.iter().any(|entry| {
+        if entry.contains(['/', '\\']) {
+            Path::new(entry) == program
+        } else {
+            file_name == Some(entry.as_str())
+        }
+    })
+}
+
+fn to_metadata(record: SecretRecord) -> SecretMetadata {
+    SecretMetadata {
+        id: record.id,
+        name: record.name,
+        kind: record.kind,
+        note: record.note,
+        owner: record.owner,
+        contact: record.contact,
+        immutable: record.immutable,
+        bind_host: record.bind_host,
+        allowed_commands: record.allowed_commands,
+        expires_at: record.expires_at,
+        certificate: record.certificate,
+        fields: record.fields,
+        provenance: record.provenance,
+        created_at: record.created_at,
+        updated_at: record.updated_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    async fn test_service(dir: &tempfile::TempDir) -> SecretService {
+        test_service_with_key(dir, testing::DEFAULT_KEY_SEED).await
+    }
+
+    async fn test_service_with_key(dir: &tempfile::TempDir, key_seed: u8) -> SecretService {
+        testing::open_service(dir.path(), key_seed).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn protected_delete_needs_second_approver() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir)
+            .await
+            .with_protected_prefixes(vec!["prod/".into()]);
+        for name in ["prod/db", "dev/db"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+
+        assert!(service.delete_secret("dev/db").await.unwrap());
+        // a short name is not under `prod/` just because `prod/` starts with it
+        for name in ["p", "pro"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+            assert!(!service.is_protected_name(name));
+            assert!(service.delete_secret(name).await.unwrap());
+        }
+        assert!(service.is_protected_prefix("pro"));
+        assert!(!service.is_protected_prefix("dev/"));
+        assert!(service.delete_secret("prod/db").await.is_err());
+        // retrying reuses the pending request instead of filing another
+        assert!(service.delete_secret("prod/db").await.is_err());
+        let pending = service.pending_approvals().await.unwrap();
+        assert_eq!(pending.len(), 1);
+
+        let request = &pending[0];
+        let requester = request.requested_by.clone();
+        assert!(service.approve_as(request.id, &requester).await.is_err());
+        service
+            .approve_as(request.id, "device:laptop")
+            .await
+            .unwrap();
+
+        assert!(service.delete_secret("prod/db").await.unwrap());
+        assert!(service.pending_approvals().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn metadata_commands_need_no_master_key() {
+        let dir = tempfile::tempdir().unwrap();
+        test_service(&dir)
+            .await
+            .add_secret("api/token", b"v", SecretAttributes::default())
+            .await
+            .unwrap();
+        let repo = Repository::connect(&dir.path().join("test.db"))
+            .await
+            .unwrap();
+        let keyless = SecretService::new(
+            repo,
+            MasterKeyProvider::new(
+                keymgr::MasterKeySource {
+                    base64_inline: None,
+                    key_file: None,
+                    allow_keyring: false,
+                },
+                "devinventory-test".into(),
+                "dmk".into(),
+            ),
+        );
+        assert_eq!(keyless.list_secrets().await.unwrap().len(), 1);
+        assert_eq!(keyless.search_secrets("api").await.unwrap().len(), 1);
+        assert_eq!(keyless.stats(1).await.unwrap().secrets, 1);
+        assert!(keyless.ensure_master_key().await.is_err());
+        assert!(keyless.get_secret("api/token").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rotation_moves_values_to_the_vault_cipher() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("old", b"v1", SecretAttributes::default())
+            .await
+            .unwrap();
+        service.set_cipher(Cipher::Aes256Gcm).await.unwrap();
+        service
+            .add_secret("new", b"v2", SecretAttributes::default())
+            .await
+            .unwrap();
+        let cipher_of = async |name: &str| {
+            let record = service.repo.fetch_secret(name).await.unwrap().unwrap();
+            Cipher::of(&record.ciphertext)
+        };
+        assert_eq!(cipher_of("old").await, Cipher::ChaCha20Poly1305);
+        assert_eq!(cipher_of("new").await, Cipher::Aes256Gcm);
+        assert_eq!(service.get_secret("old").await.unwrap().plaintext, b"v1");
+
+        let chain = service.key_chain().await.unwrap();
+        service
+            .rotate_to(chain, testing::deterministic_key(7), false)
+            .await
+            .unwrap();
+        drop(service);
+        let service = test_service_with_key(&dir, 7).await;
+        for (name, value) in [("old", b"v1"), ("new", b"v2")] {
+            let record = service.repo.fetch_secret(name).await.unwrap().unwrap();
+            assert_eq!(Cipher::of(&record.ciphertext), Cipher::Aes256Gcm);
+            assert_eq!(service.get_secret(name).await.unwrap().plaintext, value);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn yubikey_vaults_unlock_only_with_their_yubikey() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let open = async |secret: &str| {
+            // answers like a YubiKey whose HMAC secret is `secret`
+            let program = dir.path().join(format!("yk-{secret}"));
+            std::fs::write(
+                &program,
+                format!("#!/bin/sh\necho \"{secret} $*\" | sha1sum | cut -d' ' -f1\n"),
+            )
+            .unwrap();
+            std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o700)).unwrap();
+            let repo = Repository::connect(&dir.path().join("test.db"))
+                .await
+                .unwrap();
+            repo.migrate().await.unwrap();
+            let provider = MasterKeyProvider::new(
+                keymgr::MasterKeySource {
+                    base64_inline: None,
+                    key_file: None,
+                    allow_keyring: false,
+                },
+                "devinventory-test".into(),
+                "dmk".into(),
+            )
+            .with_yubikey(crate::yubikey::Device {
+                slot: 2,
+                program: program.to_string_lossy().into_owned(),
+            });
+            SecretService::new(repo, provider)
+        };
+
+        let service = open("mine").await;
+        service.init_with(KeyOrigin::Yubikey).await.unwrap();
+        service
+            .add_secret("db", b"v1", SecretAttributes::default())
+            .await
+            .unwrap();
+        assert!(service.init_with(KeyOrigin::Passphrase).await.is_err());
+        let challenge = service.yubikey_challenge().await.unwrap().unwrap();
+        drop(service);
+
+        let service = open("mine").await;
+        assert_eq!(service.get_secret("db").await.unwrap().plaintext, b"v1");
+        service.rotate_master_key(false, None).await.unwrap();
+        let rotated = service.yubikey_challenge().await.unwrap().unwrap();
+        assert_ne!(rotated.challenge, challenge.challenge);
+        drop(service);
+
+        assert_eq!(
+            open("mine").await.get_secret("db").await.unwrap().plaintext,
+            b"v1"
+        );
+        assert!(open("other").await.get_secret("db").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn lazy_rotation_upgrades_records_on_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = test_service(&dir).await;
+        for (name, value) in [("a", b"v1"), ("b", b"v2"), ("c", b"v3")] {
+            old.add_secret(name, value, SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        old.delete_secret("c").await.unwrap();
+        let chain = old.key_chain().await.unwrap();
+        old.rotate_to(chain, testing::deterministic_key(7), true)
+            .await
+            .unwrap();
+        drop(old);
+
+        let service = test_service_with_key(&dir, 7)
+            .await
+            .with_reencrypt_on_read(true);
+        assert_eq!(service.stale_secret_counts().await.unwrap(), (2, 1));
+        assert_eq!(service.get_secret("a").await.unwrap().plaintext, b"v1");
+        assert_eq!(service.stale_secret_counts().await.unwrap(), (1, 1));
+
+        // an eager rotation finishes the rest and drops retired keys
+        let chain = service.key_chain().await.unwrap();
+        service
+            .rotate_to(chain, testing::deterministic_key(8), false)
+            .await
+            .unwrap();
+        drop(service);
+        let service = test_service_with_key(&dir, 8).await;
+        assert_eq!(service.stale_secret_counts().await.unwrap(), (0, 0));
+        assert_eq!(service.get_secret("b").await.unwrap().plaintext, b"v2");
+    }
+
+    #[tokio::test]
+    async fn rotation_rewraps_data_keys_and_upgrades_old_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("new", b"v1", SecretAttributes::default())
+            .await
+            .unwrap();
+        // a record from before envelopes: sealed with the master key itself
+        let legacy = SecretCrypto::new(testing::deterministic_key(testing::DEFAULT_KEY_SEED))
+            .encrypt("old", b"v2")
+            .unwrap();
+        let mut record = service.repo.fetch_secret("new").await.unwrap().unwrap();
+        record.id = Uuid::new_v4();
+        record.name = "old".into();
+        record.ciphertext = legacy;
+        record.data_key = None;
+        service.repo.put_record(&record).await.unwrap();
+        let before = service.repo.fetch_secret("new").await.unwrap().unwrap();
+
+        let chain = service.key_chain().await.unwrap();
+        service
+            .rotate_to(chain, testing::deterministic_key(7), false)
+            .await
+            .unwrap();
+        drop(service);
+        let service = test_service_with_key(&dir, 7).await;
+        let after = service.repo.fetch_secret("new").await.unwrap().unwrap();
+        assert_eq!(after.ciphertext, before.ciphertext);
+        assert_ne!(after.data_key, before.data_key);
+        assert!(
+            service
+                .repo
+                .fetch_secret("old")
+                .await
+                .unwrap()
+                .unwrap()
+                .data_key
+                .is_some()
+        );
+        assert_eq!(service.get_secret("new").await.unwrap().plaintext, b"v1");
+        assert_eq!(service.get_secret("old").await.unwrap().plaintext, b"v2");
+    }
+
+    #[tokio::test]
+    async fn key_slots_unwrap_the_current_master_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let (slot, recovery) = service
+            .add_key_slot(SlotKind::Recovery, "paper")
+            .await
+            .unwrap();
+        let unlock_key = keymgr::parse_recovery_key(&recovery.unwrap()).unwrap();
+        let opened = service.open_slot(&slot, &unlock_key).await.unwrap();
+        assert_eq!(
+            opened.0,
+            testing::deterministic_key(testing::DEFAULT_KEY_SEED).0
+        );
+        assert!(service.open_slot(&slot, &[7; 32]).await.is_err());
+
+        let chain = service.key_chain().await.unwrap();
+        service
+            .rotate_to(chain, testing::deterministic_key(7), false)
+            .await
+            .unwrap();
+        let opened = service.open_slot(&slot, &unlock_key).await.unwrap();
+        assert_eq!(opened.0, testing::deterministic_key(7).0);
+
+        let (second, _) = service
+            .add_key_slot(SlotKind::Recovery, "spare")
+            .await
+            .unwrap();
+        assert_eq!(second.id, 1);
+        service.remove_key_slot(0).await.unwrap();
+        let slots = service.list_key_slots().await.unwrap();
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].label, "spare");
+        assert!(service.remove_key_slot(0).await.is_err());
+    }
+
+    /// Answers prompts from a script, as a person at the terminal would.
+    struct Scripted(std::sync::Mutex<Vec<String>>);
+
+    impl keymgr::Prompter for Scripted {
+        fn can_ask(&self) -> bool {
+            true
+        }
+
+        fn secret(&self, _prompt: &str) -> Result<String> {
+            let mut answers = self.0.lock().unwrap();
+            Ok(answers.remove(0))
+        }
+    }
+
+    #[tokio::test]
+    async fn key_slots_are_asked_for_through_the_prompter() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("api", b"k1", SecretAttributes::default())
+            .await
+            .unwrap();
+        let (_, recovery) = service
+            .add_key_slot(SlotKind::Recovery, "paper")
+            .await
+            .unwrap();
+        let keyless = || {
+            MasterKeyProvider::new(
+                keymgr::MasterKeySource {
+                    base64_inline: None,
+                    key_file: None,
+                    allow_keyring: false,
+                },
+                "devinventory-test".into(),
+                "dmk".into(),
+            )
+        };
+        let unasked = SecretService::new(service.repo.clone(), keyless());
+        assert!(unasked.get_secret("api").await.is_err());
+
+        let answers = vec!["not it".to_string(), recovery.unwrap()];
+        let prompter = std::sync::Arc::new(Scripted(std::sync::Mutex::new(answers)));
+        let asked = SecretService::new(service.repo.clone(), keyless().with_prompter(prompter));
+        assert_eq!(asked.get_secret("api").await.unwrap().plaintext, b"k1");
+    }
+
+    #[tokio::test]
+    async fn attestation_detects_changes_and_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("a", b"v1", SecretAttributes::default())
+            .await
+            .unwrap();
+        let snapshot = service.attest().await.unwrap();
+        let now = service.verify_attestation(&snapshot).await.unwrap();
+        assert_eq!(
+            (now.root.as_str(), now.secrets),
+            (snapshot.root.as_str(), 1)
+        );
+
+        service
+            .add_secret("a", b"v2", SecretAttributes::default())
+            .await
+            .unwrap();
+        let now = service.verify_attestation(&snapshot).await.unwrap();
+        assert_ne!(now.root, snapshot.root);
+
+        let mut forged = snapshot.clone();
+        forged.root = now.root;
+        assert!(service.verify_attestation(&forged).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reveals_past_the_hourly_limit_need_confirmation() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await.with_reveal_limit(Some(2));
+        service
+            .add_secret("a", b"v", SecretAttributes::default())
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            assert_eq!(service.reveal_cooldown("a").await.unwrap(), None);
+            service.reveal_secret("a").await.unwrap();
+        }
+        assert_eq!(service.reveal_cooldown("a").await.unwrap(), Some(2));
+        // plain reads (exec, templates) do not count
+        service.get_secret("b").await.ok();
+        assert_eq!(service.reveal_cooldown("b").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn values_over_the_size_limit_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await.with_value_limits(Some(4), Some(8));
+        service
+            .add_secret("a", b"12345678", SecretAttributes::default())
+            .await
+            .unwrap();
+        let err = service
+            .add_secret("a", b"123456789", SecretAttributes::default())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("'a' is 9 bytes, over the 8-byte limit")
+        );
+        let item = ImportItem {
+            name: "big".into(),
+            value: vec![0; 9],
+            attrs: SecretAttributes::default(),
+        };
+        assert!(
+            service
+                .import_secrets("test", vec![item], false)
+                .await
+                .is_err()
+        );
+        assert!(service.find_secret("big").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn import_skips_existing_names_unless_overwriting() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("kc/a", b"old", SecretAttributes::default())
+            .await
+            .unwrap();
+        let items = || {
+            ["kc/a", "kc/b"]
+                .map(|name| ImportItem {
+                    name: name.into(),
+                    value: b"new".to_vec(),
+                    attrs: SecretAttributes::default(),
+                })
+                .to_vec()
+        };
+        let summary = service
+            .import_secrets("test", items(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            (summary.added, summary.skipped),
+            (vec!["kc/b".to_string()], vec!["kc/a".to_string()])
+        );
+        assert_eq!(service.get_secret("kc/a").await.unwrap().plaintext, b"old");
+
+        let summary = service.import_secrets("test", items(), true).await.unwrap();
+        assert_eq!(summary.updated.len(), 2);
+        assert_eq!(service.get_secret("kc/a").await.unwrap().plaintext, b"new");
+
+        // one rejected item rolls back the whole batch
+        service
+            .add_secret(
+                "kc/frozen",
+                b"v",
+                SecretAttributes {
+                    immutable: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let batch = ["kc/c", "kc/frozen"]
+            .map(|name| ImportItem {
+                name: name.into(),
+                value: b"new".to_vec(),
+                attrs: SecretAttributes::default(),
+            })
+            .to_vec();
+        assert!(service.import_secrets("test", batch, true).await.is_err());
+        assert!(service.get_secret("kc/c").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn env_export_maps_tagged_secrets_to_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["app/db-pass", "app/api.key", "app/other", "web/x"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        let backend = vec!["backend".to_string()];
+        for name in ["app/db-pass", "app/api.key", "web/x"] {
+            service.tag_secret(name, &backend).await.unwrap();
+        }
+        service
+            .set_env_mapping("app/api.key", "API_TOKEN")
+            .await
+            .unwrap();
+        let vars = service.env_export("app/", Some("backend")).await.unwrap();
+        let vars: Vec<(&str, &str)> = vars
+            .iter()
+            .map(|(var, s)| (var.as_str(), s.name.as_str()))
+            .collect();
+        assert_eq!(
+            vars,
+            [("API_TOKEN", "app/api.key"), ("DB_PASS", "app/db-pass")]
+        );
+
+        service
+            .set_env_mapping("app/other", "DB_PASS")
+            .await
+            .unwrap();
+        assert!(service.env_export("app/", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn env_mappings_override_derived_names_until_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("db/prod", b"pw", SecretAttributes::default())
+            .await
+            .unwrap();
+        let names = vec!["db/prod".to_string(), "api".to_string()];
+        assert!(service.set_env_mapping("db/prod", "DB-PASS").await.is_err());
+        assert!(service.set_env_mapping("missing", "X").await.is_err());
+        service
+            .set_env_mapping("db/prod", "DATABASE_PASSWORD")
+            .await
+            .unwrap();
+        assert_eq!(
+            service.env_vars_for(&names).await.unwrap(),
+            ["DATABASE_PASSWORD", "API"]
+        );
+        service.delete_secret("db/prod").await.unwrap();
+        assert!(service.env_mappings().await.unwrap().is_empty());
+        assert!(!service.remove_env_mapping("db/prod").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rename_reseals_value_and_keeps_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let attrs = SecretAttributes {
+            owner: Some("ops".into()),
+            ..Default::default()
+        };
+        let before = service.add_secret("old", b"pw", attrs).await.unwrap();
+        service
+            .add_secret("taken", b"x", SecretAttributes::default())
+            .await
+            .unwrap();
+        service.set_env_mapping("old", "PW").await.unwrap();
+        assert!(service.rename_secret("old", "taken").await.is_err());
+
+        let after = service.rename_secret("old", "new").await.unwrap();
+        assert_eq!((after.id, after.owner.as_deref()), (before.id, Some("ops")));
+        assert_eq!(service.get_secret("new").await.unwrap().plaintext, b"pw");
+        assert!(service.find_secret("old").await.unwrap().is_none());
+        assert_eq!(service.env_mappings().await.unwrap()["new"], "PW");
+    }
+
+    #[tokio::test]
+    async fn tags_follow_the_secret_and_select_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["a", "b"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        let tags = |t: &[&str]| t.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert!(
+            service
+                .tag_secret("a", &tags(&["two words"]))
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            service
+                .tag_secret("a", &tags(&["aws", "prod"]))
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(service.tag_secret("a", &tags(&["aws"])).await.unwrap(), 0);
+        service.tag_secret("b", &tags(&["aws"])).await.unwrap();
+
+        // the value is rewritten and the secret renamed; tags stay attached
+        service
+            .add_secret("a", b"v2", SecretAttributes::default())
+            .await
+            .unwrap();
+        service.rename_secret("a", "c").await.unwrap();
+        let selector = Selector {
+            tag: Some("prod".into()),
+            ..Default::default()
+        };
+        let selected = service.select_secrets(&selector).await.unwrap();
+        assert_eq!(
+            selected.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            ["c"]
+        );
+
+        assert_eq!(
+            service.untag_secret("c", &tags(&["prod"])).await.unwrap(),
+            1
+        );
+        service.delete_secret("b").await.unwrap();
+        assert_eq!(
+            service.tag_counts().await.unwrap(),
+            [("aws".to_string(), 1)]
+        );
+        assert_eq!(service.secret_tags("c").await.unwrap(), ["aws"]);
+    }
+
+    #[tokio::test]
+    async fn bulk_set_patches_matching_metadata_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let owned = SecretAttributes {
+            owner: Some("infra".into()),
+            note: Some("old".into()),
+            ..Default::default()
+        };
+        for name in ["legacy/a", "legacy/b", "current/c"] {
+            service.add_secret(name, b"v", owned.clone()).await.unwrap();
+        }
+        assert!(
+            service
+                .bulk_set("legacy/*", &MetadataPatch::default())
+                .await
+                .is_err()
+        );
+        let patch = MetadataPatch {
+            note: Some("migrate by Q3".into()),
+            tags: vec!["deprecated".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            service.bulk_set("legacy/*", &patch).await.unwrap(),
+            ["legacy/a", "legacy/b"]
+        );
+        let secret = service.get_secret("legacy/b").await.unwrap();
+        assert_eq!(secret.note.as_deref(), Some("migrate by Q3"));
+        assert_eq!(secret.owner.as_deref(), Some("infra"));
+        assert_eq!(secret.plaintext, b"v");
+        assert_eq!(
+            service.secret_tags("legacy/a").await.unwrap(),
+            ["deprecated"]
+        );
+        let untouched = service.get_secret("current/c").await.unwrap();
+        assert_eq!(untouched.note.as_deref(), Some("old"));
+    }
+
+    #[tokio::test]
+    async fn ids_address_secrets_across_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let saved = service
+            .add_secret("old", b"v", SecretAttributes::default())
+            .await
+            .unwrap();
+        service.rename_secret("old", "new").await.unwrap();
+        assert_eq!(service.resolve(saved.id).await.unwrap(), "new");
+        assert_eq!(service.get_secret(saved.id).await.unwrap().plaintext, b"v");
+        let unknown = Uuid::new_v4();
+        assert!(service.resolve(unknown).await.is_err());
+        assert!(service.find_secret(unknown).await.unwrap().is_none());
+        assert!(!service.delete_secret(unknown).await.unwrap());
+        assert!(service.delete_secret(saved.id).await.unwrap());
+        assert!(service.secret_metadata("new").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn transactions_commit_or_roll_back_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("counter", b"1", SecretAttributes::default())
+            .await
+            .unwrap();
+        let failed: Result<()> = service
+            .transaction(async |tx| {
+                tx.add_secret("counter", b"2", SecretAttributes::default())
+                    .await?;
+                tx.add_secret("other", b"x", SecretAttributes::default())
+                    .await?;
+                bail!("changed my mind")
+            })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(service.get_secret("counter").await.unwrap().plaintext, b"1");
+        assert!(service.find_secret("other").await.unwrap().is_none());
+
+        let read = service
+            .transaction(async |tx| {
+                let value = tx.get_secret("counter").await?.plaintext;
+                tx.transaction(async |inner| {
+                    inner
+                        .add_secret("counter", b"2", SecretAttributes::default())
+                        .await
+                })
+                .await?;
+                Ok(value)
+            })
+            .await
+            .unwrap();
+        assert_eq!(read, b"1");
+        assert_eq!(service.get_secret("counter").await.unwrap().plaintext, b"2");
+    }
+
+    #[tokio::test]
+    async fn audit_entries_filter_by_secret_and_action() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["a", "b"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        service.get_secret("a").await.unwrap();
+        let filter = AuditFilter {
+            secret_name: Some("a".into()),
+            ..Default::default()
+        };
+        let entries = service.audit_entries(&filter).await.unwrap();
+        let actions: Vec<&str> = entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["read", "add"]);
+        assert_eq!(
+            entries[0].user.as_deref(),
+            Some(host::login_name().as_str())
+        );
+        let filter = AuditFilter {
+            action: Some(AuditAction::Add),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let entries = service.audit_entries(&filter).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].secret_name.as_deref(), Some("b"));
+        let filter = AuditFilter {
+            host: Some("elsewhere.invalid".into()),
+            ..Default::default()
+        };
+        assert!(service.audit_entries(&filter).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn integrity_check_reports_damaged_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["good", "bad"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        assert!(service.last_integrity_check().await.unwrap().is_none());
+        let mut record = service.repo.fetch_secret("bad").await.unwrap().unwrap();
+        let last = record.ciphertext.len() - 1;
+        record.ciphertext[last] ^= 1;
+        service.repo.put_record(&record).await.unwrap();
+
+        let report = service
+            .check_integrity(10, std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].starts_with("bad: "));
+        let kept = service.last_integrity_check().await.unwrap().unwrap();
+        assert_eq!(kept.failures, report.failures);
+    }
+
+    #[tokio::test]
+    async fn templates_seed_missing_secrets_under_a_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let blueprint = crate::blueprint::parse(
+            "[[secret]]\nname = \"db/password\"\nkind = \"password\"\ntags = [\"db\"]\n\n\
+             [[secret]]\nname = \"api/key\"\nlength = 8\ncharset = \"hex\"\n",
+        )
+        .unwrap();
+        service
+            .add_secret("shop/api/key", b"kept", SecretAttributes::default())
+            .await
+            .unwrap();
+        let summary = service
+            .apply_template("webapp", &blueprint, "shop/")
+            .await
+            .unwrap();
+        assert_eq!(
+            summary.created,
+            [("shop/db/password".to_string(), 32.0 * 62f64.log2())]
+        );
+        assert_eq!(summary.skipped, ["shop/api/key"]);
+        assert_eq!(
+            service.get_secret("shop/api/key").await.unwrap().plaintext,
+            b"kept"
+        );
+        let created = service.get_secret("shop/db/password").await.unwrap();
+        assert_eq!(created.plaintext.len(), 32);
+        assert_eq!(created.kind.as_deref(), Some("password"));
+        assert_eq!(
+            created.provenance.unwrap().to_string(),
+            "generated (template webapp)"
+        );
+    }
+
+    #[tokio::test]
+    async fn salvage_exports_what_still_decrypts() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["bad", "good"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        let mut record = service.repo.fetch_secret("bad").await.unwrap().unwrap();
+        record.ciphertext[0] ^= 1;
+        service.repo.put_record(&record).await.unwrap();
+        assert!(service.backup(None).await.is_err());
+
+        let (file, manifest) = service.salvage(None).await.unwrap();
+        assert_eq!((file.secrets, manifest.exported), (1, 1));
+        assert_eq!(manifest.unrecoverable.len(), 1);
+        assert_eq!(manifest.unrecoverable[0].name, "bad");
+        assert_eq!(manifest.unrecoverable[0].id, record.id);
+        assert!(
+            manifest.unrecoverable[0]
+                .reason
+                .contains("cannot decrypt 'bad'")
+        );
+
+        let other = tempfile::tempdir().unwrap();
+        let fresh = test_service(&other).await;
+        fresh
+            .restore_backup(&file, None, RestoreStrategy::Skip)
+            .await
+            .unwrap();
+        assert_eq!(fresh.get_secret("good").await.unwrap().plaintext, b"v");
+    }
+
+    #[tokio::test]
+    async fn stats_count_kinds_tags_and_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let token = SecretAttributes {
+            kind: Some("token".into()),
+            ..Default::default()
+        };
+        for name in ["a", "b"] {
+            service.add_secret(name, b"v", token.clone()).await.unwrap();
+        }
+        service
+            .add_secret("c", b"v", SecretAttributes::default())
+            .await
+            .unwrap();
+        service.tag_secret("b", &["ci".into()]).await.unwrap();
+        service.delete_secret("c").await.unwrap();
+
+        let stats = service.stats(1).await.unwrap();
+        assert_eq!((stats.secrets, stats.trashed), (2, 1));
+        assert!(stats.ciphertext_bytes > 0);
+        assert_eq!(stats.by_kind, [(Some("token".to_string()), 2)]);
+        assert_eq!(stats.by_tag, [("ci".to_string(), 1)]);
+        assert_eq!(stats.oldest.len(), 1);
+        assert_eq!(stats.oldest[0].0, "a");
+    }
+
+    #[tokio::test]
+    async fn backups_hold_every_live_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["a", "b", "gone"] {
+            service
+                .add_secret(name, name.as_bytes(), SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        service.tag_secret("b", &["ci".into()]).await.unwrap();
+        service.delete_secret("gone").await.unwrap();
+
+        let (file, host_bound) = service.backup(None).await.unwrap();
+        assert!(host_bound.is_empty());
+        let file = BackupFile::parse(&serde_json::to_vec(&file).unwrap()).unwrap();
+        assert_eq!(file.protection, Protection::MasterKey);
+        let entries = file
+            .entries(&testing::deterministic_key(testing::DEFAULT_KEY_SEED))
+            .unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(entries[1].tags, ["ci"]);
+        assert!(file.entries(&testing::deterministic_key(1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn backups_of_unchanged_contents_are_byte_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["b", "a"] {
+            service
+                .add_secret(name, name.as_bytes(), SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        service
+            .tag_secret("a", &["zeta".into(), "alpha".into()])
+            .await
+            .unwrap();
+        let bytes = |file: &BackupFile| serde_json::to_vec(file).unwrap();
+
+        let (first, _) = service.backup(None).await.unwrap();
+        let (second, _) = service.backup(None).await.unwrap();
+        assert_eq!(bytes(&first), bytes(&second));
+        let newest = service.list_secrets().await.unwrap();
+        assert_eq!(
+            Some(first.created_at),
+            newest.iter().map(|s| s.updated_at).max()
+        );
+        let entries = first
+            .entries(&testing::deterministic_key(testing::DEFAULT_KEY_SEED))
+            .unwrap();
+        assert_eq!(entries[0].name, "a");
+        assert_eq!(entries[0].tags, ["alpha", "zeta"]);
+
+        service
+            .add_secret("b", b"changed", SecretAttributes::default())
+            .await
+            .unwrap();
+        let (third, _) = service.backup(None).await.unwrap();
+        assert_ne!(bytes(&third), bytes(&first));
+
+        let key = testing::deterministic_key(testing::DEFAULT_KEY_SEED);
+        assert_eq!(
+            Protection::passphrase_for(&key),
+            Protection::passphrase_for(&key)
+        );
+        assert_ne!(
+            Protection::passphrase_for(&key),
+            Protection::passphrase_for(&testing::deterministic_key(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn get_many_reports_every_problem_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let attrs = SecretAttributes {
+            allowed_commands: vec!["psql".into()],
+            ..Default::default()
+        };
+        service.add_secret("db", b"pw", attrs).await.unwrap();
+        service
+            .add_secret("api", b"k1", SecretAttributes::default())
+            .await
+            .unwrap();
+        service
+            .repo
+            .upsert_secret(
+                "broken",
+                &SecretAttributes::default(),
+                &Envelope {
+                    wrapped_key: b"junk".to_vec(),
+                    ciphertext: b"junk".to_vec(),
+                },
+                1,
+            )
+            .await
+            .unwrap();
+
+        let names: Vec<String> = ["api", "missing", "broken", "db"].map(String::from).into();
+        let fetched = service.get_many(&names).await.unwrap();
+        assert!(matches!(&fetched[0].1, Fetched::Found(s) if s.plaintext == b"k1"));
+        assert!(matches!(fetched[1].1, Fetched::Missing));
+        assert!(
+            matches!(&fetched[2].1, Fetched::Unreadable(why) if why.contains("cannot decrypt"))
+        );
+
+        let err = service
+            .secrets_for_exec(&names, Path::new("/bin/sh"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("3 secrets are unavailable:\n  missing: not found\n  broken: "));
+        assert!(err.ends_with("\n  db: may not be passed to '/bin/sh' (allowed: psql)"));
+        let found = service.get_all(&names[..1]).await.unwrap();
+        assert_eq!(found[0].name, "api");
+    }
+
+    #[tokio::test]
+    async fn transfer_reencrypts_under_the_target_key() {
+        let (a, b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let scratch = test_service(&a).await;
+        let team = test_service_with_key(&b, 3).await;
+        let attrs = SecretAttributes {
+            owner: Some("platform".into()),
+            ..Default::default()
+        };
+        scratch.add_secret("api", b"k1", attrs).await.unwrap();
+        scratch.tag_secret("api", &["ci".into()]).await.unwrap();
+        team.add_secret("db", b"team", SecretAttributes::default())
+            .await
+            .unwrap();
+
+        let copied = scratch.transfer("api", &team, false, false).await.unwrap();
+        assert_eq!(copied.owner.as_deref(), Some("platform"));
+        assert_eq!(team.get_secret("api").await.unwrap().plaintext, b"k1");
+        assert_eq!(team.secret_tags("api").await.unwrap(), ["ci"]);
+        assert!(scratch.transfer("api", &team, false, true).await.is_err());
+
+        scratch.transfer("api", &team, true, true).await.unwrap();
+        assert!(scratch.find_secret("api").await.unwrap().is_none());
+        assert_eq!(team.get_secret("db").await.unwrap().plaintext, b"team");
+
+        for name in ["app/db", "app/key", "db"] {
+            scratch
+                .add_secret(name, b"mine", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        let names = ["app/db".to_string(), "app/key".into(), "db".into()];
+        let summary = scratch
+            .transfer_many(&names, &team, false, true)
+            .await
+            .unwrap();
+        assert_eq!(summary.copied, ["app/db", "app/key"]);
+        assert_eq!(summary.skipped[0].0, "db");
+        assert_eq!(team.get_secret("db").await.unwrap().plaintext, b"team");
+        assert_eq!(team.get_secret("app/key").await.unwrap().plaintext, b"mine");
+        let left: Vec<_> = scratch.list_secrets().await.unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].name, "db");
+    }
+
+    #[tokio::test]
+    async fn subvaults_hold_only_the_prefix_under_their_own_key() {
+        let (a, b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let service = test_service(&a).await;
+        let contractor = test_service_with_key(&b, 5).await;
+        for name in ["client-x/db", "client-x/api", "client-y/db", "ops/root"] {
+            service
+                .add_secret(name, name.as_bytes(), SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        assert!(
+            service
+                .export_subvault("client-z/", &contractor)
+                .await
+                .is_err()
+        );
+        let summary = service
+            .export_subvault("client-x/", &contractor)
+            .await
+            .unwrap();
+        assert_eq!(summary.copied, ["client-x/api", "client-x/db"]);
+        let names: Vec<_> = contractor
+            .list_secrets()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, ["client-x/api", "client-x/db"]);
+        assert_eq!(
+            contractor
+                .get_secret("client-x/db")
+                .await
+                .unwrap()
+                .plaintext,
+            b"client-x/db"
+        );
+        assert_eq!(service.list_secrets().await.unwrap().len(), 4);
+        assert!(
+            service
+                .export_subvault("client-x/", &contractor)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn sync_copies_newer_secrets_one_way() {
+        let (home, work) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (laptop, desktop) = (test_service(&home).await, test_service(&work).await);
+        let add = async |service: &SecretService, name: &str, value: &[u8]| {
+            service
+                .add_secret(name, value, SecretAttributes::default())
+                .await
+                .unwrap();
+        };
+        add(&desktop, "a", b"old").await;
+        add(&laptop, "a", b"new").await;
+        add(&laptop, "b", b"laptop").await;
+        add(&laptop, "c", b"only laptop").await;
+        laptop.tag_secret("c", &["ci".into()]).await.unwrap();
+        add(&desktop, "b", b"desktop").await;
+        add(&desktop, "d", b"only desktop").await;
+
+        let diff = laptop.diff_vault(&desktop).await.unwrap();
+        let statuses: Vec<_> = diff
+            .iter()
+            .map(|i| (i.name.as_str(), i.status, i.newer_here()))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("a", VaultDiffStatus::Changed, true),
+                ("b", VaultDiffStatus::Changed, false),
+                ("c", VaultDiffStatus::Added, true),
+                ("d", VaultDiffStatus::Removed, false),
+            ]
+        );
+
+        let elsewhere = tempfile::tempdir().unwrap();
+        let stranger = test_service_with_key(&elsewhere, 3).await;
+        add(&stranger, "x", b"v").await;
+        // the CLI opens the other vault with this vault's key
+        let stranger = laptop.with_repository(stranger.repo.clone());
+        assert!(
+            laptop
+                .sync_into(&stranger, false, &BTreeMap::new())
+                .await
+                .is_err()
+        );
+        assert!(stranger.get_secret("a").await.is_err());
+
+        let summary = laptop
+            .sync_into(&desktop, true, &BTreeMap::new())
+            .await
+            .unwrap();
+        assert_eq!(summary.copied, ["a", "c"]);
+        assert_eq!(
+            summary.skipped,
+            [("b".to_string(), "newer in the target".to_string())]
+        );
+        assert_eq!(summary.trashed, ["d"]);
+        assert_eq!(desktop.get_secret("a").await.unwrap().plaintext, b"new");
+        assert_eq!(desktop.secret_tags("c").await.unwrap(), ["ci"]);
+        let left: Vec<_> = laptop
+            .diff_vault(&desktop)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.name)
+            .collect();
+        assert_eq!(left, ["b"]);
+    }
+
+    #[tokio::test]
+    async fn conflicts_are_resolved_per_name() {
+        let (home, work) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (laptop, desktop) = (test_service(&home).await, test_service(&work).await);
+        let add = async |service: &SecretService, name: &str, value: &[u8]| {
+            service
+                .add_secret(name, value, SecretAttributes::default())
+                .await
+                .unwrap();
+        };
+        add(&desktop, "a", b"old").await;
+        add(&laptop, "a", b"new").await;
+        add(&laptop, "b", b"laptop").await;
+        add(&desktop, "b", b"desktop").await;
+
+        let conflicts = laptop.sync_conflicts(&desktop).await.unwrap();
+        let names: Vec<_> = conflicts.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_ne!(
+            conflicts[0].existing_fingerprint,
+            conflicts[0].incoming_fingerprint
+        );
+        assert_eq!(conflicts[1].incoming_size, 6);
+
+        let resolutions = BTreeMap::from([
+            ("a".to_string(), Resolution::KeepExisting),
+            ("b".to_string(), Resolution::KeepBoth("b-synced".into())),
+        ]);
+        let summary = laptop
+            .sync_into(&desktop, false, &resolutions)
+            .await
+            .unwrap();
+        assert_eq!(summary.copied, ["b-synced"]);
+        assert_eq!(
+            summary.skipped,
+            [("a".to_string(), "kept the target's version".to_string())]
+        );
+        let value = async |name: &str| desktop.get_secret(name).await.unwrap().plaintext;
+        assert_eq!(value("a").await, b"old");
+        assert_eq!(value("b").await, b"desktop");
+        assert_eq!(value("b-synced").await, b"laptop");
+
+        // the older laptop value still wins when asked to
+        let resolutions = BTreeMap::from([("b".to_string(), Resolution::TakeIncoming)]);
+        let summary = laptop
+            .sync_into(&desktop, false, &resolutions)
+            .await
+            .unwrap();
+        assert_eq!(summary.copied, ["a", "b"]);
+        assert_eq!(value("b").await, b"laptop");
+
+        let items = ["a", "z"].map(|name| ImportItem {
+            name: name.into(),
+            value: b"new".to_vec(),
+            attrs: SecretAttributes::default(),
+        });
+        let conflicts = desktop.import_conflicts("csv", &items).await.unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].existing_fingerprint,
+            conflicts[0].incoming_fingerprint
+        );
+    }
+
+    #[tokio::test]
+    async fn restore_applies_the_strategy_to_existing_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = test_service(&dir).await;
+        for name in ["a", "b"] {
+            source
+                .add_secret(name, b"backup", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        source.tag_secret("b", &["ci".into()]).await.unwrap();
+        let (file, _) = source.backup(None).await.unwrap();
+
+        let other = tempfile::tempdir().unwrap();
+        let target = test_service(&other).await;
+        target
+            .add_secret("a", b"local", SecretAttributes::default())
+            .await
+            .unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        let stranger = test_service_with_key(&elsewhere, 3).await;
+        assert!(
+            stranger
+                .restore_backup(&file, None, RestoreStrategy::Skip)
+                .await
+                .is_err()
+        );
+        let summary = target
+            .restore_backup(&file, None, RestoreStrategy::Rename)
+            .await
+            .unwrap();
+        assert_eq!(summary.added, ["b"]);
+        assert_eq!(
+            summary.renamed,
+            [("a".to_string(), "a-restored".to_string())]
+        );
+        assert_eq!(target.get_secret("a").await.unwrap().plaintext, b"local");
+        assert_eq!(
+            target.get_secret("a-restored").await.unwrap().plaintext,
+            b"backup"
+        );
+        assert_eq!(target.secret_tags("b").await.unwrap(), ["ci"]);
+
+        let summary = target
+            .restore_backup(&file, None, RestoreStrategy::Overwrite)
+            .await
+            .unwrap();
+        assert_eq!(summary.overwritten, ["a", "b"]);
+        assert_eq!(target.get_secret("a").await.unwrap().plaintext, b"backup");
+    }
+
+    #[tokio::test]
+    async fn deleted_secrets_wait_in_the_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("db/pw", b"v1", SecretAttributes::default())
+            .await
+            .unwrap();
+        service.tag_secret("db/pw", &["prod".into()]).await.unwrap();
+        assert!(service.delete_secret("db/pw").await.unwrap());
+        assert!(service.list_secrets().await.unwrap().is_empty());
+        assert!(service.get_secret("db/pw").await.is_err());
+        let trash = service.trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].0.name, "db/pw");
+
+        service.restore_secret("db/pw").await.unwrap();
+        assert_eq!(service.get_secret("db/pw").await.unwrap().plaintext, b"v1");
+        assert_eq!(service.tag_counts().await.unwrap(), [("prod".into(), 1)]);
+        assert!(service.restore_secret("db/pw").await.is_err());
+
+        // a trashed name is not reused behind its back, by a new secret or a rename
+        service.delete_secret("db/pw").await.unwrap();
+        let err = service
+            .add_secret("db/pw", b"v2", SecretAttributes::default())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "'db/pw' is in the trash; restore or purge it first"
+        );
+        service
+            .add_secret("db/other", b"v3", SecretAttributes::default())
+            .await
+            .unwrap();
+        assert!(service.rename_secret("db/other", "db/pw").await.is_err());
+        assert_eq!(service.trash().await.unwrap().len(), 1);
+        service.restore_secret("db/pw").await.unwrap();
+        assert_eq!(service.get_secret("db/pw").await.unwrap().plaintext, b"v1");
+
+        service.delete_secret("db/pw").await.unwrap();
+        assert!(service.purge_secret("db/pw").await.unwrap());
+        assert!(!service.purge_secret("db/pw").await.unwrap());
+        assert!(service.trash().await.unwrap().is_empty());
+        service
+            .add_secret("db/pw", b"v2", SecretAttributes::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn promotion_copies_everything_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for (name, value) in [("staging/a", "a1"), ("staging/b", "b1"), ("prod/b", "b0")] {
+            service
+                .add_secret(name, value.as_bytes(), SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        let value = async |name: &str| service.get_secret(name).await.unwrap().plaintext;
+
+        service.lock_secret("prod/b", None).await.unwrap();
+        let plan = service
+            .plan_promotion("staging/", "prod/", None)
+            .await
+            .unwrap();
+        assert!(service.promote(&plan, false).await.is_err());
+        assert!(service.find_secret("prod/a").await.unwrap().is_none());
+        assert_eq!(value("prod/b").await, b"b0");
+
+        service.unlock_secret("prod/b").await.unwrap();
+        assert_eq!(service.promote(&plan, true).await.unwrap(), 2);
+        assert_eq!(value("staging/a").await, b"a1");
+        assert_eq!(value("staging/b").await, b"b1");
+        for (source, target) in [("staging/a", "prod/a"), ("staging/b", "prod/b")] {
+            let promoted = value(target).await;
+            assert_ne!(promoted, value(source).await);
+            assert_ne!(promoted, b"b0");
+        }
+
+        let plan = service
+            .plan_promotion("staging/", "prod/", Some("a"))
+            .await
+            .unwrap();
+        assert_eq!(service.promote(&plan, false).await.unwrap(), 1);
+        assert_eq!(value("prod/a").await, b"a1");
+    }
+
+    #[tokio::test]
+    async fn folders_move_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir)
+            .await
+            .with_protected_prefixes(vec!["old/c".into()]);
+        for name in ["old/a", "old/b"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        let attrs = SecretAttributes {
+            immutable: true,
+            ..Default::default()
+        };
+        service.add_secret("old/c", b"v", attrs).await.unwrap();
+        let names = async || -> Vec<String> {
+            let secrets = service.list_secrets().await.unwrap();
+            secrets.into_iter().map(|m| m.name).collect()
+        };
+
+        let err = service.rename_prefix("old/", "new/").await.unwrap_err();
+        assert!(err.to_string().contains("immutable"));
+        assert_eq!(names().await, ["old/a", "old/b", "old/c"]);
+
+        // a protected name without an approval stops the move before anything changes
+        service.clear_immutable("old/c").await.unwrap();
+        assert!(service.rename_prefix("old/", "new/").await.is_err());
+        assert_eq!(names().await, ["old/a", "old/b", "old/c"]);
+        let request = &service.pending_approvals().await.unwrap()[0];
+        service
+            .approve_as(request.id, "device:laptop")
+            .await
+            .unwrap();
+        service.rename_prefix("old/", "new/").await.unwrap();
+        assert_eq!(names().await, ["new/a", "new/b", "new/c"]);
+        assert!(service.pending_approvals().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn folders_move_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["staging/db/pw", "staging/api", "staging-old", "prod/api"] {
+            service
+                .add_secret(name, b"v", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        assert!(service.rename_prefix("staging/", "prod/").await.is_err());
+        assert!(
+            service
+                .find_secret("staging/db/pw")
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let moved = service
+            .rename_prefix("staging/db/", "prod/db/")
+            .await
+            .unwrap();
+        assert_eq!(
+            moved,
+            [("staging/db/pw".to_string(), "prod/db/pw".to_string())]
+        );
+        let prod: Vec<String> = service
+            .list_secrets_with_prefix("prod/")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(prod, ["prod/api", "prod/db/pw"]);
+    }
+
+    #[tokio::test]
+    async fn leases_redeem_exactly_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        service
+            .add_secret("ci/token", b"v", SecretAttributes::default())
+            .await
+            .unwrap();
+        let ttl = chrono::Duration::minutes(15);
+        assert!(service.create_lease("missing", ttl).await.is_err());
+        assert!(
+            service
+                .create_lease("ci/token", chrono::Duration::zero())
+                .await
+                .is_err()
+        );
+
+        let (lease, token) = service.create_lease("ci/token", ttl).await.unwrap();
+        assert!(token.starts_with(LEASE_TOKEN_PREFIX));
+        assert_eq!(service.open_leases().await.unwrap().len(), 1);
+        // the lease follows a rename
+        service
+            .rename_secret("ci/token", "ci/deploy")
+            .await
+            .unwrap();
+        let secret = service.redeem_lease(&token).await.unwrap();
+        assert_eq!(
+            (secret.name.as_str(), secret.plaintext.as_slice()),
+            ("ci/deploy", &b"v"[..])
+        );
+        assert!(service.redeem_lease(&token).await.is_err());
+        assert!(service.open_leases().await.unwrap().is_empty());
+        assert!(!service.revoke_lease(Uuid::new_v4()).await.unwrap());
+        assert!(service.revoke_lease(lease.id).await.unwrap());
+
+        let (_, voided) = service.create_lease("ci/deploy", ttl).await.unwrap();
+        let (lease, _) = service.create_lease("ci/deploy", ttl).await.unwrap();
+        service.revoke_lease(lease.id).await.unwrap();
+        assert_eq!(service.open_leases().await.unwrap().len(), 1);
+        // deleting the secret voids its leases
+        service.delete_secret("ci/deploy").await.unwrap();
+        assert!(service.redeem_lease(&voided).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn batch_rotation_keeps_going_past_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let pinned = SecretAttributes {
+            immutable: true,
+            ..Default::default()
+        };
+        for (name, attrs) in [
+            ("aws/a", SecretAttributes::default()),
+            ("aws/b", pinned),
+            ("aws/c", SecretAttributes::default()),
+            ("gcp/d", SecretAttributes::default()),
+        ] {
+            service.add_secret(name, b"old", attrs).await.unwrap();
+        }
+        let selector = Selector {
+            prefix: Some("aws/".into()),
+            ..Default::default()
+        };
+        let summary = service
+            .rotate_secrets(&selector, &Provider::Random)
+            .await
+            .unwrap();
+        assert_eq!(summary.rotated, ["aws/a", "aws/c"]);
+        assert_eq!(summary.failed.len(), 1);
+        assert!(summary.failed[0].1.contains("immutable"));
+        for (name, old) in [("aws/a", false), ("aws/b", true), ("gcp/d", true)] {
+            let value = service.get_secret(name).await.unwrap().plaintext;
+            assert_eq!(value == b"old", old, "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn add_and_get_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+
+        let attrs = SecretAttributes {
+            kind: Some("token".into()),
+            ..Default::default()
+        };
+        let meta = service.add_secret("gh", b"ghp_123", attrs).await.unwrap();
+        assert_eq!(meta.kind.as_deref(), Some("token"));
+
+        let secret = service.get_secret("gh").await.unwrap();
+        assert_eq!(secret.plaintext, b"ghp_123");
+    }
+
+    #[tokio::test]
+    async fn audit_log_records_activity() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+
+        service
+            .add_secret("gh", b"v1", SecretAttributes::default())
+            .await
+            .unwrap();
+        service
+            .add_secret("gh", b"v2", SecretAttributes::default())
+            .await
+            .unwrap();
+        service.get_secret("gh").await.unwrap();
+        service.delete_secret("gh").await.unwrap();
+
+        let actions: Vec<String> = service
+            .audit_since(chrono::Duration::days(1))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.action)
+            .collect();
+        assert_eq!(actions, ["add", "update", "read", "delete"]);
+    }
+
+    #[tokio::test]
+    async fn immutable_secret_rejects_overwrite_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+
+        let attrs = SecretAttributes {
+            immutable: true,
+            ..Default::default()
+        };
+        service.add_secret("root", b"v1", attrs).await.unwrap();
+
+        assert!(
+            service
+                .add_secret("root", b"v2", SecretAttributes::default())
+                .await
+                .is_err()
+        );
+        assert!(service.delete_secret("root").await.is_err());
+        assert_eq!(service.get_secret("root").await.unwrap().plaintext, b"v1");
+
+        service.clear_immutable("root").await.unwrap();
+        assert!(service.delete_secret("root").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn totp_secrets_need_a_seed_and_yield_codes() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        let totp = || SecretAttributes {
+            kind: Some(totp::TOTP_KIND.into()),
+            ..Default::default()
+        };
+        assert!(
+            service
+                .add_secret("ci/2fa", b"not a seed!", totp())
+                .await
+                .is_err()
+        );
+        service
+            .add_secret("ci/2fa", b"JBSWY3DPEHPK3PXP", totp())
+            .await
+            .unwrap();
+        let (code, remaining) = service.totp_code("ci/2fa").await.unwrap();
+        assert!(code.len() == 6 && code.bytes().all(|b| b.is_ascii_digit()));
+        assert!((1..=30).contains(&remaining));
+
+        service
+            .add_secret("plain", b"JBSWY3DPEHPK3PXP", SecretAttributes::default())
+            .await
+            .unwrap();
+        assert!(service.totp_code("plain").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn locked_secret_rejects_overwrite_rename_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        for name in ["db/pw", "db/user"] {
+            service
+                .add_secret(name, b"v1", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        assert!(
+            service
+                .lock_secret("db/pw", Some("migration"))
+                .await
+                .unwrap()
+        );
+        assert!(!service.lock_secret("db/pw", None).await.unwrap());
+
+        let err = service
+            .add_secret("db/pw", b"v2", SecretAttributes::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("migration"));
+        assert!(service.delete_secret("db/pw").await.is_err());
+        assert!(service.rename_secret("db/pw", "db/pass").await.is_err());
+        // the unlocked sibling stays put too: folders move all or nothing
+        assert!(service.rename_prefix("db/", "pg/").await.is_err());
+        assert!(service.find_secret("db/user").await.unwrap().is_some());
+        assert_eq!(service.get_secret("db/pw").await.unwrap().plaintext, b"v1");
+
+        assert!(service.unlock_secret("db/pw").await.unwrap());
+        assert!(!service.unlock_secret("db/pw").await.unwrap());
+        assert!(service.secret_lock("db/pw").await.unwrap().is_none());
+        assert!(service.delete_secret("db/pw").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn exec_enforces_allowed_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+
+        let attrs = SecretAttributes {
+            allowed_commands: vec!["psql".into(), "/opt/tf/terraform".into()],
+            ..Default::default()
+        };
+        service.add_secret("db", b"pw", attrs).await.unwrap();
+        let names = vec!["db".to_string()];
+
+        for ok in ["/usr/bin/psql", "/opt/tf/terraform"] {
+            assert!(
+                service
+                    .secrets_for_exec(&names, Path::new(ok))
+                    .await
+                    .is_ok()
+            );
+        }
+        // a bare name is the whole file name: other scripts sharing the stem are refused
+        for denied in [
+            "/usr/bin/curl",
+            "/usr/local/bin/terraform",
+            "/tmp/psql.sh",
+            "/usr/bin/psql.py",
+        ] {
+            assert!(
+                service
+                    .secrets_for_exec(&names, Path::new(denied))
+                    .await
+                    .is_err()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn replicas_converge_through_oplog_merge() {
+        let dir_a = tempfile::tempdir().unwrap();
+        let dir_b = tempfile::tempdir().unwrap();
+        let a = test_service(&dir_a).await;
+        let b = test_service(&dir_b).await;
+
+        a.add_secret("api", b"v1", SecretAttributes::default())
+            .await
+            .unwrap();
+        b.merge_operations(a.operations().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(b.get_secret("api").await.unwrap().plaintext, b"v1");
+
+        b.delete_secret("api").await.unwrap();
+        let summary = a
+            .merge_operations(b.operations().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.deleted, vec!["api".to_string()]);
+        assert!(a.get_secret("api").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_merges_leave_nothing_behind() {
+        let dir_a = tempfile::tempdir().unwrap();
+        let dir_b = tempfile::tempdir().unwrap();
+        let a = test_service(&dir_a).await;
+        let b = test_service(&dir_b).await;
+        for name in ["api", "db"] {
+            a.add_secret(name, b"v1", SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        b.add_secret("mine", b"v", SecretAttributes::default())
+            .await
+            .unwrap();
+        let clock = b.vector_clock().await.unwrap();
+        let ops_before = b.operations().await.unwrap().len();
+
+        // "db" arrives claiming the id of b's own secret, so it cannot be stored
+        let taken = b.repo.fetch_secret("mine").await.unwrap().unwrap().id;
+        let mut ops = a.operations().await.unwrap();
+        for op in &mut ops {
+            if let Some(record) = op.record.as_mut().filter(|r| r.name == "db") {
+                record.id = taken;
+            }
+        }
+        assert!(b.merge_operations(ops).await.is_err());
+        assert!(b.find_secret("api").await.unwrap().is_none());
+        assert_eq!(b.vector_clock().await.unwrap(), clock);
+        assert_eq!(b.operations().await.unwrap().len(), ops_before);
+
+        let summary = b
+            .merge_operations(a.operations().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.updated, ["api", "db"]);
+    }
+
+    #[tokio::test]
+    async fn values_remember_how_they_entered_the_vault() {
+        let dir_a = tempfile::tempdir().unwrap();
+        let dir_b = tempfile::tempdir().unwrap();
+        let a = test_service(&dir_a).await;
+        let b = test_service(&dir_b).await;
+        let origin = |meta: Option<SecretMetadata>| meta.unwrap().provenance.unwrap();
+
+        let item = ImportItem {
+            name: "keychain/github".into(),
+            value: b"v".to_vec(),
+            attrs: SecretAttributes::default(),
+        };
+        a.import_secrets("macos-keychain", vec![item], false)
+            .await
+            .unwrap();
+        let imported = origin(a.secret_metadata("keychain/github").await.unwrap());
+        assert_eq!(imported, Provenance::new(Origin::Import, "macos-keychain"));
+
+        let selector = Selector {
+            prefix: Some("keychain/".into()),
+            ..Default::default()
+        };
+        a.rotate_secrets(&selector, &Provider::Random)
+            .await
+            .unwrap();
+        let rotated = origin(a.secret_metadata("keychain/github").await.unwrap());
+        assert_eq!(rotated.origin, Origin::Rotation);
+
+        b.merge_operations(a.operations().await.unwrap())
+            .await
+            .unwrap();
+        let synced = origin(b.secret_metadata("keychain/github").await.unwrap());
+        assert_eq!(synced.origin, Origin::Sync);
+        assert_eq!(synced.source, Some(a.replica_id().await.unwrap()));
+    }
+
+    #[test]
+    fn host_bound_values_need_their_machine_id() {
+        let crypto = SecretCrypto::new(testing::deterministic_key(7));
+        let here = Binding::on("api", Some("machine-a".into()));
+        let sealed = here.seal(&crypto, b"value").unwrap();
+        let wrapped = Some(sealed.wrapped_key.as_slice());
+        assert_eq!(
+            here.open(&crypto, wrapped, &sealed.ciphertext).unwrap(),
+            b"value"
+        );
+        let elsewhere = Binding::on("api", Some("machine-b".into()));
+        assert!(
+            elsewhere
+                .open(&crypto, wrapped, &sealed.ciphertext)
+                .is_err()
+        );
+        // the master key and the right associated data are not enough on their own
+        assert!(crypto.open(&here.aad, wrapped, &sealed.ciphertext).is_err());
+
+        // values sealed before the machine-keyed wrapping open, and rotation moves them to it
+        let legacy = crypto.seal(&here.aad, b"old").unwrap();
+        assert_eq!(
+            here.open(&crypto, Some(&legacy.wrapped_key), &legacy.ciphertext)
+                .unwrap(),
+            b"old"
+        );
+        let rotated = SecretCrypto::new(testing::deterministic_key(8));
+        let rewrapped = here.rewrap(&crypto, &rotated, &legacy.wrapped_key).unwrap();
+        assert_eq!(
+            here.open(&rotated, Some(&rewrapped), &legacy.ciphertext)
+                .unwrap(),
+            b"old"
+        );
+        assert!(
+            rotated
+                .open(&here.aad, Some(&rewrapped), &legacy.ciphertext)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn changing_the_passphrase_leaves_every_value_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = test_service(&dir).await;
+        assert!(service.change_passphrase("new").await.is_err());
+        // the test key wrapped under "old", as `init --passphrase` stores it
+        let key = testing::deterministic_key(testing::DEFAULT_KEY_SEED);
+        let kdf = PassphraseKdf::wrap(&key, "old", 64, 1).unwrap();
+        service
+            .repo
+            .set_meta_value(PASSPHRASE_KDF_KEY, &serde_json::to_string(&kdf).unwrap())
+            .await
+            .unwrap();
+        for name in ["a", "b"] {
+            service
+                .add_secret(name, name.as_bytes(), SecretAttributes::default())
+                .await
+                .unwrap();
+        }
+        let before = service.repo.list_secrets().await.unwrap();
+
+        service.change_passphrase("new").await.unwrap();
+        let changed = service.passphrase_kdf().await.unwrap().unwrap();
+        assert_ne!(changed, kdf);
+        assert_eq!(changed.derive("new").unwrap().0, key.0);
+        assert!(changed.derive("old").is_err());
+        let after = service.repo.list_secrets().await.unwrap();
+        assert_eq!(before.len(), after.len());
+        for (old, new) in before.iter().zip(&after) {
+            assert_eq!(old.ciphertext, new.ciphertext);
+            assert_eq!(old.data_key, new.data_key);
+            assert_eq!(old.key_version, new.key_version);
+        }
+        assert_eq!(service.get_secret("b").await.unwrap().plaintext, b"b");
+    }
+}
@@ -0,0 +1,93 @@
+//! Templates with `{{ secret("name") }}` markers, rendered into config files such
+//! as kubeconfigs or pgpass files without the values ever being committed.
+
+use anyhow::{Result, anyhow, bail};
+use std::collections::BTreeMap;
+
+/// One marker: its byte range in the template and the secret it names.
+struct Marker<'a> {
+    start: usize,
+    end: usize,
+    name: &'a str,
+}
+
+/// Every `{{ ... }}` block is a marker, so a typo fails instead of reaching the output.
+fn markers(template: &str) -> Result<Vec<Marker<'_>>> {
+    let mut markers = Vec::new();
+    let mut offset = 0;
+    while let Some(found) = template[offset..].find("{{") {
+        let start = offset + found;
+        let line = template[..start].matches('\n').count() + 1;
+        let close = template[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow!("line {line}: '{{{{' is never closed"))?;
+        let end = start + close + 2;
+        let inner = template[start + 2..end - 2].trim();
+        let name = inner
+            .strip_prefix("secret")
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix('('))
+            .and_then(|rest| rest.strip_suffix(')'))
+            .map(str::trim)
+            .and_then(|arg| {
+                ['"', '\'']
+                    .iter()
+                    .find_map(|q| arg.strip_prefix(*q)?.strip_suffix(*q))
+            })
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| anyhow!("line {line}: expected {{{{ secret(\"name\") }}}}"))?;
+        markers.push(Marker { start, end, name });
+        offset = end;
+    }
+    Ok(markers)
+}
+
+/// Secret names the template refers to, each once, in name order.
+pub fn secret_names(template: &str) -> Result<Vec<String>> {
+    let mut names: Vec<String> = markers(template)?
+        .into_iter()
+        .map(|m| m.name.to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// The template with each marker replaced by its value from `values`.
+pub fn render(template: &str, values: &BTreeMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut copied = 0;
+    for marker in markers(template)? {
+        let Some(value) = values.get(marker.name) else {
+            bail!("no value for secret '{}'", marker.name);
+        };
+        out.push_str(&template[copied..marker.start]);
+        out.push_str(value);
+        copied = marker.end;
+    }
+    out.push_str(&template[copied..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_each_marker() {
+        let template = "host=db\npassword={{ secret(\"prod/db/password\") }}\n\
+                        again={{secret('prod/db/password')}} user={{ secret( \"u\" ) }}\n";
+        assert_eq!(secret_names(template).unwrap(), ["prod/db/password", "u"]);
+        let values = BTreeMap::from([
+            ("prod/db/password".to_string(), "pw".to_string()),
+            ("u".to_string(), "app".to_string()),
+        ]);
+        assert_eq!(
+            render(template, &values).unwrap(),
+            "host=db\npassword=pw\nagain=pw user=app\n"
+        );
+        assert!(secret_names("a\n{{ secret(name) }}").is_err());
+        assert!(secret_names("{{ secret(\"x\")").is_err());
+        assert!(render("{{ secret(\"gone\") }}", &values).is_err());
+    }
+}
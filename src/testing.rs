@@ -0,0 +1,200 @@
+//! Temp-vault fixtures for tests, here and in downstream crates (`test-fixtures` feature).
+//!
+//! ```ignore
+//! let vault = TempVault::builder().with_sample_data().build().await?;
+//! let secret = vault.service.get_secret("prod/db/password").await?;
+//! ```
+
+use crate::{
+    crypto::MasterKey,
+    db::Repository,
+    domain::SecretAttributes,
+    keymgr::{MasterKeyProvider, MasterKeySource},
+    service::SecretService,
+};
+use anyhow::Result;
+use base64::{Engine as _, engine::general_purpose};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Key seed used when a test does not pick one.
+pub const DEFAULT_KEY_SEED: u8 = 9;
+
+const DB_FILE: &str = "test.db";
+
+/// Master key with every byte set to `seed`; never use outside tests.
+pub fn deterministic_key(seed: u8) -> MasterKey {
+    MasterKey([seed; 32])
+}
+
+/// Base64 form of [`deterministic_key`], as `--dmk` takes it.
+pub fn deterministic_key_base64(seed: u8) -> String {
+    general_purpose::STANDARD.encode([seed; 32])
+}
+
+/// A handful of realistic secrets: namespaced names, kinds, owners and a note.
+pub fn sample_secrets() -> Vec<(&'static str, &'static str, SecretAttributes)> {
+    let attrs = |kind: &str, owner: &str| SecretAttributes {
+        kind: Some(kind.to_string()),
+        owner: Some(owner.to_string()),
+        ..Default::default()
+    };
+    vec![
+        (
+            "dev/db/password",
+            "dev-db-pass",
+            attrs("password", "platform"),
+        ),
+        (
+            "prod/db/password",
+            "prod-db-pass",
+            attrs("password", "platform"),
+        ),
+        (
+            "prod/api/token",
+            "prod-api-token",
+            attrs("token", "backend"),
+        ),
+        (
+            "ci/deploy-key",
+            "ci-deploy-key",
+            SecretAttributes {
+                note: Some("used by the release pipeline".into()),
+                ..attrs("ssh", "release")
+            },
+        ),
+    ]
+}
+
+/// Open (creating if needed) the vault in `dir` with the deterministic key `key_seed`.
+pub async fn open_service(dir: &Path, key_seed: u8) -> Result<SecretService> {
+    let repo = Repository::connect(&dir.join(DB_FILE)).await?;
+    repo.migrate().await?;
+    let provider = MasterKeyProvider::new(
+        MasterKeySource {
+            base64_inline: Some(deterministic_key_base64(key_seed)),
+            key_file: None,
+            allow_keyring: false,
+        },
+        "devinventory-test".into(),
+        "dmk".into(),
+    );
+    Ok(SecretService::new(repo, provider))
+}
+
+/// A vault in a temporary directory, removed when dropped.
+pub struct TempVault {
+    pub service: SecretService,
+    dir: TempDir,
+}
+
+impl TempVault {
+    pub fn builder() -> TempVaultBuilder {
+        TempVaultBuilder::default()
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.dir.path().join(DB_FILE)
+    }
+
+    /// Another service over the same database, e.g. after a key rotation.
+    pub async fn open(&self, key_seed: u8) -> Result<SecretService> {
+        open_service(self.dir.path(), key_seed).await
+    }
+}
+
+pub struct TempVaultBuilder {
+    key_seed: u8,
+    secrets: Vec<(String, Vec<u8>, SecretAttributes)>,
+    protected_prefixes: Vec<String>,
+}
+
+impl Default for TempVaultBuilder {
+    fn default() -> Self {
+        Self {
+            key_seed: DEFAULT_KEY_SEED,
+            secrets: Vec::new(),
+            protected_prefixes: Vec::new(),
+        }
+    }
+}
+
+impl TempVaultBuilder {
+    pub fn with_key_seed(mut self, seed: u8) -> Self {
+        self.key_seed = seed;
+        self
+    }
+
+    pub fn with_secret(self, name: &str, value: &str) -> Self {
+        self.with_secret_attrs(name, value, SecretAttributes::default())
+    }
+
+    pub fn with_secret_attrs(mut self, name: &str, value: &str, attrs: SecretAttributes) -> Self {
+        self.secrets
+            .push((name.to_string(), value.as_bytes().to_vec(), attrs));
+        self
+    }
+
+    /// Add [`sample_secrets`].
+    pub fn with_sample_data(mut self) -> Self {
+        for (name, value, attrs) in sample_secrets() {
+            self = self.with_secret_attrs(name, value, attrs);
+        }
+        self
+    }
+
+    pub fn with_protected_prefixes(mut self, prefixes: Vec<String>) -> Self {
+        self.protected_prefixes = prefixes;
+        self
+    }
+
+    pub async fn build(self) -> Result<TempVault> {
+        let dir = tempfile::tempdir()?;
+        let service = open_service(dir.path(), self.key_seed)
+            .await?
+            .with_protected_prefixes(self.protected_prefixes);
+        for (name, value, attrs) in self.secrets {
+            service.add_secret(&name, &value, attrs).await?;
+        }
+        Ok(TempVault { service, dir })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn builder_seeds_a_readable_vault() {
+        let vault = TempVault::builder()
+            .with_sample_data()
+            .with_secret("extra", "x")
+            .build()
+            .await
+            .unwrap();
+        let names: Vec<String> = vault
+            .service
+            .list_secrets()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(names.len(), sample_secrets().len() + 1);
+        assert!(vault.db_path().exists());
+
+        let again = vault.open(DEFAULT_KEY_SEED).await.unwrap();
+        let secret = again.get_secret("prod/db/password").await.unwrap();
+        assert_eq!(secret.plaintext, b"prod-db-pass");
+        assert_eq!(secret.owner.as_deref(), Some("platform"));
+        assert!(
+            vault
+                .open(DEFAULT_KEY_SEED + 1)
+                .await
+                .unwrap()
+                .get_secret("extra")
+                .await
+                .is_err()
+        );
+    }
+}
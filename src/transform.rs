@@ -0,0 +1,72 @@
+//! Encodings applied to a value before it is printed, so it never passes through
+//! shell tools (and `ps` or history) to get encoded.
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pipe {
+    /// Standard base64 with padding (Kubernetes `data:`, basic-auth headers)
+    Base64,
+    /// Lower-case hex
+    Hex,
+    /// Escaped for use inside a JSON string, without the quotes
+    JsonEscape,
+    /// Percent-encoded for use in a URL (RFC 3986 unreserved characters kept)
+    UrlEncode,
+    /// Leading and trailing whitespace removed
+    Trim,
+}
+
+impl Pipe {
+    pub fn apply(self, value: &[u8]) -> Vec<u8> {
+        match self {
+            Pipe::Base64 => STANDARD.encode(value).into_bytes(),
+            Pipe::Hex => crate::attest::hex(value).into_bytes(),
+            Pipe::JsonEscape => {
+                let quoted = serde_json::Value::String(String::from_utf8_lossy(value).into_owned())
+                    .to_string();
+                quoted.as_bytes()[1..quoted.len() - 1].to_vec()
+            }
+            Pipe::UrlEncode => value
+                .iter()
+                .flat_map(|&b| {
+                    if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                        vec![b]
+                    } else {
+                        format!("%{b:02X}").into_bytes()
+                    }
+                })
+                .collect(),
+            Pipe::Trim => value.trim_ascii().to_vec(),
+        }
+    }
+}
+
+/// Run `value` through `pipes` left to right.
+pub fn apply_all(pipes: &[Pipe], value: &[u8]) -> Vec<u8> {
+    pipes
+        .iter()
+        .fold(value.to_vec(), |value, pipe| pipe.apply(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipes_apply_in_order() {
+        assert_eq!(
+            apply_all(&[Pipe::Trim, Pipe::Base64], b" user:pw\n"),
+            b"dXNlcjpwdw=="
+        );
+        assert_eq!(apply_all(&[Pipe::Base64, Pipe::Trim], b"a\n"), b"YQo=");
+        assert_eq!(Pipe::Hex.apply(b"\x00\xff"), b"00ff");
+        assert_eq!(Pipe::JsonEscape.apply(b"a\"b\\\n"), br#"a\"b\\\n"#);
+        assert_eq!(
+            Pipe::UrlEncode.apply("p@ss w/rd~é".as_bytes()),
+            b"p%40ss%20w%2Frd~%C3%A9"
+        );
+        assert_eq!(apply_all(&[], b"raw"), b"raw");
+    }
+}
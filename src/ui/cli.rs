@@ -0,0 +1,5509 @@
+use crate::ui::clipboard;
+use crate::ui::common::{
+    Column, Output, OutputFormat, TimeStyle, history_scrub_hint, human_bytes, mask, metadata_table,
+    metadata_table_with, name_tree, parse_duration, parse_expiry, parse_since, relative_time,
+};
+use crate::ui::editor;
+use crate::ui::explain;
+use crate::ui::picker;
+use crate::ui::prompt;
+use crate::{
+    agent,
+    attest::Snapshot,
+    audit, autostart, aws, backup, blueprint,
+    config::{
+        Config, ConfigOverrides, ConfigSnapshot, DMK_FILE_VAR, SNAPSHOT_FORMAT_VERSION,
+        VaultSummary,
+    },
+    crypto::{Cipher, age},
+    curl,
+    db::Repository,
+    docker,
+    domain::{
+        AuditAction, AuditFilter, MetadataPatch, Origin, Provenance, Secret, SecretAttributes,
+        SecretMetadata, SecretRef, SlotKind, env_var_name,
+    },
+    drift::{self, DriftStatus},
+    export, generator, host, import, jwt,
+    keymgr::{self, MasterKeyProvider, MasterKeySource},
+    kube,
+    oplog::Operation,
+    perms, registry, report, rotation, scan,
+    service::{
+        Conflict, KeyOrigin, PromoteStatus, Resolution, SecretService, VaultDiffItem,
+        VaultDiffStatus,
+    },
+    template, transform,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use clap::{
+    ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum,
+    builder::{EnumValueParser, TypedValueParser},
+};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tabled::{Table, Tabled, settings::Style};
+use uuid::Uuid;
+
+/// `template-func` exit code when the requested name is not in the vault.
+pub const TEMPLATE_FUNC_NOT_FOUND: i32 = 3;
+
+const CHEZMOI_SNIPPET: &str = r#"# ~/.config/chezmoi/chezmoi.toml
+[secret]
+    command = "devinventory"
+    args = ["template-func"]
+
+# any template, e.g. ~/.local/share/chezmoi/dot_netrc.tmpl
+machine github.com
+    login me
+    password {{ secret "gh/token" }}
+
+# exit codes: 0 value printed, 3 name not in vault, 2 usage, 1 vault/key error;
+# add --dmk or --db-path to `args` when the defaults do not apply
+"#;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "devinventory",
+    version,
+    about = "Manage infrastructure secrets locally with encryption"
+)]
+pub struct Cli {
+    /// Optional override for database file path
+    #[arg(long, global = true)]
+    pub db_path: Option<PathBuf>,
+
+    /// Use the `[vaults.NAME]` profile from config.toml (vault file and keyring entry)
+    #[arg(long, global = true, value_name = "NAME", conflicts_with_all = ["db_path", "ephemeral"])]
+    pub vault: Option<String>,
+
+    /// Do not write master key to OS keyring; print it once instead
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_keyring: bool,
+
+    /// Provide master key (base64) explicitly; skips keyring lookup
+    #[arg(long, global = true)]
+    pub dmk: Option<String>,
+
+    /// Read the master key from this file (base64 or 32 raw bytes, mode 0600); `init`
+    /// creates it. Also `$DEVINVENTORY_DMK_FILE`
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "dmk")]
+    pub dmk_file: Option<PathBuf>,
+
+    /// Keep the vault in memory for this run; nothing is written to disk
+    #[arg(long, global = true)]
+    pub ephemeral: bool,
+
+    /// Seed the ephemeral vault from an `oplog export` file
+    #[arg(long, global = true, requires = "ephemeral")]
+    pub seed: Option<PathBuf>,
+
+    /// Write the ephemeral vault's operation log (values stay encrypted) here on exit
+    #[arg(long, global = true, requires = "ephemeral")]
+    pub dump: Option<PathBuf>,
+
+    /// Increase log verbosity on top of the configured level (-v debug, -vv trace)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Print list, search, get, stats and tag list as json or yaml
+    #[arg(long, global = true, value_parser = format_of::<OutputFormat>(), default_value = "table")]
+    pub format: String,
+    /// How tables show times: utc, local or relative (default: `[display] time`)
+    #[arg(long, global = true, value_enum)]
+    pub time: Option<TimeStyle>,
+
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Cli {
+    /// Global flags merged with overrides specific to the chosen command.
+    pub fn config_overrides(&self) -> ConfigOverrides {
+        let mut overrides = ConfigOverrides {
+            db_path: self.db_path.clone(),
+            ephemeral: self.ephemeral,
+            vault: self.vault.clone(),
+            ..Default::default()
+        };
+        if let Commands::Init(InitArgs {
+            keyring_service,
+            keyring_account,
+            ..
+        }) = &self.command
+        {
+            overrides.keyring_service = keyring_service.clone();
+            overrides.keyring_account = keyring_account.clone();
+        }
+        overrides
+    }
+
+    /// Parse the process arguments; invoked through a `docker-credential-devinventory`
+    /// link, behave as `devinventory docker-credential-helper`.
+    pub fn from_env() -> Self {
+        let mut args: Vec<OsString> = std::env::args_os().collect();
+        let invoked_as = args
+            .first()
+            .and_then(|arg0| Path::new(arg0).file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned());
+        if invoked_as.as_deref() == Some(docker::HELPER_BINARY) {
+            args.insert(1, "docker-credential-helper".into());
+        }
+        Self::parse_from(args)
+    }
+
+    /// Output format and time style, the `--time` flag winning over `[display] time`.
+    pub fn output(&self, config: &Config) -> Output {
+        Output {
+            format: self.output_format(),
+            time: self.time.or(config.file.display.time).unwrap_or_default(),
+        }
+    }
+
+    /// How list, search, get, stats and tag list print. A subcommand's own `--format`
+    /// (e.g. `export --format dotenv`) lands in the same field and means tables here.
+    pub fn output_format(&self) -> OutputFormat {
+        OutputFormat::from_str(&self.format, false).unwrap_or_default()
+    }
+
+    pub fn master_key_source(&self) -> MasterKeySource {
+        MasterKeySource {
+            base64_inline: self.dmk.clone(),
+            key_file: self.dmk_file.clone().or_else(|| {
+                std::env::var_os(DMK_FILE_VAR)
+                    .filter(|v| !v.is_empty())
+                    .map(PathBuf::from)
+            }),
+            allow_keyring: !self.no_keyring,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Add or update a secret
+    Add(AddArgs),
+    /// Store a freshly generated random password (or word passphrase)
+    Generate(GenerateArgs),
+    /// Get and print a secret (masked by default)
+    Get(GetArgs),
+    /// Copy a secret to the clipboard and clear it again after a while
+    Copy {
+        #[command(flatten)]
+        target: SecretTarget,
+        /// How long the value stays on the clipboard (default: `[clipboard] clear_after_secs`)
+        #[arg(long = "for", value_name = "DURATION", value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// Change a secret's value in $VISUAL/$EDITOR (handy for PEM keys and other
+    /// multi-line values)
+    Edit {
+        #[command(flatten)]
+        target: PickedTarget,
+    },
+    /// Show a secret for a limited time, then wipe it from screen and clipboard
+    Reveal {
+        #[command(flatten)]
+        target: SecretTarget,
+        /// How long the value stays visible (e.g. 30s, 2m)
+        #[arg(long = "for", value_name = "DURATION", default_value = "30s", value_parser = parse_duration)]
+        duration: Duration,
+    },
+    /// Print the current one-time code of a `kind=totp` secret
+    Totp {
+        #[command(flatten)]
+        target: SecretTarget,
+    },
+    /// Run a command with secrets injected as environment variables
+    Exec {
+        /// Secret to inject, optionally as NAME=VAR (default variable: its `map`
+        /// entry, else the upper-cased name)
+        #[arg(short, long = "secret", value_name = "NAME[=VAR]", required = true)]
+        secrets: Vec<String>,
+        /// First list on stderr which variables are set or overridden (values masked)
+        #[arg(long)]
+        diff: bool,
+        /// Command and arguments, after `--`
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Print `export` lines for secrets, for `eval "$(devinventory env -s ...)"`
+    Env {
+        /// Secret to print, optionally as NAME=VAR (same naming as `exec`)
+        #[arg(short, long = "secret", value_name = "NAME[=VAR]", required = true)]
+        secrets: Vec<String>,
+    },
+    /// Configure the environment variable each secret is injected as
+    Map {
+        #[command(subcommand)]
+        action: MapCommands,
+    },
+    /// Export decrypted secrets in a format other tools consume
+    Export(ExportArgs),
+    /// Print a shell completion script; secret names complete from the vault
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Secret names starting with a prefix, one per line, for completion scripts
+    #[command(name = "__complete-names", hide = true)]
+    CompleteNames {
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+    /// Reference pages beyond --help: key sources, formats, exit codes, policies, config
+    Explain {
+        #[arg(value_enum)]
+        topic: explain::Topic,
+    },
+    /// Fill `{{ secret("name") }}` markers in a template file with vault values
+    Render {
+        template: PathBuf,
+        /// Write to this file (owner-only) instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Print one raw value for templaters (chezmoi etc.)
+    ///
+    /// Reads NAME from the argument or a single stdin line and writes the value
+    /// with no trailing newline. Exit codes: 0 found, 3 not found, 2 usage, 1 any other error.
+    TemplateFunc { name: Option<String> },
+    /// Print AWS CLI `credential_process` JSON for a stored access key
+    AwsCredentialProcess { name: String },
+    /// Print a Kubernetes ExecCredential for kubeconfig `exec` users
+    KubectlAuth { name: String },
+    /// Docker credential helper protocol; server URL or credentials JSON on stdin
+    DockerCredentialHelper {
+        #[arg(value_enum)]
+        action: DockerAction,
+    },
+    /// Run curl with an Authorization header built from a secret, passed in a private
+    /// header file instead of the command line
+    Curl {
+        /// Secret holding the token (or `user:password` for --scheme basic)
+        name: String,
+        /// How the value goes into the Authorization header
+        #[arg(long, value_enum, default_value = "bearer")]
+        scheme: curl::AuthScheme,
+        /// Custom header instead, with {value} for the secret, e.g. 'X-Api-Key: {value}'
+        #[arg(long, conflicts_with = "scheme")]
+        header_template: Option<String>,
+        /// curl arguments, after `--`
+        #[arg(last = true, required = true, value_name = "CURL_ARGS")]
+        args: Vec<String>,
+    },
+    /// Inject package registry publish tokens
+    Registry {
+        #[command(subcommand)]
+        action: RegistryCommands,
+    },
+    /// Print setup snippets for third-party tools
+    Integrations {
+        #[command(subcommand)]
+        tool: IntegrationCommands,
+    },
+    /// Report which deployed credentials differ from the vault; exits 1 when any are stale
+    Drift {
+        /// A rendered .env file, or `k8s:[NAMESPACE/]NAME` for a Secret read via kubectl
+        target: String,
+        /// Only compare secrets under this prefix (stripped from keys, as in `export`)
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Only compare secrets carrying this tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Fail when files contain values stored in the vault
+    Scan {
+        /// Scan the staged contents of the current git repository
+        #[arg(long, conflicts_with = "files")]
+        staged: bool,
+        /// Files to scan
+        #[arg(required_unless_present = "staged")]
+        files: Vec<PathBuf>,
+    },
+    /// Install integration hooks
+    Hooks {
+        #[command(subcommand)]
+        action: HookCommands,
+    },
+    /// Report secrets (e.g. certificates, short-lived tokens) that expire soon or
+    /// already have; exits 1 when there are any
+    #[command(visible_aliases = ["due", "expiring"])]
+    Check {
+        /// How far ahead to look
+        #[arg(long, default_value = "30d", value_parser = parse_duration)]
+        within: Duration,
+    },
+    /// Approve a pending delete/export of a protected secret (lists pending requests without an id)
+    Approve { request_id: Option<Uuid> },
+    /// Hand out one-time tokens that the agent exchanges for a secret's value
+    Lease {
+        #[command(subcommand)]
+        action: LeaseCommands,
+    },
+    /// Copy secrets into another vault profile, re-encrypted under that vault's key
+    #[command(visible_alias = "copy-to")]
+    Transfer(TransferArgs),
+    /// Hand part of the vault to someone else as a standalone vault file
+    Subvault {
+        #[command(subcommand)]
+        action: SubvaultCommands,
+    },
+    /// Compare this vault with another vault file by name and last update (values are never shown)
+    Diff { other_db: PathBuf },
+    /// Copy new and newer secrets one way between this vault and another vault file
+    Sync(SyncArgs),
+    /// Copy secrets from one namespace prefix to another after a diff preview
+    Promote {
+        /// Source prefix, e.g. `staging/`
+        #[arg(long)]
+        from: String,
+        /// Destination prefix, e.g. `prod/`
+        #[arg(long)]
+        to: String,
+        /// Only names (relative to --from) matching this glob, e.g. 'app/*'
+        #[arg(long)]
+        only: Option<String>,
+        /// Give each promoted secret a fresh random value instead of the source's
+        #[arg(long)]
+        rotate: bool,
+        /// Show the preview only
+        #[arg(long)]
+        dry_run: bool,
+        /// Skip the confirmation prompt
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Copy credentials from another store into the vault
+    Import(ImportArgs),
+    /// Query the audit log, export it or ship it to a collector off this machine
+    Audit {
+        #[command(subcommand)]
+        action: AuditCommands,
+    },
+    /// Show recent vault activity from the audit log, grouped by day
+    Timeline {
+        /// How many days back to show
+        #[arg(long, default_value_t = 7)]
+        days: u32,
+    },
+    /// List secrets (metadata only)
+    List(ListArgs),
+    /// Print the names starting with a prefix, for editor completion (no values read;
+    /// answered by a running agent when there is one)
+    Names {
+        /// Name prefix, e.g. `app/`
+        #[arg(long, default_value = "")]
+        prefix: String,
+        #[arg(long, value_parser = format_of::<NamesFormat>(), default_value = "text")]
+        format: String,
+        /// Stop after this many names
+        #[arg(long)]
+        limit: Option<u32>,
+    },
+    /// Change metadata of every secret matching a glob at once, after a preview
+    Set(SetArgs),
+    /// Attach, detach or list tags
+    Tag {
+        #[command(subcommand)]
+        action: TagCommands,
+    },
+    /// Search secrets by substring (name/kind/note/owner)
+    Search {
+        /// Case-insensitive substring to match
+        query: String,
+    },
+    /// Initialize master key (generate, optionally store to keyring)
+    Init(InitArgs),
+    /// Check vault and config file permissions
+    Doctor,
+    /// Summarize the vault: counts per kind and tag, sizes and the longest-unchanged
+    /// secrets; --large lists the secrets taking the most space
+    Stats {
+        /// List the biggest records and their share of the database
+        #[arg(long)]
+        large: bool,
+        /// How many records --large lists
+        #[arg(long, default_value_t = 10, requires = "large")]
+        top: usize,
+        /// How many of the least recently changed secrets to list
+        #[arg(long, default_value_t = 5)]
+        oldest: usize,
+    },
+    /// Housekeeping for master keys kept in the OS keyring, and key slots
+    Key {
+        #[command(subcommand)]
+        action: KeyCommands,
+    },
+    /// Enroll, list or revoke devices that unlock the vault with their own key
+    Device {
+        #[command(subcommand)]
+        action: DeviceCommands,
+    },
+    /// Write every secret to one portable, encrypted backup file
+    Backup {
+        out: PathBuf,
+        /// Encrypt under a passphrase (asked twice) instead of the master key
+        #[arg(long)]
+        passphrase: bool,
+        /// Replace an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Back up every secret that still decrypts from a damaged vault and list the rest
+    Salvage {
+        /// Backup file to write; `restore` reads it
+        #[arg(long)]
+        out: PathBuf,
+        /// Where to list the secrets left out (default: OUT with `.manifest.json` added)
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+        /// Encrypt under a passphrase (asked twice) instead of the master key
+        #[arg(long)]
+        passphrase: bool,
+        /// Replace existing files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Keypair that exports and backups are sealed to, independent of the master key
+    BackupKey {
+        #[command(subcommand)]
+        action: BackupKeyCommands,
+    },
+    /// Inspect, export or merge the operation log used to reconcile offline replicas
+    Oplog {
+        #[command(subcommand)]
+        action: OplogCommands,
+    },
+    /// Inspect, export or import configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+    /// Give a secret a new name, keeping its value and metadata
+    Rename {
+        old: String,
+        new: String,
+        /// Treat OLD and NEW as folder prefixes and move everything under OLD
+        #[arg(long)]
+        prefix: bool,
+    },
+    /// Move a secret to the trash (`restore` brings it back, `purge` removes it for good)
+    Rm {
+        /// Picked from the vault when omitted on a terminal
+        #[arg(conflicts_with = "prefix")]
+        name: Option<String>,
+        /// Remove the secret with this id instead of naming it
+        #[arg(long, conflicts_with_all = ["name", "prefix"])]
+        id: Option<Uuid>,
+        /// Remove every secret whose name starts with this (e.g. `staging/`)
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Skip the confirmation prompt for --prefix
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// List secrets in the trash with when they were deleted
+    Trash,
+    /// Bring a secret back from the trash, or a backup file into the vault
+    Restore {
+        /// Trashed secret to bring back
+        #[arg(required_unless_present = "backup", conflicts_with = "backup")]
+        name: Option<String>,
+        /// Backup file written by `backup`
+        #[arg(long, value_name = "FILE")]
+        backup: Option<PathBuf>,
+        /// What to do with names the vault already holds
+        #[arg(long, value_enum, default_value = "skip", requires = "backup")]
+        strategy: backup::RestoreStrategy,
+        /// Secret key for a backup sealed to the backup key
+        #[arg(long, value_name = "FILE", requires = "backup")]
+        backup_key: Option<PathBuf>,
+        /// Skip the confirmation prompt
+        #[arg(long, short, requires = "backup")]
+        yes: bool,
+    },
+    /// Permanently delete secrets in the trash
+    Purge {
+        /// Secret to purge; every trashed secret with --all
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
+        #[arg(long)]
+        all: bool,
+        /// Skip the confirmation prompt for --all
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Clear the write-once flag of an immutable secret (asks for confirmation)
+    ClearImmutable {
+        #[command(flatten)]
+        target: SecretTarget,
+    },
+    /// Block overwriting, renaming and removing a secret until `unlock`
+    Lock {
+        #[command(flatten)]
+        target: SecretTarget,
+        /// Shown to whoever runs into the lock
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Lift a lock set with `lock`
+    Unlock {
+        #[command(flatten)]
+        target: SecretTarget,
+    },
+    /// Rotate master key and re-encrypt all secrets
+    Rotate {
+        /// Only retire the old key; records move to the new one as they are rewritten
+        /// (or read, with `[crypto] reencrypt_on_read`)
+        #[arg(long)]
+        lazy: bool,
+        /// Protect the new key with a passphrase (asked twice) instead of the keyring;
+        /// to only change a passphrase vault's passphrase, use `key change-passphrase`
+        #[arg(long, conflicts_with_all = ["yubikey", "pkcs11"])]
+        passphrase: bool,
+        /// Derive the new key from the YubiKey's answer to a fresh challenge; a YubiKey
+        /// vault always does
+        #[arg(long, conflicts_with = "pkcs11")]
+        yubikey: bool,
+        /// Wrap the new key with the `[pkcs11]` token's key; a PKCS#11 vault always does
+        #[arg(long)]
+        pkcs11: bool,
+        /// Switch the vault to this cipher; values are re-encrypted with it (on their
+        /// next write with --lazy)
+        #[arg(long, value_enum)]
+        cipher: Option<Cipher>,
+    },
+    /// Give every secret matching --tag/--prefix/--kind/--field a new value
+    RotateSecret(RotateSecretArgs),
+    /// Run or query the local agent that serves secrets over a Unix socket (Unix only;
+    /// Windows has no agent yet)
+    Agent {
+        /// Socket path (default: $XDG_RUNTIME_DIR/devinventory/agent.sock)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        #[command(subcommand)]
+        action: AgentCommands,
+    },
+    /// Write a signed Merkle-root snapshot of the vault (or verify one)
+    Attest {
+        /// File to write the snapshot to instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+        #[command(subcommand)]
+        action: Option<AttestCommands>,
+    },
+    /// Produce shareable documents about the vault (metadata only, never values)
+    Report {
+        #[command(subcommand)]
+        action: ReportCommands,
+    },
+}
+
+/// Arguments of [`Commands::Add`].
+#[derive(Args, Debug)]
+pub struct AddArgs {
+    /// Unique name for this secret
+    name: String,
+    /// Optional type/kind label
+    #[arg(long)]
+    kind: Option<String>,
+    /// Optional description
+    #[arg(long)]
+    note: Option<String>,
+    /// Team or person responsible for rotating this secret
+    #[arg(long)]
+    owner: Option<String>,
+    /// How to reach the owner
+    #[arg(long)]
+    contact: Option<String>,
+    /// Provide secret via argument instead of prompt (discouraged: it stays in shell
+    /// history and shows in the process list; `[security] allow_value_flag = false`
+    /// refuses it)
+    #[arg(long)]
+    value: Option<String>,
+    /// Read the value from a file, byte for byte (keystores, kubeconfigs, DER certs)
+    #[arg(long, conflicts_with = "value")]
+    file: Option<PathBuf>,
+    /// Read the value from stdin, minus one trailing newline (the default when stdin
+    /// is piped and neither --value nor --file is given)
+    #[arg(long, conflicts_with_all = ["value", "file"])]
+    stdin: bool,
+    /// Write-once: reject later overwrites and deletes until cleared
+    #[arg(long, default_value_t = false)]
+    immutable: bool,
+    /// Bind to this machine: the secret cannot be decrypted on another host
+    #[arg(long, default_value_t = false)]
+    bind_host: bool,
+    /// Only allow `exec` to pass this secret to the given executable (repeatable)
+    #[arg(long = "allow-exec", value_name = "PROGRAM")]
+    allow_exec: Vec<String>,
+    /// Tag to attach (repeatable)
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// When the value stops working: a date, an RFC 3339 time or a duration like 90d
+    #[arg(long, value_name = "WHEN", value_parser = parse_expiry)]
+    expires: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Arguments of [`Commands::Generate`].
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    name: String,
+    /// Number of characters
+    #[arg(long, default_value_t = 32)]
+    length: usize,
+    #[arg(long, value_enum, default_value = "alnum")]
+    charset: generator::Charset,
+    /// Generate a passphrase of this many words instead
+    #[arg(long, conflicts_with_all = ["length", "charset"])]
+    words: Option<usize>,
+    /// Joins passphrase words
+    #[arg(long, default_value = "-", requires = "words")]
+    separator: String,
+    #[arg(long)]
+    kind: Option<String>,
+    #[arg(long)]
+    note: Option<String>,
+    /// Tag to attach (repeatable)
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Replace an existing secret of that name
+    #[arg(long)]
+    force: bool,
+    /// Also print the generated value (e.g. to paste into a sign-up form)
+    #[arg(long)]
+    show: bool,
+}
+
+/// Arguments of [`Commands::Get`].
+#[derive(Args, Debug)]
+pub struct GetArgs {
+    #[command(flatten)]
+    target: PickedTarget,
+    /// Show plaintext without masking (ask for confirmation)
+    #[arg(long, action = ArgAction::SetTrue)]
+    show: bool,
+    /// Decode a JWT value's header and claims (the signature is not verified)
+    #[arg(long, conflicts_with = "show")]
+    inspect: bool,
+    /// Print the value through these encodings, in order (repeatable; implies --show)
+    #[arg(long, value_enum, value_name = "PIPE", conflicts_with = "inspect")]
+    pipe: Vec<transform::Pipe>,
+    /// Put the value (after any --pipe) on the clipboard instead of printing it, and
+    /// clear it after `[clipboard] clear_after_secs`
+    #[arg(long, conflicts_with_all = ["show", "inspect"])]
+    copy: bool,
+    /// Write the value (after any --pipe) to this file byte for byte, owner-only
+    #[arg(long, conflicts_with_all = ["show", "inspect", "copy"])]
+    out: Option<PathBuf>,
+    /// Write only the value (after any --pipe) to stdout: exact bytes, no newline,
+    /// no metadata; for `$(...)` and pipes into other tools
+    #[arg(long, conflicts_with_all = ["inspect", "copy", "out"])]
+    raw: bool,
+}
+
+/// Arguments of [`Commands::Export`].
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Output format
+    #[arg(
+        id = "format",
+        long = "to",
+        visible_alias = "format",
+        value_name = "TO",
+        value_parser = format_of::<ExportFormat>()
+    )]
+    to: String,
+    /// Only export secrets whose name starts with this prefix (stripped from variable names)
+    #[arg(long, default_value = "")]
+    prefix: String,
+    /// Only export secrets carrying this tag
+    #[arg(long)]
+    tag: Option<String>,
+    /// File holding the Ansible Vault password
+    #[arg(long)]
+    vault_password_file: Option<PathBuf>,
+    /// Write to this file instead of stdout
+    #[arg(long)]
+    out: Option<PathBuf>,
+    /// Seal the output to `[backup] public_key`; only the backup secret key opens it
+    #[arg(long, requires = "out")]
+    seal: bool,
+    /// Encrypt the output in the age format to this X25519 recipient (repeatable);
+    /// each of them opens it with `age -d -i KEYFILE`
+    #[arg(
+        long = "age-recipient",
+        value_name = "AGE1...",
+        requires = "out",
+        conflicts_with = "seal"
+    )]
+    age_recipients: Vec<String>,
+}
+
+/// Arguments of [`Commands::Transfer`].
+#[derive(Args, Debug)]
+pub struct TransferArgs {
+    /// Secrets to copy
+    #[arg(required_unless_present_any = ["all", "prefix"], conflicts_with_all = ["all", "prefix"])]
+    names: Vec<String>,
+    /// Copy every secret (or every one under --prefix)
+    #[arg(long)]
+    all: bool,
+    /// Copy the secrets whose name starts with this
+    #[arg(long)]
+    prefix: Option<String>,
+    /// `[vaults.NAME]` profile to copy into
+    #[arg(long, value_name = "VAULT")]
+    to_vault: String,
+    /// Master key of the target vault (base64); default: its keyring entry
+    #[arg(long, value_name = "BASE64")]
+    to_dmk: Option<String>,
+    /// Replace the secret if the target vault already has it
+    #[arg(long)]
+    overwrite: bool,
+    /// Move instead of copy: the secret then goes to this vault's trash
+    #[arg(long = "move")]
+    remove: bool,
+}
+
+/// Arguments of [`Commands::Sync`].
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    other_db: PathBuf,
+    /// Copy from this vault into OTHER_DB
+    #[arg(long, required_unless_present = "pull", conflicts_with = "pull")]
+    push: bool,
+    /// Copy from OTHER_DB into this vault
+    #[arg(long)]
+    pull: bool,
+    /// Also move secrets the source lacks to the target's trash
+    #[arg(long)]
+    delete: bool,
+    /// Show the differences without copying
+    #[arg(long)]
+    dry_run: bool,
+    /// Ask which version to keep for every name changed on both sides
+    #[arg(long, short)]
+    interactive: bool,
+    /// Skip the confirmation prompt
+    #[arg(short = 'y', long)]
+    yes: bool,
+}
+
+/// Arguments of [`Commands::Import`].
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Where to read credentials from
+    #[arg(
+        id = "format",
+        long,
+        visible_alias = "format",
+        value_name = "FROM",
+        value_parser = format_of::<ImportSource>()
+    )]
+    from: String,
+    /// File to read (browser-csv, csv, json)
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Only items whose service/server (keychain) or target (wincred) starts with this
+    #[arg(long)]
+    service_prefix: Option<String>,
+    /// libsecret collection to import from, e.g. `login`
+    #[arg(long)]
+    collection: Option<String>,
+    /// libsecret attribute to search for (repeatable)
+    #[arg(long = "attribute", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    attributes: Vec<(String, String)>,
+    /// Ask about each item instead of importing everything that matched
+    #[arg(long)]
+    pick: bool,
+    /// Prefix for the vault names (default: `<store>/`, e.g. `keychain/`)
+    #[arg(long)]
+    prefix: Option<String>,
+    /// What to do with secrets that already exist under the same name
+    #[arg(long, value_enum, default_value = "skip")]
+    merge: MergeMode,
+    /// Same as --merge overwrite
+    #[arg(long, conflicts_with = "merge")]
+    overwrite: bool,
+    /// List what would be imported
+    #[arg(long)]
+    dry_run: bool,
+    /// Skip the confirmation prompt
+    #[arg(long, short)]
+    yes: bool,
+}
+
+/// Arguments of [`Commands::List`].
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// Only secrets owned by this team or person
+    #[arg(long)]
+    owner: Option<String>,
+    /// Only secrets carrying this tag
+    #[arg(long)]
+    tag: Option<String>,
+    /// Only secrets whose name starts with this; `prod/` is the prod folder
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Only secrets whose value entered the vault this way
+    #[arg(long, value_enum)]
+    origin: Option<Origin>,
+    /// Draw names as a folder tree instead of a table
+    #[arg(long)]
+    tree: bool,
+    /// Table columns, comma-separated, e.g. id,name,owner
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "tree")]
+    columns: Vec<Column>,
+}
+
+/// Arguments of [`Commands::Set`].
+#[derive(Args, Debug)]
+pub struct SetArgs {
+    /// Shell-style name pattern, e.g. 'legacy/*'
+    #[arg(long, required_unless_present = "id")]
+    glob: Option<String>,
+    /// Change only the secret with this id
+    #[arg(long, conflicts_with = "glob")]
+    id: Option<Uuid>,
+    #[arg(long)]
+    note: Option<String>,
+    #[arg(long)]
+    owner: Option<String>,
+    #[arg(long)]
+    contact: Option<String>,
+    /// A date, an RFC 3339 time or a duration like 90d
+    #[arg(long, value_name = "WHEN", value_parser = parse_expiry)]
+    expires: Option<chrono::DateTime<chrono::Utc>>,
+    /// Tag to attach (repeatable)
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Skip the confirmation prompt
+    #[arg(long, short)]
+    yes: bool,
+}
+
+/// Arguments of [`Commands::Init`].
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    /// Keyring service to store the new key under (overrides config/env)
+    #[arg(long)]
+    keyring_service: Option<String>,
+    /// Keyring account to store the new key under (overrides config/env)
+    #[arg(long)]
+    keyring_account: Option<String>,
+    /// Derive the master key from a passphrase with Argon2id instead of keeping a
+    /// generated key in the keyring
+    #[arg(long, conflicts_with_all = ["yubikey", "pkcs11"])]
+    passphrase: bool,
+    /// Derive the master key from a YubiKey's HMAC-SHA1 challenge-response (the
+    /// `[yubikey]` slot) instead of keeping a generated key in the keyring
+    #[arg(long, conflicts_with = "pkcs11")]
+    yubikey: bool,
+    /// Wrap a random master key with the `[pkcs11]` token's key (HSM or smart
+    /// card) instead of keeping it in the keyring
+    #[arg(long)]
+    pkcs11: bool,
+    /// Encrypt values with this cipher (default chacha20-poly1305)
+    #[arg(long, value_enum)]
+    cipher: Option<Cipher>,
+    /// Seed the vault with a template's secrets, each generated: built-in `webapp`
+    /// or `database`, or `<config dir>/devinventory/templates/NAME.toml`
+    #[arg(long, value_name = "NAME")]
+    template: Option<String>,
+    /// Create the template's secrets under this prefix, e.g. `shop/`
+    #[arg(long, requires = "template")]
+    prefix: Option<String>,
+}
+
+/// Arguments of [`Commands::RotateSecret`].
+#[derive(Args, Debug)]
+pub struct RotateSecretArgs {
+    /// Only secrets carrying this tag
+    #[arg(long)]
+    tag: Option<String>,
+    /// Only secrets whose name starts with this
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Only secrets of this kind
+    #[arg(long)]
+    kind: Option<String>,
+    /// Only secrets carrying this custom field (repeatable)
+    #[arg(long = "field", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    fields: Vec<(String, String)>,
+    /// Where new values come from
+    #[arg(long, value_enum, default_value = "random")]
+    provider: RotationProvider,
+    /// Hook for `--provider command`, after `--`: gets the current value on stdin and
+    /// the name in $DEVINVENTORY_SECRET_NAME, prints the new value
+    #[arg(last = true, value_name = "COMMAND")]
+    command: Vec<String>,
+    /// List the secrets that would be rotated
+    #[arg(long)]
+    dry_run: bool,
+    /// Skip the confirmation prompt
+    #[arg(long, short)]
+    yes: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AgentCommands {
+    /// Serve the unlocked vault to your own processes until Ctrl-C
+    Start,
+    /// Check that an agent answers and which protocol it speaks
+    Status,
+    /// Print a secret's value fetched through the agent
+    Get { name: String },
+    /// Print the value a lease token grants, using it up (reads the token from stdin when omitted)
+    Redeem { token: Option<String> },
+    /// Start the agent at login and run `check` daily (systemd, launchd or Task Scheduler)
+    InstallService {
+        /// Service manager to target (default: this platform's)
+        #[arg(long, value_enum)]
+        platform: Option<autostart::Platform>,
+        /// Print the unit files and commands instead of installing them
+        #[arg(long)]
+        print: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TagCommands {
+    /// Attach tags to a secret
+    Add {
+        name: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Detach tags from a secret
+    Rm {
+        name: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Tags of one secret, or every tag with how many secrets carry it
+    List { name: Option<String> },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditCommands {
+    /// Show audit entries, newest first, with the host and user behind each
+    List {
+        /// Only entries about this secret
+        #[arg(long)]
+        secret: Option<String>,
+        #[arg(long, value_enum)]
+        action: Option<AuditAction>,
+        /// Only entries recorded on this machine
+        #[arg(long)]
+        host: Option<String>,
+        /// Only entries from this time on: 2025-06-30, an RFC 3339 time or e.g. 7d
+        #[arg(long)]
+        since: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// Write audit entries, oldest first
+    Export {
+        #[arg(long, value_parser = format_of::<AuditFormat>(), default_value = "jsonl")]
+        format: String,
+        /// Only entries from this time on: 2025-06-30, an RFC 3339 time or e.g. 7d
+        #[arg(long)]
+        since: Option<String>,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Send the entries a collector has not received yet
+    Ship {
+        /// file:PATH, syslog or an http(s):// URL (default: `[audit] ship_to`)
+        #[arg(long)]
+        to: Option<String>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum NamesFormat {
+    /// One name per line
+    Text,
+    /// A JSON array of names
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum AuditFormat {
+    /// One JSON object per line
+    Jsonl,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MapCommands {
+    /// Inject NAME as VAR in `exec` and `env`
+    Set { name: String, var: String },
+    /// Go back to the variable derived from the name
+    Rm { name: String },
+    /// Show every mapping
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeyCommands {
+    /// Delete keyring entries whose vaults no longer exist (asks first)
+    Gc {
+        /// Skip the confirmation prompt
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Extra ways to unlock the vault: passphrases, recovery keys or keyring keys
+    /// that each hold a wrapped copy of the master key
+    Slot {
+        #[command(subcommand)]
+        action: SlotCommands,
+    },
+    /// Give a passphrase vault a new passphrase; only the master key's wrapping changes,
+    /// so no secret is re-encrypted (`rotate` replaces the key itself)
+    ChangePassphrase,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SlotCommands {
+    /// Wrap the master key in a new slot (a recovery key is printed once)
+    Add {
+        #[arg(value_enum)]
+        kind: SlotKind,
+        /// What the slot is for, e.g. `paper copy in the safe` (default: the kind)
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Remove a slot by number; it no longer unlocks the vault
+    Remove { id: i64 },
+    /// Show the slots, without anything that unlocks them
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SubvaultCommands {
+    /// Write the secrets under a prefix to a new vault file, re-encrypted under its
+    /// own key; revoke it by rotating those secrets here (`rotate-secret --prefix`)
+    Create {
+        /// Secrets whose name starts with this, e.g. `client-x/`
+        #[arg(long)]
+        prefix: String,
+        /// Vault file to create
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+        /// Generate a fresh master key for it (printed once unless --key-out is given)
+        #[arg(long, required_unless_present = "key", conflicts_with = "key")]
+        new_key: bool,
+        /// Master key to use instead (base64), e.g. one the recipient generated
+        #[arg(long, value_name = "BASE64")]
+        key: Option<String>,
+        /// Write the new key to this owner-only file instead of printing it
+        #[arg(long, value_name = "PATH", requires = "new_key")]
+        key_out: Option<PathBuf>,
+        /// Replace OUT if it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LeaseCommands {
+    /// Print a token redeemable once, before the ttl runs out, with `agent redeem`
+    Create {
+        name: String,
+        #[arg(long, default_value = "15m", value_parser = parse_duration)]
+        ttl: Duration,
+    },
+    /// Show leases not yet redeemed or expired
+    List,
+    /// Void a lease before it is redeemed
+    Revoke { id: Uuid },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReportCommands {
+    /// Names, kinds, owners, expiry and last rotation of every secret
+    Inventory {
+        #[arg(long, value_parser = format_of::<report::Format>(), default_value = "md")]
+        format: String,
+        /// File to write the report to instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AttestCommands {
+    /// Check a snapshot's signature and whether the vault still matches it
+    Verify { snapshot: PathBuf },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ImportSource {
+    /// macOS login keychain (generic and internet passwords)
+    MacosKeychain,
+    /// Windows Credential Manager (generic credentials)
+    Wincred,
+    /// libsecret / GNOME Keyring, via `secret-tool`
+    Libsecret,
+    /// Chrome/Chromium or Firefox password export (CSV, needs --file)
+    BrowserCsv,
+    /// CSV with a header row: name, value and optionally kind, note, owner, contact,
+    /// expires; other columns become fields (needs --file)
+    Csv,
+    /// JSON array of objects with the same keys as csv, or an object of name/value pairs
+    /// (needs --file)
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeMode {
+    /// Keep the vault's value and report the name
+    Skip,
+    /// Replace the vault's value
+    Overwrite,
+    /// Show both versions of each existing name and ask which to keep
+    Ask,
+}
+
+impl ImportSource {
+    fn default_prefix(self) -> &'static str {
+        match self {
+            ImportSource::MacosKeychain => "keychain/",
+            ImportSource::Wincred => "wincred/",
+            ImportSource::Libsecret => "libsecret/",
+            ImportSource::BrowserCsv => "web/",
+            ImportSource::Csv | ImportSource::Json => "",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum RotationProvider {
+    /// 32 random bytes, base64url encoded
+    Random,
+    /// Value printed by the command given after `--`
+    Command,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    /// Ansible-vault encrypted YAML vars file
+    AnsibleVault,
+    /// `.env` file of KEY='value' lines, keys named as for `exec`
+    Dotenv,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RegistryCommands {
+    /// Print `export` lines for the tool's token variable
+    Env {
+        #[command(flatten)]
+        target: RegistryTarget,
+    },
+    /// Run a command with the token injected (npm also gets a temporary npmrc)
+    Exec {
+        #[command(flatten)]
+        target: RegistryTarget,
+        /// Command and arguments, after `--`
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct RegistryTarget {
+    #[arg(long, value_enum)]
+    tool: RegistryTool,
+    /// Secret holding the publish token
+    #[arg(short, long)]
+    secret: String,
+    /// cargo: registry name from .cargo/config.toml; npm: registry URL
+    #[arg(long)]
+    registry: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum RegistryTool {
+    Cargo,
+    Npm,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum DockerAction {
+    Get,
+    Store,
+    Erase,
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IntegrationCommands {
+    /// chezmoi config and template example using `template-func`
+    Chezmoi,
+    /// `~/.aws/config` profile using `aws-credential-process`
+    Aws {
+        /// Secret holding the access key
+        name: String,
+        /// Profile name to print
+        #[arg(long, default_value = "default")]
+        profile: String,
+    },
+    /// kubeconfig user entry using `kubectl-auth`
+    Kubectl {
+        /// Secret holding the token or client certificate
+        name: String,
+        /// kubeconfig user name to print
+        #[arg(long, default_value = "devinventory")]
+        user: String,
+    },
+}
+
+#[derive(Tabled)]
+struct PromoteRow {
+    status: String,
+    source: String,
+    target: String,
+}
+
+#[derive(Tabled)]
+struct VaultDiffRow {
+    status: String,
+    name: String,
+    #[tabled(rename = "this vault")]
+    here: String,
+    #[tabled(rename = "other vault")]
+    there: String,
+}
+
+#[derive(Tabled)]
+struct ConflictRow {
+    field: &'static str,
+    existing: String,
+    incoming: String,
+}
+
+/// Both versions of a conflicting name side by side, values as fingerprints.
+fn conflict_table(conflict: &Conflict, time: TimeStyle) -> Table {
+    let (existing, incoming) = (&conflict.existing, &conflict.incoming);
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    let rows = [
+        ("kind", text(&existing.kind), text(&incoming.kind)),
+        ("owner", text(&existing.owner), text(&incoming.owner)),
+        ("note", text(&existing.note), text(&incoming.note)),
+        (
+            "updated",
+            time.show(existing.updated_at),
+            conflict
+                .incoming_updated
+                .map(|at| time.show(at))
+                .unwrap_or_default(),
+        ),
+        (
+            "origin",
+            existing
+                .provenance
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            incoming.provenance.to_string(),
+        ),
+        (
+            "value",
+            format!(
+                "{} ({})",
+                conflict.existing_fingerprint,
+                human_bytes(conflict.existing_size as i64)
+            ),
+            format!(
+                "{} ({})",
+                conflict.incoming_fingerprint,
+                human_bytes(conflict.incoming_size as i64)
+            ),
+        ),
+    ];
+    let mut table = Table::new(rows.map(|(field, existing, incoming)| ConflictRow {
+        field,
+        existing,
+        incoming,
+    }));
+    table.with(Style::rounded());
+    table
+}
+
+/// Show `conflict` and ask what to do with it. Keeping both stores the incoming
+/// version under a new name, `<name>-<suffix>` unless another is typed; names in
+/// `taken` or already in `vault` are refused.
+async fn resolve_conflict(
+    vault: &SecretService,
+    conflict: &Conflict,
+    suffix: &str,
+    taken: &BTreeSet<String>,
+    time: TimeStyle,
+) -> Result<Resolution> {
+    println!("⚠️  '{}' differs", conflict.name);
+    println!("{}", conflict_table(conflict, time));
+    loop {
+        let answer = prompt::line("Keep [e]xisting, take [i]ncoming or keep [b]oth? [E/i/b] ")?;
+        match answer.to_ascii_lowercase().as_str() {
+            "" | "e" => return Ok(Resolution::KeepExisting),
+            "i" => return Ok(Resolution::TakeIncoming),
+            "b" => {}
+            _ => continue,
+        }
+        let default = format!("{}-{suffix}", conflict.name);
+        let name = prompt::line(&format!("Name for the incoming version [{default}]: "))?;
+        let name = if name.is_empty() { default } else { name };
+        let in_vault = vault.secret_names(&name, Some(1)).await?.first() == Some(&name);
+        if in_vault || taken.contains(&name) {
+            println!("'{name}' is already taken");
+            continue;
+        }
+        return Ok(Resolution::KeepBoth(name));
+    }
+}
+
+fn vault_diff_table(diff: &[VaultDiffItem]) -> Table {
+    let when =
+        |at: Option<chrono::DateTime<chrono::Utc>>| at.map(relative_time).unwrap_or_default();
+    let rows = diff.iter().map(|item| {
+        let status = match (item.status, item.newer_here()) {
+            (VaultDiffStatus::Added, _) => "+ only here",
+            (VaultDiffStatus::Removed, _) => "- only there",
+            (VaultDiffStatus::Changed, true) => "~ newer here",
+            (VaultDiffStatus::Changed, false) => "~ newer there",
+        };
+        VaultDiffRow {
+            status: if item.host_bound {
+                format!("{status} (host-bound)")
+            } else {
+                status.to_string()
+            },
+            name: item.name.clone(),
+            here: when(item.here),
+            there: when(item.there),
+        }
+    });
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    table
+}
+
+/// Open the `[vaults.NAME]` profile as a vault of its own, with its own key.
+async fn open_profile_vault(
+    name: &str,
+    dmk: Option<String>,
+    config: &Config,
+) -> Result<SecretService> {
+    let overrides = ConfigOverrides {
+        vault: Some(name.to_string()),
+        ..Default::default()
+    };
+    let source = MasterKeySource {
+        base64_inline: dmk,
+        key_file: None,
+        allow_keyring: true,
+    };
+    let target = Config::build(overrides, source)?;
+    if !target.db_path.is_file() {
+        bail!(
+            "vault '{name}' has no file at {}; run `devinventory --vault {name} init` first",
+            target.db_path.to_string_lossy()
+        );
+    }
+    if !config.ephemeral
+        && std::fs::canonicalize(&target.db_path).ok()
+            == std::fs::canonicalize(&config.db_path).ok()
+    {
+        bail!("vault '{name}' is this vault");
+    }
+    let repo = Repository::connect(&target.db_path).await?;
+    repo.migrate().await?;
+    let key_provider = MasterKeyProvider::new(
+        target.master_key_source.clone(),
+        target.keyring_service.clone(),
+        target.keyring_account.clone(),
+    )
+    .with_index(
+        Config::keyring_index_path()?,
+        std::path::absolute(&target.db_path)?,
+    )
+    .with_yubikey(target.file.yubikey.device())
+    .with_pkcs11(target.file.pkcs11.token())
+    .with_prompter(Arc::new(prompt::Terminal));
+    Ok(SecretService::new(repo, key_provider)
+        .with_protected_prefixes(target.file.approval.protected_prefixes.clone())
+        .with_reencrypt_on_read(target.file.crypto.reencrypt_on_read)
+        .with_reveal_limit(target.file.reveal.max_per_hour)
+        .with_value_limits(
+            target.file.limits.warn_value_bytes(),
+            target.file.limits.max_value_bytes(),
+        ))
+}
+
+/// Open another vault file with this vault's keys and settings.
+async fn open_other_vault(
+    path: &Path,
+    config: &Config,
+    service: &SecretService,
+) -> Result<SecretService> {
+    if !path.is_file() {
+        bail!("no vault at {}", path.to_string_lossy());
+    }
+    if !config.ephemeral
+        && std::fs::canonicalize(path).ok() == std::fs::canonicalize(&config.db_path).ok()
+    {
+        bail!("{} is this vault", path.to_string_lossy());
+    }
+    let repo = Repository::connect(path).await?;
+    repo.migrate().await?;
+    Ok(service.with_repository(repo))
+}
+
+#[derive(Tabled)]
+struct ExecEnvRow {
+    status: &'static str,
+    variable: String,
+    secret: String,
+    value: String,
+}
+
+/// `value` as an environment variable's value, byte for byte. NUL cannot be in one,
+/// and off Unix neither can anything but text.
+fn env_value(name: &str, value: &[u8]) -> Result<OsString> {
+    if value.contains(&0) {
+        bail!("'{name}' holds a NUL byte, which cannot go into an environment variable");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(OsString::from_vec(value.to_vec()))
+    }
+    #[cfg(not(unix))]
+    match std::str::from_utf8(value) {
+        Ok(text) => Ok(text.into()),
+        Err(_) => bail!(
+            "'{name}' holds binary data, which this platform cannot put in an environment variable"
+        ),
+    }
+}
+
+/// What `exec` does to each variable compared with the environment `current` reads.
+fn exec_env_table(
+    names: &[String],
+    vars: &[String],
+    resolved: &[Secret],
+    current: impl Fn(&str) -> Option<OsString>,
+) -> Table {
+    let rows = names
+        .iter()
+        .zip(vars)
+        .zip(resolved)
+        .map(|((name, var), secret)| {
+            let status = match current(var) {
+                None => "+ set",
+                Some(current) if current.as_encoded_bytes() == secret.plaintext => "= same",
+                Some(_) => "~ override",
+            };
+            ExecEnvRow {
+                status,
+                variable: var.clone(),
+                secret: name.clone(),
+                value: mask(&secret.plaintext),
+            }
+        });
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    table
+}
+
+#[derive(Tabled)]
+struct DueRow {
+    name: String,
+    kind: String,
+    expires_at: String,
+    status: String,
+}
+
+/// Secrets from `expiring_within` with their expiry and how far off it is.
+fn due_table(due: Vec<SecretMetadata>) -> Table {
+    let now = chrono::Utc::now();
+    let rows: Vec<DueRow> = due
+        .into_iter()
+        .filter_map(|meta| {
+            let expires_at = meta.expires_at?;
+            let days = (expires_at - now).num_days();
+            Some(DueRow {
+                name: meta.name,
+                kind: meta.kind.unwrap_or_default(),
+                expires_at: expires_at.to_rfc3339(),
+                status: if expires_at <= now {
+                    "expired".to_string()
+                } else {
+                    format!("in {days}d")
+                },
+            })
+        })
+        .collect();
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    table
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HookCommands {
+    /// Install a hook that runs `scan --staged` before each commit
+    Install {
+        #[arg(value_enum)]
+        target: HookTarget,
+        /// Replace an existing hook not written by devinventory
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum HookTarget {
+    /// git pre-commit hook in the current repository
+    Git,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BackupKeyCommands {
+    /// Write a new secret key (owner-only) and print the public key for `[backup] public_key`
+    Generate {
+        /// Where the secret key goes; keep it offline, away from the vault
+        #[arg(long)]
+        out: PathBuf,
+        /// Replace an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Decrypt a sealed export or backup with the secret key
+    Open {
+        file: PathBuf,
+        /// Secret key file written by `backup-key generate`
+        #[arg(long)]
+        key: PathBuf,
+        /// Write to this file (owner-only) instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DeviceCommands {
+    /// Generate a keypair for this machine and wrap the master key for it
+    Enroll {
+        /// Human-readable device name (e.g. work-laptop)
+        label: String,
+    },
+    /// List enrolled devices
+    List,
+    /// Revoke a device by id or label; its key no longer unlocks the vault
+    Revoke { device: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum OplogCommands {
+    /// Show this replica's id, vector clock and log size
+    Status,
+    /// Write the operation log as JSON lines
+    Export { out: PathBuf },
+    /// Merge another replica's exported log into this vault
+    Merge { file: PathBuf },
+}
+
+/// A secret given by name or, with `--id`, by the id it keeps across renames.
+#[derive(Args, Debug)]
+pub struct SecretTarget {
+    #[arg(required_unless_present = "id")]
+    name: Option<String>,
+    /// Address the secret by id (`list --columns id,name`) instead of name
+    #[arg(long, conflicts_with = "name")]
+    id: Option<Uuid>,
+}
+
+impl From<SecretTarget> for SecretRef {
+    fn from(target: SecretTarget) -> Self {
+        match (target.id, target.name) {
+            (Some(id), _) => SecretRef::Id(id),
+            (None, name) => SecretRef::Name(name.unwrap_or_default()),
+        }
+    }
+}
+
+/// Like [`SecretTarget`], but picked from the vault with a fuzzy finder when neither
+/// is given on a terminal.
+#[derive(Args, Debug)]
+pub struct PickedTarget {
+    /// Picked from the vault when omitted on a terminal
+    name: Option<String>,
+    /// Address the secret by id (`list --columns id,name`) instead of name
+    #[arg(long, conflicts_with = "name")]
+    id: Option<Uuid>,
+}
+
+impl PickedTarget {
+    async fn resolve(self, service: &SecretService) -> Result<String> {
+        match (self.id, self.name) {
+            (Some(id), _) => service.resolve(id).await,
+            (None, Some(name)) => Ok(name),
+            (None, None) => pick_secret(service).await,
+        }
+    }
+}
+
+/// A secret chosen with the fuzzy picker over every name in the vault.
+async fn pick_secret(service: &SecretService) -> Result<String> {
+    if !picker::available() {
+        bail!("give a secret name (no terminal to pick one on)");
+    }
+    let secrets = service.list_secrets().await?;
+    if secrets.is_empty() {
+        bail!("the vault holds no secrets yet");
+    }
+    picker::pick("Secret: ", &secrets)?.ok_or_else(|| anyhow!("aborted"))
+}
+
+#[derive(Tabled)]
+struct AuditRow {
+    at: String,
+    action: String,
+    secret: String,
+    host: String,
+    user: String,
+    detail: String,
+}
+
+/// `get` as JSON or YAML: the metadata, tags and either the value or its mask.
+#[derive(Serialize)]
+struct SecretOutput {
+    #[serde(flatten)]
+    metadata: SecretMetadata,
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    masked: Option<String>,
+}
+
+#[derive(Tabled)]
+struct SizeRow {
+    name: String,
+    value: String,
+    history: String,
+    share: String,
+}
+
+#[derive(Tabled)]
+struct SlotRow {
+    slot: i64,
+    kind: &'static str,
+    label: String,
+    added: String,
+}
+
+#[derive(Tabled)]
+struct DeviceRow {
+    id: String,
+    label: String,
+    enrolled: String,
+    status: String,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Print the config file location
+    Path,
+    /// Print an example config file
+    Example,
+    /// Bundle config, vault metadata and key-source description (never the key) into one file
+    Export { out: PathBuf },
+    /// Install a snapshot from `config export`, then optionally store the master key
+    Import {
+        file: PathBuf,
+        /// Replace an existing config file
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
+pub async fn run_cli(
+    command: Commands,
+    output: Output,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let Output { format, time } = output;
+    match command {
+        Commands::Init(args) => run_init(args, config, service).await?,
+        Commands::Config { action } => run_config(action, config, service).await?,
+        Commands::Stats { large, top, oldest } => {
+            run_stats(large, top, oldest, format, service).await?
+        }
+        Commands::Key { action } => run_key(action, time, config, service).await?,
+        Commands::Backup {
+            out,
+            passphrase,
+            force,
+        } => run_backup(out, passphrase, force, config, service).await?,
+        Commands::Salvage {
+            out,
+            manifest,
+            passphrase,
+            force,
+        } => run_salvage(out, manifest, passphrase, force, config, service).await?,
+        Commands::BackupKey { action } => run_backup_key(action).await?,
+        Commands::Device { action } => run_device(action, service).await?,
+        Commands::Oplog { action } => run_oplog(action, service).await?,
+        Commands::Doctor => run_doctor(config, service).await?,
+        Commands::Add(args) => run_add(args, config, service).await?,
+        Commands::Generate(args) => run_generate(args, service).await?,
+        Commands::Edit { target } => run_edit(target, service).await?,
+        Commands::Get(args) => run_get(args, format, config, service).await?,
+        Commands::Copy { target, duration } => run_copy(target, duration, config, service).await?,
+        Commands::Reveal { target, duration } => run_reveal(target, duration, service).await?,
+        Commands::Totp { target } => run_totp(target, service).await?,
+        Commands::Exec {
+            secrets,
+            diff,
+            command,
+        } => run_exec(secrets, diff, command, service).await?,
+        Commands::Env { secrets } => run_env(secrets, service).await?,
+        Commands::Map { action } => run_map(action, service).await?,
+        Commands::Export(args) => run_export(args, config, service).await?,
+        Commands::Explain { topic } => print!("{}", explain::render(topic)),
+        Commands::Render { template, out } => run_render(template, out, service).await?,
+        Commands::TemplateFunc { name } => run_template_func(name, service).await?,
+        Commands::AwsCredentialProcess { name } => {
+            run_aws_credential_process(name, service).await?
+        }
+        Commands::KubectlAuth { name } => run_kubectl_auth(name, service).await?,
+        Commands::DockerCredentialHelper { action } => {
+            run_docker_credential_helper(action, service).await?
+        }
+        Commands::Curl {
+            name,
+            scheme,
+            header_template,
+            args,
+        } => run_curl(name, scheme, header_template, args, service).await?,
+        Commands::Registry { action } => run_registry(action, service).await?,
+        Commands::Integrations { tool } => run_integrations(tool).await?,
+        Commands::Drift {
+            target,
+            prefix,
+            tag,
+        } => run_drift(target, prefix, tag, service).await?,
+        Commands::Scan { staged, files } => run_scan(staged, files, service).await?,
+        Commands::Hooks { action } => run_hooks(action).await?,
+        Commands::Check { within } => run_check(within, service).await?,
+        Commands::Lease { action } => run_lease(action, service).await?,
+        Commands::Approve { request_id } => run_approve(request_id, service).await?,
+        Commands::Transfer(args) => run_transfer(args, config, service).await?,
+        Commands::Subvault { action } => run_subvault(action, config, service).await?,
+        Commands::Diff { other_db } => run_diff(other_db, config, service).await?,
+        Commands::Sync(args) => run_sync(args, time, config, service).await?,
+        Commands::Promote {
+            from,
+            to,
+            only,
+            rotate,
+            dry_run,
+            yes,
+        } => run_promote(from, to, only, rotate, dry_run, yes, service).await?,
+        Commands::Import(args) => run_import(args, time, service).await?,
+        Commands::Audit { action } => run_audit(action, config, service).await?,
+        Commands::Timeline { days } => run_timeline(days, service).await?,
+        Commands::Completions { shell } => print_completions(shell)?,
+        Commands::CompleteNames { prefix } => run_complete_names(prefix, config, service).await?,
+        Commands::Names {
+            prefix,
+            format,
+            limit,
+        } => run_names(prefix, format, limit, config, service).await?,
+        Commands::List(args) => run_list(args, format, time, config, service).await?,
+        Commands::Tag { action } => run_tag(action, format, service).await?,
+        Commands::Set(args) => run_set(args, service).await?,
+        Commands::Search { query } => run_search(query, format, time, config, service).await?,
+        Commands::Rename { old, new, prefix } => run_rename(old, new, prefix, service).await?,
+        Commands::Rm {
+            name,
+            id,
+            prefix,
+            yes,
+        } => run_rm(name, id, prefix, yes, service).await?,
+        Commands::Trash => run_trash(service).await?,
+        Commands::Restore {
+            name,
+            backup,
+            strategy,
+            backup_key,
+            yes,
+        } => run_restore(name, backup, strategy, backup_key, yes, config, service).await?,
+        Commands::Purge { name, yes, .. } => run_purge(name, yes, service).await?,
+        Commands::Lock { target, reason } => run_lock(target, reason, service).await?,
+        Commands::Unlock { target } => run_unlock(target, service).await?,
+        Commands::ClearImmutable { target } => run_clear_immutable(target, service).await?,
+        Commands::Rotate {
+            lazy,
+            passphrase,
+            yubikey,
+            pkcs11,
+            cipher,
+        } => run_rotate(lazy, passphrase, yubikey, pkcs11, cipher, service).await?,
+        Commands::RotateSecret(args) => run_rotate_secret(args, service).await?,
+        Commands::Agent { socket, action } => {
+            run_agent_command(socket, action, config, service).await?
+        }
+        Commands::Attest { out, action } => run_attest(out, action, service).await?,
+        Commands::Report { action } => run_report(action, service).await?,
+    }
+
+    Ok(())
+}
+
+async fn run_init(args: InitArgs, config: &Config, service: &SecretService) -> Result<()> {
+    let InitArgs {
+        passphrase,
+        yubikey,
+        pkcs11,
+        cipher,
+        template,
+        prefix,
+        ..
+    } = args;
+    // a typo in the template name should fail before anything is created
+    let blueprint = template
+        .as_deref()
+        .map(|name| blueprint::load(name, &Config::templates_dir()?))
+        .transpose()?;
+    match key_origin(passphrase, yubikey, pkcs11) {
+        Some(origin) => service.init_with(origin).await?,
+        None => service.init_master_key().await?,
+    }
+    if let Some(cipher) = cipher {
+        service.set_cipher(cipher).await?;
+    }
+    println!("✅ master key initialized");
+    if config.ephemeral {
+        println!("   vault:   in memory (ephemeral)");
+    } else {
+        perms::make_owner_only(&config.db_path)?;
+        println!("   vault:   {}", config.db_path.to_string_lossy());
+    }
+    if service.passphrase_kdf().await?.is_some() {
+        println!("   key:     wrapped under the vault passphrase (Argon2id)");
+    } else if let Some(challenge) = service.yubikey_challenge().await? {
+        println!(
+            "   key:     derived from the YubiKey (slot {}, HMAC-SHA1 challenge-response)",
+            challenge.slot
+        );
+    } else if let Some(wrapped) = service.pkcs11_key().await? {
+        println!(
+            "   key:     wrapped by the PKCS#11 token key {} ({})",
+            wrapped
+                .wrapping
+                .key_id
+                .map(|id| format!("id {id}"))
+                .or(wrapped.wrapping.key_label.map(|l| format!("'{l}'")))
+                .unwrap_or_default(),
+            wrapped.wrapping.mechanism
+        );
+    } else if let Some(path) = &config.master_key_source.key_file {
+        println!("   key:     {}", path.to_string_lossy());
+    } else {
+        println!(
+            "   keyring: service '{}' account '{}'",
+            config.keyring_service, config.keyring_account
+        );
+    }
+    println!("   cipher:  {}", service.cipher().await?);
+    if let (Some(name), Some(blueprint)) = (template, blueprint) {
+        let prefix = prefix.unwrap_or_default();
+        let summary = service.apply_template(&name, &blueprint, &prefix).await?;
+        println!(
+            "🧩 template {name}: {} created, {} already present",
+            summary.created.len(),
+            summary.skipped.len()
+        );
+        for (created, bits) in &summary.created {
+            println!("   + {created} ({bits:.0} bits)");
+        }
+        for skipped in &summary.skipped {
+            println!("   = {skipped} (kept)");
+        }
+    }
+    Ok(())
+}
+
+async fn run_config(
+    action: ConfigCommands,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    match action {
+        ConfigCommands::Path => {
+            println!("{}", Config::config_file_path()?.to_string_lossy());
+        }
+        ConfigCommands::Example => {
+            print!("{}", Config::generate_example_config());
+        }
+        ConfigCommands::Export { out } => {
+            let secrets = service.list_secrets().await?;
+            let snapshot = ConfigSnapshot {
+                format_version: SNAPSHOT_FORMAT_VERSION,
+                exported_at: chrono::Utc::now(),
+                config: config.file.clone(),
+                vault: VaultSummary {
+                    path: config.db_path.to_string_lossy().into_owned(),
+                    secret_count: secrets.len(),
+                    last_updated: secrets.iter().map(|s| s.updated_at).max(),
+                },
+                key_source: config.key_source_description(),
+            };
+            std::fs::write(&out, serde_json::to_string_pretty(&snapshot)?)?;
+            perms::make_owner_only(&out)?;
+            info!("config snapshot written to {}", out.to_string_lossy());
+            println!("📦 config snapshot written: {}", out.to_string_lossy());
+        }
+        ConfigCommands::Import { file, force } => {
+            let snapshot = ConfigSnapshot::read(&file)?;
+            let config_path = Config::config_file_path()?;
+            snapshot.install_config(&config_path, force)?;
+            println!("✅ config written: {}", config_path.to_string_lossy());
+
+            let vault_path = match &snapshot.config.database.path {
+                Some(p) => PathBuf::from(p),
+                None => Config::default_db_path()?,
+            };
+            println!(
+                "   vault: {} ({} secrets on the exporting machine)",
+                vault_path.to_string_lossy(),
+                snapshot.vault.secret_count
+            );
+            if !vault_path.exists() {
+                println!(
+                    "   copy the vault file from {} to this path",
+                    snapshot.vault.path
+                );
+            }
+
+            let key_source = &snapshot.key_source;
+            if key_source.mode == "keyring" && !config.master_key_source.allow_keyring {
+                println!("   keyring disabled (--no-keyring); provide the key with --dmk");
+            } else if key_source.mode == "keyring" {
+                let key = prompt::secret("Master key (base64, empty to skip): ")?;
+                if !key.trim().is_empty() {
+                    let provider = MasterKeyProvider::new(
+                        config.master_key_source.clone(),
+                        key_source.keyring_service.clone(),
+                        key_source.keyring_account.clone(),
+                    )
+                    .with_index(
+                        Config::keyring_index_path()?,
+                        std::path::absolute(&vault_path)?,
+                    );
+                    provider.store_in_keyring(&key)?;
+                    println!(
+                        "🔑 master key stored in keyring service '{}' account '{}'",
+                        key_source.keyring_service, key_source.keyring_account
+                    );
+                }
+            } else if key_source.mode == "key-file" {
+                println!("   key is read from a key file; provide it with --dmk-file");
+            } else {
+                println!("   key is managed manually; provide it with --dmk");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_stats(
+    large: bool,
+    top: usize,
+    oldest: usize,
+    format: OutputFormat,
+    service: &SecretService,
+) -> Result<()> {
+    let stats = service.stats(oldest).await?;
+    let largest = if large {
+        service.largest_secrets(top).await?
+    } else {
+        Vec::new()
+    };
+    let structured = serde_json::json!({
+        "secrets": stats.secrets,
+        "trashed": stats.trashed,
+        "ciphertext_bytes": stats.ciphertext_bytes,
+        "database_bytes": stats.database_bytes,
+        "by_kind": stats.by_kind.iter()
+            .map(|(kind, n)| serde_json::json!({"kind": kind, "secrets": n}))
+            .collect::<Vec<_>>(),
+        "by_tag": stats.by_tag.iter()
+            .map(|(tag, n)| serde_json::json!({"tag": tag, "secrets": n}))
+            .collect::<Vec<_>>(),
+        "oldest": stats.oldest.iter()
+            .map(|(name, at)| serde_json::json!({"name": name, "updated_at": at}))
+            .collect::<Vec<_>>(),
+        "largest": largest.iter()
+            .map(|s| serde_json::json!({
+                "name": s.name,
+                "value_bytes": s.ciphertext,
+                "history_bytes": s.history,
+            }))
+            .collect::<Vec<_>>(),
+    });
+    if format.print(&structured)? {
+        return Ok(());
+    }
+    let db_size = stats.database_bytes;
+    let counts = |counts: Vec<(String, i64)>| {
+        counts
+            .into_iter()
+            .map(|(name, n)| format!("{name} {n}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    println!("secrets:    {} ({} in trash)", stats.secrets, stats.trashed);
+    println!("ciphertext: {}", human_bytes(stats.ciphertext_bytes));
+    println!("database:   {}", human_bytes(db_size));
+    if !stats.by_kind.is_empty() {
+        let by_kind = stats
+            .by_kind
+            .into_iter()
+            .map(|(kind, n)| (kind.unwrap_or_else(|| "(none)".to_string()), n))
+            .collect();
+        println!("by kind:    {}", counts(by_kind));
+    }
+    if !stats.by_tag.is_empty() {
+        println!("by tag:     {}", counts(stats.by_tag));
+    }
+    if !stats.oldest.is_empty() {
+        println!("longest unchanged:");
+        for (name, updated_at) in &stats.oldest {
+            println!("  {name}  updated {}", relative_time(*updated_at));
+        }
+    }
+    if large {
+        let share = |bytes: i64| format!("{:.1}%", bytes as f64 * 100.0 / db_size.max(1) as f64);
+        let rows: Vec<SizeRow> = largest
+            .into_iter()
+            .map(|s| SizeRow {
+                share: share(s.ciphertext + s.history),
+                name: s.name,
+                value: human_bytes(s.ciphertext),
+                history: human_bytes(s.history),
+            })
+            .collect();
+        if !rows.is_empty() {
+            let mut table = Table::new(rows);
+            table.with(Style::rounded());
+            println!("{table}");
+            println!("history: copies kept in the operation log for sync");
+        }
+    }
+    Ok(())
+}
+
+async fn run_key(
+    action: KeyCommands,
+    time: TimeStyle,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    match action {
+        KeyCommands::Gc { yes } => {
+            if !config.master_key_source.allow_keyring {
+                bail!("key gc works on the OS keyring; drop --no-keyring");
+            }
+            let index_path = Config::keyring_index_path()?;
+            let mut index = keymgr::KeyringIndex::load(&index_path)?;
+            if index.entries.is_empty() {
+                println!(
+                    "no keyring entries recorded in {}",
+                    index_path.to_string_lossy()
+                );
+                return Ok(());
+            }
+            let mut orphans = Vec::new();
+            for (service, account) in index.orphans() {
+                if keymgr::keyring_entry_exists(&service, &account)? {
+                    orphans.push((service, account));
+                } else {
+                    // removed by hand or with its vault; nothing left to collect
+                    index.forget(&service, &account);
+                }
+            }
+            for entry in &index.entries {
+                let state = if entry.vault.exists() {
+                    "in use"
+                } else {
+                    "vault gone"
+                };
+                println!(
+                    "  {}/{}  {} ({state})",
+                    entry.service,
+                    entry.account,
+                    entry.vault.to_string_lossy()
+                );
+            }
+            if orphans.is_empty() {
+                index.save(&index_path)?;
+                println!("✅ no orphaned keyring entries");
+                return Ok(());
+            }
+            if !yes {
+                let answer = prompt::line(&format!(
+                    "Delete {} orphaned keyring entr{}? [y/N] ",
+                    orphans.len(),
+                    if orphans.len() == 1 { "y" } else { "ies" }
+                ))?;
+                if !answer.eq_ignore_ascii_case("y") {
+                    bail!("aborted");
+                }
+            }
+            for (service, account) in &orphans {
+                keymgr::delete_keyring_entry(service, account)?;
+                index.forget(service, account);
+                println!("🗑️  deleted {service}/{account}");
+            }
+            index.save(&index_path)?;
+        }
+        KeyCommands::ChangePassphrase => {
+            if service.passphrase_kdf().await?.is_none() {
+                bail!(
+                    "this vault's key does not come from a passphrase; see 'rotate --passphrase'"
+                );
+            }
+            // the current passphrase first, so a wrong one fails before asking for the new
+            service.ensure_master_key().await?;
+            let passphrase =
+                keymgr::ask_passphrase_twice(&prompt::Terminal, "New vault passphrase: ")?;
+            service.change_passphrase(&passphrase).await?;
+            println!("🔑 vault passphrase changed; no secret was re-encrypted");
+        }
+        KeyCommands::Slot { action } => match action {
+            SlotCommands::Add { kind, label } => {
+                let label = label.unwrap_or_else(|| kind.as_str().to_string());
+                let (slot, recovery) = service.add_key_slot(kind, &label).await?;
+                println!(
+                    "🔑 key slot {} added ({}: {})",
+                    slot.id,
+                    kind.as_str(),
+                    label
+                );
+                if let Some(recovery) = recovery {
+                    println!(
+                        "   recovery key, shown only this once; write it down and keep it offline:"
+                    );
+                    println!("\n   {recovery}\n");
+                }
+                if kind == SlotKind::Keyring {
+                    println!("   unlocks the vault on this machine while its keyring entry exists");
+                }
+            }
+            SlotCommands::Remove { id } => {
+                let slot = service.remove_key_slot(id).await?;
+                println!("🗑️  key slot {id} removed ({})", slot.label);
+            }
+            SlotCommands::List => {
+                let slots = service.list_key_slots().await?;
+                if slots.is_empty() {
+                    println!("no key slots; add one with `key slot add recovery`");
+                    return Ok(());
+                }
+                let rows: Vec<SlotRow> = slots
+                    .into_iter()
+                    .map(|s| SlotRow {
+                        slot: s.id,
+                        kind: s.kind.as_str(),
+                        label: s.label,
+                        added: time.show(s.created_at),
+                    })
+                    .collect();
+                let mut table = Table::new(rows);
+                table.with(Style::rounded());
+                println!("{}", table);
+            }
+        },
+    }
+    Ok(())
+}
+
+async fn run_backup(
+    out: PathBuf,
+    passphrase: bool,
+    force: bool,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    if out.exists() && !force {
+        bail!(
+            "{} already exists; pass --force to replace it",
+            out.to_string_lossy()
+        );
+    }
+    let recipient = config
+        .file
+        .backup
+        .public_key
+        .as_deref()
+        .map(backup::parse_public_key)
+        .transpose()?;
+    let passphrase = passphrase.then(new_backup_passphrase).transpose()?;
+    let (file, host_bound) = service.backup(passphrase.as_deref()).await?;
+    write_backup(&out, &file, recipient.as_ref())?;
+    info!(
+        "backed up {} secrets to {}",
+        file.secrets,
+        out.to_string_lossy()
+    );
+    println!(
+        "💾 backed up {} secrets: {}",
+        file.secrets,
+        out.to_string_lossy()
+    );
+    println!(
+        "   encrypted under {}{}",
+        if passphrase.is_some() {
+            "the backup passphrase"
+        } else {
+            "the master key"
+        },
+        if recipient.is_some() {
+            ", sealed to the backup key"
+        } else {
+            ""
+        }
+    );
+    if !host_bound.is_empty() {
+        println!(
+            "⚠️  {} host-bound secret(s) left out: {}",
+            host_bound.len(),
+            host_bound.join(", ")
+        );
+    }
+    Ok(())
+}
+
+async fn run_salvage(
+    out: PathBuf,
+    manifest: Option<PathBuf>,
+    passphrase: bool,
+    force: bool,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let manifest_path = manifest.unwrap_or_else(|| {
+        let mut path = out.clone().into_os_string();
+        path.push(".manifest.json");
+        PathBuf::from(path)
+    });
+    for path in [&out, &manifest_path] {
+        if path.exists() && !force {
+            bail!(
+                "{} already exists; pass --force to replace it",
+                path.to_string_lossy()
+            );
+        }
+    }
+    let recipient = config
+        .file
+        .backup
+        .public_key
+        .as_deref()
+        .map(backup::parse_public_key)
+        .transpose()?;
+    let passphrase = passphrase.then(new_backup_passphrase).transpose()?;
+    let (file, manifest) = service.salvage(passphrase.as_deref()).await?;
+    write_backup(&out, &file, recipient.as_ref())?;
+    std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+    perms::make_owner_only(&manifest_path)?;
+    println!(
+        "🛟 salvaged {} secret(s): {}",
+        file.secrets,
+        out.to_string_lossy()
+    );
+    if manifest.unrecoverable.is_empty() {
+        println!("   every secret was readable");
+    } else {
+        println!(
+            "⚠️  {} secret(s) left out, listed in {}:",
+            manifest.unrecoverable.len(),
+            manifest_path.to_string_lossy()
+        );
+        for lost in &manifest.unrecoverable {
+            println!("   - {}: {}", lost.name, lost.reason);
+        }
+    }
+    Ok(())
+}
+
+async fn run_backup_key(action: BackupKeyCommands) -> Result<()> {
+    match action {
+        BackupKeyCommands::Generate { out, force } => {
+            if out.exists() && !force {
+                bail!(
+                    "{} already exists; pass --force to replace it",
+                    out.to_string_lossy()
+                );
+            }
+            let keypair = backup::generate_keypair();
+            std::fs::write(&out, backup::secret_key_file(&keypair))?;
+            perms::make_owner_only(&out)?;
+            println!("🔑 backup secret key written: {}", out.to_string_lossy());
+            println!("   move it offline; exports sealed to it cannot be opened without it");
+            println!("   add to config.toml:\n\n[backup]");
+            println!("public_key = \"{}\"", backup::encode_key(&keypair.public));
+        }
+        BackupKeyCommands::Open { file, key, out } => {
+            let data = std::fs::read(&file)
+                .with_context(|| format!("reading {}", file.to_string_lossy()))?;
+            let plaintext = backup::open(&backup::read_secret_key(&key)?, &data)?;
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, &plaintext)?;
+                    perms::make_owner_only(&path)?;
+                    println!(
+                        "🔓 opened {}: {}",
+                        file.to_string_lossy(),
+                        path.to_string_lossy()
+                    );
+                }
+                None => io::stdout().write_all(&plaintext)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_device(action: DeviceCommands, service: &SecretService) -> Result<()> {
+    match action {
+        DeviceCommands::Enroll { label } => {
+            let device = service.enroll_device(&label).await?;
+            println!("💻 enrolled device '{}' ({})", device.label, device.id);
+            println!(
+                "   device key stored in keyring; revoke with `device revoke {}`",
+                device.label
+            );
+        }
+        DeviceCommands::List => {
+            let rows: Vec<DeviceRow> = service
+                .list_devices()
+                .await?
+                .into_iter()
+                .map(|d| DeviceRow {
+                    id: d.id.to_string(),
+                    label: d.label,
+                    enrolled: d.created_at.to_rfc3339(),
+                    status: match d.revoked_at {
+                        Some(at) => format!("revoked {}", at.to_rfc3339()),
+                        None => "active".into(),
+                    },
+                })
+                .collect();
+            let mut table = Table::new(rows);
+            table.with(Style::rounded());
+            println!("{}", table);
+        }
+        DeviceCommands::Revoke { device } => {
+            service.revoke_device(&device).await?;
+            println!("🚫 revoked device: {}", device);
+        }
+    }
+    Ok(())
+}
+
+async fn run_oplog(action: OplogCommands, service: &SecretService) -> Result<()> {
+    match action {
+        OplogCommands::Status => {
+            let replica = service.replica_id().await?;
+            let ops = service.operations().await?;
+            let clock = service.vector_clock().await?;
+            println!("replica:    {}", replica);
+            println!("operations: {}", ops.len());
+            println!("clock:      {}", serde_json::to_string(&clock)?);
+        }
+        OplogCommands::Export { out } => {
+            let count = write_oplog(service, &out).await?;
+            println!(
+                "📤 exported {} operations: {}",
+                count,
+                out.to_string_lossy()
+            );
+        }
+        OplogCommands::Merge { file } => {
+            let summary = service.merge_operations(read_oplog(&file)?).await?;
+            println!(
+                "🔀 merged {} of {} operations; {} updated, {} deleted",
+                summary.new_ops,
+                summary.received,
+                summary.updated.len(),
+                summary.deleted.len()
+            );
+            for name in summary.updated {
+                println!("   ~ {}", name);
+            }
+            for name in summary.deleted {
+                println!("   - {}", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_doctor(config: &Config, service: &SecretService) -> Result<()> {
+    let mut warnings = 0;
+    if !config.ephemeral {
+        if let Some(dir) = config.db_path.parent() {
+            warnings += check_private(dir, "vault directory", "700")?;
+        }
+        warnings += check_private(&config.db_path, "vault", "600")?;
+    }
+    let config_file = Config::config_file_path()?;
+    if config_file.exists() {
+        warnings += check_private(&config_file, "config file", "600")?;
+    }
+    if let Some(report) = service.last_integrity_check().await? {
+        let at = report.checked_at.with_timezone(&chrono::Local);
+        if report.failures.is_empty() {
+            println!(
+                "ℹ️  last integrity check ({}) verified {} record(s)",
+                at.format("%Y-%m-%d %H:%M"),
+                report.checked
+            );
+        } else {
+            warnings += 1;
+            println!(
+                "❌ integrity check at {} found damaged records; restore them from a backup:",
+                at.format("%Y-%m-%d %H:%M")
+            );
+            for failure in &report.failures {
+                println!("   - {failure}");
+            }
+            println!("   `salvage --out partial-export.dvi` saves everything still readable first");
+        }
+    }
+    let (stale, stale_trashed) = service.stale_secret_counts().await?;
+    if stale > 0 {
+        println!(
+            "ℹ️  {stale} secret(s) still use a retired master key version; run `rotate` to finish"
+        );
+    }
+    if stale_trashed > 0 {
+        println!(
+            "ℹ️  {stale_trashed} trashed secret(s) still use a retired master key version; `rotate` or `purge` clears them"
+        );
+    }
+    if warnings == 0 {
+        println!("✅ no problems found");
+    }
+    Ok(())
+}
+
+async fn run_add(args: AddArgs, config: &Config, service: &SecretService) -> Result<()> {
+    let AddArgs {
+        name,
+        kind,
+        note,
+        owner,
+        contact,
+        value,
+        file,
+        stdin,
+        immutable,
+        bind_host,
+        allow_exec,
+        tags,
+        expires,
+    } = args;
+    if value.is_some() {
+        if !config.file.security.value_flag_allowed() {
+            bail!(
+                "--value is turned off by [security] allow_value_flag; pipe the value \
+                 (--stdin), read it from a file (--file) or type it at the prompt"
+            );
+        }
+        eprintln!(
+            "⚠️  --value leaves the secret in your shell history and the process list; \
+             prefer --stdin, --file or the prompt"
+        );
+        let shell = std::env::var("SHELL")
+            .unwrap_or_else(|_| if cfg!(windows) { "powershell" } else { "" }.into());
+        eprintln!("   {}", history_scrub_hint(&shell));
+    }
+    let piped = value.is_none() && file.is_none() && !io::stdin().is_terminal();
+    let mut provenance = Provenance {
+        origin: Origin::Manual,
+        source: file.as_ref().map(|f| f.to_string_lossy().into_owned()),
+    };
+    let secret = match (value, file) {
+        (Some(v), _) => v.into_bytes(),
+        (None, Some(path)) => {
+            std::fs::read(&path).with_context(|| format!("reading {}", path.to_string_lossy()))?
+        }
+        (None, None) if stdin || piped => match read_stdin_value()? {
+            Some(value) => {
+                provenance.source = Some("stdin".to_string());
+                value
+            }
+            None if stdin => bail!("no value on stdin"),
+            // e.g. </dev/null from a launcher: ask as if nothing were piped
+            None => prompt::secret("Secret value: ")?.into_bytes(),
+        },
+        (None, None) => prompt::secret("Secret value: ")?.into_bytes(),
+    };
+    let attrs = SecretAttributes {
+        kind,
+        note,
+        owner,
+        contact,
+        immutable,
+        bind_host,
+        allowed_commands: allow_exec,
+        expires_at: expires,
+        provenance,
+        ..Default::default()
+    };
+    let saved = service.add_secret(&name, &secret, attrs).await?;
+    service.tag_secret(&name, &tags).await?;
+    if saved.immutable {
+        println!("🔒 saved (immutable): {}", saved.name);
+    } else {
+        println!("✅ saved: {}", saved.name);
+    }
+    if let Some(cert) = &saved.certificate {
+        println!(
+            "📜 {} expires {}",
+            cert.subject,
+            cert.not_after.to_rfc3339()
+        );
+    }
+    Ok(())
+}
+
+async fn run_generate(args: GenerateArgs, service: &SecretService) -> Result<()> {
+    let GenerateArgs {
+        name,
+        length,
+        charset,
+        words,
+        separator,
+        kind,
+        note,
+        tags,
+        force,
+        show,
+    } = args;
+    let mut attrs = match service.secret_metadata(&name).await? {
+        Some(_) if !force => {
+            bail!("'{name}' already exists; pass --force to replace its value")
+        }
+        // keep owner, expiry and the rest; only the value changes
+        Some(_) => service.get_secret(&name).await?.to_attributes(),
+        None => SecretAttributes::default(),
+    };
+    attrs.kind = kind.or(attrs.kind);
+    attrs.note = note.or(attrs.note);
+    attrs.provenance = Provenance {
+        origin: Origin::Generated,
+        source: None,
+    };
+    let (value, bits) = match words {
+        Some(words) => (
+            generator::passphrase(words, &separator)?,
+            generator::passphrase_bits(words),
+        ),
+        None => (generator::password(length, charset)?, charset.bits(length)),
+    };
+    service.add_secret(&name, value.as_bytes(), attrs).await?;
+    service.tag_secret(&name, &tags).await?;
+    println!("✅ generated {name} ({bits:.0} bits)");
+    if show {
+        println!("{value}");
+    }
+    Ok(())
+}
+
+async fn run_edit(target: PickedTarget, service: &SecretService) -> Result<()> {
+    let name = target.resolve(service).await?;
+    confirm_reveal(service, &name).await?;
+    let current = service.reveal_secret(&name).await?;
+    if current.immutable {
+        bail!("secret '{name}' is immutable; run `clear-immutable {name}` first");
+    }
+    let edited = editor::edit(&editor::editor_command(), &current.plaintext)?;
+    if edited == current.plaintext {
+        println!("no changes to {name}");
+        return Ok(());
+    }
+    service
+        .add_secret(&name, &edited, current.to_attributes())
+        .await?;
+    println!("✅ updated: {name}");
+    Ok(())
+}
+
+async fn run_get(
+    args: GetArgs,
+    format: OutputFormat,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let GetArgs {
+        target,
+        show,
+        inspect,
+        pipe,
+        copy,
+        out,
+        raw,
+    } = args;
+    let name = target.resolve(service).await?;
+    let show = show || !pipe.is_empty() || copy || out.is_some() || raw;
+    let secret = if show && !inspect {
+        confirm_reveal(service, &name).await?;
+        service.reveal_secret(&name).await
+    } else {
+        service.get_secret(&name).await
+    }
+    .inspect_err(salvage_hint)?;
+    if raw {
+        warn!("secret '{}' written to stdout as raw bytes", name);
+        return write_raw(&mut io::stdout().lock(), &pipe, &secret.plaintext);
+    }
+    if format != OutputFormat::Table && !inspect && !copy && out.is_none() {
+        let value = if show {
+            let value = transform::apply_all(&pipe, &secret.plaintext);
+            let value = String::from_utf8(value)
+                .map_err(|_| anyhow!("'{name}' holds binary data; use --pipe base64 or --raw"))?;
+            warn!("secret '{}' printed in plaintext", name);
+            Some(value)
+        } else {
+            None
+        };
+        format.print(&SecretOutput {
+            masked: value.is_none().then(|| mask(&secret.plaintext)),
+            value,
+            tags: service.secret_tags(&name).await?,
+            metadata: secret.to_metadata(),
+        })?;
+        return Ok(());
+    }
+    let copied = if copy {
+        let value = transform::apply_all(&pipe, &secret.plaintext);
+        let value = String::from_utf8_lossy(&value).into_owned();
+        Some((clipboard::copy(&value)?, value))
+    } else {
+        None
+    };
+    if inspect {
+        let token = String::from_utf8_lossy(&secret.plaintext);
+        let parsed = jwt::decode(&token).with_context(|| format!("inspecting '{name}'"))?;
+        println!("header: {}", serde_json::to_string_pretty(&parsed.header)?);
+        println!("claims: {}", serde_json::to_string_pretty(&parsed.claims)?);
+        for claim in ["iat", "nbf", "exp"] {
+            if let Some(at) = parsed.time_claim(claim) {
+                println!("{claim}: {} ({})", at.to_rfc3339(), relative_time(at));
+            }
+        }
+        match parsed.time_claim("exp") {
+            Some(exp) if exp <= chrono::Utc::now() => println!("❌ token expired"),
+            Some(_) => println!("✅ token not expired"),
+            None => println!("⚠️  token has no exp claim"),
+        }
+    } else if copied.is_some() {
+        println!("{name} => 📋 on the clipboard");
+    } else if let Some(path) = &out {
+        let value = transform::apply_all(&pipe, &secret.plaintext);
+        std::fs::write(path, &value)?;
+        perms::make_owner_only(path)?;
+        println!(
+            "{name} => 💾 {} written to {}",
+            human_bytes(value.len() as i64),
+            path.to_string_lossy()
+        );
+    } else if show {
+        let value = transform::apply_all(&pipe, &secret.plaintext);
+        if std::str::from_utf8(&value).is_err() {
+            if io::stdout().is_terminal() {
+                bail!("'{name}' holds binary data; use --out FILE or --pipe base64");
+            }
+            warn!("secret '{}' written to stdout as raw bytes", name);
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&value)?;
+            stdout.flush()?;
+            return Ok(());
+        }
+        warn!("secret '{}' printed in plaintext", name);
+        println!("{}", String::from_utf8_lossy(&value));
+    } else {
+        let masked = mask(&secret.plaintext);
+        println!("{} => {}", name, masked);
+    }
+    if let Some(owner) = &secret.owner {
+        match &secret.contact {
+            Some(contact) => println!("  owner:    {owner} ({contact})"),
+            None => println!("  owner:    {owner}"),
+        }
+    }
+    if let Some(provenance) = &secret.provenance {
+        println!("  origin:   {provenance}");
+    }
+    let tags = service.secret_tags(&name).await?;
+    if !tags.is_empty() {
+        println!("  tags:     {}", tags.join(", "));
+    }
+    if let Some(lock) = service.secret_lock(&name).await? {
+        let reason = lock.reason.map(|r| format!(": {r}")).unwrap_or_default();
+        println!(
+            "  locked:   by {} {}{reason}",
+            lock.locked_by,
+            relative_time(lock.locked_at)
+        );
+    }
+    if let (None, Some(at)) = (&secret.certificate, secret.expires_at) {
+        println!("  expires:  {} ({})", at.to_rfc3339(), relative_time(at));
+    }
+    for (key, value) in &secret.fields {
+        println!("  {key}: {value}");
+    }
+    if let Some(cert) = &secret.certificate {
+        println!("  subject:  {}", cert.subject);
+        if !cert.sans.is_empty() {
+            println!("  SANs:     {}", cert.sans.join(", "));
+        }
+        println!("  notAfter: {}", cert.not_after.to_rfc3339());
+    }
+    if let Some((handle, value)) = copied {
+        hold_clipboard(handle, &value, config.file.clipboard.clear_after()).await;
+    }
+    Ok(())
+}
+
+async fn run_copy(
+    target: SecretTarget,
+    duration: Option<Duration>,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let name = service.resolve(target).await?;
+    confirm_reveal(service, &name).await?;
+    let secret = service.reveal_secret(&name).await?;
+    let value = String::from_utf8_lossy(&secret.plaintext).into_owned();
+    let handle = clipboard::copy(&value)?;
+    println!("{name} => 📋 on the clipboard");
+    let duration = duration.unwrap_or_else(|| config.file.clipboard.clear_after());
+    hold_clipboard(handle, &value, duration).await;
+    Ok(())
+}
+
+async fn run_reveal(
+    target: SecretTarget,
+    duration: Duration,
+    service: &SecretService,
+) -> Result<()> {
+    let name = service.resolve(target).await?;
+    if !io::stdout().is_terminal() {
+        bail!("reveal needs a terminal; use `get --show` in scripts");
+    }
+    confirm_reveal(service, &name).await?;
+    let secret = service.reveal_secret(&name).await?;
+    let value = String::from_utf8_lossy(&secret.plaintext).into_owned();
+    warn!("secret '{}' revealed for {}s", name, duration.as_secs());
+    reveal_for(&name, &value, duration).await?;
+    clipboard::clear_if_holds(&value);
+    println!("🙈 {} hidden", name);
+    Ok(())
+}
+
+async fn run_totp(target: SecretTarget, service: &SecretService) -> Result<()> {
+    let name = service.resolve(target).await?;
+    let (code, remaining) = service.totp_code(&name).await?;
+    // the code alone on stdout, so `$(devinventory totp ...)` works
+    println!("{code}");
+    eprintln!("⏱️  valid for {remaining}s");
+    Ok(())
+}
+
+async fn run_exec(
+    secrets: Vec<String>,
+    diff: bool,
+    command: Vec<String>,
+    service: &SecretService,
+) -> Result<()> {
+    let (names, vars) = resolve_env_specs(service, &secrets).await?;
+    let program = host::find_program(&command[0])
+        .ok_or_else(|| anyhow!("command not found: {}", command[0]))?;
+    let resolved = service.secrets_for_exec(&names, &program).await?;
+    if diff {
+        eprintln!(
+            "{}",
+            exec_env_table(&names, &vars, &resolved, |var| std::env::var_os(var))
+        );
+    }
+    let mut child = std::process::Command::new(&program);
+    child.args(&command[1..]);
+    for ((var, name), secret) in vars.iter().zip(&names).zip(&resolved) {
+        child.env(var, env_value(name, &secret.plaintext)?);
+    }
+    info!(
+        "exec {} with {} secret(s)",
+        program.to_string_lossy(),
+        resolved.len()
+    );
+    drop(resolved);
+    let status = child.status()?;
+    std::process::exit(status.code().unwrap_or(1))
+}
+
+async fn run_env(secrets: Vec<String>, service: &SecretService) -> Result<()> {
+    let (names, vars) = resolve_env_specs(service, &secrets).await?;
+    let lines: Vec<String> = service
+        .get_all(&names)
+        .await?
+        .iter()
+        .zip(&vars)
+        .map(|(secret, var)| {
+            registry::shell_export(var, &String::from_utf8_lossy(&secret.plaintext))
+        })
+        .collect();
+    warn!("{} secret(s) printed for eval", lines.len());
+    for line in lines {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+async fn run_map(action: MapCommands, service: &SecretService) -> Result<()> {
+    match action {
+        MapCommands::Set { name, var } => {
+            service.set_env_mapping(&name, &var).await?;
+            println!("🔗 {name} -> ${var}");
+        }
+        MapCommands::Rm { name } => {
+            if service.remove_env_mapping(&name).await? {
+                println!("🔗 {name} -> ${} (derived)", env_var_name(&name));
+            } else {
+                println!("no mapping for {name}");
+            }
+        }
+        MapCommands::List => {
+            let mappings = service.env_mappings().await?;
+            if mappings.is_empty() {
+                println!("no mappings; variables are derived from secret names");
+            }
+            for (name, var) in mappings {
+                println!("{name} -> ${var}");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_export(args: ExportArgs, config: &Config, service: &SecretService) -> Result<()> {
+    let ExportArgs {
+        to,
+        prefix,
+        tag,
+        vault_password_file,
+        out,
+        seal,
+        age_recipients,
+    } = args;
+    let age_recipients = age_recipients
+        .iter()
+        .map(|r| age::Recipient::parse(r))
+        .collect::<Result<Vec<_>>>()?;
+    let recipient = if seal {
+        let key = config.file.backup.public_key.as_deref().ok_or_else(|| {
+            anyhow!("--seal needs [backup] public_key; create one with `backup-key generate`")
+        })?;
+        Some(backup::parse_public_key(key)?)
+    } else {
+        None
+    };
+    service.require_export_approval(&prefix).await?;
+    let vars: Vec<(String, String)> = service
+        .env_export(&prefix, tag.as_deref())
+        .await?
+        .into_iter()
+        .map(|(var, s)| (var, String::from_utf8_lossy(&s.plaintext).into_owned()))
+        .collect();
+    let count = vars.len();
+    let rendered = render_export(vars, format_as(&to)?, vault_password_file.as_deref())?;
+    let mut detail = format!("{count} secrets with prefix '{prefix}'");
+    if let Some(tag) = &tag {
+        detail.push_str(&format!(" tagged {tag}"));
+    }
+    detail.push_str(&format!(" as {to:?}"));
+    if seal {
+        detail.push_str(" sealed to the backup key");
+    }
+    if !age_recipients.is_empty() {
+        detail.push_str(&format!(
+            " encrypted to {}",
+            age_recipients
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    service
+        .audit(AuditAction::Export, None, Some(&detail))
+        .await?;
+    info!("exported {} secrets with prefix '{}'", count, prefix);
+    let rendered = match recipient {
+        Some(public) => backup::seal(&public, rendered.as_bytes())?,
+        None if !age_recipients.is_empty() => age::encrypt(&age_recipients, rendered.as_bytes())?,
+        None => rendered.into_bytes(),
+    };
+    match out {
+        Some(path) => {
+            std::fs::write(&path, rendered)?;
+            perms::make_owner_only(&path)?;
+            println!("📤 exported {} secrets: {}", count, path.to_string_lossy());
+        }
+        None => io::stdout().write_all(&rendered)?,
+    }
+    Ok(())
+}
+
+/// `get --raw`: the value after `pipe` and nothing else, not even a newline.
+fn write_raw(out: &mut impl Write, pipe: &[transform::Pipe], value: &[u8]) -> Result<()> {
+    out.write_all(&transform::apply_all(pipe, value))?;
+    out.flush()?;
+    Ok(())
+}
+
+/// The export file for `vars`, the same bytes for the same variables in any order.
+fn render_export(
+    mut vars: Vec<(String, String)>,
+    format: ExportFormat,
+    vault_password_file: Option<&Path>,
+) -> Result<String> {
+    // by variable, not secret name, so `map set` cannot reorder the file
+    vars.sort();
+    Ok(match format {
+        ExportFormat::AnsibleVault => {
+            let password_file = vault_password_file
+                .ok_or_else(|| anyhow!("--vault-password-file is required for ansible-vault"))?;
+            let password = std::fs::read_to_string(password_file)?;
+            let vars: Vec<(String, String)> = vars
+                .into_iter()
+                .map(|(var, value)| (var.to_lowercase(), value))
+                .collect();
+            export::ansible_vault_encrypt(
+                export::yaml_vars(&vars)?.as_bytes(),
+                password.trim_end().as_bytes(),
+            )?
+        }
+        ExportFormat::Dotenv => export::dotenv(&vars),
+    })
+}
+
+async fn run_render(
+    template: PathBuf,
+    out: Option<PathBuf>,
+    service: &SecretService,
+) -> Result<()> {
+    let text = std::fs::read_to_string(&template)?;
+    let secrets = service
+        .get_all_checked(&template::secret_names(&text)?, |secret| {
+            if !secret.allowed_commands.is_empty() {
+                Some("may only be passed to its allowed commands".to_string())
+            } else if std::str::from_utf8(&secret.plaintext).is_err() {
+                Some("is not text and cannot go into a template".to_string())
+            } else {
+                None
+            }
+        })
+        .await?;
+    let values: BTreeMap<String, String> = secrets
+        .into_iter()
+        .map(|s| (s.name, String::from_utf8_lossy(&s.plaintext).into_owned()))
+        .collect();
+    let rendered = template::render(&text, &values)?;
+    info!(
+        "rendered {} with {} secret(s)",
+        template.to_string_lossy(),
+        values.len()
+    );
+    match out {
+        Some(path) => {
+            std::fs::write(&path, rendered)?;
+            perms::make_owner_only(&path)?;
+            println!(
+                "📝 rendered {} secret(s) into {}",
+                values.len(),
+                path.to_string_lossy()
+            );
+        }
+        None => print!("{rendered}"),
+    }
+    Ok(())
+}
+
+async fn run_template_func(name: Option<String>, service: &SecretService) -> Result<()> {
+    let name = match name {
+        Some(name) => name,
+        None => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    if name.is_empty() || name.contains(['\r', '\n']) {
+        bail!("template-func expects a single secret name");
+    }
+    let Some(secret) = service.find_secret(&name).await? else {
+        eprintln!("secret not found: {name}");
+        std::process::exit(TEMPLATE_FUNC_NOT_FOUND);
+    };
+    debug!("template-func resolved '{}'", name);
+    let mut out = io::stdout();
+    out.write_all(&secret.plaintext)?;
+    out.flush()?;
+    Ok(())
+}
+
+async fn run_aws_credential_process(name: String, service: &SecretService) -> Result<()> {
+    let secret = service.get_secret(&name).await?;
+    let creds = aws::parse_credentials(
+        &String::from_utf8_lossy(&secret.plaintext),
+        secret.expires_at,
+    )
+    .with_context(|| format!("'{name}' is not an AWS access key"))?;
+    debug!("aws credential_process served '{}'", name);
+    println!("{}", serde_json::to_string(&creds)?);
+    Ok(())
+}
+
+async fn run_kubectl_auth(name: String, service: &SecretService) -> Result<()> {
+    let secret = service.get_secret(&name).await?;
+    let exec_info = std::env::var("KUBERNETES_EXEC_INFO").ok();
+    let credential = kube::exec_credential(
+        &String::from_utf8_lossy(&secret.plaintext),
+        kube::requested_api_version(exec_info.as_deref()),
+        secret.expires_at,
+    )
+    .with_context(|| format!("'{name}' is not a Kubernetes credential"))?;
+    debug!("kubectl exec credential served '{}'", name);
+    println!("{}", serde_json::to_string(&credential)?);
+    Ok(())
+}
+
+async fn run_docker_credential_helper(action: DockerAction, service: &SecretService) -> Result<()> {
+    let mut input = String::new();
+    if !matches!(action, DockerAction::List) {
+        io::stdin().read_to_string(&mut input)?;
+    }
+    match action {
+        DockerAction::Get => {
+            let name = docker::secret_name(&input);
+            let Some(secret) = service.find_secret(&name).await? else {
+                println!("{}", docker::NOT_FOUND);
+                std::process::exit(1);
+            };
+            let creds = docker::Credentials {
+                server_url: input.trim().to_string(),
+                username: secret.note.unwrap_or_default(),
+                secret: String::from_utf8_lossy(&secret.plaintext).into_owned(),
+            };
+            println!("{}", serde_json::to_string(&creds)?);
+        }
+        DockerAction::Store => {
+            let creds: docker::Credentials = serde_json::from_str(&input)
+                .context("docker credentials JSON expected on stdin")?;
+            let attrs = SecretAttributes {
+                kind: Some(docker::CREDENTIAL_KIND.to_string()),
+                note: Some(creds.username),
+                ..Default::default()
+            };
+            service
+                .add_secret(
+                    &docker::secret_name(&creds.server_url),
+                    creds.secret.as_bytes(),
+                    attrs,
+                )
+                .await?;
+        }
+        DockerAction::Erase => {
+            let name = docker::secret_name(&input);
+            if !service.delete_secret(&name).await? {
+                println!("{}", docker::NOT_FOUND);
+                std::process::exit(1);
+            }
+        }
+        DockerAction::List => {
+            let servers: serde_json::Map<String, serde_json::Value> = service
+                .list_secrets()
+                .await?
+                .into_iter()
+                .filter(|m| m.kind.as_deref() == Some(docker::CREDENTIAL_KIND))
+                .filter_map(|m| {
+                    let url = docker::server_url(&m.name)?.to_string();
+                    Some((url, m.note.unwrap_or_default().into()))
+                })
+                .collect();
+            println!("{}", serde_json::to_string(&servers)?);
+        }
+    }
+    Ok(())
+}
+
+async fn run_curl(
+    name: String,
+    scheme: curl::AuthScheme,
+    header_template: Option<String>,
+    args: Vec<String>,
+    service: &SecretService,
+) -> Result<()> {
+    let program = host::find_program("curl").ok_or_else(|| anyhow!("command not found: curl"))?;
+    let resolved = service
+        .secrets_for_exec(std::slice::from_ref(&name), &program)
+        .await?;
+    let header = curl::header_line(
+        scheme,
+        header_template.as_deref(),
+        &String::from_utf8_lossy(&resolved[0].plaintext),
+    )?;
+    drop(resolved);
+    let dir = tempfile::Builder::new()
+        .prefix("devinventory-curl")
+        .tempdir()?;
+    let header_file = dir.path().join("headers");
+    std::fs::write(&header_file, header + "\n")?;
+    perms::make_owner_only(&header_file)?;
+    info!("curl with header from '{}'", name);
+    let status = std::process::Command::new(&program)
+        .arg("-H")
+        .arg(format!("@{}", header_file.to_string_lossy()))
+        .args(&args)
+        .status()?;
+    drop(dir);
+    std::process::exit(status.code().unwrap_or(1))
+}
+
+async fn run_registry(action: RegistryCommands, service: &SecretService) -> Result<()> {
+    match action {
+        RegistryCommands::Env { target } => {
+            let token = service.get_secret(&target.secret).await?;
+            let token = String::from_utf8_lossy(&token.plaintext);
+            warn!("registry token '{}' printed for eval", target.secret);
+            match target.tool {
+                RegistryTool::Cargo => {
+                    let var = registry::cargo_token_var(target.registry.as_deref());
+                    println!("{}", registry::shell_export(&var, &token));
+                }
+                RegistryTool::Npm => {
+                    let url = target
+                        .registry
+                        .as_deref()
+                        .unwrap_or(registry::NPM_DEFAULT_REGISTRY);
+                    println!(
+                        "{}",
+                        registry::shell_export(registry::NPM_TOKEN_VAR, &token)
+                    );
+                    for line in registry::npmrc(url).lines() {
+                        println!("# npmrc: {line}");
+                    }
+                }
+            }
+        }
+        RegistryCommands::Exec { target, command } => {
+            let program = host::find_program(&command[0])
+                .ok_or_else(|| anyhow!("command not found: {}", command[0]))?;
+            let resolved = service
+                .secrets_for_exec(std::slice::from_ref(&target.secret), &program)
+                .await?;
+            let token = String::from_utf8_lossy(&resolved[0].plaintext).into_owned();
+            let mut child = std::process::Command::new(&program);
+            child.args(&command[1..]);
+            // holds the temporary npmrc until the child exits
+            let mut scratch = None;
+            match target.tool {
+                RegistryTool::Cargo => {
+                    child.env(
+                        registry::cargo_token_var(target.registry.as_deref()),
+                        &token,
+                    );
+                }
+                RegistryTool::Npm => {
+                    let url = target
+                        .registry
+                        .as_deref()
+                        .unwrap_or(registry::NPM_DEFAULT_REGISTRY);
+                    let dir = tempfile::Builder::new()
+                        .prefix("devinventory-npm")
+                        .tempdir()?;
+                    let npmrc = dir.path().join("npmrc");
+                    std::fs::write(&npmrc, registry::npmrc(url))?;
+                    perms::make_owner_only(&npmrc)?;
+                    child
+                        .env(registry::NPM_TOKEN_VAR, &token)
+                        .env("NPM_CONFIG_USERCONFIG", &npmrc);
+                    scratch = Some(dir);
+                }
+            }
+            info!(
+                "registry exec {} with token '{}'",
+                program.to_string_lossy(),
+                target.secret
+            );
+            drop((resolved, token));
+            let status = child.status()?;
+            drop(scratch);
+            std::process::exit(status.code().unwrap_or(1));
+        }
+    }
+    Ok(())
+}
+
+async fn run_integrations(tool: IntegrationCommands) -> Result<()> {
+    match tool {
+        IntegrationCommands::Chezmoi => print!("{CHEZMOI_SNIPPET}"),
+        IntegrationCommands::Aws { name, profile } => {
+            let section = if profile == "default" {
+                profile
+            } else {
+                format!("profile {profile}")
+            };
+            println!("# ~/.aws/config");
+            println!("[{section}]");
+            println!("credential_process = devinventory aws-credential-process {name}");
+        }
+        IntegrationCommands::Kubectl { name, user } => {
+            print!(
+                "# ~/.kube/config\nusers:\n- name: {user}\n  user:\n    exec:\n      apiVersion: {}\n      command: devinventory\n      args: [\"kubectl-auth\", \"{name}\"]\n      interactiveMode: Never\n",
+                kube::DEFAULT_API_VERSION
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn run_drift(
+    target: String,
+    prefix: String,
+    tag: Option<String>,
+    service: &SecretService,
+) -> Result<()> {
+    let deployed: BTreeMap<String, Vec<u8>> = match target.strip_prefix("k8s:") {
+        Some(secret) => {
+            let (namespace, name) = match secret.split_once('/') {
+                Some((namespace, name)) => (Some(namespace), name),
+                None => (None, secret),
+            };
+            kube::fetch_secret_data(namespace, name)?
+        }
+        None => export::parse_dotenv(&std::fs::read_to_string(&target)?)?
+            .into_iter()
+            .map(|(key, value)| (key, value.into_bytes()))
+            .collect(),
+    };
+    let vault: Vec<(String, String, Vec<u8>)> = service
+        .env_export(&prefix, tag.as_deref())
+        .await?
+        .into_iter()
+        .map(|(var, s)| (var, s.name, s.plaintext))
+        .collect();
+    let items = drift::compare(&deployed, &vault);
+    for item in &items {
+        let icon = match item.status {
+            DriftStatus::Current => "✅",
+            DriftStatus::Stale => "⚠️ ",
+            DriftStatus::Untracked => "❔",
+            DriftStatus::Missing => "➖",
+        };
+        let secret = item
+            .secret
+            .as_deref()
+            .map(|name| format!(" ({name})"))
+            .unwrap_or_default();
+        println!("{icon} {:<9} {}{secret}", item.status.as_str(), item.key);
+    }
+    let stale = items
+        .iter()
+        .filter(|i| i.status == DriftStatus::Stale)
+        .count();
+    info!("compared {} deployed key(s) with the vault", deployed.len());
+    if stale > 0 {
+        eprintln!("🚨 {stale} deployed credential(s) are out of date in {target}");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run_scan(staged: bool, files: Vec<PathBuf>, service: &SecretService) -> Result<()> {
+    let (root, contents) = if staged {
+        (scan::git_toplevel()?, scan::staged_files()?)
+    } else {
+        let root = scan::git_toplevel().or_else(|_| std::env::current_dir())?;
+        let mut contents = Vec::new();
+        for path in files {
+            let bytes = std::fs::read(&path)?;
+            contents.push((path.to_string_lossy().into_owned(), bytes));
+        }
+        (root, contents)
+    };
+    let allowed = scan::read_allow_list(&root)?;
+    let secrets: Vec<(String, Vec<u8>)> = service
+        .secrets_with_prefix("")
+        .await?
+        .into_iter()
+        .filter(|s| !allowed.contains(&s.name))
+        .map(|s| (s.name, s.plaintext))
+        .collect();
+    let mut findings = Vec::new();
+    for (path, bytes) in &contents {
+        for name in scan::find_leaks(bytes, &secrets) {
+            findings.push(scan::Finding {
+                path: path.clone(),
+                secret_name: name.to_string(),
+            });
+        }
+    }
+    info!(
+        "scanned {} file(s) against {} secret(s)",
+        contents.len(),
+        secrets.len()
+    );
+    if findings.is_empty() {
+        println!("✅ no vault values found in {} file(s)", contents.len());
+    } else {
+        for finding in &findings {
+            eprintln!(
+                "🚨 {} contains the value of '{}'",
+                finding.path, finding.secret_name
+            );
+        }
+        eprintln!(
+            "remove the values, or list intentional names in {}",
+            root.join(scan::ALLOW_FILE).to_string_lossy()
+        );
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run_hooks(action: HookCommands) -> Result<()> {
+    match action {
+        HookCommands::Install {
+            target: HookTarget::Git,
+            force,
+        } => {
+            let dir = scan::git_hooks_dir()?;
+            let path = dir.join("pre-commit");
+            if let Ok(existing) = std::fs::read_to_string(&path)
+                && !existing.contains(scan::HOOK_MARKER)
+                && !force
+            {
+                bail!(
+                    "{} already exists; pass --force to replace it",
+                    path.to_string_lossy()
+                );
+            }
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(&path, scan::pre_commit_hook())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+            }
+            println!("🪝 installed pre-commit hook: {}", path.to_string_lossy());
+        }
+    }
+    Ok(())
+}
+
+async fn run_check(within: Duration, service: &SecretService) -> Result<()> {
+    let window = chrono::Duration::from_std(within)?;
+    let due = service.expiring_within(window).await?;
+    if due.is_empty() {
+        println!("✅ nothing expires within {}d", window.num_days());
+    } else {
+        let count = due.len();
+        println!("{}", due_table(due));
+        println!("⏰ {count} secret(s) expire within {}d", window.num_days());
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run_lease(action: LeaseCommands, service: &SecretService) -> Result<()> {
+    match action {
+        LeaseCommands::Create { name, ttl } => {
+            let (lease, token) = service
+                .create_lease(&name, chrono::Duration::from_std(ttl)?)
+                .await?;
+            eprintln!(
+                "🎟️  lease {} on '{}' until {}; redeem once with `devinventory agent redeem`",
+                lease.id,
+                lease.secret_name,
+                lease.expires_at.to_rfc3339()
+            );
+            println!("{token}");
+        }
+        LeaseCommands::List => {
+            let leases = service.open_leases().await?;
+            if leases.is_empty() {
+                println!("no open leases");
+            }
+            for lease in leases {
+                println!(
+                    "{}  {}  by {}, expires {}",
+                    lease.id,
+                    lease.secret_name,
+                    lease.created_by,
+                    relative_time(lease.expires_at)
+                );
+            }
+        }
+        LeaseCommands::Revoke { id } => {
+            if service.revoke_lease(id).await? {
+                println!("🗑️  lease {id} revoked");
+            } else {
+                println!("no lease {id}");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_approve(request_id: Option<Uuid>, service: &SecretService) -> Result<()> {
+    match request_id {
+        Some(id) => {
+            let request = service.approve(id).await?;
+            println!(
+                "✅ approved {} of '{}' requested by {}; it runs on their next attempt",
+                request.action, request.target, request.requested_by
+            );
+        }
+        None => {
+            let pending = service.pending_approvals().await?;
+            if pending.is_empty() {
+                println!("no pending approval requests");
+            }
+            for request in pending {
+                let state = match &request.approved_by {
+                    Some(by) => format!("approved by {by}"),
+                    None => "pending".to_string(),
+                };
+                println!(
+                    "{}  {} '{}' by {} at {} ({state})",
+                    request.id,
+                    request.action,
+                    request.target,
+                    request.requested_by,
+                    request.created_at.to_rfc3339()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_transfer(args: TransferArgs, config: &Config, service: &SecretService) -> Result<()> {
+    let TransferArgs {
+        names,
+        all,
+        prefix,
+        to_vault,
+        to_dmk,
+        overwrite,
+        remove,
+    } = args;
+    let target = open_profile_vault(&to_vault, to_dmk, config).await?;
+    if let [name] = names.as_slice() {
+        service.transfer(name, &target, overwrite, remove).await?;
+        if remove {
+            println!("📦 moved '{name}' to vault '{to_vault}' (the original is in the trash)");
+        } else {
+            println!("📦 copied '{name}' to vault '{to_vault}'");
+        }
+        return Ok(());
+    }
+    let names = transfer_names(names, all, prefix, service).await?;
+    if names.is_empty() {
+        println!("no secrets to copy");
+        return Ok(());
+    }
+    let summary = service
+        .transfer_many(&names, &target, overwrite, remove)
+        .await?;
+    println!(
+        "📦 {} {} to vault '{to_vault}', {} skipped",
+        if remove { "moved" } else { "copied" },
+        summary.copied.len(),
+        summary.skipped.len()
+    );
+    for (name, reason) in &summary.skipped {
+        println!("   = {name} ({reason})");
+    }
+    if remove && !summary.copied.is_empty() {
+        println!("   the originals are in the trash");
+    }
+    Ok(())
+}
+
+/// The secrets `copy-to` works on: the named ones, or every one under `prefix`.
+async fn transfer_names(
+    names: Vec<String>,
+    all: bool,
+    prefix: Option<String>,
+    service: &SecretService,
+) -> Result<Vec<String>> {
+    if !all && prefix.is_none() {
+        return Ok(names);
+    }
+    let prefix = prefix.unwrap_or_default();
+    Ok(service
+        .list_secrets()
+        .await?
+        .into_iter()
+        .map(|s| s.name)
+        .filter(|name| name.starts_with(&prefix))
+        .collect())
+}
+
+async fn run_subvault(
+    action: SubvaultCommands,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let SubvaultCommands::Create {
+        prefix,
+        out,
+        new_key,
+        key,
+        key_out,
+        force,
+    } = action;
+    if out.exists() {
+        if !force {
+            bail!(
+                "{} already exists; pass --force to replace it",
+                out.to_string_lossy()
+            );
+        }
+        if std::fs::canonicalize(&out).ok() == std::fs::canonicalize(&config.db_path).ok() {
+            bail!("{} is this vault", out.to_string_lossy());
+        }
+        std::fs::remove_file(&out)
+            .with_context(|| format!("removing {}", out.to_string_lossy()))?;
+    }
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let generated = keymgr::generate_key();
+            if let Some(path) = &key_out {
+                keymgr::write_key_file(path, &generated)?;
+            }
+            keymgr::encode_key(&generated)
+        }
+    };
+    let repo = Repository::connect(&out).await?;
+    repo.migrate().await?;
+    let provider = MasterKeyProvider::new(
+        MasterKeySource {
+            base64_inline: Some(key.clone()),
+            key_file: None,
+            allow_keyring: false,
+        },
+        config.keyring_service.clone(),
+        config.keyring_account.clone(),
+    );
+    let target = SecretService::new(repo, provider);
+    let summary = match service.export_subvault(&prefix, &target).await {
+        Ok(summary) => summary,
+        Err(e) => {
+            drop(target);
+            let _ = std::fs::remove_file(&out);
+            return Err(e);
+        }
+    };
+    perms::make_owner_only(&out)?;
+    println!(
+        "📦 wrote {} secret(s) under '{prefix}' to {}, {} skipped",
+        summary.copied.len(),
+        out.to_string_lossy(),
+        summary.skipped.len()
+    );
+    for (name, reason) in &summary.skipped {
+        println!("   = {name} ({reason})");
+    }
+    let unlock = match (new_key, &key_out) {
+        (true, Some(path)) => {
+            println!("🔑 its master key is in {}", path.to_string_lossy());
+            format!("--dmk-file {}", path.to_string_lossy())
+        }
+        (true, None) => {
+            println!("🔑 its master key (shown once, hand it over separately):");
+            println!("{key}");
+            "--dmk KEY".to_string()
+        }
+        _ => "--dmk KEY".to_string(),
+    };
+    println!(
+        "   open it with `devinventory --db-path {} {unlock} list`",
+        out.to_string_lossy()
+    );
+    println!("   revoke it by rotating those secrets here: `rotate-secret --prefix {prefix}`");
+    Ok(())
+}
+
+async fn run_diff(other_db: PathBuf, config: &Config, service: &SecretService) -> Result<()> {
+    let other = open_other_vault(&other_db, config, service).await?;
+    let diff = service.diff_vault(&other).await?;
+    if diff.is_empty() {
+        println!("✅ both vaults hold the same secrets");
+    } else {
+        println!("{}", vault_diff_table(&diff));
+        println!("{} secret(s) differ", diff.len());
+    }
+    Ok(())
+}
+
+async fn run_sync(
+    args: SyncArgs,
+    time: TimeStyle,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let SyncArgs {
+        other_db,
+        push,
+        pull: _,
+        delete,
+        dry_run,
+        interactive,
+        yes,
+    } = args;
+    let other = open_other_vault(&other_db, config, service).await?;
+    let diff = service.diff_vault(&other).await?;
+    if diff.is_empty() {
+        println!("✅ both vaults hold the same secrets");
+        return Ok(());
+    }
+    println!("{}", vault_diff_table(&diff));
+    let (source, target) = if push {
+        (service, &other)
+    } else {
+        (&other, service)
+    };
+    let mut resolutions = BTreeMap::new();
+    if interactive && !dry_run {
+        let taken = diff.iter().map(|item| item.name.clone()).collect();
+        for conflict in source.sync_conflicts(target).await? {
+            let resolution = resolve_conflict(target, &conflict, "synced", &taken, time).await?;
+            resolutions.insert(conflict.name, resolution);
+        }
+    }
+    // pulling copies what is newer there, including what only exists there
+    let copies = diff
+        .iter()
+        .filter(|item| {
+            !item.host_bound
+                && match resolutions.get(&item.name) {
+                    Some(resolution) => *resolution != Resolution::KeepExisting,
+                    None => item.newer_here() == push,
+                }
+        })
+        .count();
+    let removals = diff
+        .iter()
+        .filter(|item| {
+            if push {
+                item.here.is_none()
+            } else {
+                item.there.is_none()
+            }
+        })
+        .count();
+    let direction = if push { "into" } else { "from" };
+    let mut plan = format!(
+        "{copies} secret(s) to copy {direction} {}",
+        other_db.to_string_lossy()
+    );
+    if delete {
+        plan.push_str(&format!(", {removals} to move to the target's trash"));
+    }
+    if dry_run {
+        println!("{plan}");
+        return Ok(());
+    }
+    if !yes {
+        let answer = prompt::line(&format!("Sync {plan}? [y/N] "))?;
+        if !answer.eq_ignore_ascii_case("y") {
+            bail!("aborted");
+        }
+    }
+    let summary = source.sync_into(target, delete, &resolutions).await?;
+    println!(
+        "🔁 copied {} secret(s){}",
+        summary.copied.len(),
+        if delete {
+            format!(", trashed {}", summary.trashed.len())
+        } else {
+            String::new()
+        }
+    );
+    for (name, reason) in &summary.skipped {
+        println!("⏭️  {name}: {reason}");
+    }
+    Ok(())
+}
+
+async fn run_promote(
+    from: String,
+    to: String,
+    only: Option<String>,
+    rotate: bool,
+    dry_run: bool,
+    yes: bool,
+    service: &SecretService,
+) -> Result<()> {
+    let plan = service.plan_promotion(&from, &to, only.as_deref()).await?;
+    if plan.is_empty() {
+        println!("nothing under '{from}' to promote");
+        return Ok(());
+    }
+    let rows: Vec<PromoteRow> = plan
+        .iter()
+        .map(|item| PromoteRow {
+            status: match item.status {
+                PromoteStatus::New => "+ new",
+                PromoteStatus::Changed => "~ changed",
+                PromoteStatus::Unchanged => "= unchanged",
+            }
+            .to_string(),
+            source: item.source.clone(),
+            target: item.target.clone(),
+        })
+        .collect();
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    println!("{table}");
+    let pending = plan
+        .iter()
+        .filter(|i| i.status != PromoteStatus::Unchanged)
+        .count();
+    if pending == 0 || dry_run {
+        println!("{pending} secret(s) would be copied");
+        return Ok(());
+    }
+    if !yes {
+        let answer = prompt::line(&format!("Promote {pending} secret(s) to '{to}'? [y/N] "))?;
+        if !answer.eq_ignore_ascii_case("y") {
+            bail!("aborted");
+        }
+    }
+    let copied = service.promote(&plan, rotate).await?;
+    println!("🚀 promoted {copied} secret(s) from '{from}' to '{to}'");
+    if rotate {
+        println!("🔄 promoted sources under '{from}' now hold fresh random values");
+    }
+    Ok(())
+}
+
+async fn run_import(args: ImportArgs, time: TimeStyle, service: &SecretService) -> Result<()> {
+    let ImportArgs {
+        from,
+        file,
+        service_prefix,
+        collection,
+        attributes,
+        pick,
+        prefix,
+        merge,
+        overwrite,
+        dry_run,
+        yes,
+    } = args;
+    let from = format_as::<ImportSource>(&from)?;
+    let prefix = prefix.unwrap_or_else(|| from.default_prefix().to_string());
+    let file_name = file.as_ref().map(|f| f.to_string_lossy().into_owned());
+    let read_file = |file: Option<PathBuf>| -> Result<String> {
+        let file = file.ok_or_else(|| {
+            anyhow!(
+                "--from {} needs --file",
+                from.to_possible_value()
+                    .map(|v| v.get_name().to_string())
+                    .unwrap_or_default()
+            )
+        })?;
+        std::fs::read_to_string(&file)
+            .with_context(|| format!("reading {}", file.to_string_lossy()))
+    };
+    let mut items = match from {
+        ImportSource::MacosKeychain => {
+            import::keychain::read_items(service_prefix.as_deref(), &prefix)?
+        }
+        ImportSource::Wincred => import::wincred::read_items(service_prefix.as_deref(), &prefix)?,
+        ImportSource::Libsecret => {
+            import::libsecret::read_items(collection.as_deref(), &attributes, &prefix)?
+        }
+        ImportSource::BrowserCsv => {
+            let text = read_file(file)?;
+            let parsed = import::browser::parse(&text, &prefix)?;
+            if parsed.duplicates > 0 {
+                println!(
+                    "🔁 {} duplicate login(s) in the export skipped",
+                    parsed.duplicates
+                );
+            }
+            parsed.items
+        }
+        ImportSource::Csv => import::table::parse_csv_file(&read_file(file)?, &prefix)?,
+        ImportSource::Json => import::table::parse_json_file(&read_file(file)?, &prefix)?,
+    };
+    if pick && !dry_run {
+        let mut picked = Vec::new();
+        for item in items {
+            let answer = prompt::line(&format!("Import {}? [y/N] ", item.name))?;
+            if answer.eq_ignore_ascii_case("y") {
+                picked.push(item);
+            }
+        }
+        items = picked;
+    }
+    if items.is_empty() {
+        println!("nothing to import");
+        return Ok(());
+    }
+    for item in &items {
+        println!("  + {}", item.name);
+    }
+    if dry_run {
+        println!("{} item(s) would be imported", items.len());
+        return Ok(());
+    }
+    if !yes && !pick {
+        let answer = prompt::line(&format!("Import {} item(s)? [y/N] ", items.len()))?;
+        if !answer.eq_ignore_ascii_case("y") {
+            bail!("aborted");
+        }
+    }
+    let mut source = from
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default();
+    if let Some(file) = &file_name {
+        source = format!("{source} {file}");
+    }
+    if merge == MergeMode::Ask {
+        let mut taken: BTreeSet<String> = items.iter().map(|i| i.name.clone()).collect();
+        let mut resolutions = BTreeMap::new();
+        for conflict in service.import_conflicts(&source, &items).await? {
+            let resolution = resolve_conflict(service, &conflict, "imported", &taken, time).await?;
+            if let Resolution::KeepBoth(name) = &resolution {
+                taken.insert(name.clone());
+            }
+            resolutions.insert(conflict.name, resolution);
+        }
+        items.retain_mut(|item| match resolutions.get(&item.name) {
+            Some(Resolution::KeepExisting) => false,
+            Some(Resolution::KeepBoth(name)) => {
+                item.name = name.clone();
+                true
+            }
+            Some(Resolution::TakeIncoming) | None => true,
+        });
+    }
+    let overwrite = overwrite || merge != MergeMode::Skip;
+    let summary = service.import_secrets(&source, items, overwrite).await?;
+    println!(
+        "📥 imported {} new and {} updated secret(s)",
+        summary.added.len(),
+        summary.updated.len()
+    );
+    if !summary.skipped.is_empty() {
+        println!(
+            "⏭️  {} already in the vault (use --merge overwrite to replace): {}",
+            summary.skipped.len(),
+            summary.skipped.join(", ")
+        );
+    }
+    Ok(())
+}
+
+async fn run_audit(action: AuditCommands, config: &Config, service: &SecretService) -> Result<()> {
+    match action {
+        AuditCommands::List {
+            secret,
+            action,
+            host,
+            since,
+            limit,
+        } => {
+            let filter = AuditFilter {
+                secret_name: secret,
+                action,
+                host,
+                since: since.as_deref().map(parse_since).transpose()?,
+                limit: Some(limit),
+            };
+            let rows: Vec<AuditRow> = service
+                .audit_entries(&filter)
+                .await?
+                .into_iter()
+                .map(|e| AuditRow {
+                    at: e
+                        .at
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string(),
+                    action: e.action,
+                    secret: e.secret_name.unwrap_or_default(),
+                    host: e.host.unwrap_or_default(),
+                    user: e.user.unwrap_or_default(),
+                    detail: e.detail.unwrap_or_default(),
+                })
+                .collect();
+            if rows.is_empty() {
+                println!("no matching audit entries");
+            } else {
+                let mut table = Table::new(rows);
+                table.with(Style::rounded());
+                println!("{table}");
+            }
+        }
+        AuditCommands::Export { format, since, out } => {
+            let since = since
+                .as_deref()
+                .map(parse_since)
+                .transpose()?
+                .unwrap_or(chrono::DateTime::UNIX_EPOCH);
+            let entries = service.audit_from(since).await?;
+            let rendered = match format_as::<AuditFormat>(&format)? {
+                AuditFormat::Jsonl => audit::jsonl(&entries)?,
+            };
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, rendered)?;
+                    perms::make_owner_only(&path)?;
+                    println!(
+                        "📤 exported {} audit entries: {}",
+                        entries.len(),
+                        path.to_string_lossy()
+                    );
+                }
+                None => print!("{rendered}"),
+            }
+        }
+        AuditCommands::Ship { to } => {
+            let target = to
+                .or_else(|| config.file.audit.ship_to.clone())
+                .ok_or_else(|| anyhow!("give --to or set [audit] ship_to"))?;
+            let count = service.ship_audit(&audit::Sink::parse(&target)?).await?;
+            println!("📤 shipped {count} audit entries to {target}");
+        }
+    }
+    Ok(())
+}
+
+async fn run_timeline(days: u32, service: &SecretService) -> Result<()> {
+    let entries = service
+        .audit_since(chrono::Duration::days(days.into()))
+        .await?;
+    if entries.is_empty() {
+        println!("no activity in the last {days} day(s)");
+    }
+    let owners: std::collections::HashMap<String, String> = service
+        .list_secrets()
+        .await?
+        .into_iter()
+        .filter_map(|m| Some((m.name, m.owner?)))
+        .collect();
+    let mut current_day = None;
+    for entry in entries {
+        let local = entry.at.with_timezone(&chrono::Local);
+        let day = local.date_naive();
+        if current_day != Some(day) {
+            println!("📅 {}", local.format("%a %Y-%m-%d"));
+            current_day = Some(day);
+        }
+        let mut line = format!("  {}  {:<7}", local.format("%H:%M:%S"), entry.action);
+        if let Some(name) = &entry.secret_name {
+            line.push_str(&format!(" {name}"));
+            if let Some(owner) = owners.get(name) {
+                line.push_str(&format!(" @{owner}"));
+            }
+        }
+        if let Some(detail) = &entry.detail {
+            line.push_str(&format!(" ({detail})"));
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
+
+async fn run_complete_names(
+    prefix: String,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let socket = agent::default_socket_path(&config.db_path);
+    for name in completion_names(&socket, &prefix, service).await {
+        println!("{name}");
+    }
+    Ok(())
+}
+
+/// Names for `__complete-names`, from the agent when one runs. Completion must stay
+/// quiet: no names is better than an error mid-line.
+async fn completion_names(socket: &Path, prefix: &str, service: &SecretService) -> Vec<String> {
+    match names_from_agent(socket, prefix, None).await {
+        Some(names) => names,
+        None => service.secret_names(prefix, None).await.unwrap_or_default(),
+    }
+}
+
+async fn run_names(
+    prefix: String,
+    format: String,
+    limit: Option<u32>,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let socket = agent::default_socket_path(&config.db_path);
+    let names = match names_from_agent(&socket, &prefix, limit).await {
+        Some(names) => names,
+        None => service.secret_names(&prefix, limit).await?,
+    };
+    print!("{}", names_output(&names, format_as(&format)?)?);
+    Ok(())
+}
+
+/// `names` output: one name per line, or a JSON array on one line.
+fn names_output(names: &[String], format: NamesFormat) -> Result<String> {
+    Ok(match format {
+        NamesFormat::Text => names.iter().map(|name| format!("{name}\n")).collect(),
+        NamesFormat::Json => serde_json::to_string(names)? + "\n",
+    })
+}
+
+async fn run_list(
+    args: ListArgs,
+    format: OutputFormat,
+    time: TimeStyle,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    let ListArgs {
+        owner,
+        tag,
+        prefix,
+        origin,
+        tree,
+        columns,
+    } = args;
+    // metadata is readable without the key; the gate is opt-in
+    if config.file.security.key_required_for_list() {
+        service.ensure_master_key().await?;
+    }
+    let mut rows = match (tag, prefix) {
+        (Some(tag), prefix) => {
+            let selector = rotation::Selector {
+                tag: Some(tag),
+                prefix,
+                ..Default::default()
+            };
+            service.select_secrets(&selector).await?
+        }
+        (None, Some(prefix)) => service.list_secrets_with_prefix(&prefix).await?,
+        (None, None) => service.list_secrets().await?,
+    };
+    if let Some(owner) = owner {
+        rows.retain(|m| m.owner.as_deref() == Some(owner.as_str()));
+    }
+    if let Some(origin) = origin {
+        rows.retain(|m| m.provenance.as_ref().map(|p| p.origin) == Some(origin));
+    }
+    let count = rows.len();
+    info!("listed {} secrets (metadata only)", count);
+    if format.print(&rows)? {
+        return Ok(());
+    }
+    if tree {
+        print!("{}", name_tree(rows.iter().map(|m| m.name.as_str())));
+    } else if columns.is_empty() {
+        println!("{}", metadata_table(rows, time));
+    } else {
+        println!("{}", metadata_table_with(&rows, &columns, time));
+    }
+    Ok(())
+}
+
+async fn run_tag(action: TagCommands, format: OutputFormat, service: &SecretService) -> Result<()> {
+    match action {
+        TagCommands::Add { name, tags } => {
+            let added = service.tag_secret(&name, &tags).await?;
+            println!("🏷️  {name}: {added} tag(s) added");
+        }
+        TagCommands::Rm { name, tags } => {
+            let removed = service.untag_secret(&name, &tags).await?;
+            println!("🏷️  {name}: {removed} tag(s) removed");
+        }
+        TagCommands::List { name: Some(name) } => {
+            let tags = service.secret_tags(&name).await?;
+            if !format.print(&tags)? {
+                println!("{}", tags.join(", "));
+            }
+        }
+        TagCommands::List { name: None } => {
+            let counts = service.tag_counts().await?;
+            let structured: BTreeMap<_, _> = counts.iter().cloned().collect();
+            if format.print(&structured)? {
+                return Ok(());
+            }
+            if counts.is_empty() {
+                println!("no tags yet; add some with `tag add NAME TAG...`");
+            }
+            for (tag, uses) in counts {
+                println!("{tag} ({uses})");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_set(args: SetArgs, service: &SecretService) -> Result<()> {
+    let SetArgs {
+        glob,
+        id,
+        note,
+        owner,
+        contact,
+        expires,
+        tags,
+        yes,
+    } = args;
+    let patch = MetadataPatch {
+        note,
+        owner,
+        contact,
+        expires_at: expires,
+        tags,
+    };
+    let changed = patch.changed_fields();
+    if changed.is_empty() {
+        bail!("nothing to set; give --note, --owner, --contact, --expires or --tag");
+    }
+    let targets = match (id, glob) {
+        (Some(id), _) => {
+            let name = service.resolve(id).await?;
+            service.secret_metadata(&name).await?.into_iter().collect()
+        }
+        (None, Some(glob)) => {
+            let targets = service.secrets_matching(&glob).await?;
+            if targets.is_empty() {
+                println!("no secret matches {glob}");
+                return Ok(());
+            }
+            targets
+        }
+        (None, None) => bail!("give --glob or --id"),
+    };
+    for target in &targets {
+        println!("  - {}", target.name);
+    }
+    if !yes {
+        let answer = prompt::line(&format!(
+            "Set {} on {} secret(s)? [y/N] ",
+            changed.join(", "),
+            targets.len()
+        ))?;
+        if !answer.eq_ignore_ascii_case("y") {
+            bail!("aborted");
+        }
+    }
+    let updated = service.set_metadata(&targets, &patch).await?;
+    println!("✏️  updated {} secret(s)", updated.len());
+    Ok(())
+}
+
+async fn run_search(
+    query: String,
+    format: OutputFormat,
+    time: TimeStyle,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    if config.file.security.key_required_for_list() {
+        service.ensure_master_key().await?;
+    }
+    let rows = service.search_secrets(&query).await?;
+    let count = rows.len();
+    info!("search '{}' matched {} secrets", query, count);
+    if !format.print(&rows)? {
+        println!("{}", metadata_table(rows, time));
+    }
+    Ok(())
+}
+
+async fn run_rename(old: String, new: String, prefix: bool, service: &SecretService) -> Result<()> {
+    if prefix {
+        let moved = service.rename_prefix(&old, &new).await?;
+        for (from, to) in &moved {
+            println!("  {from} -> {to}");
+        }
+        println!("✏️  moved {} secret(s) from {old} to {new}", moved.len());
+    } else {
+        let renamed = service.rename_secret(&old, &new).await?;
+        println!("✏️  renamed: {old} -> {}", renamed.name);
+    }
+    Ok(())
+}
+
+async fn run_rm(
+    name: Option<String>,
+    id: Option<Uuid>,
+    prefix: Option<String>,
+    yes: bool,
+    service: &SecretService,
+) -> Result<()> {
+    if let Some(prefix) = prefix {
+        service.ensure_master_key().await?;
+        let names: Vec<String> = service
+            .list_secrets_with_prefix(&prefix)
+            .await?
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        if names.is_empty() {
+            println!("nothing under {prefix}");
+            return Ok(());
+        }
+        for name in &names {
+            println!("  - {name}");
+        }
+        if !yes {
+            let answer = prompt::line(&format!("Remove {} secret(s)? [y/N] ", names.len()))?;
+            if !answer.eq_ignore_ascii_case("y") {
+                bail!("aborted");
+            }
+        }
+        for name in &names {
+            service.delete_secret(name).await?;
+        }
+        println!("🗑️ removed {} secret(s) under {prefix}", names.len());
+        return Ok(());
+    }
+    let name = match (id, name) {
+        (Some(id), _) => service.resolve(id).await?,
+        (None, Some(name)) => name,
+        (None, None) => {
+            let name = pick_secret(service).await?;
+            let answer = prompt::line(&format!("Remove {name}? [y/N] "))?;
+            if !answer.eq_ignore_ascii_case("y") {
+                bail!("aborted");
+            }
+            name
+        }
+    };
+    service.ensure_master_key().await?;
+    let deleted = service.delete_secret(&name).await?;
+    if deleted {
+        info!("removed secret: {}", name);
+        println!("🗑️ removed: {}", name);
+    } else {
+        warn!("secret not found for removal: {}", name);
+        println!("not found: {}", name);
+    }
+    Ok(())
+}
+
+async fn run_trash(service: &SecretService) -> Result<()> {
+    let trash = service.trash().await?;
+    if trash.is_empty() {
+        println!("trash is empty");
+    }
+    for (secret, deleted_at) in trash {
+        println!("  {}  deleted {}", secret.name, relative_time(deleted_at));
+    }
+    Ok(())
+}
+
+async fn run_restore(
+    name: Option<String>,
+    backup: Option<PathBuf>,
+    strategy: backup::RestoreStrategy,
+    backup_key: Option<PathBuf>,
+    yes: bool,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    match (name, backup) {
+        (Some(name), _) => {
+            let restored = service.restore_secret(&name).await?;
+            println!("♻️ restored: {}", restored.name);
+        }
+        (None, Some(path)) => {
+            let mut data = std::fs::read(&path)
+                .with_context(|| format!("reading {}", path.to_string_lossy()))?;
+            if backup::is_sealed(&data) {
+                let key = backup_key.ok_or_else(|| {
+                    anyhow!("backup is sealed to the backup key; pass --backup-key FILE")
+                })?;
+                data = backup::open(&backup::read_secret_key(&key)?, &data)?;
+            }
+            let file = backup::BackupFile::parse(&data)?;
+            println!(
+                "📦 backup of {} secrets from {} (format {}, checksum ok)",
+                file.secrets,
+                file.created_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                file.format_version
+            );
+            let passphrase = match file.protection {
+                backup::Protection::MasterKey => None,
+                backup::Protection::Passphrase { .. } => {
+                    Some(prompt::secret("Backup passphrase: ")?)
+                }
+            };
+            if !yes {
+                let existing = match strategy {
+                    backup::RestoreStrategy::Skip => "kept",
+                    backup::RestoreStrategy::Overwrite => "replaced",
+                    backup::RestoreStrategy::Rename => "kept, with a -restored copy",
+                };
+                let answer = prompt::line(&format!(
+                    "Restore into {} (secrets already there are {existing})? [y/N] ",
+                    config.db_path.to_string_lossy()
+                ))?;
+                if !answer.eq_ignore_ascii_case("y") {
+                    bail!("aborted");
+                }
+            }
+            let summary = service
+                .restore_backup(&file, passphrase.as_deref(), strategy)
+                .await?;
+            println!(
+                "♻️ restored {} new, {} overwritten and {} renamed secret(s)",
+                summary.added.len(),
+                summary.overwritten.len(),
+                summary.renamed.len()
+            );
+            for (from, to) in &summary.renamed {
+                println!("   {from} -> {to}");
+            }
+            if !summary.skipped.is_empty() {
+                println!(
+                    "⏭️  {} already in the vault (use --strategy overwrite or rename): {}",
+                    summary.skipped.len(),
+                    summary.skipped.join(", ")
+                );
+            }
+        }
+        (None, None) => unreachable!("clap requires a name or --backup"),
+    }
+    Ok(())
+}
+
+async fn run_purge(name: Option<String>, yes: bool, service: &SecretService) -> Result<()> {
+    let names = match name {
+        Some(name) => vec![name],
+        None => {
+            let names: Vec<String> = service
+                .trash()
+                .await?
+                .into_iter()
+                .map(|(secret, _)| secret.name)
+                .collect();
+            if names.is_empty() {
+                println!("trash is empty");
+                return Ok(());
+            }
+            for name in &names {
+                println!("  - {name}");
+            }
+            if !yes {
+                let answer = prompt::line(&format!(
+                    "Permanently delete {} secret(s)? [y/N] ",
+                    names.len()
+                ))?;
+                if !answer.eq_ignore_ascii_case("y") {
+                    bail!("aborted");
+                }
+            }
+            names
+        }
+    };
+    for name in &names {
+        if service.purge_secret(name).await? {
+            println!("🔥 purged: {name}");
+        } else {
+            println!("not in trash: {name}");
+        }
+    }
+    Ok(())
+}
+
+async fn run_lock(
+    target: SecretTarget,
+    reason: Option<String>,
+    service: &SecretService,
+) -> Result<()> {
+    let name = service.resolve(target).await?;
+    if service.lock_secret(&name, reason.as_deref()).await? {
+        println!("🔒 {name} locked; `unlock {name}` to change or remove it again");
+    } else {
+        println!("{name} is already locked");
+    }
+    Ok(())
+}
+
+async fn run_unlock(target: SecretTarget, service: &SecretService) -> Result<()> {
+    let name = service.resolve(target).await?;
+    if service.unlock_secret(&name).await? {
+        println!("🔓 {name} unlocked");
+    } else {
+        println!("{name} is not locked");
+    }
+    Ok(())
+}
+
+async fn run_clear_immutable(target: SecretTarget, service: &SecretService) -> Result<()> {
+    let name = service.resolve(target).await?;
+    service.ensure_master_key().await?;
+    let typed = prompt::line(&format!(
+        "Type '{name}' to allow overwriting or deleting it: "
+    ))?;
+    if typed != name {
+        bail!("confirmation did not match; '{name}' stays immutable");
+    }
+    service.clear_immutable(&name).await?;
+    println!("🔓 immutable flag cleared: {}", name);
+    Ok(())
+}
+
+async fn run_rotate(
+    lazy: bool,
+    passphrase: bool,
+    yubikey: bool,
+    pkcs11: bool,
+    cipher: Option<Cipher>,
+    service: &SecretService,
+) -> Result<()> {
+    if let Some(cipher) = cipher {
+        service.set_cipher(cipher).await?;
+    }
+    service
+        .rotate_master_key(lazy, key_origin(passphrase, yubikey, pkcs11))
+        .await?;
+    match service.key_origin().await? {
+        Some(KeyOrigin::Passphrase) => {
+            println!("🔑 master key rotated; it is now wrapped under the new passphrase")
+        }
+        Some(KeyOrigin::Yubikey) => {
+            println!("🔑 master key rotated; it is now derived from a new YubiKey challenge")
+        }
+        Some(KeyOrigin::Pkcs11) => {
+            println!("🔑 master key rotated; the new key is wrapped by the PKCS#11 token")
+        }
+        None => println!("🔑 master key rotated; remember to back it up"),
+    }
+    if let Some(cipher) = cipher {
+        println!(
+            "   cipher:  {cipher}{}",
+            if lazy {
+                " (values move over as they are rewritten)"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+async fn run_rotate_secret(args: RotateSecretArgs, service: &SecretService) -> Result<()> {
+    let RotateSecretArgs {
+        tag,
+        prefix,
+        kind,
+        fields,
+        provider,
+        command,
+        dry_run,
+        yes,
+    } = args;
+    let selector = rotation::Selector {
+        prefix,
+        kind,
+        tag,
+        fields,
+    };
+    if selector.is_empty() {
+        bail!("select secrets with --tag, --prefix, --kind or --field");
+    }
+    let provider = match provider {
+        RotationProvider::Random if !command.is_empty() => {
+            bail!("a command after `--` needs --provider command")
+        }
+        RotationProvider::Random => rotation::Provider::Random,
+        RotationProvider::Command if command.is_empty() => {
+            bail!("--provider command needs the hook after `--`")
+        }
+        RotationProvider::Command => rotation::Provider::Command(command),
+    };
+    let matching: Vec<String> = service
+        .select_secrets(&selector)
+        .await?
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    if matching.is_empty() {
+        println!("no secrets match");
+        return Ok(());
+    }
+    for name in &matching {
+        println!("  ↻ {name}");
+    }
+    if dry_run {
+        println!("{} secret(s) would be rotated", matching.len());
+        return Ok(());
+    }
+    if !yes {
+        let answer = prompt::line(&format!("Rotate {} secret(s)? [y/N] ", matching.len()))?;
+        if !answer.eq_ignore_ascii_case("y") {
+            bail!("aborted");
+        }
+    }
+    let summary = service.rotate_secrets(&selector, &provider).await?;
+    println!("🔄 rotated {} secret(s)", summary.rotated.len());
+    if !summary.failed.is_empty() {
+        for (name, reason) in &summary.failed {
+            println!("  ✗ {name}: {reason}");
+        }
+        bail!(
+            "{} secret(s) failed to rotate and keep their old values",
+            summary.failed.len()
+        );
+    }
+    Ok(())
+}
+
+async fn run_agent_command(
+    socket: Option<PathBuf>,
+    action: AgentCommands,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    if let AgentCommands::InstallService { platform, print } = action {
+        install_service(
+            platform.unwrap_or_else(autostart::Platform::current),
+            print,
+            config,
+        )?;
+    } else {
+        let socket = socket.unwrap_or_else(|| agent::default_socket_path(&config.db_path));
+        run_agent(action, &socket, config, service).await?;
+    }
+    Ok(())
+}
+
+async fn run_attest(
+    out: Option<PathBuf>,
+    action: Option<AttestCommands>,
+    service: &SecretService,
+) -> Result<()> {
+    match action {
+        None => {
+            let snapshot = service.attest().await?;
+            let json = serde_json::to_string_pretty(&snapshot)?;
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, json + "\n")?;
+                    println!(
+                        "🧾 attested {} secret(s), root {} -> {}",
+                        snapshot.secrets,
+                        snapshot.root,
+                        path.to_string_lossy()
+                    );
+                }
+                None => println!("{json}"),
+            }
+        }
+        Some(AttestCommands::Verify { snapshot }) => {
+            let text = std::fs::read_to_string(&snapshot)
+                .with_context(|| format!("reading {}", snapshot.to_string_lossy()))?;
+            let then: Snapshot = serde_json::from_str(&text)
+                .with_context(|| format!("{} is not a snapshot", snapshot.to_string_lossy()))?;
+            let now = service.verify_attestation(&then).await?;
+            println!("✅ signature valid (made {})", then.created_at.to_rfc3339());
+            if now.root == then.root && now.devices == then.devices {
+                println!(
+                    "✅ vault unchanged: {} secret(s), root {}",
+                    now.secrets, now.root
+                );
+            } else {
+                println!(
+                    "⚠️  vault changed: secrets {} -> {}, devices {} -> {}, root {} -> {}",
+                    then.secrets, now.secrets, then.devices, now.devices, then.root, now.root
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_report(action: ReportCommands, service: &SecretService) -> Result<()> {
+    match action {
+        ReportCommands::Inventory { format, out } => {
+            let secrets = service.list_secrets().await?;
+            let document = report::inventory(&secrets, format_as(&format)?, chrono::Utc::now());
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, document)?;
+                    println!(
+                        "📄 inventory of {} secret(s) -> {}",
+                        secrets.len(),
+                        path.to_string_lossy()
+                    );
+                }
+                None => print!("{document}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn run_agent(
+    action: AgentCommands,
+    socket: &Path,
+    config: &Config,
+    service: &SecretService,
+) -> Result<()> {
+    match action {
+        AgentCommands::Start => {
+            // Fail now rather than on the first request if the key is unavailable.
+            service.ensure_master_key().await?;
+            let listener = agent::bind(socket).await?;
+            println!(
+                "🤝 agent listening on {} (Ctrl-C to stop)",
+                socket.to_string_lossy()
+            );
+            let service = Arc::new(service.clone());
+            let checker = config.file.agent.integrity_check_every().map(|every| {
+                tokio::spawn(agent::watch_integrity(
+                    service.clone(),
+                    every,
+                    config.file.agent.integrity_sample(),
+                ))
+            });
+            let names = Arc::new(agent::NameCache::new(config.file.agent.names_cache_ttl()));
+            let served = agent::serve(service, names, listener, socket).await;
+            if let Some(checker) = checker {
+                checker.abort();
+            }
+            served?;
+        }
+        AgentCommands::Status => {
+            let mut stream = agent::connect(socket).await?;
+            if let agent::Reply::Pong { agent: release } =
+                agent::call(&mut stream, agent::AgentOp::Ping).await?
+            {
+                println!(
+                    "✅ agent {release} on {} (protocol v{})",
+                    socket.to_string_lossy(),
+                    agent::PROTOCOL_VERSION
+                );
+            }
+        }
+        AgentCommands::Get { name } => {
+            let mut stream = agent::connect(socket).await?;
+            let reply = agent::call(&mut stream, agent::AgentOp::Get { name }).await?;
+            print_agent_value(&reply.into_value()?)?;
+        }
+        AgentCommands::Redeem { token } => {
+            let token = match token {
+                Some(token) => token,
+                None => prompt::line("lease token: ")?,
+            };
+            let mut stream = agent::connect(socket).await?;
+            let reply = agent::call(&mut stream, agent::AgentOp::Redeem { token }).await?;
+            print_agent_value(&reply.into_value()?)?;
+        }
+        AgentCommands::InstallService { .. } => unreachable!("handled by install_service"),
+    }
+    Ok(())
+}
+
+fn install_service(platform: autostart::Platform, print: bool, config: &Config) -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let db_path = std::path::absolute(&config.db_path)?;
+    let global_args = vec![
+        "--db-path".to_string(),
+        db_path.to_string_lossy().into_owned(),
+    ];
+    let unit_dir = platform.unit_dir()?;
+    let plan = autostart::plan(platform, &exe, &global_args, unit_dir.as_deref())?;
+    if print {
+        for (path, content) in &plan.files {
+            println!("# {}\n{content}", path.to_string_lossy());
+        }
+        for command in &plan.commands {
+            println!("$ {}", command.join(" "));
+        }
+        return Ok(());
+    }
+    for (path, content) in &plan.files {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, content)?;
+        println!("📝 wrote {}", path.to_string_lossy());
+    }
+    for command in &plan.commands {
+        let status = std::process::Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .with_context(|| format!("running {}", command[0]))?;
+        if !status.success() {
+            bail!("`{}` failed ({status})", command.join(" "));
+        }
+    }
+    println!("✅ agent and daily check registered with {platform:?}");
+    Ok(())
+}
+
+/// Subcommands whose first argument is a live secret's name, completed from the vault.
+fn name_taking_commands() -> Vec<String> {
+    // these take names from the trash, which `__complete-names` does not list
+    const TRASH: &[&str] = &["restore", "purge"];
+    Cli::command()
+        .get_subcommands()
+        .filter(|command| {
+            !TRASH.contains(&command.get_name())
+                && command
+                    .get_positionals()
+                    .next()
+                    .is_some_and(|arg| arg.get_id() == "name")
+        })
+        .map(|command| command.get_name().to_string())
+        .collect()
+}
+
+pub fn print_completions(shell: clap_complete::Shell) -> Result<()> {
+    io::stdout().lock().write_all(&completion_script(shell))?;
+    Ok(())
+}
+
+/// The clap-generated script for `shell`, plus (bash, zsh, fish) a wrapper that
+/// completes the name argument from `__complete-names`.
+fn completion_script(shell: clap_complete::Shell) -> Vec<u8> {
+    use clap_complete::Shell;
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "devinventory", &mut script);
+    let commands = name_taking_commands().join(" ");
+    let names = match shell {
+        Shell::Bash => format!(
+            r#"
+_devinventory_names() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    if [[ $COMP_CWORD -eq 2 && " {commands} " == *" ${{COMP_WORDS[1]}} "* && $cur != -* ]]; then
+        mapfile -t COMPREPLY < <(devinventory __complete-names "$cur" 2>/dev/null)
+    else
+        _devinventory "$@"
+    fi
+}}
+complete -F _devinventory_names -o bashdefault -o default devinventory
+"#
+        ),
+        Shell::Zsh => format!(
+            r#"
+_devinventory_names() {{
+    local -a named=({commands})
+    if (( CURRENT == 3 && ${{named[(Ie)${{words[2]}}]}} )) && [[ ${{words[CURRENT]}} != -* ]]; then
+        local -a names
+        names=(${{(f)"$(devinventory __complete-names "${{words[CURRENT]}}" 2>/dev/null)"}})
+        compadd -a names
+    else
+        _devinventory "$@"
+    fi
+}}
+compdef _devinventory_names devinventory
+"#
+        ),
+        Shell::Fish => format!(
+            "complete -c devinventory -n \"__fish_seen_subcommand_from {commands}\" -f \
+             -a \"(devinventory __complete-names (commandline -ct) 2>/dev/null)\"\n"
+        ),
+        _ => String::new(),
+    };
+    script.extend_from_slice(names.as_bytes());
+    script
+}
+
+/// Names from the agent on `socket`, or `None` when no agent answers.
+#[cfg(unix)]
+async fn names_from_agent(socket: &Path, prefix: &str, limit: Option<u32>) -> Option<Vec<String>> {
+    if !socket.exists() {
+        return None;
+    }
+    let mut stream = agent::connect(socket).await.ok()?;
+    let op = agent::AgentOp::Names {
+        prefix: prefix.to_string(),
+        limit,
+    };
+    match agent::call(&mut stream, op).await {
+        Ok(agent::Reply::Names { names }) => Some(names),
+        Ok(_) => None,
+        Err(e) => {
+            debug!("agent could not list names: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn names_from_agent(_: &Path, _: &str, _: Option<u32>) -> Option<Vec<String>> {
+    None
+}
+
+/// A value from the agent: text with a newline, binary data byte for byte.
+#[cfg(unix)]
+fn print_agent_value(value: &[u8]) -> Result<()> {
+    match std::str::from_utf8(value) {
+        Ok(text) => println!("{text}"),
+        Err(_) => std::io::stdout().write_all(value)?,
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn run_agent(_: AgentCommands, _: &Path, _: &Config, _: &SecretService) -> Result<()> {
+    bail!("the agent is Unix only: it relies on Unix sockets and their peer credentials")
+}
+
+/// Parse an `oplog export` file (one JSON operation per line).
+pub fn read_oplog(path: &Path) -> Result<Vec<Operation>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.to_string_lossy()))?;
+    let ops = content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Operation>, _>>()?;
+    Ok(ops)
+}
+
+/// Write every operation of the vault to `path` as JSON lines; returns how many.
+pub async fn write_oplog(service: &SecretService, path: &Path) -> Result<usize> {
+    let ops = service.operations().await?;
+    let mut lines = String::new();
+    for op in &ops {
+        lines.push_str(&serde_json::to_string(op)?);
+        lines.push('\n');
+    }
+    std::fs::write(path, lines)?;
+    perms::make_owner_only(path)?;
+    Ok(ops.len())
+}
+
+/// Show `value` on the alternate screen so it never lands in scrollback, then
+/// restore the normal screen after `duration` (or on Ctrl-C).
+async fn hold_clipboard(handle: arboard::Clipboard, value: &str, duration: Duration) {
+    warn!("secret copied to the clipboard for {}s", duration.as_secs());
+    eprintln!("(cleared in {}s, Ctrl-C to clear now)", duration.as_secs());
+    clipboard::hold(handle, value, duration).await;
+    println!("🧹 clipboard cleared");
+}
+
+async fn reveal_for(name: &str, value: &str, duration: Duration) -> Result<()> {
+    let mut out = io::stdout();
+    // enter alternate screen, clear it, home the cursor
+    write!(out, "\x1b[?1049h\x1b[2J\x1b[H")?;
+    writeln!(out, "{name}:\n{value}\n")?;
+    writeln!(
+        out,
+        "(hidden in {}s, Ctrl-C to hide now)",
+        duration.as_secs()
+    )?;
+    out.flush()?;
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    // clear, leave alternate screen, purge scrollback where supported
+    write!(out, "\x1b[2J\x1b[?1049l\x1b[3J")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Point at `salvage` when a stored value no longer decrypts.
+fn salvage_hint(err: &anyhow::Error) {
+    if err
+        .chain()
+        .any(|cause| cause.to_string().starts_with("cannot decrypt"))
+    {
+        eprintln!(
+            "💡 if more values fail, `salvage --out partial-export.dvi` saves what is still readable"
+        );
+    }
+}
+
+/// The key origin picked by `init`/`rotate` flags, if any.
+/// Parser for a `--format` taking `E`'s values. The global flag and the subcommands'
+/// own share the id `format`, so clap hands values between them and every one of them
+/// has to parse to the same type: the value's name, read back with [`format_as`].
+fn format_of<E: ValueEnum + Clone + Send + Sync + 'static>() -> impl TypedValueParser<Value = String>
+{
+    EnumValueParser::<E>::new().map(|value| {
+        value
+            .to_possible_value()
+            .expect("format values are never skipped")
+            .get_name()
+            .to_string()
+    })
+}
+
+/// A `--format` value parsed by [`format_of`]; an error when it came from the global
+/// flag and is not one of this command's formats.
+fn format_as<E: ValueEnum>(name: &str) -> Result<E> {
+    E::from_str(name, false).map_err(|_| {
+        let names: Vec<String> = E::value_variants()
+            .iter()
+            .filter_map(|v| v.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect();
+        anyhow!(
+            "--format {name} does not apply to this command; use {}",
+            names.join(", ")
+        )
+    })
+}
+
+fn key_origin(passphrase: bool, yubikey: bool, pkcs11: bool) -> Option<KeyOrigin> {
+    match (passphrase, yubikey, pkcs11) {
+        (true, _, _) => Some(KeyOrigin::Passphrase),
+        (_, true, _) => Some(KeyOrigin::Yubikey),
+        (_, _, true) => Some(KeyOrigin::Pkcs11),
+        _ => None,
+    }
+}
+
+/// A new backup passphrase, asked twice.
+fn new_backup_passphrase() -> Result<String> {
+    let first = prompt::secret("Backup passphrase: ")?;
+    if first.is_empty() {
+        bail!("the backup passphrase cannot be empty");
+    }
+    if prompt::secret("Repeat passphrase: ")? != first {
+        bail!("passphrases do not match");
+    }
+    Ok(first)
+}
+
+/// Write `file` to `out`, owner-only, sealed to `recipient` when one is configured.
+fn write_backup(out: &Path, file: &backup::BackupFile, recipient: Option<&[u8; 32]>) -> Result<()> {
+    let mut data = serde_json::to_vec_pretty(file)?;
+    if let Some(public) = recipient {
+        data = backup::seal(public, &data)?;
+    }
+    std::fs::write(out, data)?;
+    perms::make_owner_only(out)
+}
+
+/// Print a warning when `path` is group/world accessible; returns the number of warnings.
+fn check_private(path: &Path, label: &str, fix_mode: &str) -> Result<usize> {
+    if !path.exists() || path.as_os_str().is_empty() {
+        return Ok(0);
+    }
+    match perms::exposed_mode(path)? {
+        Some(mode) => {
+            warn!("{} {} is mode {:o}", label, path.to_string_lossy(), mode);
+            println!(
+                "⚠️  {label} {} is group/world accessible (mode {mode:o}); run `chmod {fix_mode} {}`",
+                path.to_string_lossy(),
+                path.to_string_lossy()
+            );
+            Ok(1)
+        }
+        None => {
+            println!("✅ {label} {} is private", path.to_string_lossy());
+            Ok(0)
+        }
+    }
+}
+
+/// Past the hourly reveal limit, make the person at the terminal type the name again.
+async fn confirm_reveal(service: &SecretService, name: &str) -> Result<()> {
+    let Some(count) = service.reveal_cooldown(name).await? else {
+        return Ok(());
+    };
+    warn!("'{name}' revealed {count} times in the past hour");
+    if !prompt::can_ask() {
+        bail!("'{name}' was revealed {count} times in the past hour; confirm interactively");
+    }
+    let answer = prompt::line(&format!(
+        "'{name}' was revealed {count} times in the past hour. Type its name to reveal again: "
+    ))?;
+    if answer != name {
+        bail!("reveal of '{name}' not confirmed");
+    }
+    Ok(())
+}
+
+fn parse_key_value(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{arg}'")),
+    }
+}
+
+/// All of stdin without one trailing newline, or `None` when that leaves nothing.
+fn read_stdin_value() -> Result<Option<Vec<u8>>> {
+    let mut value = Vec::new();
+    io::stdin().lock().read_to_end(&mut value)?;
+    if value.ends_with(b"\n") {
+        value.pop();
+        if value.ends_with(b"\r") {
+            value.pop();
+        }
+    }
+    Ok((!value.is_empty()).then_some(value))
+}
+
+/// Split `NAME[=VAR]` specs into secret names and variables; without `=VAR` the
+/// secret's mapping or derived name is used.
+async fn resolve_env_specs(
+    service: &SecretService,
+    specs: &[String],
+) -> Result<(Vec<String>, Vec<String>)> {
+    let (names, explicit): (Vec<String>, Vec<Option<String>>) = specs
+        .iter()
+        .map(|spec| match spec.split_once('=') {
+            Some((name, var)) => (name.to_string(), Some(var.to_string())),
+            None => (spec.clone(), None),
+        })
+        .unzip();
+    let vars = service
+        .env_vars_for(&names)
+        .await?
+        .into_iter()
+        .zip(explicit)
+        .map(|(mapped, explicit)| explicit.unwrap_or(mapped))
+        .collect();
+    Ok((names, vars))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempVault;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("devinventory").chain(args.iter().copied())).unwrap()
+    }
+
+    /// A temp vault holding `secrets` as (name, value) pairs.
+    async fn vault_with(secrets: &[(&str, &str)]) -> TempVault {
+        let mut builder = TempVault::builder();
+        for (name, value) in secrets {
+            builder = builder.with_secret(name, value);
+        }
+        builder.build().await.unwrap()
+    }
+
+    #[test]
+    fn the_command_line_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn exec_passes_values_byte_for_byte() {
+        assert!(env_value("nul", b"a\0b").is_err());
+        let binary = [b'k', 0xff, 0xfe, b'\n', 0x80];
+        #[cfg(unix)]
+        {
+            let output = std::process::Command::new("sh")
+                .args(["-c", "printf %s \"$VALUE\""])
+                .env("VALUE", env_value("blob", &binary).unwrap())
+                .output()
+                .unwrap();
+            assert_eq!(output.stdout, binary);
+        }
+        #[cfg(not(unix))]
+        assert!(env_value("blob", &binary).is_err());
+        assert_eq!(env_value("api", b"token").unwrap(), "token");
+    }
+
+    #[test]
+    fn copying_to_the_clipboard_takes_a_hold_time() {
+        let Commands::Copy { duration, .. } = parse(&["copy", "db/prod", "--for", "2m"]).command
+        else {
+            panic!("not copy");
+        };
+        assert_eq!(duration, Some(Duration::from_secs(120)));
+        let Commands::Copy { duration, .. } = parse(&["copy", "db/prod"]).command else {
+            panic!("not copy");
+        };
+        assert_eq!(duration, None);
+        assert_eq!(
+            crate::config::ClipboardConfig::default().clear_after(),
+            Duration::from_secs(20)
+        );
+
+        let Commands::Get(args) = parse(&["get", "db/prod", "--copy"]).command else {
+            panic!("not get");
+        };
+        assert!(args.copy && !args.show);
+        // the value goes to one place only
+        for clash in ["--show", "--inspect", "--raw"] {
+            assert!(
+                Cli::try_parse_from(["devinventory", "get", "db/prod", "--copy", clash]).is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn vault_profiles_are_chosen_instead_of_a_path() {
+        let overrides = parse(&["--vault", "work", "list"]).config_overrides();
+        assert_eq!(overrides.vault.as_deref(), Some("work"));
+        assert_eq!(
+            parse(&["list", "--vault", "work"])
+                .config_overrides()
+                .vault
+                .as_deref(),
+            Some("work")
+        );
+        for clash in [["--db-path", "x.db"].as_slice(), &["--ephemeral"]] {
+            let args = ["devinventory", "--vault", "work"]
+                .into_iter()
+                .chain(clash.iter().copied());
+            assert!(Cli::try_parse_from(args.chain(["list"])).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn completion_lists_names_for_commands_that_take_one() {
+        let commands = name_taking_commands();
+        for command in ["get", "copy", "rm", "lock", "edit"] {
+            assert!(commands.iter().any(|c| c == command), "{command} missing");
+        }
+        for command in ["restore", "purge", "list", "__complete-names"] {
+            assert!(!commands.iter().any(|c| c == command), "{command} listed");
+        }
+
+        let bash = String::from_utf8(completion_script(clap_complete::Shell::Bash)).unwrap();
+        assert!(bash.contains(&format!(" {} ", commands.join(" "))));
+        assert!(
+            bash.ends_with(
+                "complete -F _devinventory_names -o bashdefault -o default devinventory\n"
+            )
+        );
+        let checked = std::process::Command::new("bash")
+            .args(["-n", "-c", &bash])
+            .status();
+        // no bash, nothing to check the syntax with
+        assert!(checked.map_or(true, |status| status.success()));
+        let fish = String::from_utf8(completion_script(clap_complete::Shell::Fish)).unwrap();
+        assert!(
+            fish.contains("-a \"(devinventory __complete-names (commandline -ct) 2>/dev/null)\"")
+        );
+        let elvish = completion_script(clap_complete::Shell::Elvish);
+        assert!(
+            !String::from_utf8(elvish)
+                .unwrap()
+                .contains("_devinventory_names")
+        );
+
+        // the helper is hidden from --help but parses
+        assert!(
+            !Cli::command()
+                .render_help()
+                .to_string()
+                .contains("__complete-names")
+        );
+        let Commands::CompleteNames { prefix } = parse(&["__complete-names", "app/"]).command
+        else {
+            panic!("not __complete-names");
+        };
+        let vault = vault_with(&[("app/db", "pw"), ("other", "x")]).await;
+        let socket = vault.db_path().with_file_name("agent.sock");
+        assert_eq!(
+            completion_names(&socket, &prefix, &vault.service).await,
+            ["app/db"]
+        );
+    }
+
+    #[test]
+    fn raw_output_is_exactly_the_value() {
+        let Commands::Get(args) = parse(&["get", "tls/key", "--raw", "--pipe", "trim"]).command
+        else {
+            panic!("not get");
+        };
+        assert!(args.raw);
+        assert!(matches!(args.pipe[..], [transform::Pipe::Trim]));
+        for clash in ["--inspect", "--copy"] {
+            assert!(Cli::try_parse_from(["devinventory", "get", "k", "--raw", clash]).is_err());
+        }
+
+        let raw = |pipe: &[transform::Pipe], value: &[u8]| {
+            let mut out = Vec::new();
+            write_raw(&mut out, pipe, value).unwrap();
+            out
+        };
+        // trailing newlines and binary bytes pass through untouched
+        assert_eq!(raw(&[], b"-----BEGIN KEY-----\n"), b"-----BEGIN KEY-----\n");
+        assert_eq!(raw(&[], &[0xff, 0x00, 0x80]), [0xff, 0x00, 0x80]);
+        assert_eq!(raw(&[], b""), b"");
+        assert_eq!(raw(&[transform::Pipe::Trim], b" tok \n"), b"tok");
+        assert_eq!(raw(&[transform::Pipe::Base64], &[0xff]), b"/w==");
+    }
+
+    #[test]
+    fn exports_are_byte_stable_whatever_the_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = dir.path().join("vault-pass");
+        std::fs::write(&password, "pw\n").unwrap();
+        let vars = |names: &[&str]| -> Vec<(String, String)> {
+            names
+                .iter()
+                .map(|name| (name.to_string(), format!("{name}-value")))
+                .collect()
+        };
+        let (one, other) = (vars(&["B", "A", "C"]), vars(&["C", "B", "A"]));
+        assert_eq!(
+            render_export(one.clone(), ExportFormat::Dotenv, None).unwrap(),
+            "A='A-value'\nB='B-value'\nC='C-value'\n"
+        );
+        let ansible = |vars| render_export(vars, ExportFormat::AnsibleVault, Some(&password));
+        assert_eq!(ansible(one).unwrap(), ansible(other).unwrap());
+        assert_ne!(
+            ansible(vars(&["A", "B"])).unwrap(),
+            ansible(vars(&["A", "B", "C"])).unwrap()
+        );
+        assert!(render_export(vars(&["A"]), ExportFormat::AnsibleVault, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn exec_diff_shows_what_each_variable_becomes_without_values() {
+        let Commands::Exec {
+            secrets,
+            diff,
+            command,
+        } = parse(&[
+            "exec",
+            "--diff",
+            "-s",
+            "db/prod",
+            "-s",
+            "api=API_TOKEN",
+            "--",
+            "env",
+        ])
+        .command
+        else {
+            panic!("not exec");
+        };
+        assert!(diff);
+        assert_eq!(command, ["env"]);
+
+        let vault = vault_with(&[
+            ("db/prod", "correct horse"),
+            ("api", "tok-123456"),
+            ("home", "/root"),
+        ])
+        .await;
+        let service = &vault.service;
+        service.set_env_mapping("home", "HOME").await.unwrap();
+        let mut secrets = secrets;
+        secrets.push("home".into());
+        let (names, vars) = resolve_env_specs(service, &secrets).await.unwrap();
+        assert_eq!(vars, ["DB_PROD", "API_TOKEN", "HOME"]);
+        let resolved = service.get_all(&names).await.unwrap();
+        let table = exec_env_table(&names, &vars, &resolved, |var| match var {
+            "API_TOKEN" => Some("old".into()),
+            "HOME" => Some("/root".into()),
+            _ => None,
+        })
+        .to_string();
+        let row = |var: &str| {
+            table
+                .lines()
+                .find(|line| line.contains(var))
+                .unwrap()
+                .to_string()
+        };
+        assert!(row("DB_PROD").contains("+ set") && row("DB_PROD").contains("co***se"));
+        assert!(row("API_TOKEN").contains("~ override"));
+        assert!(row("HOME").contains("= same"));
+        for value in ["correct horse", "tok-123456", "/root"] {
+            assert!(!table.contains(value), "{value} shown in\n{table}");
+        }
+    }
+
+    #[tokio::test]
+    async fn names_come_from_the_agent_when_one_is_running() {
+        let Commands::Names {
+            prefix,
+            format,
+            limit,
+        } = parse(&[
+            "names", "--prefix", "app/", "--format", "json", "--limit", "5",
+        ])
+        .command
+        else {
+            panic!("not names");
+        };
+        assert_eq!((prefix.as_str(), limit), ("app/", Some(5)));
+        let names = ["app/db".to_string(), "app/key".to_string()];
+        assert_eq!(
+            names_output(&names, format_as(&format).unwrap()).unwrap(),
+            "[\"app/db\",\"app/key\"]\n"
+        );
+        assert_eq!(
+            names_output(&names, NamesFormat::Text).unwrap(),
+            "app/db\napp/key\n"
+        );
+        assert_eq!(names_output(&[], NamesFormat::Text).unwrap(), "");
+
+        let vault = vault_with(&[("api", "token"), ("app/db", "pw"), ("app/key", "k")]).await;
+        let socket = vault.db_path().with_file_name("agent.sock");
+        assert_eq!(names_from_agent(&socket, "app/", None).await, None);
+        #[cfg(unix)]
+        {
+            let listener = agent::bind(&socket).await.unwrap();
+            let cache = Arc::new(agent::NameCache::new(Duration::ZERO));
+            let served = socket.clone();
+            let service = Arc::new(vault.service);
+            let agent =
+                tokio::spawn(async move { agent::serve(service, cache, listener, &served).await });
+            assert_eq!(
+                names_from_agent(&socket, "app/", Some(1)).await,
+                Some(vec!["app/db".to_string()])
+            );
+            agent.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_to_takes_names_or_a_selection() {
+        let transfer = |args: &[&str]| match parse(args).command {
+            Commands::Transfer(args) => args,
+            _ => panic!("not copy-to"),
+        };
+        let args = transfer(&["copy-to", "--to-vault", "team", "api", "db", "--move"]);
+        assert_eq!(args.names, ["api", "db"]);
+        assert_eq!(args.to_vault, "team");
+        assert!(args.remove && !args.overwrite && !args.all);
+        let args = transfer(&["transfer", "--to-vault", "team", "--prefix", "app/"]);
+        assert!(args.names.is_empty());
+        assert_eq!(args.prefix.as_deref(), Some("app/"));
+        for clash in [
+            &["copy-to", "api"][..],
+            &["copy-to", "--to-vault", "team"],
+            &["copy-to", "--to-vault", "team", "api", "--all"],
+            &["copy-to", "--to-vault", "team", "api", "--prefix", "app/"],
+        ] {
+            let args = std::iter::once("devinventory").chain(clash.iter().copied());
+            assert!(Cli::try_parse_from(args).is_err(), "{clash:?}");
+        }
+
+        let vault = vault_with(&[("api", "token"), ("app/db", "pw"), ("app/key", "k")]).await;
+        let select = async |args: &[&str]| {
+            let TransferArgs {
+                names, all, prefix, ..
+            } = transfer(args);
+            transfer_names(names, all, prefix, &vault.service)
+                .await
+                .unwrap()
+        };
+        let base = ["copy-to", "--to-vault", "team"];
+        assert_eq!(select(&[&base[..], &["--all"]].concat()).await.len(), 3);
+        assert_eq!(
+            select(&[&base[..], &["--prefix", "app/"]].concat()).await,
+            ["app/db", "app/key"]
+        );
+        assert!(
+            select(&[&base[..], &["--prefix", "none/"]].concat())
+                .await
+                .is_empty()
+        );
+        // named secrets are taken as given; the copy reports the missing ones
+        assert_eq!(
+            select(&[&base[..], &["api", "gone"]].concat()).await,
+            ["api", "gone"]
+        );
+    }
+
+    #[test]
+    fn expiring_is_another_name_for_check() {
+        for command in ["check", "due", "expiring"] {
+            let Commands::Check { within } = parse(&[command, "--within", "7d"]).command else {
+                panic!("{command} is not check");
+            };
+            assert_eq!(within, Duration::from_secs(7 * 86400));
+        }
+        let Commands::Check { within } = parse(&["expiring"]).command else {
+            panic!("not check");
+        };
+        assert_eq!(within, Duration::from_secs(30 * 86400));
+    }
+
+    #[test]
+    fn format_goes_before_or_after_the_subcommand() {
+        let format = |args: &[&str]| parse(args).output_format();
+        assert_eq!(format(&["list", "--format", "json"]), OutputFormat::Json);
+        assert_eq!(format(&["--format", "yaml", "list"]), OutputFormat::Yaml);
+        assert_eq!(format(&["list"]), OutputFormat::Table);
+
+        // commands with a --format of their own keep it
+        let cli = parse(&["export", "--format", "dotenv"]);
+        assert_eq!(cli.output_format(), OutputFormat::Table);
+        let Commands::Export(ExportArgs { to, .. }) = cli.command else {
+            panic!("not export");
+        };
+        assert!(matches!(
+            format_as::<ExportFormat>(&to).unwrap(),
+            ExportFormat::Dotenv
+        ));
+        let Commands::Names { format, .. } = parse(&["--format", "json", "names"]).command else {
+            panic!("not names");
+        };
+        assert!(matches!(
+            format_as::<NamesFormat>(&format).unwrap(),
+            NamesFormat::Json
+        ));
+        let Commands::Names { format, .. } = parse(&["names"]).command else {
+            panic!("not names");
+        };
+        assert!(matches!(
+            format_as::<NamesFormat>(&format).unwrap(),
+            NamesFormat::Text
+        ));
+        assert!(Cli::try_parse_from(["devinventory", "names", "--format", "yaml"]).is_err());
+        let Commands::Report {
+            action: ReportCommands::Inventory { format, .. },
+        } = parse(&["--format", "yaml", "report", "inventory"]).command
+        else {
+            panic!("not report inventory");
+        };
+        assert_eq!(
+            format_as::<report::Format>(&format)
+                .err()
+                .unwrap()
+                .to_string(),
+            "--format yaml does not apply to this command; use md, html, csv"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! System clipboard helpers for short-lived secret exposure.
+
+use anyhow::{Context, Result};
+use arboard::Clipboard;
+use log::{debug, info, warn};
+use std::time::Duration;
+
+/// The two things clearing needs from a clipboard.
+trait Board {
+    fn text(&mut self) -> Option<String>;
+    fn clear(&mut self) -> Result<()>;
+}
+
+impl Board for Clipboard {
+    fn text(&mut self) -> Option<String> {
+        self.get_text().ok()
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        Ok(Clipboard::clear(self)?)
+    }
+}
+
+/// Put `value` on the clipboard. Keep the returned handle until `hold` is done: on
+/// X11 and Wayland the copying process has to stay alive to serve the value.
+pub fn copy(value: &str) -> Result<Clipboard> {
+    let mut clipboard = Clipboard::new().context("clipboard unavailable")?;
+    clipboard
+        .set_text(value)
+        .context("cannot write to the clipboard")?;
+    Ok(clipboard)
+}
+
+/// Wait for `duration` or Ctrl-C, whichever comes first, then clear the clipboard if it
+/// still holds `value`.
+pub async fn hold(mut clipboard: Clipboard, value: &str, duration: Duration) {
+    hold_board(&mut clipboard, value, duration).await;
+}
+
+async fn hold_board(board: &mut impl Board, value: &str, duration: Duration) {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    clear_board(board, value);
+}
+
+/// Clear the clipboard if it still holds `value`, leaving unrelated content alone.
+pub fn clear_if_holds(value: &str) {
+    match Clipboard::new() {
+        Ok(mut clipboard) => clear_board(&mut clipboard, value),
+        Err(e) => debug!("clipboard unavailable: {e}"),
+    }
+}
+
+fn clear_board(board: &mut impl Board, value: &str) {
+    if board.text().is_some_and(|t| t == value) {
+        match board.clear() {
+            Ok(()) => info!("clipboard cleared"),
+            Err(e) => warn!("cannot clear clipboard: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    struct FakeBoard(Option<String>);
+
+    impl Board for FakeBoard {
+        fn text(&mut self) -> Option<String> {
+            self.0.clone()
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            self.0 = None;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn copied_values_are_cleared_once_the_hold_ends() {
+        let mut board = FakeBoard(Some("hunter2".into()));
+        let started = Instant::now();
+        hold_board(&mut board, "hunter2", Duration::from_millis(50)).await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(board.0, None);
+
+        // something copied since then is not ours to clear
+        let mut board = FakeBoard(Some("a shopping list".into()));
+        hold_board(&mut board, "hunter2", Duration::ZERO).await;
+        assert_eq!(board.0.as_deref(), Some("a shopping list"));
+    }
+}
@@ -0,0 +1,455 @@
+use crate::domain::SecretMetadata;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, SubsecRound, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tabled::{Table, builder::Builder, settings::Style};
+
+/// How `list`, `search`, `get`, `stats` and `tag list` print their results.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Tables and lines for people
+    #[default]
+    Table,
+    /// Pretty-printed JSON, e.g. for `jq`
+    Json,
+    /// Block-style YAML
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Print `value` as JSON or YAML and return `true`; tables are left to the caller.
+    pub fn print(self, value: &impl Serialize) -> Result<bool> {
+        match self {
+            OutputFormat::Table => return Ok(false),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            OutputFormat::Yaml => print!("{}", yaml(&serde_json::to_value(value)?)),
+        }
+        Ok(true)
+    }
+}
+
+/// How tables show times; JSON and YAML always carry RFC 3339.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeStyle {
+    /// RFC 3339 in UTC, as stored
+    #[default]
+    Utc,
+    /// This machine's time zone, to the minute
+    Local,
+    /// Distance from now, e.g. `3d ago`
+    Relative,
+}
+
+impl TimeStyle {
+    pub fn show(self, at: DateTime<Utc>) -> String {
+        match self {
+            TimeStyle::Utc => at.to_rfc3339(),
+            TimeStyle::Local => at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            TimeStyle::Relative => relative_time(at),
+        }
+    }
+}
+
+/// Output settings from the command line and config, handed to every command.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Output {
+    pub format: OutputFormat,
+    pub time: TimeStyle,
+}
+
+/// `value` as block-style YAML. Strings are double-quoted with JSON escapes, which
+/// YAML reads the same way, so no value can turn into a number, bool or null.
+pub fn yaml(value: &Value) -> String {
+    fn scalar(value: &Value) -> String {
+        match value {
+            Value::String(s) => serde_json::to_string(s).expect("strings serialize"),
+            Value::Array(_) => "[]".to_string(),
+            Value::Object(_) => "{}".to_string(),
+            other => other.to_string(),
+        }
+    }
+    fn key(key: &str) -> String {
+        let plain = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-./".contains(c))
+            && !matches!(
+                key.to_ascii_lowercase().as_str(),
+                "true" | "false" | "null" | "yes" | "no" | "on" | "off" | "y" | "n"
+            );
+        if plain {
+            key.to_string()
+        } else {
+            serde_json::to_string(key).expect("strings serialize")
+        }
+    }
+    fn nested(value: &Value) -> bool {
+        matches!(value, Value::Array(a) if !a.is_empty())
+            || matches!(value, Value::Object(o) if !o.is_empty())
+    }
+    fn block(value: &Value, indent: usize, out: &mut String) {
+        let pad = " ".repeat(indent);
+        match value {
+            Value::Array(items) if !items.is_empty() => {
+                for item in items {
+                    // the item's first line starts right after the dash
+                    let mut inner = String::new();
+                    block(item, indent + 2, &mut inner);
+                    out.push_str(&format!("{pad}- {}", &inner[indent + 2..]));
+                }
+            }
+            Value::Object(map) if !map.is_empty() => {
+                for (name, value) in map {
+                    out.push_str(&format!("{pad}{}:", key(name)));
+                    if nested(value) {
+                        out.push('\n');
+                        block(value, indent + 2, out);
+                    } else {
+                        out.push_str(&format!(" {}\n", scalar(value)));
+                    }
+                }
+            }
+            other => out.push_str(&format!("{pad}{}\n", scalar(other))),
+        }
+    }
+    let mut out = String::new();
+    block(value, 0, &mut out);
+    out
+}
+
+/// A column `list --columns` can show.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// Stable id, unchanged by renames
+    Id,
+    Name,
+    Kind,
+    Owner,
+    Contact,
+    Note,
+    #[value(name = "expires_at")]
+    ExpiresAt,
+    #[value(name = "created_at")]
+    CreatedAt,
+    #[value(name = "updated_at")]
+    UpdatedAt,
+    /// How the current value entered the vault
+    Provenance,
+}
+
+/// Columns shown when none are asked for.
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Name,
+    Column::Kind,
+    Column::Owner,
+    Column::CreatedAt,
+    Column::UpdatedAt,
+];
+
+impl Column {
+    fn cell(self, meta: &SecretMetadata, time: TimeStyle) -> String {
+        match self {
+            Column::Id => meta.id.to_string(),
+            Column::Name => meta.name.clone(),
+            Column::Kind => meta.kind.clone().unwrap_or_default(),
+            Column::Owner => meta.owner.clone().unwrap_or_default(),
+            Column::Contact => meta.contact.clone().unwrap_or_default(),
+            Column::Note => meta.note.clone().unwrap_or_default(),
+            Column::ExpiresAt => meta.expires_at.map(|at| time.show(at)).unwrap_or_default(),
+            Column::CreatedAt => time.show(meta.created_at),
+            Column::UpdatedAt => time.show(meta.updated_at),
+            Column::Provenance => meta
+                .provenance
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Render metadata rows as a rounded table.
+pub fn metadata_table(rows: Vec<SecretMetadata>, time: TimeStyle) -> Table {
+    metadata_table_with(&rows, DEFAULT_COLUMNS, time)
+}
+
+/// Render metadata rows as a rounded table of `columns`, in that order.
+pub fn metadata_table_with(rows: &[SecretMetadata], columns: &[Column], time: TimeStyle) -> Table {
+    let mut builder = Builder::default();
+    builder.push_record(columns.iter().map(|c| {
+        c.to_possible_value()
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default()
+    }));
+    for meta in rows {
+        builder.push_record(columns.iter().map(|c| c.cell(meta, time)));
+    }
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    table
+}
+
+pub fn mask(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "(empty)".to_string();
+    }
+    let Ok(s) = std::str::from_utf8(bytes) else {
+        return format!("({} bytes of binary data)", bytes.len());
+    };
+    let len = s.chars().count();
+    let head = s.chars().take(2).collect::<String>();
+    let tail = s.chars().rev().take(2).collect::<String>();
+    match len {
+        0 => "(empty)".into(),
+        1..=3 => "***".into(),
+        _ => format!("{}***{}", head, tail.chars().rev().collect::<String>()),
+    }
+}
+
+/// Coarse distance from now, e.g. `in 3d` or `2h ago`.
+pub fn relative_time(at: DateTime<Utc>) -> String {
+    let delta = at - Utc::now();
+    let secs = delta.num_seconds().abs();
+    let amount = match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    };
+    if delta.num_seconds() > 0 {
+        format!("in {amount}")
+    } else {
+        format!("{amount} ago")
+    }
+}
+
+/// Byte count in binary units, e.g. `512 B`, `3.4 KiB`, `12.0 MiB`.
+pub fn human_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes.abs() < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// How to drop the last command from the history of `shell` (`$SHELL`, e.g.
+/// `/usr/bin/zsh`) and keep such commands out of it next time.
+pub fn history_scrub_hint(shell: &str) -> &'static str {
+    let name = shell.rsplit(['/', '\\']).next().unwrap_or(shell);
+    match name.trim_end_matches(".exe") {
+        "bash" => {
+            "bash: `history -d -2 && history -w` removes it (bash 5+); with \
+             HISTCONTROL=ignorespace, commands typed after a space are not saved"
+        }
+        "zsh" => {
+            "zsh: delete the line from $HISTFILE (this shell keeps it until it exits); with \
+             `setopt HIST_IGNORE_SPACE`, commands typed after a space are not saved"
+        }
+        "fish" => {
+            "fish: `history delete --contains -- --value` lets you pick the entry to remove; \
+             commands typed after a space are not saved"
+        }
+        "pwsh" | "powershell" => {
+            "PowerShell: `Clear-History -CommandLine *--value*` clears this session; also \
+             delete the line from (Get-PSReadLineOption).HistorySavePath"
+        }
+        _ => "remove the command from your shell's history file",
+    }
+}
+
+/// Parse a short human duration such as `30s`, `5m`, `2h` or `1d` (bare numbers are seconds).
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (digits, unit) = input.split_at(split);
+    let amount: u64 = digits
+        .parse()
+        .map_err(|_| anyhow!("invalid duration '{input}'"))?;
+    let secs = match unit {
+        "" | "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 3600,
+        "d" => amount * 86400,
+        _ => return Err(anyhow!("unknown duration unit '{unit}' (use s, m, h or d)")),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+/// Parse an expiry: RFC 3339 (`2025-06-30T12:00:00Z`), a date (`2025-06-30`, midnight
+/// UTC) or a duration from now (`12h`, `90d`).
+pub fn parse_expiry(input: &str) -> Result<DateTime<Utc>> {
+    let input = input.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(input) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    if let Ok(day) = chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(day.and_time(chrono::NaiveTime::MIN).and_utc());
+    }
+    let ahead = parse_duration(input).map_err(|_| {
+        anyhow!("invalid expiry '{input}' (use 2025-06-30, an RFC 3339 time or e.g. 90d)")
+    })?;
+    Ok((Utc::now() + chrono::Duration::from_std(ahead)?).trunc_subsecs(0))
+}
+
+/// Parse a starting point: RFC 3339, a date (midnight UTC) or a duration back from
+/// now (`24h`, `7d`).
+pub fn parse_since(input: &str) -> Result<DateTime<Utc>> {
+    let input = input.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(input) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    if let Ok(day) = chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(day.and_time(chrono::NaiveTime::MIN).and_utc());
+    }
+    let back = parse_duration(input).map_err(|_| {
+        anyhow!("invalid time '{input}' (use 2025-06-30, an RFC 3339 time or e.g. 7d)")
+    })?;
+    Ok(Utc::now() - chrono::Duration::from_std(back)?)
+}
+
+/// Names drawn as a folder tree, splitting on `/`.
+pub fn name_tree<'a>(names: impl IntoIterator<Item = &'a str>) -> String {
+    #[derive(Default)]
+    struct Node<'a> {
+        /// Whether a secret has exactly this name (a folder can be a secret too)
+        leaf: bool,
+        children: BTreeMap<&'a str, Node<'a>>,
+    }
+    fn render(node: &Node, indent: &str, out: &mut String) {
+        let count = node.children.len();
+        for (i, (part, child)) in node.children.iter().enumerate() {
+            let last = i + 1 == count;
+            let folder = if child.children.is_empty() { "" } else { "/" };
+            let marker = if child.leaf && !folder.is_empty() {
+                " *"
+            } else {
+                ""
+            };
+            let branch = if last { "└── " } else { "├── " };
+            out.push_str(&format!("{indent}{branch}{part}{folder}{marker}\n"));
+            let next = format!("{indent}{}", if last { "    " } else { "│   " });
+            render(child, &next, out);
+        }
+    }
+    let mut root = Node::default();
+    for name in names {
+        let mut node = &mut root;
+        for part in name.split('/') {
+            node = node.children.entry(part).or_default();
+        }
+        node.leaf = true;
+    }
+    let mut out = String::new();
+    render(&root, "", &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_expiry_dates_times_and_durations() {
+        assert_eq!(
+            parse_expiry("2025-06-30").unwrap().to_rfc3339(),
+            "2025-06-30T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_expiry("2025-06-30T12:00:00+02:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2025-06-30T10:00:00+00:00"
+        );
+        let in_a_day = parse_expiry("1d").unwrap() - Utc::now();
+        assert!((86_390..=86_400).contains(&in_a_day.num_seconds()));
+        assert!(parse_expiry("next week").is_err());
+    }
+
+    #[test]
+    fn draws_folders_as_a_tree() {
+        let tree = name_tree(["prod/db/password", "prod/api", "prod", "ci"]);
+        assert_eq!(
+            tree,
+            "├── ci\n└── prod/ *\n    ├── api\n    └── db/\n        └── password\n"
+        );
+    }
+
+    #[test]
+    fn parses_duration_units() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("m").is_err());
+    }
+
+    #[test]
+    fn masks_text_and_binary_values() {
+        assert_eq!(mask(b"hunter2"), "hu***r2");
+        assert_eq!(mask(b"abc"), "***");
+        assert_eq!(mask(&[0xff, 0x00, 0x10]), "(3 bytes of binary data)");
+    }
+
+    #[test]
+    fn yaml_quotes_strings_and_nests_blocks() {
+        let value = serde_json::json!({
+            "name": "db/pass",
+            "tags": ["prod", "no"],
+            "fields": {},
+            "items": [{"a": 1, "b": null}, [true]],
+            "odd key": "x\ny",
+        });
+        assert_eq!(
+            yaml(&value),
+            "fields: {}\n\
+             items:\n  - a: 1\n    b: null\n  - - true\n\
+             name: \"db/pass\"\n\
+             \"odd key\": \"x\\ny\"\n\
+             tags:\n  - \"prod\"\n  - \"no\"\n"
+        );
+    }
+
+    #[test]
+    fn shows_times_in_the_chosen_style() {
+        let at = "2025-06-30T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(TimeStyle::Utc.show(at), "2025-06-30T10:00:00+00:00");
+        assert!(TimeStyle::Relative.show(at).ends_with(" ago"));
+        assert_eq!(TimeStyle::Local.show(at).len(), "2025-06-30 10:00".len());
+    }
+
+    #[test]
+    fn suggests_history_scrubbing_per_shell() {
+        assert!(history_scrub_hint("/bin/bash").starts_with("bash: `history -d -2"));
+        assert!(history_scrub_hint("/usr/bin/zsh").starts_with("zsh:"));
+        assert!(
+            history_scrub_hint(r"C:\Program Files\PowerShell\7\pwsh.exe").starts_with("PowerShell")
+        );
+        assert_eq!(
+            history_scrub_hint(""),
+            "remove the command from your shell's history file"
+        );
+    }
+
+    #[test]
+    fn formats_byte_counts() {
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(3482), "3.4 KiB");
+        assert_eq!(human_bytes(12 * 1024 * 1024), "12.0 MiB");
+    }
+}
@@ -0,0 +1,100 @@
+//! Editing secret values in the user's `$VISUAL`/`$EDITOR`.
+
+use crate::perms;
+use anyhow::{Context, Result, bail};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// `$VISUAL`, then `$EDITOR`, split on whitespace (`code --wait` works); falls back
+/// to `vi` (`notepad` on Windows).
+pub fn editor_command() -> Vec<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(|cmd| cmd.split_whitespace().map(String::from).collect::<Vec<_>>())
+        .find(|argv| !argv.is_empty())
+        .unwrap_or_else(|| vec![if cfg!(windows) { "notepad" } else { "vi" }.to_string()])
+}
+
+/// Open `initial` in `editor` and return what was saved. The scratch file lives in a
+/// private directory (RAM-backed `/dev/shm` when available) and is overwritten with
+/// zeros before removal.
+pub fn edit(editor: &[String], initial: &[u8]) -> Result<Vec<u8>> {
+    let Some((program, args)) = editor.split_first() else {
+        bail!("no editor configured; set $EDITOR");
+    };
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("devinventory-edit");
+    let dir = match scratch_root() {
+        Some(root) => builder.tempdir_in(root)?,
+        None => builder.tempdir()?,
+    };
+    let path = dir.path().join("secret");
+    std::fs::write(&path, initial)?;
+    perms::make_owner_only(&path)?;
+    let status = Command::new(program)
+        .args(args)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("starting editor '{program}'"));
+    let edited = std::fs::read(&path);
+    if let Ok(len) = std::fs::metadata(&path).map(|m| m.len())
+        && let Ok(mut file) = std::fs::OpenOptions::new().write(true).open(&path)
+    {
+        let _ = file.write_all(&vec![0u8; len as usize]);
+        let _ = file.sync_all();
+    }
+    drop(dir);
+    let status = status?;
+    if !status.success() {
+        bail!("editor exited with {status}; secret left unchanged");
+    }
+    Ok(trim_added_newline(initial, edited?))
+}
+
+/// Editors end files with a newline; drop it again when the original value had none.
+pub fn trim_added_newline(original: &[u8], mut edited: Vec<u8>) -> Vec<u8> {
+    if !original.ends_with(b"\n") {
+        if edited.ends_with(b"\r\n") {
+            edited.truncate(edited.len() - 2);
+        } else if edited.ends_with(b"\n") {
+            edited.pop();
+        }
+    }
+    edited
+}
+
+fn scratch_root() -> Option<PathBuf> {
+    let shm = PathBuf::from("/dev/shm");
+    (cfg!(target_os = "linux") && shm.is_dir()).then_some(shm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_newlines_only_when_the_value_had_one() {
+        assert_eq!(
+            trim_added_newline(b"token", b"token2\n".to_vec()),
+            b"token2"
+        );
+        assert_eq!(
+            trim_added_newline(b"token", b"token2\r\n".to_vec()),
+            b"token2"
+        );
+        let pem = b"-----BEGIN KEY-----\nabc\n-----END KEY-----\n";
+        assert_eq!(trim_added_newline(pem, pem.to_vec()), pem);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_the_editor_on_a_private_scratch_file() {
+        let editor = |script: &str| vec!["sh".to_string(), "-c".into(), script.into()];
+        // the scratch file is passed as the last argument, `$0` for `sh -c`
+        let edited = edit(&editor("printf 'new\\n' >> \"$0\""), b"old").unwrap();
+        assert_eq!(edited, b"oldnew");
+        assert!(edit(&editor("exit 1"), b"old").is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! `explain <topic>`: reference pages built from the same definitions the CLI and
+//! config loader use, so they cannot fall behind them.
+
+use crate::config::{self, CONFIG_KEYS};
+use crate::ui::cli::{AuditFormat, Cli, ExportFormat, TEMPLATE_FUNC_NOT_FOUND};
+use crate::{generator, report, transform};
+use clap::{CommandFactory, ValueEnum};
+use std::fmt::Write as _;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topic {
+    /// Where the master key comes from and in which order
+    KeySources,
+    /// Export, report, audit and encoding formats
+    Formats,
+    /// What each exit status means
+    ExitCodes,
+    /// Per-secret restrictions and the config settings enforcing vault-wide ones
+    Policies,
+    /// Every config.toml key with its type and default
+    Config,
+}
+
+pub fn render(topic: Topic) -> String {
+    match topic {
+        Topic::KeySources => key_sources(),
+        Topic::Formats => formats(),
+        Topic::ExitCodes => exit_codes(),
+        Topic::Policies => policies(),
+        Topic::Config => config_schema(&|_| true),
+    }
+}
+
+fn key_sources() -> String {
+    let command = Cli::command();
+    let flag_help = |id: &str| {
+        command
+            .get_arguments()
+            .find(|a| a.get_id() == id)
+            .and_then(|a| a.get_help())
+            .map(ToString::to_string)
+            .unwrap_or_default()
+    };
+    let mut out = String::from("Master key, first match wins:\n");
+    let _ = writeln!(out, "  1. --dmk BASE64     {}", flag_help("dmk"));
+    let _ = writeln!(out, "  2. --dmk-file PATH  {}", flag_help("dmk_file"));
+    let _ = writeln!(
+        out,
+        "  3. OS keyring       service/account from --keyring-* flags, ${}/${}, [keyring] or \"devinventory\"/\"dmk\"",
+        config::KEYRING_SERVICE_VAR,
+        config::KEYRING_ACCOUNT_VAR
+    );
+    let _ = writeln!(
+        out,
+        "     instead of 3, a passphrase vault asks for its passphrase, a YubiKey vault for the [yubikey] slot's answer and a PKCS#11 vault for the [pkcs11] token to unwrap its key"
+    );
+    let _ = writeln!(
+        out,
+        "  4. Fallbacks        this machine's device key, then key slots (`key slot list`)"
+    );
+    let _ = writeln!(out, "     --no-keyring     {}", flag_help("no_keyring"));
+    let _ = writeln!(out, "     --ephemeral      {}", flag_help("ephemeral"));
+    let _ = writeln!(
+        out,
+        "\nVault file: --db-path, then ${}, then [database] path.",
+        config::DB_PATH_VAR
+    );
+    out
+}
+
+fn variants<T: ValueEnum>(out: &mut String, title: &str) {
+    let _ = writeln!(out, "{title}:");
+    for variant in T::value_variants() {
+        if let Some(value) = variant.to_possible_value() {
+            let help = value
+                .get_help()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            let _ = writeln!(out, "  {:<14} {help}", value.get_name());
+        }
+    }
+    out.push('\n');
+}
+
+fn formats() -> String {
+    let mut out = String::new();
+    variants::<ExportFormat>(&mut out, "export --to / --format");
+    variants::<report::Format>(&mut out, "report inventory --format");
+    variants::<AuditFormat>(&mut out, "audit export --format");
+    variants::<transform::Pipe>(&mut out, "get --pipe");
+    variants::<generator::Charset>(&mut out, "generate --charset");
+    out.push_str("Templates (render): {{ secret(\"name\") }} markers, single or double quotes\n");
+    out
+}
+
+fn exit_codes() -> String {
+    let not_found = TEMPLATE_FUNC_NOT_FOUND.to_string();
+    let codes = [
+        ("0", "success; for checks, nothing was found"),
+        (
+            "1",
+            "error, or a check found something: check, scan, drift, attest verify",
+        ),
+        ("2", "invalid arguments (usage error)"),
+        (&not_found, "template-func: the name is not in the vault"),
+        ("other", "exec, curl, registry exec: the child's own status"),
+    ];
+    let mut out = String::new();
+    for (code, meaning) in codes {
+        let _ = writeln!(out, "  {code:<6} {meaning}");
+    }
+    out
+}
+
+fn policies() -> String {
+    let mut out = String::from(
+        "Per secret (flags on add):\n\
+         \x20 --immutable          overwrites and deletes are rejected until clear-immutable\n\
+         \x20 --bind-host          sealed to this machine's id; a copied vault cannot open it\n\
+         \x20 --allow-exec PROG    exec only passes it to these programs; leases and render refuse it\n\
+         \x20 lock NAME            overwrites, renames and deletes are rejected until unlock\n\n\
+         Vault-wide (config.toml):\n\n",
+    );
+    out.push_str(&config_schema(&|section| {
+        matches!(section, "approval" | "reveal" | "crypto" | "clipboard")
+    }));
+    out
+}
+
+/// The schema as a commented config.toml holding the defaults.
+fn config_schema(include: &dyn Fn(&str) -> bool) -> String {
+    let mut out = String::new();
+    let mut section = "";
+    for key in CONFIG_KEYS.iter().filter(|k| include(k.section)) {
+        if key.section != section {
+            if !section.is_empty() {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "[{}]", key.section);
+            section = key.section;
+        }
+        let _ = writeln!(out, "# {} ({})", key.doc, key.ty);
+        let _ = writeln!(out, "# {} = {}", key.key, key.default);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_come_from_live_definitions() {
+        let formats = render(Topic::Formats);
+        assert!(formats.contains("dotenv"));
+        assert!(formats.contains("base64"));
+        let config = render(Topic::Config);
+        for key in CONFIG_KEYS {
+            assert!(config.contains(&format!("# {} = ", key.key)));
+        }
+        assert!(render(Topic::KeySources).contains("--dmk BASE64     Provide master key"));
+        assert!(render(Topic::Policies).contains("[reveal]"));
+        assert!(!render(Topic::Policies).contains("[database]"));
+    }
+}
@@ -0,0 +1,7 @@
+pub mod cli;
+pub mod clipboard;
+pub mod common;
+pub mod editor;
+pub mod explain;
+pub mod picker;
+pub mod prompt;
@@ -0,0 +1,212 @@
+//! Fuzzy selection of a secret for commands run without a name, drawn inline on the
+//! terminal as skim and fzf do.
+
+use crate::domain::SecretMetadata;
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Print, Stylize},
+    terminal::{self, Clear, ClearType},
+};
+use std::io::{self, IsTerminal, Write};
+
+/// Matches shown at once; the list scrolls to keep the selection visible.
+const HEIGHT: usize = 10;
+
+/// Whether a picker can be shown: stdin and stderr are both terminals.
+pub fn available() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// How well `query` matches `candidate`, or `None` when its characters do not all
+/// appear in order. Case-insensitive; runs of characters and characters starting a
+/// path segment or word score higher, gaps lower.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut last: Option<usize> = None;
+    let mut candidate = candidate.chars().enumerate();
+    let mut previous = None;
+    for wanted in query.chars().flat_map(char::to_lowercase) {
+        loop {
+            let (index, c) = candidate.next()?;
+            let boundary = previous.is_none_or(|p: char| "/-_.: ".contains(p));
+            previous = Some(c);
+            if !c.to_lowercase().eq(std::iter::once(wanted)) {
+                continue;
+            }
+            score += 16;
+            match last {
+                Some(last) if last + 1 == index => score += 8,
+                Some(last) => score -= (index - last - 1).min(8) as i64,
+                None => score -= index.min(8) as i64,
+            }
+            if boundary {
+                score += 10;
+            }
+            last = Some(index);
+            break;
+        }
+    }
+    // shorter names win ties, so `db` ranks above `db/replica`
+    Some(score - candidate.count().min(32) as i64 / 4)
+}
+
+/// `items` matching `query`, best first; equal scores keep their order.
+pub fn rank<'a>(query: &str, items: &'a [SecretMetadata]) -> Vec<&'a SecretMetadata> {
+    let mut scored: Vec<_> = items
+        .iter()
+        .filter_map(|meta| Some((score(query, &meta.name)?, meta)))
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, meta)| meta).collect()
+}
+
+/// Let the user choose one of `items` by typing part of its name: arrows or Ctrl-P/N
+/// move, Enter picks, Esc or Ctrl-C cancels (`None`). Drawn on stderr below the
+/// cursor and cleared afterwards.
+pub fn pick(prompt: &str, items: &[SecretMetadata]) -> Result<Option<String>> {
+    terminal::enable_raw_mode()?;
+    let result = run(prompt, items);
+    let mut out = io::stderr();
+    let _ = queue!(
+        out,
+        cursor::MoveToColumn(0),
+        Clear(ClearType::FromCursorDown)
+    );
+    let _ = out.flush();
+    let _ = terminal::disable_raw_mode();
+    result
+}
+
+fn run(prompt: &str, items: &[SecretMetadata]) -> Result<Option<String>> {
+    let mut out = io::stderr();
+    let mut query = String::new();
+    let mut selected = 0;
+    loop {
+        let matches = rank(&query, items);
+        selected = selected.min(matches.len().saturating_sub(1));
+        draw(&mut out, prompt, &query, &matches, selected, items.len())?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        // Windows also reports releases
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c' | 'd') if ctrl => return Ok(None),
+            KeyCode::Enter => {
+                if let Some(meta) = matches.get(selected) {
+                    return Ok(Some(meta.name.clone()));
+                }
+            }
+            KeyCode::Up | KeyCode::BackTab => selected = selected.saturating_sub(1),
+            KeyCode::Char('p' | 'k') if ctrl => selected = selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => selected += 1,
+            KeyCode::Char('n' | 'j') if ctrl => selected += 1,
+            KeyCode::Char('u') if ctrl => {
+                query.clear();
+                selected = 0;
+            }
+            KeyCode::Backspace => {
+                query.pop();
+                selected = 0;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The prompt line with the query, a match count, then the visible matches; the
+/// cursor is left after the query.
+fn draw(
+    out: &mut impl Write,
+    prompt: &str,
+    query: &str,
+    matches: &[&SecretMetadata],
+    selected: usize,
+    total: usize,
+) -> Result<()> {
+    let width = match terminal::size() {
+        Ok((columns, _)) if columns > 0 => columns as usize,
+        _ => 80,
+    };
+    let fit = |line: String| {
+        line.chars()
+            .take(width.saturating_sub(1))
+            .collect::<String>()
+    };
+    queue!(
+        out,
+        cursor::MoveToColumn(0),
+        Clear(ClearType::FromCursorDown),
+        Print(fit(format!("{prompt}{query}"))),
+        Print("\r\n"),
+        Print(format!("  {}/{total}", matches.len()).dark_grey()),
+    )?;
+    let top = selected.saturating_sub(HEIGHT - 1);
+    let shown = &matches[top..matches.len().min(top + HEIGHT)];
+    for (offset, meta) in shown.iter().enumerate() {
+        let mut line = meta.name.clone();
+        if let Some(kind) = &meta.kind {
+            line.push_str(&format!("  ({kind})"));
+        }
+        queue!(out, Print("\r\n"))?;
+        if top + offset == selected {
+            queue!(out, Print(fit(format!("> {line}")).reverse()))?;
+        } else {
+            queue!(out, Print(fit(format!("  {line}"))))?;
+        }
+    }
+    let column = (prompt.chars().count() + query.chars().count()).min(width.saturating_sub(1));
+    queue!(
+        out,
+        cursor::MoveUp(shown.len() as u16 + 1),
+        cursor::MoveToColumn(column as u16)
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_names_by_fuzzy_match() {
+        assert!(score("dbp", "db/prod-pass").is_some());
+        assert!(score("DBP", "db/prod-pass").is_some());
+        assert!(score("pdb", "db/prod-pass").is_none());
+        assert!(score("gh", "github/token") > score("gh", "staging/hash"));
+
+        let meta = |name: &str| SecretMetadata {
+            id: uuid::Uuid::new_v4(),
+            name: name.into(),
+            kind: None,
+            note: None,
+            owner: None,
+            contact: None,
+            immutable: false,
+            bind_host: false,
+            allowed_commands: Vec::new(),
+            expires_at: None,
+            certificate: None,
+            fields: Default::default(),
+            provenance: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let items = ["aws/prod/key", "db/replica", "db", "web/db-admin"].map(meta);
+        let names: Vec<_> = rank("db", &items).iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["db", "db/replica", "web/db-admin"]);
+        assert_eq!(rank("", &items).len(), 4);
+    }
+}
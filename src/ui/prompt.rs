@@ -0,0 +1,257 @@
+//! Passphrase and confirmation prompts, with an external helper for runs without a
+//! terminal (IDE tasks, git hooks): an askpass program or pinentry.
+
+use crate::config::SecurityConfig;
+use crate::keymgr::Prompter;
+use anyhow::{Context, Result, anyhow, bail};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// Askpass program; wins over `[security] askpass`.
+pub const ASKPASS_VAR: &str = "DEVINVENTORY_ASKPASS";
+
+/// Program asked when there is no terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Helper {
+    /// Called with the prompt as its only argument; prints the answer, non-zero exit cancels
+    Askpass(String),
+    /// Spoken to in the Assuan protocol (`GETPIN`), as gpg does
+    Pinentry(String),
+}
+
+static HELPER: OnceLock<Option<Helper>> = OnceLock::new();
+
+/// Pick the helper from `$DEVINVENTORY_ASKPASS`, then `[security] askpass`, then
+/// `[security] pinentry`; call once at startup.
+pub fn init(security: &SecurityConfig) {
+    let helper = std::env::var(ASKPASS_VAR)
+        .ok()
+        .or_else(|| security.askpass.clone())
+        .filter(|p| !p.is_empty())
+        .map(Helper::Askpass)
+        .or_else(|| security.pinentry.clone().map(Helper::Pinentry));
+    let _ = HELPER.set(helper);
+}
+
+fn helper() -> Option<&'static Helper> {
+    HELPER.get().and_then(Option::as_ref)
+}
+
+/// Whether someone can be asked: a terminal on stdin or a configured helper.
+pub fn can_ask() -> bool {
+    io::stdin().is_terminal() || helper().is_some()
+}
+
+/// A hidden answer, e.g. a passphrase or secret value.
+pub fn secret(prompt: &str) -> Result<String> {
+    match helper() {
+        Some(helper) if !has_terminal() => ask(helper, prompt),
+        None if !has_terminal() => bail!(
+            "no terminal to ask '{}'; set ${ASKPASS_VAR}, [security] askpass or [security] pinentry",
+            prompt.trim_end_matches([':', ' '])
+        ),
+        _ => Ok(rpassword::prompt_password(prompt)?),
+    }
+}
+
+/// The terminal (or configured helper) as the key layer's [`Prompter`].
+pub struct Terminal;
+
+impl Prompter for Terminal {
+    fn can_ask(&self) -> bool {
+        can_ask()
+    }
+
+    fn secret(&self, prompt: &str) -> Result<String> {
+        secret(prompt)
+    }
+
+    fn notice(&self, message: &str) {
+        if io::stderr().is_terminal() {
+            eprintln!("{message}");
+        }
+    }
+}
+
+/// A visible answer, e.g. `y` to a confirmation. Piped stdin still answers when there
+/// is no terminal and no helper, so scripts can confirm with `echo y |`.
+pub fn line(prompt: &str) -> Result<String> {
+    if let Some(helper) = helper()
+        && !io::stdin().is_terminal()
+        && !has_terminal()
+    {
+        return ask(helper, prompt);
+    }
+    eprint!("{prompt}");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// Whether this process has a controlling terminal to prompt on.
+fn has_terminal() -> bool {
+    #[cfg(unix)]
+    {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .is_ok()
+    }
+    #[cfg(not(unix))]
+    {
+        io::stdin().is_terminal()
+    }
+}
+
+pub fn ask(helper: &Helper, prompt: &str) -> Result<String> {
+    match helper {
+        Helper::Askpass(program) => askpass(program, prompt),
+        Helper::Pinentry(program) => pinentry(program, prompt),
+    }
+}
+
+fn askpass(program: &str, prompt: &str) -> Result<String> {
+    let output = Command::new(program)
+        .arg(prompt)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("running askpass program '{program}'"))?;
+    if !output.status.success() {
+        bail!("prompt cancelled ({program} exited with {})", output.status);
+    }
+    let answer = String::from_utf8(output.stdout)
+        .map_err(|_| anyhow!("askpass program '{program}' printed invalid UTF-8"))?;
+    Ok(answer.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn pinentry(program: &str, prompt: &str) -> Result<String> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("running pinentry program '{program}'"))?;
+    let mut input = child.stdin.take().expect("stdin is piped");
+    let mut output = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let result = (|| {
+        expect_ok(&mut output)?;
+        for command in [
+            "SETTITLE devinventory".to_string(),
+            format!("SETDESC {}", assuan_escape(prompt)),
+        ] {
+            writeln!(input, "{command}")?;
+            expect_ok(&mut output)?;
+        }
+        writeln!(input, "GETPIN")?;
+        let mut pin = String::new();
+        loop {
+            let response = read_response(&mut output)?;
+            if let Some(data) = response.strip_prefix("D ") {
+                pin.push_str(&assuan_unescape(data));
+            } else if response == "OK" || response.starts_with("OK ") {
+                return Ok(pin);
+            } else if response.starts_with("ERR") {
+                bail!("prompt cancelled ({program}: {response})");
+            }
+        }
+    })();
+    let _ = writeln!(input, "BYE");
+    drop(input);
+    let _ = child.wait();
+    result
+}
+
+fn read_response(output: &mut impl BufRead) -> Result<String> {
+    loop {
+        let mut line = String::new();
+        if output.read_line(&mut line)? == 0 {
+            bail!("pinentry closed the connection");
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        // status and comment lines carry nothing the prompt needs
+        if !line.starts_with("S ") && !line.starts_with('#') {
+            return Ok(line.to_string());
+        }
+    }
+}
+
+fn expect_ok(output: &mut impl BufRead) -> Result<()> {
+    let response = read_response(output)?;
+    if response == "OK" || response.starts_with("OK ") {
+        Ok(())
+    } else {
+        bail!("pinentry answered '{response}'")
+    }
+}
+
+fn assuan_escape(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn assuan_unescape(data: &str) -> String {
+    let bytes = data.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = data
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn script(dir: &tempfile::TempDir, name: &str, body: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.path().join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{body}")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn helpers_answer_without_a_terminal() {
+        let dir = tempfile::tempdir().unwrap();
+        let askpass = script(&dir, "askpass", "echo \"answer to $1\"\n");
+        assert_eq!(
+            ask(&Helper::Askpass(askpass), "Passphrase: ").unwrap(),
+            "answer to Passphrase: "
+        );
+        let cancel = script(&dir, "cancel", "exit 1\n");
+        assert!(ask(&Helper::Askpass(cancel), "Passphrase: ").is_err());
+
+        let pinentry = script(
+            &dir,
+            "pinentry",
+            "echo 'OK ready'\nread a; echo OK\nread a; echo OK\nread a\n\
+             echo 'S PROGRESS'; echo 'D p%25ss%0Aword'; echo OK\nread a\n",
+        );
+        assert_eq!(
+            ask(&Helper::Pinentry(pinentry), "Backup\npassphrase").unwrap(),
+            "p%ss\nword"
+        );
+        let refuse = script(
+            &dir,
+            "refuse",
+            "echo OK\nread a; echo OK\nread a; echo OK\nread a\necho 'ERR 83886179 Operation cancelled'\nread a\n",
+        );
+        assert!(ask(&Helper::Pinentry(refuse), "x").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! YubiKey HMAC-SHA1 challenge-response (an OTP slot set up with
+//! `ykman otp chalresp --generate 2`), asked through `ykchalresp` or `ykman`, for
+//! vaults whose master key is derived from the key's answer.
+
+use crate::crypto::{self, MasterKey};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine as _, engine::general_purpose};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use zeroize::Zeroize;
+
+/// OTP slot used when `[yubikey] slot` is not set; slot 1 usually holds Yubico OTP.
+pub const DEFAULT_SLOT: u8 = 2;
+/// Program used when `[yubikey] program` is not set.
+pub const DEFAULT_PROGRAM: &str = "ykchalresp";
+const KEY_INFO: &[u8] = b"devinventory yubikey key v1";
+const CHECK_INFO: &[u8] = b"devinventory yubikey check v1";
+
+/// How to reach the YubiKey, from `[yubikey]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    /// OTP slot (1 or 2) new vaults are bound to
+    pub slot: u8,
+    /// `ykchalresp` or `ykman`, by name or path
+    pub program: String,
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        Self {
+            slot: DEFAULT_SLOT,
+            program: DEFAULT_PROGRAM.to_string(),
+        }
+    }
+}
+
+impl Device {
+    /// The HMAC-SHA1 answer of `slot` to `challenge`. The key may blink until touched.
+    pub fn respond(&self, slot: u8, challenge: &[u8]) -> Result<Vec<u8>> {
+        if !(1..=2).contains(&slot) {
+            bail!("YubiKey slot must be 1 or 2, not {slot}");
+        }
+        let hex: String = challenge.iter().map(|b| format!("{b:02x}")).collect();
+        let ykman = Path::new(&self.program)
+            .file_stem()
+            .is_some_and(|stem| stem == "ykman");
+        let args = if ykman {
+            vec!["otp".to_string(), "calculate".into(), slot.to_string(), hex]
+        } else {
+            vec![format!("-{slot}"), "-x".into(), hex]
+        };
+        let output = Command::new(&self.program)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| format!("running '{}'; is it installed?", self.program))?;
+        if !output.status.success() {
+            bail!(
+                "{} failed: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let mut text = String::from_utf8(output.stdout)
+            .map_err(|_| anyhow!("{} printed invalid UTF-8", self.program))?;
+        let response = parse_hex(text.trim());
+        text.zeroize();
+        response.ok_or_else(|| anyhow!("{} did not print a hex response", self.program))
+    }
+}
+
+/// The challenge a vault's master key is derived from. Kept in the vault: none of it
+/// is secret without the YubiKey.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct YubikeyChallenge {
+    pub slot: u8,
+    /// Base64 of the random 32-byte challenge
+    pub challenge: String,
+    /// Base64 of a digest of the derived key, telling another YubiKey apart from a
+    /// damaged vault
+    pub check: String,
+}
+
+impl YubikeyChallenge {
+    /// A fresh challenge on `device`'s slot and the key its answer derives.
+    pub fn create(device: &Device) -> Result<(Self, MasterKey)> {
+        let mut challenge = [0u8; 32];
+        rand::rng().fill_bytes(&mut challenge);
+        let mut settings = Self {
+            slot: device.slot,
+            challenge: general_purpose::STANDARD.encode(challenge),
+            check: String::new(),
+        };
+        let key = settings.answer(device)?;
+        settings.check = general_purpose::STANDARD.encode(check_digest(&key));
+        Ok((settings, key))
+    }
+
+    /// The key the YubiKey's answer derives; an error when it is not this vault's key.
+    pub fn derive(&self, device: &Device) -> Result<MasterKey> {
+        let key = self.answer(device)?;
+        if !self.matches(&key) {
+            bail!(
+                "this YubiKey (slot {}) does not unlock the vault",
+                self.slot
+            );
+        }
+        Ok(key)
+    }
+
+    /// Whether `key` is the one this challenge derives.
+    pub fn matches(&self, key: &MasterKey) -> bool {
+        general_purpose::STANDARD.encode(check_digest(key)) == self.check
+    }
+
+    fn answer(&self, device: &Device) -> Result<MasterKey> {
+        let challenge = general_purpose::STANDARD
+            .decode(&self.challenge)
+            .context("YubiKey challenge is not base64")?;
+        let mut response = device.respond(self.slot, &challenge)?;
+        let key = crypto::derive_key(&response, &challenge, KEY_INFO);
+        response.zeroize();
+        Ok(key)
+    }
+}
+
+fn check_digest(key: &MasterKey) -> [u8; 16] {
+    let digest = crypto::keyed_digest(key, CHECK_INFO, b"");
+    digest[..16].try_into().expect("digest is 32 bytes")
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// A stand-in for `ykchalresp` answering with a digest of its arguments, like a
+    /// YubiKey whose HMAC secret is `secret`.
+    fn fake(dir: &tempfile::TempDir, name: &str, secret: &str) -> Device {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.path().join(name);
+        std::fs::write(
+            &path,
+            format!("#!/bin/sh\necho \"{secret} $*\" | sha1sum | cut -d' ' -f1\n"),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700)).unwrap();
+        Device {
+            slot: 2,
+            program: path.to_string_lossy().into_owned(),
+        }
+    }
+
+    #[test]
+    fn challenges_derive_the_same_key_from_the_same_yubikey() {
+        let dir = tempfile::tempdir().unwrap();
+        let mine = fake(&dir, "ykchalresp", "mine");
+        let (settings, key) = YubikeyChallenge::create(&mine).unwrap();
+        assert_eq!(settings.derive(&mine).unwrap().0, key.0);
+
+        let other = fake(&dir, "other", "other");
+        let err = settings.derive(&other).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "this YubiKey (slot 2) does not unlock the vault"
+        );
+        let (fresh, fresh_key) = YubikeyChallenge::create(&mine).unwrap();
+        assert_ne!(fresh.challenge, settings.challenge);
+        assert_ne!(fresh_key.0, key.0);
+
+        let failing = Device {
+            slot: 2,
+            program: "/bin/false".into(),
+        };
+        assert!(settings.derive(&failing).is_err());
+        assert!(mine.respond(3, b"x").is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! The `test-fixtures` feature as a downstream crate sees it: temp vaults built
+//! through the public API only.
+
+use devinventory::domain::SecretAttributes;
+use devinventory::testing::{self, DEFAULT_KEY_SEED, TempVault};
+
+#[tokio::test]
+async fn sample_vaults_open_with_their_deterministic_key() {
+    let vault = TempVault::builder()
+        .with_sample_data()
+        .with_secret("scratch/note", "hello")
+        .build()
+        .await
+        .unwrap();
+    let names: Vec<String> = vault
+        .service
+        .list_secrets()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|m| m.name)
+        .collect();
+    assert_eq!(names.len(), testing::sample_secrets().len() + 1);
+    assert!(names.iter().any(|n| n == "prod/db/password"));
+
+    let reopened = vault.open(DEFAULT_KEY_SEED).await.unwrap();
+    let secret = reopened.get_secret("scratch/note").await.unwrap();
+    assert_eq!(secret.plaintext, b"hello");
+    assert!(
+        vault
+            .open(DEFAULT_KEY_SEED + 1)
+            .await
+            .unwrap()
+            .get_secret("scratch/note")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn fixture_vaults_keep_their_settings() {
+    let attrs = SecretAttributes {
+        owner: Some("platform".into()),
+        ..Default::default()
+    };
+    let vault = TempVault::builder()
+        .with_key_seed(3)
+        .with_secret_attrs("prod/db", "pw", attrs)
+        .with_secret("dev/db", "pw")
+        .with_protected_prefixes(vec!["prod/".into()])
+        .build()
+        .await
+        .unwrap();
+    let service = &vault.service;
+    let meta = service.secret_metadata("prod/db").await.unwrap().unwrap();
+    assert_eq!(meta.owner.as_deref(), Some("platform"));
+    assert!(service.delete_secret("prod/db").await.is_err());
+    assert_eq!(service.pending_approvals().await.unwrap().len(), 1);
+    assert!(service.delete_secret("dev/db").await.unwrap());
+
+    // a second vault under the same seed reads what the first one wrote
+    let dir = tempfile::tempdir().unwrap();
+    let copy = dir.path().join(vault.db_path().file_name().unwrap());
+    std::fs::copy(vault.db_path(), &copy).unwrap();
+    let reopened = testing::open_service(dir.path(), 3).await.unwrap();
+    assert_eq!(
+        reopened.get_secret("prod/db").await.unwrap().plaintext,
+        b"pw"
+    );
+}